@@ -23,7 +23,7 @@ use zerocopy::Ref;
 
 use crate::dof::DOF_MAGIC;
 use crate::dof_bindings::*;
-use crate::{Error, Ident, Probe, Provider, Section};
+use crate::{Error, Ident, Probe, Provider, ProviderAttributes, Section};
 
 // Extract one or more null-terminated strings from the given byte slice.
 fn extract_strings(buf: &[u8], count: Option<usize>) -> Vec<String> {
@@ -62,6 +62,9 @@ fn parse_probe_section(
             .collect();
         let arg_base = probe.dofpr_nargv as usize;
         let arguments = extract_strings(&strtab[arg_base..], Some(probe.dofpr_nargc as _));
+        let translated_base = probe.dofpr_xargv as usize;
+        let translated_arguments =
+            extract_strings(&strtab[translated_base..], Some(probe.dofpr_xargc as _));
         Probe {
             name: extract_strings(&strtab[probe.dofpr_name as _..], Some(1))[0].clone(),
             function: extract_strings(&strtab[probe.dofpr_func as _..], Some(1))[0].clone(),
@@ -69,6 +72,7 @@ fn parse_probe_section(
             offsets: offs,
             enabled_offsets: enabled_offs,
             arguments,
+            translated_arguments,
         }
     };
     buf.chunks(size_of::<dof_probe>())
@@ -122,7 +126,19 @@ fn parse_providers(sections: &[dof_sec], buf: &[u8]) -> Vec<Provider> {
             .map(|probe| (probe.name.clone(), probe))
             .collect();
 
-        providers.push(Provider { name, probes });
+        let attributes = ProviderAttributes {
+            provider: provider.dofpv_provattr,
+            module: provider.dofpv_modattr,
+            function: provider.dofpv_funcattr,
+            name: provider.dofpv_nameattr,
+            args: provider.dofpv_argsattr,
+        };
+
+        providers.push(Provider {
+            name,
+            probes,
+            attributes,
+        });
     }
     providers
 }
@@ -233,6 +233,27 @@ pub struct Probe {
     pub enabled_offsets: Vec<u32>,
     /// Type information for each argument
     pub arguments: Vec<String>,
+    /// The type each argument is presented to D scripts as, which differs from its type in
+    /// `arguments` where a translator converts between the two
+    pub translated_arguments: Vec<String>,
+}
+
+/// The stability attributes of a provider, each encoded as a `dof_attr_t`
+///
+/// An attribute packs the name stability, data stability and dependency class into the top three
+/// bytes, as with the `DOF_ATTR` macro. All-zero attributes are `Internal/Internal/Unknown`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize)]
+pub struct ProviderAttributes {
+    /// Attributes of the provider itself
+    pub provider: u32,
+    /// Attributes of the module names of the provider's probes
+    pub module: u32,
+    /// Attributes of the function names of the provider's probes
+    pub function: u32,
+    /// Attributes of the names of the provider's probes
+    pub name: u32,
+    /// Attributes of the arguments of the provider's probes
+    pub args: u32,
 }
 
 /// Information about a single provider
@@ -242,4 +263,6 @@ pub struct Provider {
     pub name: String,
     /// List of probes this provider exports
     pub probes: BTreeMap<String, Probe>,
+    /// Stability attributes of the provider
+    pub attributes: ProviderAttributes,
 }
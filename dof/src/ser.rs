@@ -35,6 +35,11 @@ fn build_section_data(section: &Section) -> Vec<(u32, Vec<u8>)> {
     for (i, provider) in section.providers.values().enumerate() {
         let mut provider_section = dof_provider {
             dofpv_name: strings.len() as _,
+            dofpv_provattr: provider.attributes.provider,
+            dofpv_modattr: provider.attributes.module,
+            dofpv_funcattr: provider.attributes.function,
+            dofpv_nameattr: provider.attributes.name,
+            dofpv_argsattr: provider.attributes.args,
             ..Default::default()
         };
         strings.extend_from_slice(provider.name.as_bytes());
@@ -75,8 +80,20 @@ fn build_section_data(section: &Section) -> Vec<(u32, Vec<u8>)> {
             }
             probe_t.dofpr_nargv = argv;
             probe_t.dofpr_nargc = probe.arguments.len() as _;
+            // The translated types are only written when they differ, each translated argument
+            // mapping to the native argument in the same position.
             probe_t.dofpr_xargv = argv;
             probe_t.dofpr_xargc = probe.arguments.len() as _;
+            if !probe.translated_arguments.is_empty()
+                && probe.translated_arguments != probe.arguments
+            {
+                probe_t.dofpr_xargv = strings.len() as _;
+                for arg in probe.translated_arguments.iter() {
+                    strings.extend_from_slice(arg.as_bytes());
+                    strings.push(0);
+                }
+                probe_t.dofpr_xargc = probe.translated_arguments.len() as _;
+            }
 
             // Insert probe offsets and store indices
             probe_t.dofpr_offidx = offsets.len() as _;
@@ -220,4 +237,44 @@ mod test {
         assert_eq!(section_data[0].len(), 4);
         assert_eq!(size, 8);
     }
+
+    #[cfg(feature = "des")]
+    #[test]
+    fn test_translated_arguments() {
+        use crate::{Probe, Provider, Section};
+
+        let probe = |name: &str, translated: &[&str]| Probe {
+            name: name.to_string(),
+            function: String::from("func"),
+            address: 0x1000,
+            offsets: vec![0],
+            enabled_offsets: vec![],
+            arguments: vec![String::from("uint64_t"), String::from("char*")],
+            translated_arguments: translated.iter().map(|arg| arg.to_string()).collect(),
+        };
+        let provider = Provider {
+            name: String::from("provider"),
+            probes: [
+                ("plain", probe("plain", &[])),
+                ("typed", probe("typed", &["my_event_t *", "char*"])),
+            ]
+            .into_iter()
+            .map(|(name, probe)| (name.to_string(), probe))
+            .collect(),
+            attributes: Default::default(),
+        };
+        let section = Section {
+            providers: [(provider.name.clone(), provider)].into_iter().collect(),
+            ..Default::default()
+        };
+        let section = Section::from_bytes(&section.as_bytes()).unwrap();
+        let probes = &section.providers["provider"].probes;
+        assert_eq!(probes["plain"].arguments, ["uint64_t", "char*"]);
+        assert_eq!(probes["plain"].translated_arguments, ["uint64_t", "char*"]);
+        assert_eq!(probes["typed"].arguments, ["uint64_t", "char*"]);
+        assert_eq!(
+            probes["typed"].translated_arguments,
+            ["my_event_t *", "char*"]
+        );
+    }
 }
@@ -14,12 +14,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use pest::error::{ErrorVariant, LineColLocation};
 use pest::iterators::{Pair, Pairs};
 use pest_derive::Parser;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryFrom;
+use std::fmt;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 use thiserror::Error;
 
 type PestError = pest::error::Error<Rule>;
@@ -33,20 +36,93 @@ pub enum DTraceError {
     EmptyPairsIterator,
     #[error("Provider and probe name pairs must be unique: duplicated \"{0:?}\"")]
     DuplicateProbeName((String, String)),
-    #[error("The provider name \"{0}\" is invalid")]
-    InvalidProviderName(String),
-    #[error("The probe name \"{0}\" is invalid")]
-    InvalidProbeName(String),
+    #[error("The provider name \"{0}\" is invalid: {1}")]
+    InvalidProviderName(String, String),
+    #[error("The probe name \"{0}\" is invalid: {1}")]
+    InvalidProbeName(String, String),
+    #[error("The stability attributes \"{0}\" are invalid, expected name/data/class")]
+    InvalidAttributes(String),
+    #[error("Invalid attributes pragma \"{0}\"")]
+    InvalidPragma(String),
+    #[error("The type \"{0}\" is neither a supported type nor declared with a typedef")]
+    UnknownType(String),
+    #[error("The typedef \"{0}\" is cyclic, it refers back to itself")]
+    CyclicTypedef(String),
+    #[error("The type \"{0}\" is invalid: {1}")]
+    InvalidType(String, String),
+    #[error("The translator to \"{0}\" is invalid: {1}")]
+    InvalidTranslator(String, String),
     #[error(transparent)]
     IO(#[from] std::io::Error),
     #[error("Input is not a valid DTrace provider definition:\n{0}")]
     ParseError(#[from] Box<PestError>),
+    /// An error found at a particular place in a D file, shown with the offending line.
+    #[error("{context}")]
+    Located {
+        error: Box<DTraceError>,
+        context: Box<PestError>,
+    },
+}
+
+impl DTraceError {
+    /// Return the line and column, starting from 1, of the D source at which the error was found,
+    /// if known.
+    pub fn line_col(&self) -> Option<(usize, usize)> {
+        match self {
+            DTraceError::ParseError(e) | DTraceError::Located { context: e, .. } => {
+                match e.line_col {
+                    LineColLocation::Pos(pos) | LineColLocation::Span(pos, _) => Some(pos),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the error itself, without its location in the D source.
+    pub fn into_kind(self) -> DTraceError {
+        match self {
+            DTraceError::Located { error, .. } => *error,
+            error => error,
+        }
+    }
+
+    /// Name the D file in which the error was found, so that it's shown along with its location.
+    pub fn with_path(self, path: &str) -> DTraceError {
+        match self {
+            DTraceError::ParseError(e) => DTraceError::ParseError(Box::new(e.with_path(path))),
+            DTraceError::Located { error, context } => DTraceError::Located {
+                error,
+                context: Box::new(context.with_path(path)),
+            },
+            error => error,
+        }
+    }
 }
 
 #[derive(Parser, Debug)]
 #[grammar = "dtrace.pest"]
 struct DTraceParser;
 
+// Helper which attaches the location of the given `pest::Pair` to an error, unless the error was
+// already located within it.
+fn locate<T>(pair: &Pair<'_, Rule>, result: Result<T, DTraceError>) -> Result<T, DTraceError> {
+    result.map_err(|error| match error {
+        DTraceError::Located { .. } | DTraceError::ParseError(_) => error,
+        error => {
+            let context = PestError::new_from_span(
+                ErrorVariant::CustomError {
+                    message: error.to_string(),
+                },
+                pair.as_span(),
+            );
+            DTraceError::Located {
+                error: Box::new(error),
+                context: Box::new(context),
+            }
+        }
+    })
+}
+
 // Helper which verifies that the given `pest::Pair` conforms to the expected grammar rule.
 fn expect_token(pair: &Pair<'_, Rule>, rule: Rule) -> Result<(), DTraceError> {
     if pair.as_rule() == rule {
@@ -59,6 +135,77 @@ fn expect_token(pair: &Pair<'_, Rule>, rule: Rule) -> Result<(), DTraceError> {
     }
 }
 
+/// The maximum length of a provider name.
+///
+/// DTrace limits provider names to 63 characters, but appends the ID of the process to them, which
+/// may be up to 6 digits long.
+pub const MAX_PROVIDER_NAME_LEN: usize = 57;
+
+/// The maximum length of a probe name, as it appears to DTrace.
+pub const MAX_PROBE_NAME_LEN: usize = 63;
+
+// Check a name against the rules for a D identifier, returning the rule it violates, if any.
+fn check_identifier(name: &str, max_len: usize) -> Result<(), String> {
+    let Some(first) = name.chars().next() else {
+        return Err(String::from("names may not be empty"));
+    };
+    if !first.is_ascii_alphabetic() {
+        return Err(String::from("names must start with an ASCII letter"));
+    }
+    if !name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+    {
+        return Err(String::from(
+            "names may only contain ASCII letters, digits and underscores",
+        ));
+    }
+    if name.len() > max_len {
+        return Err(format!("names may be at most {} characters long", max_len));
+    }
+    Ok(())
+}
+
+/// Check that a name is valid for a provider.
+///
+/// Provider names must be identifiers starting with an ASCII letter, and containing only ASCII
+/// letters, digits and underscores, of up to [`MAX_PROVIDER_NAME_LEN`] characters.
+pub fn validate_provider_name(name: &str) -> Result<(), DTraceError> {
+    let invalid = |reason| DTraceError::InvalidProviderName(name.to_string(), reason);
+    if name == "provider" {
+        return Err(invalid(String::from("\"provider\" is a reserved word")));
+    }
+    check_identifier(name, MAX_PROVIDER_NAME_LEN).map_err(invalid)
+}
+
+/// Check that a name is valid for a probe.
+///
+/// Probe names follow the same rules as provider names, except that double-underscores are
+/// translated into dashes, and that the resulting name may be up to [`MAX_PROBE_NAME_LEN`]
+/// characters long. A name may not end with a double-underscore, which would translate into a
+/// trailing dash.
+pub fn validate_probe_name(name: &str) -> Result<(), DTraceError> {
+    let invalid = |reason| DTraceError::InvalidProbeName(name.to_string(), reason);
+    if name == "probe" || name == "start" {
+        return Err(invalid(format!("\"{}\" is a reserved word", name)));
+    }
+    if name.ends_with("__") {
+        return Err(invalid(String::from(
+            "names may not end with a double-underscore, which is translated into a dash",
+        )));
+    }
+    check_identifier(name, usize::MAX).map_err(invalid)?;
+    let translated = name.replace("__", "-");
+    if translated.len() > MAX_PROBE_NAME_LEN {
+        return Err(invalid(format!(
+            "names may be at most {} characters long, after translating double-underscores \
+             into dashes",
+            MAX_PROBE_NAME_LEN
+        )));
+    }
+    Ok(())
+}
+
 /// The bit-width of an integer data type
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum BitWidth {
@@ -182,54 +329,145 @@ impl TryFrom<&Pair<'_, Rule>> for DataType {
     type Error = DTraceError;
 
     fn try_from(pair: &Pair<'_, Rule>) -> Result<DataType, Self::Error> {
-        expect_token(pair, Rule::DATA_TYPE)?;
-        let inner = pair
-            .clone()
-            .into_inner()
-            .next()
-            .expect("Data type token is expected to contain a concrete type");
-        let typ = match inner.as_rule() {
-            Rule::INTEGER => {
-                let integer = pair
+        parse_data_type(pair, &Typedefs::new())
+    }
+}
+
+// The data types of the aliases declared with typedefs, by their names.
+type Typedefs = BTreeMap<String, DataType>;
+
+// Parse a data type, resolving the names of any typedefs.
+fn parse_data_type(pair: &Pair<'_, Rule>, typedefs: &Typedefs) -> Result<DataType, DTraceError> {
+    expect_token(pair, Rule::DATA_TYPE)?;
+    let inner = pair
+        .clone()
+        .into_inner()
+        .next()
+        .expect("Data type token is expected to contain a concrete type");
+    let typ = match inner.as_rule() {
+        Rule::INTEGER => {
+            let integer = pair
+                .clone()
+                .into_inner()
+                .next()
+                .expect("Expected a signed or unsigned integral type");
+            assert!(matches!(integer.as_rule(), Rule::INTEGER));
+            DataType::Integer(Integer::from(
+                integer
                     .clone()
                     .into_inner()
                     .next()
-                    .expect("Expected a signed or unsigned integral type");
-                assert!(matches!(integer.as_rule(), Rule::INTEGER));
-                DataType::Integer(Integer::from(
-                    integer
-                        .clone()
-                        .into_inner()
-                        .next()
-                        .expect("Expected an integral type"),
-                ))
-            }
-            Rule::INTEGER_POINTER => {
-                let pointer = pair
+                    .expect("Expected an integral type"),
+            ))
+        }
+        Rule::INTEGER_POINTER => {
+            let pointer = pair
+                .clone()
+                .into_inner()
+                .next()
+                .expect("Expected a pointer to a signed or unsigned integral type");
+            assert!(matches!(pointer.as_rule(), Rule::INTEGER_POINTER));
+            let mut parts = pointer.clone().into_inner();
+            let integer = parts
+                .next()
+                .expect("Expected a signed or unsigned integral type");
+            let star = parts.next().expect("Expected a literal `*`");
+            assert_eq!(star.as_rule(), Rule::STAR);
+            DataType::Pointer(Integer::from(
+                integer
                     .clone()
                     .into_inner()
                     .next()
-                    .expect("Expected a pointer to a signed or unsigned integral type");
-                assert!(matches!(pointer.as_rule(), Rule::INTEGER_POINTER));
-                let mut parts = pointer.clone().into_inner();
-                let integer = parts
-                    .next()
-                    .expect("Expected a signed or unsigned integral type");
-                let star = parts.next().expect("Expected a literal `*`");
-                assert_eq!(star.as_rule(), Rule::STAR);
-                DataType::Pointer(Integer::from(
-                    integer
-                        .clone()
-                        .into_inner()
-                        .next()
-                        .expect("Expected an integral type"),
-                ))
+                    .expect("Expected an integral type"),
+            ))
+        }
+        Rule::STRING => DataType::String,
+        Rule::TYPE_NAME => {
+            let mut parts = inner.clone().into_inner();
+            let name = parts.next().expect("Expected a type name").as_str();
+            let typ = locate(
+                &inner,
+                typedefs
+                    .get(name)
+                    .ok_or_else(|| DTraceError::UnknownType(name.to_string())),
+            )?;
+            if parts.next().is_some() {
+                locate(&inner, pointer_to(*typ, inner.as_str()))?
+            } else {
+                *typ
+            }
+        }
+        _ => unreachable!("Parsed an unexpected DATA_TYPE token"),
+    };
+    Ok(typ)
+}
+
+// Return the type of a pointer to the given type, named `name`. Only integers may be pointed to.
+fn pointer_to(typ: DataType, name: &str) -> Result<DataType, DTraceError> {
+    match typ {
+        DataType::Integer(int) => Ok(DataType::Pointer(int)),
+        _ => Err(DTraceError::InvalidType(
+            name.to_string(),
+            String::from("only pointers to integers are supported"),
+        )),
+    }
+}
+
+// Resolve the typedefs of a file into the data types they alias.
+//
+// A typedef may refer to any other, regardless of the order in which they're declared, so these
+// are resolved recursively, tracking the chain of typedefs being resolved to detect cycles.
+fn resolve_typedefs(declared: &BTreeMap<String, Pair<'_, Rule>>) -> Result<Typedefs, DTraceError> {
+    fn resolve(
+        name: &str,
+        declared: &BTreeMap<String, Pair<'_, Rule>>,
+        resolved: &mut Typedefs,
+        chain: &mut Vec<String>,
+    ) -> Result<DataType, DTraceError> {
+        if let Some(typ) = resolved.get(name) {
+            return Ok(*typ);
+        }
+        if chain.iter().any(|other| other == name) {
+            return Err(DTraceError::CyclicTypedef(name.to_string()));
+        }
+        let pair = declared
+            .get(name)
+            .ok_or_else(|| DTraceError::UnknownType(name.to_string()))?;
+        let inner = pair
+            .clone()
+            .into_inner()
+            .next()
+            .expect("Data type token is expected to contain a concrete type");
+        let typ = if inner.as_rule() == Rule::TYPE_NAME {
+            let mut parts = inner.clone().into_inner();
+            let alias = parts.next().expect("Expected a type name").as_str();
+            chain.push(name.to_string());
+            let typ = locate(&inner, resolve(alias, declared, resolved, chain))?;
+            chain.pop();
+            if parts.next().is_some() {
+                locate(&inner, pointer_to(typ, inner.as_str()))?
+            } else {
+                typ
             }
-            Rule::STRING => DataType::String,
-            _ => unreachable!("Parsed an unexpected DATA_TYPE token"),
+        } else {
+            parse_data_type(pair, resolved)?
         };
+        resolved.insert(name.to_string(), typ);
         Ok(typ)
     }
+
+    let mut resolved = Typedefs::new();
+    for name in declared.keys() {
+        resolve(name, declared, &mut resolved, &mut Vec::new())?;
+    }
+    Ok(resolved)
+}
+
+// Return whether a name is that of a supported type, which can't be redeclared with a typedef.
+fn is_builtin_type(name: &str) -> bool {
+    use pest::Parser;
+    name == "char"
+        || DTraceParser::parse(Rule::INTEGER, name).is_ok_and(|pairs| pairs.as_str() == name)
 }
 
 impl TryFrom<&Pairs<'_, Rule>> for DataType {
@@ -280,6 +518,13 @@ impl TryFrom<&Pair<'_, Rule>> for Probe {
     type Error = DTraceError;
 
     fn try_from(pair: &Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        Probe::parse(pair, &Typedefs::new())
+    }
+}
+
+impl Probe {
+    // Parse a probe, resolving the names of any typedefs among its argument types.
+    fn parse(pair: &Pair<'_, Rule>, typedefs: &Typedefs) -> Result<Self, DTraceError> {
         expect_token(pair, Rule::PROBE)?;
         let mut inner = pair.clone().into_inner();
         expect_token(
@@ -288,9 +533,7 @@ impl TryFrom<&Pair<'_, Rule>> for Probe {
         )?;
         let token = inner.next().expect("Expected a probe name");
         let name = token.as_str().to_string();
-        if name == "probe" || name == "start" {
-            return Err(DTraceError::InvalidProbeName(name));
-        }
+        locate(&token, validate_probe_name(&name))?;
         expect_token(
             &inner.next().expect("Expected the literal '('"),
             Rule::LEFT_PAREN,
@@ -303,7 +546,7 @@ impl TryFrom<&Pair<'_, Rule>> for Probe {
             let arguments = possibly_argument_list.clone().into_inner();
             for data_type in arguments {
                 expect_token(&data_type, Rule::DATA_TYPE)?;
-                types.push(DataType::try_from(&data_type)?);
+                types.push(parse_data_type(&data_type, typedefs)?);
             }
         }
         expect_token(
@@ -326,31 +569,257 @@ impl TryFrom<&Pairs<'_, Rule>> for Probe {
     }
 }
 
+/// The stability level of an interface, from least to most stable.
+///
+/// See the [stability chapter][1] of the DTrace guide for the meaning of each level.
+///
+/// [1]: https://illumos.org/books/dtrace/chp-stab.html
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Stability {
+    #[default]
+    Internal,
+    Private,
+    Obsolete,
+    External,
+    Unstable,
+    Evolving,
+    Stable,
+    Standard,
+}
+
+impl Stability {
+    const ALL: [Stability; 8] = [
+        Stability::Internal,
+        Stability::Private,
+        Stability::Obsolete,
+        Stability::External,
+        Stability::Unstable,
+        Stability::Evolving,
+        Stability::Stable,
+        Stability::Standard,
+    ];
+}
+
+impl fmt::Display for Stability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for Stability {
+    type Err = DTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Stability::ALL
+            .into_iter()
+            .find(|level| level.to_string() == s)
+            .ok_or_else(|| DTraceError::InvalidAttributes(s.to_string()))
+    }
+}
+
+/// The class of the dependencies of an interface, i.e., how widely its stability is guaranteed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DependencyClass {
+    #[default]
+    Unknown,
+    Cpu,
+    Platform,
+    Group,
+    Isa,
+    Common,
+}
+
+impl DependencyClass {
+    const ALL: [DependencyClass; 6] = [
+        DependencyClass::Unknown,
+        DependencyClass::Cpu,
+        DependencyClass::Platform,
+        DependencyClass::Group,
+        DependencyClass::Isa,
+        DependencyClass::Common,
+    ];
+}
+
+impl fmt::Display for DependencyClass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DependencyClass::Unknown => "Unknown",
+            DependencyClass::Cpu => "CPU",
+            DependencyClass::Platform => "Platform",
+            DependencyClass::Group => "Group",
+            DependencyClass::Isa => "ISA",
+            DependencyClass::Common => "Common",
+        })
+    }
+}
+
+impl FromStr for DependencyClass {
+    type Err = DTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        DependencyClass::ALL
+            .into_iter()
+            .find(|class| class.to_string() == s)
+            .ok_or_else(|| DTraceError::InvalidAttributes(s.to_string()))
+    }
+}
+
+/// The stability attributes of one part of a provider's interface.
+///
+/// These are written as `name/data/class`, e.g., `Evolving/Evolving/Common`. The default,
+/// `Internal/Internal/Unknown`, makes no promises about the interface.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Attributes {
+    /// The stability of the names of the interface.
+    pub name: Stability,
+    /// The stability of the data the interface provides.
+    pub data: Stability,
+    /// The class of the dependencies of the interface.
+    pub class: DependencyClass,
+}
+
+impl fmt::Display for Attributes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}/{}", self.name, self.data, self.class)
+    }
+}
+
+impl FromStr for Attributes {
+    type Err = DTraceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DTraceError::InvalidAttributes(s.to_string());
+        let mut parts = s.split('/');
+        let (Some(name), Some(data), Some(class), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid());
+        };
+        Ok(Attributes {
+            name: name.parse().map_err(|_| invalid())?,
+            data: data.parse().map_err(|_| invalid())?,
+            class: class.parse().map_err(|_| invalid())?,
+        })
+    }
+}
+
+/// The stability attributes of each part of a provider's interface.
+///
+/// In D, these are declared with pragmas naming the provider and the part of its interface, such
+/// as `#pragma D attributes Evolving/Evolving/Common provider foo args`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ProviderAttributes {
+    /// The attributes of the provider itself.
+    pub provider: Attributes,
+    /// The attributes of the module name of its probes.
+    pub module: Attributes,
+    /// The attributes of the function name of its probes.
+    pub function: Attributes,
+    /// The attributes of the names of its probes.
+    pub name: Attributes,
+    /// The attributes of the arguments of its probes.
+    pub args: Attributes,
+}
+
+impl ProviderAttributes {
+    /// The names of the parts of a provider's interface, as they appear in D.
+    pub const ENTITIES: [&'static str; 5] = ["provider", "module", "function", "name", "args"];
+
+    /// Set the attributes of all parts of the interface.
+    pub fn all(attributes: Attributes) -> Self {
+        Self {
+            provider: attributes,
+            module: attributes,
+            function: attributes,
+            name: attributes,
+            args: attributes,
+        }
+    }
+
+    /// Return a mutable reference to the attributes of the named part of the interface.
+    pub fn entity_mut(&mut self, entity: &str) -> Option<&mut Attributes> {
+        match entity {
+            "provider" => Some(&mut self.provider),
+            "module" => Some(&mut self.module),
+            "function" => Some(&mut self.function),
+            "name" => Some(&mut self.name),
+            "args" => Some(&mut self.args),
+            _ => None,
+        }
+    }
+
+    /// Return the attributes of each part of the interface, along with its name.
+    pub fn entities(&self) -> [(&'static str, Attributes); 5] {
+        [
+            ("provider", self.provider),
+            ("module", self.module),
+            ("function", self.function),
+            ("name", self.name),
+            ("args", self.args),
+        ]
+    }
+
+    /// Return the `#pragma D attributes` lines declaring the attributes of the named provider.
+    ///
+    /// Only attributes other than the default are declared.
+    pub fn to_pragmas(&self, provider: &str) -> Vec<String> {
+        self.entities()
+            .into_iter()
+            .filter(|(_, attributes)| *attributes != Attributes::default())
+            .map(|(entity, attributes)| {
+                format!("#pragma D attributes {attributes} provider {provider} {entity}")
+            })
+            .collect()
+    }
+}
+
+// Parse a pragma declaring the attributes of part of a provider, returning the provider, the part
+// and its attributes. Other pragmas are ignored.
+fn parse_attributes_pragma(pragma: &str) -> Result<Option<(&str, &str, Attributes)>, DTraceError> {
+    let mut words = pragma.split_whitespace().skip(1);
+    if (words.next(), words.next()) != (Some("D"), Some("attributes")) {
+        return Ok(None);
+    }
+    let words = words.collect::<Vec<_>>();
+    match words[..] {
+        [attributes, "provider", provider, entity]
+            if ProviderAttributes::ENTITIES.contains(&entity) =>
+        {
+            Ok(Some((provider, entity, attributes.parse()?)))
+        }
+        _ => Err(DTraceError::InvalidPragma(pragma.trim().to_string())),
+    }
+}
+
 /// Type representing a single DTrace provider and all of its probes.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Provider {
     pub name: String,
     pub probes: Vec<Probe>,
+    /// The stability attributes of the provider, as declared by `#pragma D attributes`.
+    pub attributes: ProviderAttributes,
 }
 
 impl TryFrom<&Pair<'_, Rule>> for Provider {
     type Error = DTraceError;
 
     fn try_from(pair: &Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        Provider::parse(pair, &Typedefs::new())
+    }
+}
+
+impl Provider {
+    // Parse a provider, resolving the names of any typedefs among its probes' argument types.
+    fn parse(pair: &Pair<'_, Rule>, typedefs: &Typedefs) -> Result<Self, DTraceError> {
         expect_token(pair, Rule::PROVIDER)?;
         let mut inner = pair.clone().into_inner();
         expect_token(
             &inner.next().expect("Expected the literal 'provider'"),
             Rule::PROVIDER_KEY,
         )?;
-        let name = inner
-            .next()
-            .expect("Expected a provider name")
-            .as_str()
-            .to_string();
-        if name == "provider" {
-            return Err(DTraceError::InvalidProviderName(name));
-        }
+        let token = inner.next().expect("Expected a provider name");
+        let name = token.as_str().to_string();
+        locate(&token, validate_provider_name(&name))?;
         expect_token(
             &inner.next().expect("Expected the literal '{'"),
             Rule::LEFT_BRACE,
@@ -360,7 +829,7 @@ impl TryFrom<&Pair<'_, Rule>> for Provider {
             .next()
             .expect("Expected at least one probe in the provider");
         while expect_token(&possibly_probe, Rule::PROBE).is_ok() {
-            probes.push(Probe::try_from(&possibly_probe)?);
+            probes.push(Probe::parse(&possibly_probe, typedefs)?);
             possibly_probe = inner.next().expect("Expected a token");
         }
         expect_token(&possibly_probe, Rule::RIGHT_BRACE)?;
@@ -368,7 +837,11 @@ impl TryFrom<&Pair<'_, Rule>> for Provider {
             &inner.next().expect("Expected a literal ';'"),
             Rule::SEMICOLON,
         )?;
-        Ok(Provider { name, probes })
+        Ok(Provider {
+            name,
+            probes,
+            attributes: ProviderAttributes::default(),
+        })
     }
 }
 
@@ -380,11 +853,89 @@ impl TryFrom<&Pairs<'_, Rule>> for Provider {
     }
 }
 
+/// Type representing a D translator, from an input type to an output type.
+///
+/// Translators aren't interpreted, only preserved: the types are kept as written, and the body
+/// verbatim, so that the translator can be reproduced with [`Translator::to_d_source`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct Translator {
+    /// The type the translator produces, e.g. `psinfo_t`.
+    pub output: String,
+    /// The type the translator consumes, e.g. `struct proc *`.
+    pub input: String,
+    /// The name of the translator's input parameter, e.g. `P`.
+    pub parameter: String,
+    /// The body of the translator, including its braces.
+    pub body: String,
+}
+
+impl TryFrom<&Pair<'_, Rule>> for Translator {
+    type Error = DTraceError;
+
+    fn try_from(pair: &Pair<'_, Rule>) -> Result<Self, Self::Error> {
+        expect_token(pair, Rule::TRANSLATOR)?;
+        let mut inner = pair.clone().into_inner();
+        expect_token(
+            &inner.next().expect("Expected the literal 'translator'"),
+            Rule::TRANSLATOR_KEY,
+        )?;
+        let output = inner
+            .next()
+            .expect("Expected an output type")
+            .as_str()
+            .trim()
+            .to_string();
+        let token = inner.next().expect("Expected an input type");
+        let input = token.as_str().trim();
+        let body = inner
+            .next()
+            .expect("Expected a translator body")
+            .as_str()
+            .to_string();
+
+        // The input is a declaration like `struct proc *P`, whose trailing identifier names the
+        // parameter.
+        let split = input
+            .rfind(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+            .map(|index| index + 1)
+            .unwrap_or(0);
+        let (input, parameter) = input.split_at(split);
+        let input = input.trim_end();
+        if input.is_empty() || check_identifier(parameter, usize::MAX).is_err() {
+            return locate(
+                &token,
+                Err(DTraceError::InvalidTranslator(
+                    output,
+                    String::from("the input must be a type followed by a parameter name"),
+                )),
+            );
+        }
+        Ok(Translator {
+            output,
+            input: input.to_string(),
+            parameter: parameter.to_string(),
+            body,
+        })
+    }
+}
+
+impl Translator {
+    /// Return the D source declaring this translator.
+    pub fn to_d_source(&self) -> String {
+        let separator = if self.input.ends_with('*') { "" } else { " " };
+        format!(
+            "translator {} < {}{}{} > {};",
+            self.output, self.input, separator, self.parameter, self.body
+        )
+    }
+}
+
 /// Type representing a single D file and all the providers it defines.
 #[derive(Debug, Clone, PartialEq)]
 pub struct File {
     name: String,
     providers: Vec<Provider>,
+    translators: Vec<Translator>,
 }
 
 impl TryFrom<&Pair<'_, Rule>> for File {
@@ -393,24 +944,94 @@ impl TryFrom<&Pair<'_, Rule>> for File {
     fn try_from(pair: &Pair<'_, Rule>) -> Result<Self, Self::Error> {
         expect_token(pair, Rule::FILE)?;
         let mut providers = Vec::new();
+        let mut translators = Vec::new();
         let mut names = HashSet::new();
+        let mut pragmas = Vec::new();
+
+        // Typedefs may be declared anywhere in the file, so they're resolved before any provider
+        // using them is parsed.
+        let mut declared = BTreeMap::new();
         for item in pair.clone().into_inner() {
-            if item.as_rule() == Rule::PROVIDER {
-                let provider = Provider::try_from(&item)?;
-                for probe in provider.probes.iter() {
+            if item.as_rule() == Rule::TYPEDEF {
+                let mut inner = item.into_inner();
+                expect_token(
+                    &inner.next().expect("Expected the literal 'typedef'"),
+                    Rule::TYPEDEF_KEY,
+                )?;
+                let data_type = inner.next().expect("Expected a data type");
+                let token = inner.next().expect("Expected a typedef name");
+                let name = token.as_str();
+                if is_builtin_type(name) {
+                    return locate(
+                        &token,
+                        Err(DTraceError::InvalidType(
+                            name.to_string(),
+                            String::from("a supported type can't be redeclared with a typedef"),
+                        )),
+                    );
+                }
+                if let Some(other) = declared.insert(name.to_string(), data_type.clone()) {
+                    if other.as_str() != data_type.as_str() {
+                        return locate(
+                            &data_type,
+                            Err(DTraceError::InvalidType(
+                                name.to_string(),
+                                String::from("the typedef is declared with conflicting types"),
+                            )),
+                        );
+                    }
+                }
+            }
+        }
+        let typedefs = resolve_typedefs(&declared)?;
+
+        for item in pair.clone().into_inner() {
+            if item.as_rule() == Rule::PRAGMA {
+                if let Some(pragma) = locate(&item, parse_attributes_pragma(item.as_str()))? {
+                    pragmas.push((item, pragma));
+                }
+            } else if item.as_rule() == Rule::PROVIDER {
+                let provider = Provider::parse(&item, &typedefs)?;
+                let tokens = item
+                    .clone()
+                    .into_inner()
+                    .filter(|token| token.as_rule() == Rule::PROBE);
+                for (probe, token) in provider.probes.iter().zip(tokens) {
                     let name = (provider.name.clone(), probe.name.clone());
                     if names.contains(&name) {
-                        return Err(DTraceError::DuplicateProbeName(name));
+                        return locate(&token, Err(DTraceError::DuplicateProbeName(name)));
                     }
                     names.insert(name.clone());
                 }
                 providers.push(provider);
+            } else if item.as_rule() == Rule::TRANSLATOR {
+                translators.push(Translator::try_from(&item)?);
             }
         }
 
+        // Attributes may be declared before or after the provider they refer to, so they're
+        // applied once all providers are known.
+        for (item, (name, entity, attributes)) in pragmas {
+            let provider = locate(
+                &item,
+                providers
+                    .iter_mut()
+                    .find(|provider| provider.name == name)
+                    .ok_or_else(|| {
+                        DTraceError::InvalidPragma(format!(
+                            "#pragma D attributes {} provider {} {}: no such provider",
+                            attributes, name, entity
+                        ))
+                    }),
+            )?;
+            // Unwrap safety: The entity is checked when parsing the pragma.
+            *provider.attributes.entity_mut(entity).unwrap() = attributes;
+        }
+
         Ok(File {
             name: "".to_string(),
             providers,
+            translators,
         })
     }
 }
@@ -425,8 +1046,11 @@ impl TryFrom<&Pairs<'_, Rule>> for File {
 
 impl File {
     /// Load and parse a provider from a D file at the given path.
+    ///
+    /// Errors in the file are reported along with its path.
     pub fn from_file(filename: &Path) -> Result<Self, DTraceError> {
-        let mut f = File::try_from(fs::read_to_string(filename)?.as_str())?;
+        let mut f = File::try_from(fs::read_to_string(filename)?.as_str())
+            .map_err(|e| e.with_path(&filename.display().to_string()))?;
         f.name = filename
             .file_stem()
             .unwrap()
@@ -445,6 +1069,11 @@ impl File {
     pub fn providers(&self) -> &Vec<Provider> {
         &self.providers
     }
+
+    /// Return the list of translators this file defines.
+    pub fn translators(&self) -> &Vec<Translator> {
+        &self.translators
+    }
 }
 
 impl TryFrom<&str> for File {
@@ -469,6 +1098,7 @@ impl TryFrom<&str> for File {
                             "  - int32_t\n",
                             "  - int64_t\n",
                             "  - &str\n",
+                            "  - types declared with a typedef of the above\n",
                         )
                     )
                 }
@@ -480,15 +1110,22 @@ impl TryFrom<&str> for File {
 
 #[cfg(test)]
 mod tests {
+    use super::validate_probe_name;
+    use super::validate_provider_name;
+    use super::Attributes;
     use super::BitWidth;
+    use super::DTraceError;
     use super::DTraceParser;
     use super::DataType;
+    use super::DependencyClass;
     use super::File;
     use super::Integer;
     use super::Probe;
     use super::Provider;
     use super::Rule;
     use super::Sign;
+    use super::Stability;
+    use super::Translator;
     use super::TryFrom;
     use ::pest::Parser;
     use rstest::{fixture, rstest};
@@ -528,8 +1165,16 @@ mod tests {
     #[test]
     fn test_data_types() {
         assert!(DTraceParser::parse(Rule::DATA_TYPE, "uint8_t").is_ok());
-        assert!(DTraceParser::parse(Rule::DATA_TYPE, "int").is_err());
-        assert!(DTraceParser::parse(Rule::DATA_TYPE, "flaot").is_err());
+        assert!(DTraceParser::parse(Rule::DATA_TYPE, "uint8_t *").is_ok());
+        assert!(DTraceParser::parse(Rule::DATA_TYPE, "*").is_err());
+        // Any other name is that of a typedef, which is unknown outside of a file declaring it.
+        for name in ["int", "flaot"] {
+            let pairs = DTraceParser::parse(Rule::DATA_TYPE, name).unwrap();
+            assert!(matches!(
+                DataType::try_from(&pairs).map_err(DTraceError::into_kind),
+                Err(DTraceError::UnknownType(typ)) if typ == name
+            ));
+        }
     }
 
     #[test]
@@ -715,4 +1360,281 @@ mod tests {
 
         assert!(File::try_from("this is not a D file").is_err());
     }
+
+    #[test]
+    fn test_attributes_from_str() {
+        let attributes: Attributes = "Evolving/Stable/Common".parse().unwrap();
+        assert_eq!(
+            attributes,
+            Attributes {
+                name: Stability::Evolving,
+                data: Stability::Stable,
+                class: DependencyClass::Common,
+            }
+        );
+        assert_eq!(attributes.to_string(), "Evolving/Stable/Common");
+        assert_eq!(
+            Attributes::default().to_string(),
+            "Internal/Internal/Unknown"
+        );
+        assert!("Evolving/Stable".parse::<Attributes>().is_err());
+        assert!("Evolving/Stable/Common/Common"
+            .parse::<Attributes>()
+            .is_err());
+        assert!("Evolving/Solid/Common".parse::<Attributes>().is_err());
+    }
+
+    #[test]
+    fn test_attributes_pragmas() {
+        let defn = r#"
+            provider foo {
+                probe bar();
+            };
+            provider baz {
+                probe bar();
+            };
+            #pragma D attributes Evolving/Evolving/Common provider foo provider
+            #pragma D attributes Stable/Evolving/ISA provider foo args
+            #pragma D option quiet
+            "#;
+        let file = File::try_from(defn).unwrap();
+        let attributes = &file.providers[0].attributes;
+        assert_eq!(attributes.provider.to_string(), "Evolving/Evolving/Common");
+        assert_eq!(attributes.args.class, DependencyClass::Isa);
+        assert_eq!(attributes.module, Attributes::default());
+        assert_eq!(file.providers[1].attributes, Default::default());
+        assert_eq!(
+            attributes.to_pragmas("foo"),
+            vec![
+                "#pragma D attributes Evolving/Evolving/Common provider foo provider",
+                "#pragma D attributes Stable/Evolving/ISA provider foo args",
+            ]
+        );
+    }
+
+    #[rstest(
+        pragma,
+        case("#pragma D attributes Evolving/Evolving provider foo name\n"),
+        case("#pragma D attributes Evolving/Evolving/Common provider foo probe\n"),
+        case("#pragma D attributes Evolving/Evolving/Common provider quux name\n"),
+        case("#pragma D attributes Evolving/Evolving/Common foo name\n")
+    )]
+    fn test_invalid_attributes_pragmas(pragma: &str) {
+        let defn = format!("provider foo {{ probe bar(); }};\n{}", pragma);
+        assert!(File::try_from(defn.as_str()).is_err());
+    }
+
+    #[test]
+    fn test_validate_provider_name() {
+        assert!(validate_provider_name("foo").is_ok());
+        assert!(validate_provider_name("foo_bar9").is_ok());
+        assert!(validate_provider_name("").is_err());
+        assert!(validate_provider_name("_foo").is_err());
+        assert!(validate_provider_name("9foo").is_err());
+        assert!(validate_provider_name("foo-bar").is_err());
+        assert!(validate_provider_name("foo bar").is_err());
+        assert!(validate_provider_name("provider").is_err());
+        assert!(validate_provider_name(&"p".repeat(57)).is_ok());
+        assert!(validate_provider_name(&"p".repeat(58)).is_err());
+    }
+
+    #[rstest(
+        name,
+        reason,
+        case("", "empty"),
+        case("_foo", "start with an ASCII letter"),
+        case("9foo", "start with an ASCII letter"),
+        case("foo-bar", "only contain"),
+        case("føø", "only contain"),
+        case("start", "reserved"),
+        case("foo__", "double-underscore")
+    )]
+    fn test_validate_probe_name_invalid(name: &str, reason: &str) {
+        let err = validate_probe_name(name).unwrap_err().to_string();
+        assert!(err.contains(reason), "{}", err);
+    }
+
+    #[test]
+    fn test_validate_probe_name_length() {
+        assert!(validate_probe_name("foo__bar_baz").is_ok());
+        assert!(validate_probe_name(&"p".repeat(63)).is_ok());
+        assert!(validate_probe_name(&"p".repeat(64)).is_err());
+        // Double-underscores count as a single character.
+        assert!(validate_probe_name(&format!("{}p", "p__".repeat(31))).is_ok());
+        assert!(validate_probe_name(&format!("{}pp", "p__".repeat(31))).is_err());
+    }
+
+    #[test]
+    fn test_typedefs() {
+        let defn = r#"
+            typedef request_id_t id_t;
+            typedef uint64_t request_id_t;
+            typedef int32_t *count_ptr_t;
+            typedef char* name_t;
+            typedef uint8_t uint8_t_alias;
+            provider foo {
+                probe bar(id_t, count_ptr_t, name_t, request_id_t*, uint8_t_alias);
+            };
+            typedef request_id_t id_t;
+            "#;
+        let file = File::try_from(defn).unwrap();
+        assert_eq!(
+            file.providers[0].probes[0].types,
+            [
+                DataType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64,
+                }),
+                DataType::Pointer(Integer {
+                    sign: Sign::Signed,
+                    width: BitWidth::Bit32,
+                }),
+                DataType::String,
+                DataType::Pointer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64,
+                }),
+                DataType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_invalid_typedefs() {
+        let parse = |typedefs: &str| {
+            File::try_from(format!("{}\nprovider foo {{ probe bar(a_t); }};", typedefs).as_str())
+                .map_err(DTraceError::into_kind)
+        };
+        assert!(matches!(
+            parse(""),
+            Err(DTraceError::UnknownType(name)) if name == "a_t"
+        ));
+        assert!(matches!(
+            parse("typedef b_t a_t;"),
+            Err(DTraceError::UnknownType(name)) if name == "b_t"
+        ));
+        assert!(matches!(
+            parse("typedef b_t a_t; typedef c_t b_t; typedef a_t c_t;"),
+            Err(DTraceError::CyclicTypedef(_))
+        ));
+        assert!(matches!(
+            parse("typedef a_t a_t;"),
+            Err(DTraceError::CyclicTypedef(name)) if name == "a_t"
+        ));
+        assert!(matches!(
+            parse("typedef char* s_t; typedef s_t* a_t;"),
+            Err(DTraceError::InvalidType(name, _)) if name == "s_t*"
+        ));
+        assert!(matches!(
+            parse("typedef uint8_t a_t; typedef uint16_t a_t;"),
+            Err(DTraceError::InvalidType(name, _)) if name == "a_t"
+        ));
+        assert!(matches!(
+            parse("typedef uint8_t uint16_t; typedef uint8_t a_t;"),
+            Err(DTraceError::InvalidType(name, _)) if name == "uint16_t"
+        ));
+    }
+
+    #[test]
+    fn test_translators() {
+        let file = File::from_file(std::path::Path::new(&data_file("server.d"))).unwrap();
+        assert_eq!(file.name(), "server");
+        let provider = &file.providers()[0];
+        assert_eq!(provider.name, "server");
+        assert_eq!(provider.probes.len(), 3);
+        assert_eq!(
+            provider.attributes.provider,
+            "Evolving/Evolving/ISA".parse().unwrap()
+        );
+        assert_eq!(
+            provider.attributes.module,
+            "Private/Private/Unknown".parse().unwrap()
+        );
+
+        let translators = file.translators();
+        assert_eq!(translators.len(), 2);
+        assert_eq!(translators[0].output, "conninfo_t");
+        assert_eq!(translators[0].input, "struct server_conn *");
+        assert_eq!(translators[0].parameter, "C");
+        assert!(translators[0].body.starts_with('{'));
+        assert!(translators[0].body.ends_with('}'));
+        assert!(translators[0]
+            .body
+            .contains("ci_port = (C->sc_flags & 0x1)"));
+        assert_eq!(translators[1].output, "reqinfo_t");
+        assert_eq!(translators[1].input, "uintptr_t");
+        assert_eq!(translators[1].parameter, "R");
+
+        // The preserved source parses back to the same translator.
+        for translator in translators {
+            let source = translator.to_d_source();
+            let pair = DTraceParser::parse(Rule::TRANSLATOR, &source)
+                .unwrap()
+                .next()
+                .unwrap();
+            assert_eq!(&Translator::try_from(&pair).unwrap(), translator);
+        }
+    }
+
+    #[test]
+    fn test_invalid_translators() {
+        let provider = "provider foo { probe bar(); };";
+        assert!(matches!(
+            File::try_from(format!("translator a_t < b_t > {{ a = 0; }};\n{}", provider).as_str())
+                .map_err(DTraceError::into_kind),
+            Err(DTraceError::InvalidTranslator(name, _)) if name == "a_t"
+        ));
+        assert!(matches!(
+            File::try_from(format!("translator a_t < b_t *B > {{ a = 0; \n{}", provider).as_str()),
+            Err(DTraceError::ParseError(_))
+        ));
+        assert!(
+            File::try_from(format!("translator a_t < b_t *B > {{ }};\n{}", provider).as_str())
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_error_locations() {
+        let located = |defn: &str| {
+            let error = File::try_from(defn).unwrap_err();
+            (error.line_col(), error.to_string(), error.into_kind())
+        };
+
+        let (line_col, message, error) =
+            located("provider foo {\n    probe bar(uint8_t);\n    probe baz(a_t);\n};");
+        assert_eq!(line_col, Some((3, 15)));
+        assert!(message.contains("3 |     probe baz(a_t);"));
+        assert!(message.contains(r#"The type "a_t" is neither"#));
+        assert!(matches!(error, DTraceError::UnknownType(name) if name == "a_t"));
+
+        let (line_col, _, error) = located("provider foo {\n    probe 9bar();\n};");
+        assert_eq!(line_col, Some((2, 11)));
+        assert!(matches!(error, DTraceError::ParseError(_)));
+
+        let (line_col, _, error) =
+            located("provider foo {\n    probe bar();\n    probe bar();\n};");
+        assert_eq!(line_col, Some((3, 5)));
+        assert!(matches!(error, DTraceError::DuplicateProbeName(_)));
+
+        let (line_col, _, error) = located(
+            "provider foo { probe bar(); };\n#pragma D attributes Evolving/Evolving/Common provider baz args\n",
+        );
+        assert_eq!(line_col, Some((2, 1)));
+        assert!(matches!(error, DTraceError::InvalidPragma(_)));
+
+        let (line_col, _, error) =
+            located("typedef uint8_t a_t;\ntypedef a_t *b_t;\ntypedef c_t d_t;\n");
+        assert_eq!(line_col, Some((3, 9)));
+        assert!(matches!(error, DTraceError::UnknownType(name) if name == "c_t"));
+
+        let error = File::try_from("provider foo { probe bar(a_t); };")
+            .unwrap_err()
+            .with_path("foo.d");
+        assert!(error.to_string().contains("--> foo.d:1:26"));
+        assert!(DTraceError::EmptyPairsIterator.line_col().is_none());
+    }
 }
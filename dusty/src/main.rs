@@ -15,14 +15,16 @@
 // limitations under the License.
 
 use clap::Parser;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use usdt::probe_records;
+use usdt::verify::{file_probes, verify_manifest};
 use usdt_impl::Error as UsdtError;
 
 /// Inspect data related to USDT probes in object files.
 #[derive(Debug, Parser)]
 struct Cmd {
-    /// The object file to inspect
+    /// The object file to inspect, or the D provider file to generate code from
     file: PathBuf,
 
     /// Operate more verbosely, printing all available information
@@ -36,10 +38,38 @@ struct Cmd {
     /// Format output as JSON
     #[arg(short, long)]
     json: bool,
+
+    /// List the provider and name of each probe, as they appear to DTrace
+    #[arg(short, long, conflicts_with_all = ["raw", "json"])]
+    list: bool,
+
+    /// Check the probes against a manifest written by the build script, exiting with an error if
+    /// any of its probes are missing, or the file contains others of its providers
+    #[arg(short, long, conflicts_with_all = ["raw", "json", "list"])]
+    manifest: Option<PathBuf>,
+
+    /// Print the Rust code generated for the providers of the file, a D provider file, for every
+    /// target, to be checked in rather than generated by a build script
+    #[arg(short, long, conflicts_with_all = ["raw", "json", "list", "manifest"])]
+    generate: bool,
+
+    /// The module to generate the providers in, with `--generate`, as a path from the root of the
+    /// crate
+    #[arg(long, requires = "generate")]
+    module: Option<String>,
 }
 
-fn main() {
+fn main() -> ExitCode {
     let cmd = Cmd::parse();
+    if cmd.generate {
+        return generate(&cmd.file, cmd.module.as_deref());
+    }
+    if let Some(manifest) = &cmd.manifest {
+        return verify(&cmd.file, manifest);
+    }
+    if cmd.list {
+        return list(&cmd.file);
+    }
     let format_mode = if cmd.raw {
         dof::fmt::FormatMode::Raw {
             include_sections: cmd.verbose,
@@ -63,4 +93,68 @@ fn main() {
             println!("Failed to parse probe information, {:?}", e);
         }
     }
+    ExitCode::SUCCESS
+}
+
+fn list(file: &Path) -> ExitCode {
+    match file_probes(file) {
+        Ok(probes) if probes.is_empty() => println!("No probe information found"),
+        Ok(probes) => {
+            for (provider, probe) in probes {
+                println!("{}:::{}", provider, probe);
+            }
+        }
+        Err(e) => {
+            println!("Failed to parse probe information, {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn generate(file: &Path, module: Option<&str>) -> ExitCode {
+    let mut builder = usdt::Builder::new(file);
+    if let Some(module) = module {
+        builder = builder.module(module);
+    }
+    match builder.generate() {
+        Ok(source) => {
+            print!("{}", source);
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Failed to generate code, {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn verify(file: &Path, manifest: &Path) -> ExitCode {
+    let manifest = match std::fs::read_to_string(manifest) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            println!("Failed to read manifest, {:?}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    match verify_manifest(file, &manifest) {
+        Ok(verification) => {
+            for (provider, probe) in &verification.missing {
+                println!("Missing probe {}:::{}", provider, probe);
+            }
+            for (provider, probe) in &verification.unexpected {
+                println!("Unexpected probe {}:::{}", provider, probe);
+            }
+            if verification.is_ok() {
+                println!("All probes of the manifest found");
+                ExitCode::SUCCESS
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+        Err(e) => {
+            println!("Failed to verify probes, {:?}", e);
+            ExitCode::FAILURE
+        }
+    }
 }
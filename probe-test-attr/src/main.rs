@@ -53,7 +53,13 @@ pub enum Whoops {
 /// difference is in the support of serializable types. This can't be conveniently expressed in D,
 /// as data there is simply a string. So if you want to provide a probe with a more complex Rust
 /// type as an argument, it must be defined using this macro.
-#[usdt::provider]
+///
+/// The provider's DTrace stability attributes may be declared too, here marking the probe names
+/// and arguments as an evolving interface. This is equivalent to `#pragma D attributes` in D.
+#[usdt::provider(
+    name_stability = "Evolving/Evolving/Common",
+    args_stability = "Evolving/Evolving/Common"
+)]
 mod test {
     /// The `Arg` type needs to be imported here, just like in any other module. Note that you
     /// _must_ use an absolute import, such as `crate::Arg` or `::std::net::IpAddr`. Relative
@@ -80,7 +86,7 @@ mod test {
     /// will be seen in DTrace.
     fn not_json_serializable(_: crate::Whoops) {}
 
-    /// Constant pointers to integer types are also supported
+    /// Raw pointers are also supported, and passed as their address
     fn work_with_pointer(_buffer: *const u8, _: u64) {}
 }
 
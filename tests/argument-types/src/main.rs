@@ -16,6 +16,8 @@
 // limitations under the License.
 
 use serde::Serialize;
+use std::borrow::Cow;
+use std::sync::Arc;
 
 /// Most struct or tuple types implementing serde::Serialize may be used in probes.
 #[derive(Default, Clone, Serialize)]
@@ -30,7 +32,17 @@ struct NotSupported<'a> {
     x: &'a [i32],
 }
 
-#[usdt::provider]
+/// Field-less enums with a primitive representation may be passed as their discriminant, and any
+/// field-less enum deriving `VariantName` as the name of its variant.
+#[derive(usdt::VariantName)]
+#[repr(u8)]
+enum State {
+    A,
+    B,
+}
+
+/// The JSON of serializable arguments may be bounded to a smaller length than the default.
+#[usdt::provider(max_serialized_len = 4096)]
 mod refs {
     /// Simple types such as integers may be taken by value ...
     fn u8_as_value(_: u8) {}
@@ -42,22 +54,119 @@ mod refs {
     fn string_as_value(_: String) {}
     fn string_as_reference(_: &String) {}
 
+    /// Borrowed-or-owned and boxed strings are passed as strings too, not serialized.
+    fn cow_str(_: std::borrow::Cow<str>) {}
+    fn box_str(_: Box<str>) {}
+    fn arc_str(_: std::sync::Arc<str>) {}
+
+    /// Each of several string arguments is passed as a `char *` of its own.
+    fn three(_: &str, _: &str, _: &str) {}
+
+    /// Paths are passed as strings as well, as their bytes on Unix.
+    fn opened(p: &std::path::Path) {}
+    fn opened_buf(_: std::path::PathBuf) {}
+
     /// Slices are supported
     fn slice(_: &[u8]) {}
 
-    /// As are arrays.
+    /// As are arrays. Small arrays of integers are passed as one argument per element ...
     fn array(_: [u8; 4]) {}
+    fn array_as_reference(_: &[u64; 6]) {}
+
+    /// ... while larger ones are serialized.
+    fn large_array(_: &[u64; 16]) {}
+    fn array_and_more(_: &[u8; 4], _: u8, _: u8, _: u8) {}
 
     /// And tuples.
     fn tuple(_: (u8, &[u8])) {}
 
-    /// Tuples cannot be passed by reference, so this won't work. This would require naming the
-    /// lifetime of the inner shared slice, which isn't currently supported.
+    // Tuples cannot be passed by reference, so this won't work. This would require naming the
+    // lifetime of the inner shared slice, which isn't currently supported.
     // fn tuple_by_reference(_: &(u8, &[u8])) {}
 
     /// Serializable types may also be taken by value or reference.
     fn serializable_as_value(_: crate::Arg) {}
     fn serializable_as_reference(_: &crate::Arg) {}
+
+    /// Native and serializable arguments may be mixed in any order.
+    fn mix(a: u64, b: &crate::Arg, c: u32) {}
+
+    /// Smart pointers are serialized as the value they point to, which needn't be cloned, and
+    /// without serde's `rc` feature.
+    fn wrapped(_: &std::sync::Arc<crate::Arg>) {}
+    fn wrapped_rc(_: std::rc::Rc<crate::Arg>) {}
+    fn boxed(_: Box<crate::Arg>) {}
+
+    /// Any serializable type may be passed to a `&dyn Serialize` argument.
+    fn serializable_trait_object(_: &dyn serde::Serialize) {}
+
+    /// JSON values are written out as they are.
+    fn json_value(_: &serde_json::Value) {}
+
+    /// Raw pointers are passed as their address, without reading the pointee.
+    fn addr(p: *const u8) {}
+    fn addr_mut(p: *mut crate::Arg) {}
+
+    /// Enums are passed as their discriminant when marked with the `#[repr(...)]` they're declared
+    /// with, by value or by reference.
+    fn state(#[repr(u8)] _: crate::State) {}
+    fn state_as_reference(#[repr(u8)] _: &crate::State) {}
+    fn state_name(#[variant_name] _: crate::State) {}
+    fn state_name_as_reference(#[variant_name] _: &crate::State) {}
+
+    /// Slices and vectors of integers marked `#[native]` are passed as the address of their
+    /// elements, their number, and the size of each, rather than serialized.
+    fn samples(#[native] _: &[u32]) {}
+    fn samples_vec(#[native] _: Vec<i16>) {}
+
+    /// Non-zero integers are passed as the integer they wrap.
+    fn id(_: std::num::NonZeroU64) {}
+    fn id_generic(_: &std::num::NonZero<i16>) {}
+
+    /// Durations, such as the time elapsed since an `Instant`, are passed as their nanoseconds.
+    fn span_ns(d: std::time::Duration) {}
+
+    /// Key/value pairs are serialized as the members of a JSON object.
+    fn fields(_: impl usdt::Fields) {}
+
+    /// Values implementing `Display` are formatted, and passed as a string.
+    fn msg(_: &dyn std::fmt::Display) {}
+    fn msg_impl(_: impl std::fmt::Display) {}
+
+    /// Mutable references are read like shared ones, without mutating the value.
+    fn touched_u8(_: &mut u8) {}
+    fn touched(_: &mut crate::Arg) {}
+
+    /// Arguments passed natively may be given the D type scripts see them as, which a translator
+    /// converts them to. This only affects DTrace's description of the probe.
+    fn event(#[d_type = "my_event_t *"] _: *const crate::Arg) {}
+
+    /// Type aliases defined in the provider module are resolved, so that aliased arguments are
+    /// passed exactly as the types they alias.
+    type Id = u64;
+    type Name = str;
+    type Octets = [u8; 4];
+    type Bytes = Vec<u8>;
+    fn aliased(_: Id, _: &Name, _: Octets, _: &Bytes) {}
+    fn unaliased(_: u64, _: &str, _: [u8; 4], _: &Vec<u8>) {}
+}
+
+/// Keys may be renamed in the JSON passed to probes, without changing how the types serialize.
+#[usdt::provider(rename_json_keys = "x=values")]
+mod renamed {
+    fn serializable(_: &crate::Arg) {}
+    fn wrapped(_: &std::sync::Arc<crate::Arg>) {}
+    fn serializable_trait_object(_: &dyn serde::Serialize) {}
+}
+
+// Fire the probes taking mutable references with the references a function holds.
+fn touch(count: &mut u8, arg: &mut Arg) {
+    refs::touched_u8!(|| &*count);
+    refs::touched!(|| &*arg);
+    *count += 1;
+    arg.x.push(i32::from(*count));
+    refs::touched_u8!(|| count);
+    refs::touched!(|| arg);
 }
 
 fn main() {
@@ -81,6 +190,20 @@ fn main() {
     refs::string_as_value!(|| String::from("owned"));
     refs::string_as_reference!(|| "&'static str");
     refs::string_as_reference!(|| String::from("owned"));
+    refs::cow_str!(|| Cow::Borrowed("borrowed"));
+    refs::cow_str!(|| Cow::<str>::Owned(String::from("owned")));
+    refs::box_str!(|| Box::<str>::from("boxed"));
+    refs::arc_str!(|| Arc::<str>::from("shared"));
+    refs::three!(|| ("one", String::from("two"), Cow::Borrowed("three")));
+
+    // Probes accepting a path may be called with anything that implements `AsRef<Path>`, which
+    // includes strings.
+    let path = std::path::PathBuf::from("/tmp/file");
+    refs::opened!(|| path.as_path());
+    refs::opened!(|| &path);
+    refs::opened!(|| "/tmp/file");
+    refs::opened_buf!(|| path.clone());
+    refs::opened_buf!(|| std::ffi::OsStr::new("relative"));
 
     // Vectors are supported as well. In this case, the probe argument behaves the way it might in
     // a "normal" function -- with a signature like `fn foo(_: Vec<T>)`, one can pass a `Vec<T>`.
@@ -100,6 +223,9 @@ fn main() {
     refs::slice!(|| &arr[..2]);
     refs::array!(|| arr);
     refs::array!(|| &arr);
+    refs::array_as_reference!(|| &[0, 1, 2, 3, 4, 5]);
+    refs::large_array!(|| [0; 16]);
+    refs::array_and_more!(|| (arr, 0, 1, 2));
 
     // Tuples may be passed in by value.
     refs::tuple!(|| (0, &x[..]));
@@ -125,4 +251,535 @@ fn main() {
 
     // This line will fail to compile, indicating that `arg` is borrowed after it's been moved.
     // println!("{:#?}", arg.x);
+
+    refs::mix!(|| (1, crate::Arg::default(), 2));
+    renamed::serializable!(crate::Arg::default);
+    renamed::wrapped!(|| Arc::new(crate::Arg::default()));
+    renamed::serializable_trait_object!(|| &[1, 2, 3]);
+
+    // An `Arc`, `Rc` or `Box` may be passed by value or by reference.
+    let wrapped = Arc::new(crate::Arg::default());
+    refs::wrapped!(|| &wrapped);
+    refs::wrapped!(|| Arc::clone(&wrapped));
+    let wrapped_rc = std::rc::Rc::new(crate::Arg::default());
+    refs::wrapped_rc!(|| &wrapped_rc);
+    refs::boxed!(|| Box::new(crate::Arg::default()));
+
+    // A probe accepting `&dyn Serialize` may be passed a reference to any serializable type. As
+    // `Serialize` can't be made into a trait object, values whose type is only known at runtime
+    // are passed as a `&dyn usdt::ErasedSerialize` instead.
+    let arg = crate::Arg::default();
+    refs::serializable_trait_object!(|| &arg);
+    refs::serializable_trait_object!(|| &x);
+    let payload: &dyn usdt::ErasedSerialize = if x.is_empty() { &arg } else { &x };
+    refs::serializable_trait_object!(|| payload);
+
+    // A `serde_json::Value` may be passed like any other serializable type, by value or reference.
+    let value = serde_json::json!({ "x": [0, 1, 2] });
+    refs::json_value!(|| &value);
+    refs::json_value!(|| serde_json::Value::Null);
+
+    // Raw pointers needn't point to anything valid, as only their address is passed.
+    refs::addr!(|| x.as_ptr().cast::<u8>());
+    refs::addr!(std::ptr::null);
+    let mut arg = crate::Arg::default();
+    refs::addr_mut!(|| &mut arg as *mut _);
+
+    // The enum needn't be `Copy` to be passed as its discriminant.
+    let state = State::B;
+    refs::state!(|| State::A);
+    refs::state!(|| &state);
+    refs::state_name!(|| &state);
+    refs::state_name_as_reference!(|| State::A);
+    refs::state_as_reference!(|| state);
+
+    // Native slices accept anything implementing `AsRef<[T]>`.
+    let samples = vec![1, 2, 3];
+    refs::samples!(|| &samples);
+    refs::samples!(|| &samples[1..]);
+    refs::samples!(|| [4, 5]);
+    refs::samples_vec!(|| vec![-1, 0, 1]);
+    refs::samples_vec!(|| &[0i16; 8][..]);
+
+    // Non-zero integers may be passed by value or by reference.
+    let id = std::num::NonZeroU64::new(42).unwrap();
+    refs::id!(|| id);
+    refs::id!(|| &id);
+    refs::id_generic!(|| std::num::NonZero::new(-1i16).unwrap());
+
+    // An `Instant` has no absolute value, but the difference between two of them does.
+    let start = std::time::Instant::now();
+    refs::span_ns!(|| start.elapsed());
+    refs::span_ns!(|| std::time::Instant::now() - start);
+    refs::span_ns!(|| &std::time::Duration::MAX);
+
+    // Key/value pairs may be passed as an array, slice or vector, with values of one type, or of
+    // any serializable type as a `&dyn ErasedSerialize`.
+    refs::fields!(|| [("method", "GET"), ("path", "/index.html")]);
+    refs::fields!(|| vec![(String::from("count"), 3)]);
+    let fields: [(&str, &dyn usdt::ErasedSerialize); 2] = [("tags", &["a", "b"]), ("id", &id)];
+    refs::fields!(|| &fields[..]);
+
+    // Any value implementing `Display` may be passed, with or without coercing it first.
+    let addr = std::net::Ipv4Addr::LOCALHOST;
+    refs::msg!(|| &addr as &dyn std::fmt::Display);
+    refs::msg!(|| addr);
+    refs::msg!(|| String::from("3 connections"));
+    refs::msg_impl!(|| &addr);
+    refs::msg_impl!(|| 42);
+
+    // Probes taking a mutable reference only read the value, so they may be passed a shared
+    // reference as well.
+    let mut count = 0u8;
+    let mut arg = crate::Arg::default();
+    touch(&mut count, &mut arg);
+    refs::event!(|| &arg as *const _);
+    assert_eq!(count, 1);
+
+    // Aliased arguments accept the same values as the types they alias.
+    refs::aliased!(|| (1, "name", arr, &x));
+    refs::unaliased!(|| (1, "name", arr, &x));
+    assert_eq!(arg.x, [1]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usdt::DataType;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_smart_pointer_strings_are_native() {
+        for name in ["cow_str", "box_str", "arc_str"] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            let types = probe
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>();
+            assert_eq!(types, ["char*"], "args[0] of {} should be a char *", name);
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_several_strings_are_native() {
+        let probe = usdt::probes()
+            .find(|probe| probe.provider == "refs" && probe.name == "three")
+            .expect("Expected to find probe refs:::three");
+        let types = probe
+            .types
+            .iter()
+            .map(DataType::to_c_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["char*", "char*", "char*"]);
+    }
+
+    // Each string is read from its own register.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_several_strings_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "Name: three")
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected the arguments of probe refs:::three");
+        assert_eq!(args, "8@%rdi 8@%rsi 8@%rdx");
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_mixed_arguments_keep_their_order() {
+        let probe = usdt::probes()
+            .find(|probe| probe.provider == "refs" && probe.name == "mix")
+            .expect("Expected to find probe refs:::mix");
+        let types = probe
+            .types
+            .iter()
+            .map(DataType::to_c_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["uint64_t", "char*", "uint32_t"]);
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_durations_are_native() {
+        let probe = usdt::probes()
+            .find(|probe| probe.provider == "refs" && probe.name == "span_ns")
+            .expect("Expected to find probe refs:::span_ns");
+        assert_eq!(probe.types, [DataType::Duration]);
+        assert_eq!(probe.types[0].to_c_type(), "uint64_t");
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_display_is_a_string() {
+        for name in ["msg", "msg_impl"] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .expect("Expected to find probe");
+            assert_eq!(probe.types, [DataType::Display]);
+            assert_eq!(probe.types[0].to_c_type(), "char*");
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_type_aliases_are_resolved() {
+        let find = |name| {
+            usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name))
+        };
+        let aliased = find("aliased");
+        assert_eq!(aliased.types, find("unaliased").types);
+        let types = aliased
+            .types
+            .iter()
+            .map(DataType::to_c_type)
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["uint64_t", "char*", "char*", "char*"]);
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_fields_are_serialized() {
+        let probe = usdt::probes()
+            .find(|probe| probe.provider == "refs" && probe.name == "fields")
+            .expect("Expected to find probe");
+        assert!(matches!(probe.types[..], [DataType::Serializable(_)]));
+        assert_eq!(probe.types[0].to_c_type(), "char*");
+    }
+
+    // Each argument is read from the register of its position, whatever the arguments before it.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_mixed_arguments_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "Name: mix")
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected the arguments of probe refs:::mix");
+        assert_eq!(args, "8@%rdi 8@%rsi 4@%edx");
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_mutable_references_are_read_like_shared_ones() {
+        for (name, expected) in [("touched_u8", "uint8_t"), ("touched", "char*")] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            let types = probe
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>();
+            assert_eq!(types, [expected], "args[0] of {}", name);
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_paths_are_native() {
+        for name in ["opened", "opened_buf"] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            let types = probe
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>();
+            assert_eq!(types, ["char*"], "args[0] of {} should be a char *", name);
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_path_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "Name: opened")
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected the arguments of probe refs:::opened");
+        assert_eq!(args, "8@%rdi");
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_raw_pointers_are_addresses() {
+        for name in ["addr", "addr_mut"] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            let types = probe
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                types,
+                ["uint64_t"],
+                "args[0] of {} should be a uint64_t",
+                name
+            );
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_non_zero_integers_are_native() {
+        for (name, c_type) in [("id", "uint64_t"), ("id_generic", "int16_t")] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            let types = probe
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                types,
+                [c_type],
+                "args[0] of {} should be a {}",
+                name,
+                c_type
+            );
+        }
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_native_slices() {
+        for name in ["samples", "samples_vec"] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            assert!(matches!(probe.types[..], [DataType::NativeSlice(_)]));
+            assert_eq!(probe.types[0].to_c_type(), "uint64_t, uint64_t, uint64_t");
+        }
+    }
+
+    // The address, length and element size are each passed in a register of their own.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_native_slice_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "Name: samples")
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected the arguments of probe refs:::samples");
+        assert_eq!(args, "8@%rdi 8@%rsi 8@%rdx");
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_non_zero_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = |name: &str| {
+            output
+                .lines()
+                .map(str::trim)
+                .skip_while(|line| *line != format!("Name: {}", name))
+                .find_map(|line| line.strip_prefix("Arguments: "))
+                .unwrap_or_else(|| panic!("Expected the arguments of probe refs:::{}", name))
+                .to_string()
+        };
+        assert_eq!(args("id"), "8@%rdi");
+        assert_eq!(args("id_generic"), "-2@%di");
+    }
+
+    // The address itself is passed, rather than read through.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_raw_pointer_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "Name: addr")
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected the arguments of probe refs:::addr");
+        assert_eq!(args, "8@%rdi");
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_repr_enums_are_discriminants() {
+        for name in ["state", "state_as_reference"] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            let types = probe
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                types,
+                ["uint8_t"],
+                "args[0] of {} should be a uint8_t",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn test_variant_names() {
+        use usdt::VariantName as _;
+        assert_eq!(State::A.variant_name(), "A");
+        assert_eq!(State::B.variant_name(), "B");
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_variant_name_enums_are_strings() {
+        for name in ["state_name", "state_name_as_reference"] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "refs" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe refs:::{}", name));
+            assert_eq!(probe.types[0].to_c_type(), "char*");
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_repr_enum_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "Name: state")
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected the arguments of probe refs:::state");
+        assert_eq!(args, "1@%dil");
+    }
 }
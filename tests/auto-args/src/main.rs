@@ -0,0 +1,87 @@
+//! Integration test verifying that probes are passed the automatic thread ID and CPU arguments.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+// The automatic arguments are passed in the same order, however they're listed.
+#[usdt::provider(auto_args = "cpu, tid")]
+mod auto {
+    fn work(_: u8, _: &str) {}
+    fn idle() {}
+}
+
+#[usdt::provider(auto_args = "cpu")]
+mod cpu_only {
+    fn tick(_: u64) {}
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    auto::work!(|| (1, "foo"));
+    auto::idle!();
+    auto::idle!(|| ());
+    cpu_only::tick!(|| 2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usdt::{DataType, ProbeInfo};
+
+    fn find_probe(provider: &str, name: &str) -> ProbeInfo {
+        usdt::probes()
+            .find(|probe| probe.provider == provider && probe.name == name)
+            .unwrap_or_else(|| panic!("Expected to find probe {}:::{}", provider, name))
+    }
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_auto_arg_types() {
+        let types = |provider, name| {
+            find_probe(provider, name)
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            types("auto", "work"),
+            ["uint8_t", "char*", "uint64_t", "int32_t"]
+        );
+        assert_eq!(types("auto", "idle"), ["uint64_t", "int32_t"]);
+        assert_eq!(types("cpu_only", "tick"), ["uint64_t", "int32_t"]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_auto_arg_values() {
+        let tid = usdt::current_thread_id();
+        assert_eq!(tid, usdt::current_thread_id());
+        let other = std::thread::spawn(usdt::current_thread_id).join().unwrap();
+        assert_ne!(tid, other);
+        assert!(usdt::current_cpu() >= 0);
+    }
+}
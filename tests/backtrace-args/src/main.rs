@@ -0,0 +1,76 @@
+//! Integration test verifying that short backtraces may be passed as probe arguments, and are only
+//! captured while the probe is enabled.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[usdt::provider]
+mod errors {
+    fn failed(code: u64, trace: &usdt::Backtrace) {}
+}
+
+// Counts how many backtraces were captured.
+static CAPTURES: AtomicUsize = AtomicUsize::new(0);
+
+fn capture(max_frames: usize) -> usdt::Backtrace {
+    CAPTURES.fetch_add(1, Ordering::SeqCst);
+    usdt::Backtrace::capture(max_frames)
+}
+
+fn fail(code: u64) {
+    errors::failed!(|| (code, capture(8)));
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    fail(1);
+    let trace = usdt::Backtrace::capture(4);
+    errors::failed!(|| (2, &trace));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The backtrace is only captured once the probe is enabled, here by setting its SystemTap
+    // semaphore as a tracer would. This is one test, so that `main` doesn't fire the probe while
+    // it's enabled.
+    #[test]
+    fn test_main() {
+        main();
+        assert_eq!(CAPTURES.load(Ordering::SeqCst), 0);
+
+        #[cfg(target_os = "linux")]
+        {
+            unsafe extern "C" {
+                static mut __usdt_sema_errors_failed: u16;
+            }
+            unsafe { (&raw mut __usdt_sema_errors_failed).write_volatile(1) };
+            fail(3);
+            unsafe { (&raw mut __usdt_sema_errors_failed).write_volatile(0) };
+            assert_eq!(CAPTURES.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[test]
+    fn test_backtrace_is_capped() {
+        let trace = usdt::Backtrace::capture(usize::MAX);
+        assert!(!trace.frames().is_empty());
+        assert!(trace.frames().len() <= usdt::MAX_BACKTRACE_FRAMES);
+    }
+}
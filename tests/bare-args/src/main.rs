@@ -0,0 +1,102 @@
+//! Integration test verifying that probes may be fired with their arguments directly, rather than
+//! a closure returning them.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Serialize)]
+pub struct Arg {
+    pub value: u64,
+}
+
+#[usdt::provider]
+mod bare {
+    use crate::Arg;
+    fn count(_: u64) {}
+    fn request(id: u64, path: &str, arg: &Arg) {}
+    fn nothing() {}
+}
+
+#[usdt::provider(auto_args = "tid")]
+mod bare_auto {
+    fn count(_: u64) {}
+}
+
+// Counts how many times the arguments are built.
+static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn evaluated<T>(value: T) -> T {
+    EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+    value
+}
+
+fn count_args() -> u64 {
+    evaluated(7)
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let count = 3u64;
+    let path = String::from("/index.html");
+    let arg = Arg { value: 1 };
+
+    // Arguments already computed needn't be wrapped in a closure, and are passed as they would
+    // be returned from one.
+    bare::count!(count);
+    bare::count!(42);
+    bare::count!(&count);
+    bare::request!((1, &path, &arg));
+    bare::request!((2, path.as_str(), Arg { value: 2 }));
+    bare::nothing!(());
+    bare_auto::count!(count);
+
+    // The closure form, and any function returning the arguments, are still only called when the
+    // probe is enabled.
+    bare::count!(|| evaluated(count));
+    bare::count!(count_args);
+    bare::request!(move || evaluated((3, path, arg)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The bare form evaluates its expression whether or not the probe is enabled, while the
+    // closure form is only called once it is, here by setting the probe's SystemTap semaphore as
+    // a tracer would. This is one test, so that `main` doesn't fire the probe while it's enabled.
+    #[test]
+    fn test_main() {
+        main();
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 0);
+        bare::count!(evaluated(1));
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 1);
+
+        #[cfg(target_os = "linux")]
+        {
+            unsafe extern "C" {
+                static mut __usdt_sema_bare_count: u16;
+            }
+            unsafe { (&raw mut __usdt_sema_bare_count).write_volatile(1) };
+            bare::count!(|| evaluated(2));
+            bare::count!(count_args);
+            unsafe { (&raw mut __usdt_sema_bare_count).write_volatile(0) };
+            assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 3);
+        }
+    }
+}
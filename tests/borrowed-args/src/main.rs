@@ -0,0 +1,118 @@
+//! Integration test verifying that probes may be fired from methods with arguments borrowed from
+//! `self`.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Serialize)]
+pub struct Arg {
+    pub value: u64,
+}
+
+#[usdt::provider]
+mod borrowed {
+    use crate::Arg;
+    fn test_stop(msg: &str, arg: &Arg) {}
+    fn counted(count: u64, name: &str) {}
+}
+
+#[usdt::provider(auto_args = "tid", dedupe_probes = true)]
+mod borrowed_auto {
+    use crate::Arg;
+    fn test_stop(msg: &str, arg: &Arg) {}
+}
+
+// Counts how many times the arguments are built.
+static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn evaluated<T>(value: T) -> T {
+    EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+    value
+}
+
+// Nothing here is `Clone`: the probes borrow the fields of `self` for as long as they're fired.
+struct Worker {
+    name: String,
+    count: u64,
+    arg: Arg,
+}
+
+impl Worker {
+    fn stop(&self, msg: &str) {
+        borrowed::test_stop!(|| evaluated((msg, &self.arg)));
+        borrowed::test_stop!(msg = msg, arg = &self.arg);
+        borrowed::test_stop!(try || Ok::<_, ()>((msg, &self.arg)));
+        borrowed_auto::test_stop!(|| (msg, &self.arg));
+    }
+
+    // An owned message may be moved into the closure, which is only ever called once.
+    fn stop_owned(&self, msg: String) {
+        borrowed::test_stop!(|| (msg, &self.arg));
+    }
+
+    // Fields may be borrowed right after others are mutated.
+    fn count(&mut self) {
+        self.count += 1;
+        self.arg.value = self.count;
+        borrowed::counted!(|| (&self.count, &self.name));
+        borrowed::counted!(|| (self.count, self.name.as_str()));
+    }
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let mut worker = Worker {
+        name: String::from("worker"),
+        count: 0,
+        arg: Arg { value: 0 },
+    };
+    worker.count();
+    worker.stop("stopped");
+    worker.stop_owned(format!("stopped after {}", worker.count));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Without a tracer attached, the arguments aren't built. Once the probe is enabled, here by
+    // setting its SystemTap semaphore as a tracer would, the closure borrowing from the worker is
+    // called. This is one test, so that `main` doesn't fire the probe while it's enabled.
+    #[test]
+    fn test_main() {
+        main();
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 0);
+
+        #[cfg(target_os = "linux")]
+        {
+            unsafe extern "C" {
+                static mut __usdt_sema_borrowed_test_stop: u16;
+            }
+            let worker = Worker {
+                name: String::from("worker"),
+                count: 1,
+                arg: Arg { value: 1 },
+            };
+            unsafe { (&raw mut __usdt_sema_borrowed_test_stop).write_volatile(1) };
+            worker.stop("stopped");
+            unsafe { (&raw mut __usdt_sema_borrowed_test_stop).write_volatile(0) };
+            assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 1);
+        }
+    }
+}
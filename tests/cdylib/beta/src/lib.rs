@@ -0,0 +1,38 @@
+//! A shared library defining the `beta` provider, loaded by the `cdylib` test.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+#[usdt::provider]
+mod beta {
+    fn fired(_: u64) {}
+}
+
+#[no_mangle]
+pub extern "C" fn beta_register_probes() -> bool {
+    usdt::register_probes().is_ok()
+}
+
+#[no_mangle]
+pub extern "C" fn beta_fire(value: u64) {
+    beta::fired!(|| value);
+}
+
+// Return the number of probes the library sees, which must only be its own.
+#[no_mangle]
+pub extern "C" fn beta_probe_count() -> usize {
+    usdt::probes().count()
+}
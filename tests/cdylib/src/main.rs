@@ -0,0 +1,171 @@
+//! Test verifying that the probes of several shared libraries, loaded at runtime, are each
+//! registered and listed by the library defining them.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+
+#[usdt::provider]
+mod loader {
+    fn loaded(_: &str) {}
+}
+
+// A shared library, loaded with `dlopen(3)`.
+struct Library {
+    name: &'static str,
+    handle: *mut libc::c_void,
+}
+
+impl Library {
+    fn open(dir: &Path, name: &'static str) -> Self {
+        let path = dir.join(format!(
+            "{}cdylib_{}{}",
+            std::env::consts::DLL_PREFIX,
+            name,
+            std::env::consts::DLL_SUFFIX
+        ));
+        let c_path = CString::new(path.to_str().unwrap()).unwrap();
+        let handle = unsafe { libc::dlopen(c_path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+        assert!(!handle.is_null(), "Failed to load {}", path.display());
+        loader::loaded!(|| path.to_str().unwrap());
+        Self { name, handle }
+    }
+
+    // Look up the library's function `<name>_<suffix>`.
+    fn function(&self, suffix: &str) -> *mut libc::c_void {
+        let symbol = CString::new(format!("{}_{}", self.name, suffix)).unwrap();
+        let function = unsafe { libc::dlsym(self.handle, symbol.as_ptr()) };
+        assert!(!function.is_null(), "Missing function {:?}", symbol);
+        function
+    }
+
+    fn register_probes(&self) -> bool {
+        let function: extern "C" fn() -> bool =
+            unsafe { std::mem::transmute(self.function("register_probes")) };
+        function()
+    }
+
+    fn fire(&self, value: u64) {
+        let function: extern "C" fn(u64) = unsafe { std::mem::transmute(self.function("fire")) };
+        function(value)
+    }
+
+    fn probe_count(&self) -> usize {
+        let function: extern "C" fn() -> usize =
+            unsafe { std::mem::transmute(self.function("probe_count")) };
+        function()
+    }
+}
+
+// Build the shared libraries, returning the directory containing them.
+//
+// Cargo doesn't build the libraries for this crate's tests, so they're built with a nested Cargo
+// invocation, into the same target directory, whose lock is released while tests run.
+fn build_libraries() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    // The executable is `<target-dir>/<profile>/deps/<exe>`.
+    let target_dir = exe.ancestors().nth(3).unwrap();
+    let status = std::process::Command::new(env!("CARGO"))
+        .arg("build")
+        .arg("--quiet")
+        .arg("-p")
+        .arg("cdylib-alpha")
+        .arg("-p")
+        .arg("cdylib-beta")
+        .arg("--target-dir")
+        .arg(target_dir)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .status()
+        .expect("Failed to run cargo");
+    assert!(status.success(), "Failed to build the shared libraries");
+    target_dir.join("debug")
+}
+
+fn main() {
+    let dir = build_libraries();
+    usdt::register_probes().unwrap();
+    let libraries = [Library::open(&dir, "alpha"), Library::open(&dir, "beta")];
+    for library in libraries.iter() {
+        assert!(library.register_probes());
+        library.fire(0);
+        println!("{}: {} probes", library.name, library.probe_count());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_libraries, Library};
+
+    #[test]
+    fn test_main() {
+        super::main();
+    }
+
+    // Each library must find the probe records of its own module, not those of the executable or
+    // of the other library.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_probes_of_each_library() {
+        let dir = build_libraries();
+        let alpha = Library::open(&dir, "alpha");
+        let beta = Library::open(&dir, "beta");
+        assert_eq!(alpha.probe_count(), 1);
+        assert_eq!(beta.probe_count(), 1);
+        let probes = usdt::probes().collect::<Vec<_>>();
+        assert_eq!(probes.len(), 1);
+        assert_eq!(probes[0].provider, "loader");
+    }
+
+    // Each library must carry the SystemTap notes of its own probes, along with the base section
+    // that consumers relocate them by.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_stapsdt_notes_of_each_library() {
+        let dir = build_libraries();
+        for (name, other) in [("alpha", "beta"), ("beta", "alpha")] {
+            let path = dir.join(format!("libcdylib_{}.so", name));
+            let notes = std::process::Command::new("readelf")
+                .arg("-n")
+                .arg(&path)
+                .output()
+                .expect("Could not run readelf");
+            let notes = String::from_utf8_lossy(&notes.stdout);
+            assert!(
+                notes.contains(&format!("Provider: {}", name)),
+                "Expected a note for the {} provider:\n{}",
+                name,
+                notes
+            );
+            assert!(!notes.contains(&format!("Provider: {}", other)));
+            let sections = std::process::Command::new("readelf")
+                .arg("-S")
+                .arg("-W")
+                .arg(&path)
+                .output()
+                .expect("Could not run readelf");
+            let sections = String::from_utf8_lossy(&sections.stdout);
+            assert!(sections.contains(".stapsdt.base"));
+            assert!(sections.contains(".probes"));
+        }
+    }
+}
@@ -28,5 +28,14 @@ mod tests {
         t.compile_fail("src/zero-arg-probe-type-check.rs");
         t.compile_fail("src/different-serializable-type.rs");
         t.compile_fail("src/relative-import.rs");
+        t.compile_fail("src/invalid-stability.rs");
+        t.compile_fail("src/invalid-provider-name.rs");
+        t.compile_fail("src/probe-name-collision.rs");
+        t.compile_fail("src/invalid-probe-name.rs");
+        t.compile_fail("src/arity-mismatch.rs");
+        t.compile_fail("src/repr-mismatch.rs");
+        t.compile_fail("src/unused-probe.rs");
+        t.compile_fail("src/invalid-section-name.rs");
+        t.compile_fail("src/named-argument-mismatch.rs");
     }
 }
@@ -0,0 +1,23 @@
+//! Test that a probe fired with an argument it has no parameter of is rejected
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#[usdt::provider]
+mod my_provider {
+    fn stop(code: u8, message: &str) {}
+}
+
+fn main() {
+    my_provider::stop!(code = 0, reason = "done");
+}
@@ -0,0 +1,36 @@
+//! Test that enums passed as their discriminant must be field-less, with a matching representation
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#[repr(u8)]
+enum State {
+    A,
+}
+
+#[repr(u8)]
+#[derive(serde::Serialize)]
+enum Data {
+    A(u8),
+}
+
+#[usdt::provider]
+mod my_provider {
+    fn state(#[repr(u32)] _: crate::State) {}
+    fn data(#[repr(u8)] _: crate::Data) {}
+}
+
+fn main() {
+    my_provider::state!(|| crate::State::A);
+    my_provider::data!(|| crate::Data::A(0));
+}
@@ -0,0 +1,28 @@
+//! Test that probes which are never fired are reported with `warn_unused_probes`
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![deny(dead_code)]
+
+#[usdt::provider(warn_unused_probes = true)]
+mod my_provider {
+    fn fired_by_macro(_: u8) {}
+    fn fired_by_function(_: u8) {}
+    fn never_fired(_: u8) {}
+}
+
+fn main() {
+    my_provider::fired_by_macro!(|| 0);
+    my_provider::fire_fired_by_function(0);
+}
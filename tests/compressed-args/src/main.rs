@@ -0,0 +1,89 @@
+//! Integration test compressing the JSON of serializable probe arguments.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Batch {
+    ids: Vec<u64>,
+}
+
+#[usdt::provider(compression = "gzip")]
+mod gzipped {
+    fn batch(_: &crate::Batch, _: u64) {}
+}
+
+#[usdt::provider(compression = "zstd")]
+mod zstded {
+    fn batch(_: &crate::Batch, _: u64) {}
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let batch = Batch {
+        ids: (0..1024).collect(),
+    };
+    gzipped::batch!(|| (&batch, batch.ids.len() as u64));
+    zstded::batch!(|| (&batch, batch.ids.len() as u64));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    // Decode an argument the way a consumer would, checking its header.
+    fn decode(arg: &[u8]) -> String {
+        assert_eq!(arg[0], 0xff, "Expected a compressed argument");
+        assert_eq!(arg[2..4], [1, 0]);
+        let len = u32::from_le_bytes(arg[4..8].try_into().unwrap()) as usize;
+        let data = &arg[8..8 + len];
+        assert_eq!(data.len(), arg.len() - 8);
+        let mut json = String::new();
+        match arg[1] {
+            1 => flate2::read::GzDecoder::new(data)
+                .read_to_string(&mut json)
+                .unwrap(),
+            2 => zstd::stream::Decoder::new(data)
+                .unwrap()
+                .read_to_string(&mut json)
+                .unwrap(),
+            encoding => panic!("Unknown encoding {}", encoding),
+        };
+        json
+    }
+
+    #[test]
+    fn test_compressed_arguments_round_trip() {
+        let batch = Batch {
+            ids: (0..1024).collect(),
+        };
+        let json = usdt::to_json_probe_arg(&batch, 64 * 1024);
+        let expected = format!("{{\"ok\":{}}}", usdt::to_json(&batch).unwrap());
+        let gzipped = usdt::gzip_probe_arg(&json);
+        assert_eq!(gzipped[1], 1);
+        assert_eq!(decode(&gzipped), expected);
+        let zstded = usdt::zstd_probe_arg(&json);
+        assert_eq!(zstded[1], 2);
+        assert_eq!(decode(&zstded), expected);
+        assert!(zstded.len() < json.len());
+    }
+}
@@ -0,0 +1,67 @@
+//! Integration test verifying that probes fired in generic functions can be deduplicated.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#[usdt::provider(dedupe_probes = true)]
+mod deduped {
+    fn value(_: u64) {}
+}
+
+#[usdt::provider]
+mod duplicated {
+    fn value(_: u64) {}
+}
+
+// Fire both probes from a generic function, which is monomorphized for each type it's called with.
+#[inline(never)]
+fn fire<T: Into<u64>>(value: T) {
+    let value = value.into();
+    deduped::value!(|| value);
+    duplicated::value!(|| value);
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    // Keep the values opaque, so that the monomorphizations aren't identical and merged.
+    fire(std::hint::black_box(0u8));
+    fire(std::hint::black_box(0u16));
+    fire(std::hint::black_box(0u32));
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    // Return the number of probe sites of the given provider in the test executable.
+    fn count_probe_sites(provider: &str) -> usize {
+        let test_exe = std::env::current_exe().unwrap();
+        let readelf = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let expected = format!("Provider: {}", provider);
+        String::from_utf8_lossy(&readelf.stdout)
+            .lines()
+            .filter(|line| line.trim() == expected)
+            .count()
+    }
+
+    #[test]
+    fn test_dedupe_probes() {
+        // Make sure each monomorphization of `fire` is linked into the test executable.
+        super::main();
+        assert_eq!(count_probe_sites("deduped"), 1);
+        assert_eq!(count_probe_sites("duplicated"), 3);
+    }
+}
@@ -0,0 +1,100 @@
+//! Integration test verifying that trailing probe arguments with defaults may be left out.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Arg {
+    pub value: u64,
+}
+
+// The arguments `elapsed` and `arg` were added after `served` was first fired, with defaults, so
+// that the call sites passing only `path` and `status` still build.
+#[usdt::provider]
+mod defaults {
+    use crate::Arg;
+    fn served(
+        path: &str,
+        status: u16,
+        #[default = 0] elapsed: u64,
+        #[default = &crate::Arg { value: 0 }] arg: &Arg,
+    ) {
+    }
+    fn tagged(#[default = "untagged"] tag: &str) {}
+    fn timed(name: &str, #[default = crate::evaluated(0)] elapsed: u64) {}
+}
+
+// Counts how many times the default of `timed`'s `elapsed` is evaluated.
+static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn evaluated<T>(value: T) -> T {
+    EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+    value
+}
+
+fn fire_timed() {
+    // A single argument given is returned as a tuple, as `|| "a"` couldn't be told apart from a
+    // closure returning all of the arguments.
+    defaults::timed!(|| ("a",));
+    defaults::timed!(|| ("a", 1));
+    defaults::timed!(name = "a");
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let arg = Arg { value: 1 };
+    defaults::served!(|| ("/", 200));
+    defaults::served!(move || { ("/", 200, 10) });
+    defaults::served!(|| ("/", 200, 10, &arg));
+    defaults::served!(path = "/", status = 404);
+    defaults::served!(arg = &arg, path = "/", status = 404);
+    // A closure returning anything but a tuple literal returns all of the arguments.
+    let args = ("/", 500, 10, &arg);
+    defaults::served!(|| args);
+    defaults::tagged!();
+    defaults::tagged!(|| ());
+    defaults::tagged!(|| "tagged");
+    defaults::tagged!(tag = "tagged");
+    fire_timed();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Without a tracer attached, the defaults aren't evaluated. Once the probe is enabled, here by
+    // setting its SystemTap semaphore as a tracer would, each call leaving `elapsed` out evaluates
+    // its default. This is one test, so that `main` doesn't fire the probe while it's enabled.
+    #[test]
+    fn test_main() {
+        main();
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 0);
+
+        #[cfg(target_os = "linux")]
+        {
+            unsafe extern "C" {
+                static mut __usdt_sema_defaults_timed: u16;
+            }
+            unsafe { (&raw mut __usdt_sema_defaults_timed).write_volatile(1) };
+            fire_timed();
+            unsafe { (&raw mut __usdt_sema_defaults_timed).write_volatile(0) };
+            assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 2);
+        }
+    }
+}
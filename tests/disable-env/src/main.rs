@@ -0,0 +1,79 @@
+//! Integration test verifying that setting `USDT_DISABLE` disables every probe when they're
+//! registered.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[usdt::provider]
+mod killed {
+    fn work(_: u64, _: &str) {}
+}
+
+// Counts how many times the arguments are built.
+static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn evaluated<T>(value: T) -> T {
+    EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+    value
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    killed::work!(|| evaluated((1, "work")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // With `USDT_DISABLE` set, nothing is registered, and the probe's arguments aren't built even
+    // once it's enabled, here by setting its SystemTap semaphore as a tracer would. This is one
+    // test, as the variable is only read by the first registration.
+    #[test]
+    fn test_main() {
+        assert!(!usdt::probes_disabled());
+        std::env::set_var(usdt::DISABLE_ENV_VAR, "1");
+        main();
+        assert!(usdt::probes_disabled());
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 0);
+
+        // The variable is read once, so clearing it doesn't enable the probes again.
+        std::env::remove_var(usdt::DISABLE_ENV_VAR);
+        usdt::register_probes().unwrap();
+        assert!(usdt::probes_disabled());
+
+        // Providers defined at runtime aren't registered either, even where they're unsupported.
+        usdt::runtime::register_provider_source("provider plugin { probe event(uint64_t); };")
+            .unwrap();
+        usdt::runtime::fire("plugin", "event", &[1u64.into()]).unwrap();
+
+        #[cfg(target_os = "linux")]
+        {
+            unsafe extern "C" {
+                static mut __usdt_sema_killed_work: u16;
+            }
+            unsafe { (&raw mut __usdt_sema_killed_work).write_volatile(1) };
+            killed::work!(|| evaluated((2, "enabled")));
+            assert!(!usdt::is_enabled("killed", "work"));
+            assert!(!usdt::provider_enabled("killed"));
+            assert_eq!(usdt::enabled_count("killed", "work"), 1);
+            unsafe { (&raw mut __usdt_sema_killed_work).write_volatile(0) };
+            assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 0);
+        }
+    }
+}
@@ -26,11 +26,18 @@ fn main() {
     does__it::work!(|| (0, "something"));
 }
 
+// Register the probes once, however many tests fire them.
+#[allow(dead_code)]
+fn register() {
+    static REGISTER: std::sync::Once = std::sync::Once::new();
+    REGISTER.call_once(|| register_probes().unwrap());
+}
+
 // Dissuade the compiler from inlining this, which would ruin the test for `probefunc`.
 #[inline(never)]
 #[allow(dead_code)]
 fn run_test(rx: std::sync::mpsc::Receiver<()>) {
-    register_probes().unwrap();
+    register();
     does__it::work!(|| (0, "something"));
     let _ = rx.recv();
 }
@@ -39,13 +46,25 @@ fn run_test(rx: std::sync::mpsc::Receiver<()>) {
 mod tests {
     use super::run_test;
 
-    #[cfg(not(target_os = "linux"))]
+    #[cfg(not(any(target_os = "linux", windows)))]
     mod dtrace {
         use super::run_test;
         use std::process::Stdio;
         use std::sync::mpsc::channel;
         use std::thread;
 
+        // The probe is fired with the values it's given, as DTrace reads them.
+        #[test]
+        fn test_argument_values() {
+            use usdt_tests_common::{trace_probe_args, ArgFormat};
+            crate::register();
+            let formats = [ArgFormat::Int, ArgFormat::Str];
+            let values = trace_probe_args("does__it", "work", &formats, || {
+                crate::does__it::work!(|| (0, "something"))
+            });
+            assert_eq!(values, ["0", "something"]);
+        }
+
         #[test]
         fn test_does_it_work() {
             use usdt_tests_common::root_command;
@@ -196,7 +215,7 @@ mod tests {
 
             // "Location: 0x00001234, Base: 0x0001234, Semaphore: 0x0001234"
             let line = lines.next().expect("Expected an addresses line");
-            let mut parts = line.trim().split_whitespace();
+            let mut parts = line.split_whitespace();
             assert_eq!(
                 parts.next().expect("Expected a 'Location:' text"),
                 "Location:",
@@ -242,16 +261,37 @@ mod tests {
                 semaphore_address
             );
 
+            // The addresses in the note are link-time addresses, which consumers relocate by the
+            // difference between `Base` and the runtime address of the `.stapsdt.base` section.
+            // They must therefore match the section headers exactly, also for PIE binaries.
+            let location = parse_address(location_address);
+            let base = parse_address(base_address);
+            let readelf = std::process::Command::new("readelf")
+                .arg("-S")
+                .arg("-W")
+                .arg(&test_exe)
+                .output()
+                .expect("Could not run readelf");
+            let sections = String::from_utf8_lossy(&readelf.stdout);
+            let (base_section, _) = section_bounds(&sections, ".stapsdt.base")
+                .expect("Expected a .stapsdt.base section");
+            assert_eq!(
+                base, base_section,
+                "Base address does not match the .stapsdt.base section: {:#x}",
+                base
+            );
+            let (text_start, text_size) =
+                section_bounds(&sections, ".text").expect("Expected a .text section");
+            assert!(
+                (text_start..text_start + text_size).contains(&location),
+                "Location address is outside of the .text section: {:#x}",
+                location
+            );
+
             // Verify the argument types
             let line = lines.next().expect("Expected a line containing arguments");
             let line = line.trim();
-            let arguments_line = if cfg!(target_arch = "x86_64") {
-                "Arguments: 1@%dil 8@%rsi"
-            } else if cfg!(target_arch = "aarch64") {
-                "Arguments: 1@x0 8@x1"
-            } else {
-                unreachable!("Unsupported Linux target architecture")
-            };
+            let arguments_line = format!("Arguments: {}", work_arg_spec());
             assert_eq!(
                 line, arguments_line,
                 "Arguments line appears incorrect: {}",
@@ -260,5 +300,115 @@ mod tests {
 
             thr.join().expect("Failed to join test runner thread");
         }
+
+        // The notes read back at runtime must be those readelf shows.
+        #[test]
+        fn test_notes() {
+            let test_exe = std::env::current_exe().unwrap();
+            let notes = usdt::stapsdt::notes();
+            let note = notes
+                .iter()
+                .find(|note| note.provider == "does__it" && note.name == "work")
+                .expect("Expected a note for the does__it:work probe");
+            assert_eq!(note.arguments, work_arg_spec());
+            assert_ne!(note.semaphore, 0);
+
+            let readelf = std::process::Command::new("readelf")
+                .arg("-S")
+                .arg("-W")
+                .arg(&test_exe)
+                .output()
+                .expect("Could not run readelf");
+            let sections = String::from_utf8_lossy(&readelf.stdout);
+            let (base_section, _) = section_bounds(&sections, ".stapsdt.base")
+                .expect("Expected a .stapsdt.base section");
+            assert_eq!(note.base as usize, base_section);
+            let (text_start, text_size) =
+                section_bounds(&sections, ".text").expect("Expected a .text section");
+            assert!((text_start..text_start + text_size).contains(&(note.location as usize)));
+            assert_eq!(usdt::stapsdt::notes_from_file(&test_exe).unwrap(), notes);
+        }
+
+        // bpftrace must list the probe by the provider and probe names in the note, which it reads
+        // from the binary without the probe being registered or enabled.
+        #[test]
+        fn test_bpftrace_lists_probes() {
+            use usdt_tests_common::root_command;
+            let has_bpftrace = std::process::Command::new("bpftrace")
+                .arg("--version")
+                .output()
+                .is_ok_and(|output| output.status.success());
+            if !has_bpftrace {
+                eprintln!("bpftrace is not available, skipping");
+                return;
+            }
+            let test_exe = std::env::current_exe().unwrap();
+            let probe = format!("usdt:{}:does__it:work", test_exe.display());
+            let output = std::process::Command::new(root_command())
+                .arg("bpftrace")
+                .arg("-l")
+                .arg(&probe)
+                .stdin(Stdio::null())
+                .output()
+                .expect("Could not run bpftrace");
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(
+                output.status.success(),
+                "bpftrace failed: {}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+            assert!(
+                stdout.lines().any(|line| line.trim() == probe),
+                "Expected bpftrace to list {}, found:\n{}",
+                probe,
+                stdout
+            );
+        }
+
+        // Parse an address printed by readelf, like `0x00001234,`.
+        fn parse_address(address: &str) -> usize {
+            let digits = address.trim_start_matches("0x").trim_end_matches(',');
+            usize::from_str_radix(digits, 16).unwrap()
+        }
+
+        // The arguments of the does__it:work probe, as the SystemTap note on this architecture
+        // records them.
+        fn work_arg_spec() -> String {
+            let source = dtrace_parser::File::try_from(include_str!("../test.d")).unwrap();
+            let provider = usdt_impl::Provider::from(&source.providers()[0]);
+            let arch = usdt_impl::Arch::host().expect("Unsupported Linux target architecture");
+            provider.probes[0].stapsdt_arg_spec(arch)
+        }
+
+        // Return the address and size of the named section, from the output of `readelf -S -W`.
+        fn section_bounds(sections: &str, name: &str) -> Option<(usize, usize)> {
+            sections.lines().find_map(|line| {
+                // Lines look like `[15] .text PROGBITS 0000000000015070 015070 0c70bb 00 AX 0 0 16`
+                let (_, rest) = line.split_once(']')?;
+                let mut parts = rest.split_whitespace();
+                if parts.next()? != name {
+                    return None;
+                }
+                let _ = parts.next()?;
+                let address = usize::from_str_radix(parts.next()?, 16).ok()?;
+                let _ = parts.next()?;
+                let size = usize::from_str_radix(parts.next()?, 16).ok()?;
+                Some((address, size))
+            })
+        }
+    }
+
+    #[cfg(windows)]
+    mod etw {
+        #[test]
+        fn test_provider_is_registered() {
+            let registration = usdt::register_probes_with_report().unwrap();
+            assert_eq!(registration.providers, 1);
+            assert!(
+                usdt::probes().any(|probe| probe.provider == "does__it" && probe.name == "work")
+            );
+            // No ETW session is collecting the provider's events.
+            assert!(!usdt::is_enabled("does__it", "work"));
+        }
     }
 }
@@ -0,0 +1,72 @@
+//! Integration test verifying that a provider defined in more than one place isn't registered.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+// Two providers of the same name, as two crates might define.
+#[usdt::provider(provider = "shared")]
+mod first {
+    fn value(_: u64) {}
+}
+
+#[usdt::provider(provider = "shared")]
+mod second {
+    fn other(_: u8) {}
+}
+
+#[usdt::provider]
+mod unique {
+    fn value(_: u64) {}
+}
+
+fn main() {
+    if let Err(e) = usdt::register_probes() {
+        eprintln!("{e}");
+    }
+    first::value!(|| 0);
+    second::other!(|| 0);
+    unique::value!(|| 0);
+}
+
+// Without a probe backend, no providers are compiled in, and none are duplicated.
+#[cfg(all(
+    test,
+    any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    )
+))]
+mod tests {
+    #[test]
+    fn test_duplicate_provider_is_an_error() {
+        match usdt::register_probes() {
+            Err(usdt::Error::DuplicateProvider { name }) => assert_eq!(name, "shared"),
+            result => panic!("Expected a duplicate provider, found {result:?}"),
+        }
+        assert!(usdt::register_probes_with_report().is_err());
+    }
+
+    #[test]
+    fn test_unique_providers_may_be_registered() {
+        usdt::register_providers(&["unique"]).unwrap();
+        let err = usdt::register_providers(&["shared", "unique"]).unwrap_err();
+        assert!(err
+            .to_string()
+            .contains("\"shared\" is defined in more than one place"));
+    }
+}
@@ -17,6 +17,13 @@
 fn main() {
     fake_lib::register_probes().unwrap();
     fake_lib::dummy();
+
+    // The probes of `fake_lib::exported` are exported, so may be fired from this crate.
+    let request = fake_lib::Request {
+        path: String::from("/"),
+    };
+    fake_lib::exported::request!(|| (&request, 1));
+    fake_lib::exported::fire_request(&request, 2);
 }
 
 #[cfg(test)]
@@ -26,4 +33,27 @@ mod test {
     fn test_main() {
         super::main();
     }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_exported_probes() {
+        assert!(usdt::probes().any(|probe| probe.provider == "exported" && probe.name == "request"));
+    }
+
+    // The exported probe is fired from this crate's call of its macro, and from its fire function
+    // in `fake_lib`.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_exported_probe_sites() {
+        let sites = usdt::stapsdt::notes()
+            .into_iter()
+            .filter(|note| note.provider == "exported" && note.name == "request")
+            .count();
+        assert_eq!(sites, 2);
+    }
 }
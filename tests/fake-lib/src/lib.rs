@@ -18,6 +18,19 @@ pub use usdt::register_probes;
 
 include!(concat!(env!("OUT_DIR"), "/test.rs"));
 
+/// Probes exported for the crates depending on this one to fire.
+#[usdt::provider(visibility = "pub")]
+pub mod exported {
+    use crate::Request;
+
+    fn request(_: &Request, _: u8) {}
+}
+
+#[derive(serde::Serialize)]
+pub struct Request {
+    pub path: String,
+}
+
 pub fn dummy() {
     test::here__i__am!();
     test::here__i__am!();
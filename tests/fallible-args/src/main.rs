@@ -0,0 +1,74 @@
+//! Integration test verifying that probes may be fired with fallible argument closures.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+#[usdt::provider]
+mod fallible {
+    fn one(_: u64) {}
+    fn many(_: u8, _: &str) {}
+    fn none() {}
+}
+
+#[usdt::provider(auto_args = "tid")]
+mod fallible_auto {
+    fn tagged(_: u64) {}
+}
+
+// Counts how many times the argument closures are called.
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let state = Mutex::new(1u64);
+    fallible::one!(try || {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        state.lock().map(|value| *value).map_err(|e| e.to_string())
+    });
+    fallible::many!(try || {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        Ok::<_, String>((2, "foo"))
+    });
+    fallible::none!(try || {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        Err::<(), _>("failed")
+    });
+    fallible_auto::tagged!(try || {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        "3".parse::<u64>()
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    // Without a tracer attached, the probes are disabled and the closures must not be called.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_closures_not_called_when_disabled() {
+        main();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,79 @@
+//! Integration test firing probes through their fire functions, rather than their macros.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use serde::{Serialize, Serializer};
+
+/// A type which can't be serialized, to check that it isn't when the probe is disabled.
+pub struct Unserializable;
+
+impl Serialize for Unserializable {
+    fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+        panic!("Serialized the argument of a disabled probe");
+    }
+}
+
+// Probes fired only through their fire functions count as used.
+#[usdt::provider(warn_unused_probes = true)]
+mod functions {
+    use crate::Unserializable;
+    fn begin(_: u8, _: &str) {}
+    fn payload(_: &Unserializable) {}
+    fn stop() {}
+}
+
+// Fire a probe through a function pointer, as generated code might.
+fn fire_each(fire: fn(u8, &str), values: &[u8]) {
+    for value in values {
+        fire(*value, "value");
+    }
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    fire_each(functions::fire_begin, &[1, 2, 3]);
+    functions::fire_payload(&Unserializable);
+    functions::fire_stop();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::main;
+
+    // The probes aren't enabled, so this also checks the unserializable argument is never
+    // serialized.
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_fire_functions_fire_the_probes() {
+        let mut probes = usdt::probes()
+            .filter(|probe| probe.provider == "functions")
+            .map(|probe| probe.name)
+            .collect::<Vec<_>>();
+        probes.sort();
+        assert_eq!(probes, ["begin", "payload", "stop"]);
+    }
+}
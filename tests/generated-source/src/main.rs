@@ -0,0 +1,64 @@
+//! Integration test verifying that the formatted code generated for a provider file ahead of time
+//! builds on its own.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+// The generated code stands in for a file checked in as a module of its own, whose full path is
+// given as the `module`.
+mod generated {
+    include!(concat!(env!("OUT_DIR"), "/vendored.rs"));
+}
+
+use generated::vendored;
+
+fn main() {
+    usdt::register_probes().unwrap();
+    vendored::start_work!(|| 1);
+    vendored::stop_work!(|| ("done", 2));
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_main() {
+        super::main();
+    }
+
+    // The code for the target is kept from that generated for every target.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_probe_names() {
+        let mut probes = usdt::probes()
+            .map(|probe| format!("{}:::{}", probe.provider, probe.name))
+            .collect::<Vec<_>>();
+        probes.sort();
+        assert_eq!(probes, ["vendored:::start_work", "vendored:::stop_work"]);
+    }
+
+    // The generated code is formatted, as it would be checked in.
+    #[test]
+    fn test_generated_code_is_formatted() {
+        let source = include_str!(concat!(env!("OUT_DIR"), "/vendored.rs"));
+        assert!(source.lines().count() > 1);
+        assert!(source.contains("pub(crate) mod vendored {\n"));
+    }
+}
@@ -0,0 +1,81 @@
+//! Integration test verifying that probes may be fired only when a predicate holds.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+#[usdt::provider(if_macros = true)]
+mod requests {
+    fn slow(_: &str, _: u64) {}
+    fn idle() {}
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let elapsed_ms = 1000;
+    requests::slow_if!(|| elapsed_ms > 100, || ("/index.html", elapsed_ms));
+    requests::slow_if!(|| false, || ("/index.html", elapsed_ms));
+    requests::idle_if!(|| true);
+    requests::idle_if!(|| false, || ());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Count how many times the predicate and the argument closure of `slow` are called.
+    static PREDICATES: AtomicUsize = AtomicUsize::new(0);
+    static ARGUMENTS: AtomicUsize = AtomicUsize::new(0);
+
+    fn fire_slow(elapsed_ms: u64) {
+        requests::slow_if!(
+            || {
+                PREDICATES.fetch_add(1, Ordering::SeqCst);
+                elapsed_ms > 100
+            },
+            || {
+                ARGUMENTS.fetch_add(1, Ordering::SeqCst);
+                ("/index.html", elapsed_ms)
+            }
+        );
+    }
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    // Without a tracer attached, neither the predicate nor the arguments are evaluated. Once the
+    // probe is enabled, here by setting its SystemTap semaphore as a tracer would, the predicate
+    // is called each time, and the arguments only when it holds.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_predicate_gates_arguments() {
+        unsafe extern "C" {
+            static mut __usdt_sema_requests_slow: u16;
+        }
+        fire_slow(1000);
+        assert_eq!(PREDICATES.load(Ordering::SeqCst), 0);
+        assert_eq!(ARGUMENTS.load(Ordering::SeqCst), 0);
+
+        unsafe { (&raw mut __usdt_sema_requests_slow).write_volatile(1) };
+        fire_slow(10);
+        fire_slow(1000);
+        unsafe { (&raw mut __usdt_sema_requests_slow).write_volatile(0) };
+        assert_eq!(PREDICATES.load(Ordering::SeqCst), 2);
+        assert_eq!(ARGUMENTS.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,146 @@
+//! Integration test verifying that a provider may be defined by the empty methods of an impl
+//! block, each firing its probe.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use serde::{Serialize, Serializer};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// The type needn't be public, and is passed to the probes of methods taking `&self` serialized.
+#[derive(Debug, Serialize)]
+struct Connection {
+    id: u64,
+    peer: String,
+}
+
+#[derive(Debug)]
+struct Packet {
+    len: usize,
+}
+
+// Counts how many times a packet is serialized, which is only when its probe is enabled.
+static SERIALIZED: AtomicUsize = AtomicUsize::new(0);
+
+impl Serialize for Packet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SERIALIZED.fetch_add(1, Ordering::SeqCst);
+        serializer.serialize_u64(self.len as u64)
+    }
+}
+
+// The provider is named `Connection`, and its macros are generated in the `connection` module.
+#[usdt::provider]
+impl Connection {
+    /// Fired when a connection is opened, with its ID.
+    fn opened(id: u64) {}
+    fn sent(&self, bytes: u64, packet: &Packet) {}
+    fn closed(&self, _: &str) {}
+    fn replaced(&self, other: &Self) {}
+}
+
+mod pool {
+    use serde::Serialize;
+
+    #[derive(Debug, Serialize)]
+    pub struct Pool {
+        pub size: usize,
+    }
+
+    // A provider renamed, and generated in a module other than the root of the crate, which is
+    // given as its path from the root.
+    #[usdt::provider(provider = "conn_pool", module = "pool::pool_probes")]
+    impl Pool {
+        pub fn resized(&self, #[default = 0] old_size: usize) {}
+    }
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let connection = Connection {
+        id: 1,
+        peer: String::from("127.0.0.1:8080"),
+    };
+    Connection::opened(connection.id);
+    connection.sent(16, &Packet { len: 16 });
+    connection.replaced(&Connection {
+        id: 2,
+        peer: String::from("127.0.0.1:8081"),
+    });
+    connection.closed("done");
+
+    let pool = pool::Pool { size: 4 };
+    pool.resized(2);
+
+    // The probes' macros are generated too, and may be fired directly where the types of their
+    // arguments are named as they are in the impl block.
+    connection::opened!(|| 3);
+    connection::sent!(|| (&connection, 0, &Packet { len: 0 }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The methods fire their probes once they're enabled, here by setting their SystemTap
+    // semaphores as a tracer would. This is one test, so that `main` doesn't fire the probes while
+    // they're enabled.
+    #[test]
+    fn test_main() {
+        main();
+        assert_eq!(SERIALIZED.load(Ordering::SeqCst), 0);
+
+        #[cfg(target_os = "linux")]
+        {
+            unsafe extern "C" {
+                static mut __usdt_sema_Connection_sent: u16;
+            }
+            let connection = Connection {
+                id: 3,
+                peer: String::from("127.0.0.1:8082"),
+            };
+            unsafe { (&raw mut __usdt_sema_Connection_sent).write_volatile(1) };
+            connection.sent(8, &Packet { len: 8 });
+            unsafe { (&raw mut __usdt_sema_Connection_sent).write_volatile(0) };
+            assert_eq!(SERIALIZED.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    // The probes are named after the methods, in a provider named after the type unless renamed.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_probe_names() {
+        let mut probes = usdt::probes()
+            .map(|probe| format!("{}:::{}", probe.provider, probe.name))
+            .collect::<Vec<_>>();
+        probes.sort();
+        assert_eq!(
+            probes,
+            [
+                "Connection:::closed",
+                "Connection:::opened",
+                "Connection:::replaced",
+                "Connection:::sent",
+                "conn_pool:::resized",
+            ]
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Integration test passing serializable probe arguments with the length of their JSON.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Payload {
+    name: String,
+}
+
+#[usdt::provider(json_len = true)]
+mod sized {
+    fn payload(_: &crate::Payload, _: u8) {}
+    fn mix(_: u32, _: &crate::Payload, _: u16) {}
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let payload = Payload {
+        name: String::from("sized"),
+    };
+    sized::payload!(|| (&payload, 1));
+    sized::mix!(|| (1, &payload, 2));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::main;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_json_is_followed_by_its_length() {
+        let probe = usdt::probes()
+            .find(|probe| probe.provider == "sized" && probe.name == "payload")
+            .expect("Expected to find probe sized:::payload");
+        let types = probe
+            .types
+            .iter()
+            .flat_map(usdt::DataType::expand)
+            .map(|ty| ty.to_c_type())
+            .collect::<Vec<_>>();
+        assert_eq!(types, ["char*", "uint64_t", "uint8_t"]);
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_sized_json_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let mut lines = output.lines().map(str::trim);
+        lines
+            .find(|line| *line == "Provider: sized")
+            .expect("Expected to find the provider sized");
+        let args = lines
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected a line containing the probe arguments");
+        assert_eq!(args, "8@%rdi 8@%rsi 1@%dl");
+    }
+
+    // The JSON and its length take the two registers following the argument before them.
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_mixed_sized_json_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let args = output
+            .lines()
+            .map(str::trim)
+            .skip_while(|line| *line != "Name: mix")
+            .find_map(|line| line.strip_prefix("Arguments: "))
+            .expect("Expected the arguments of probe sized:::mix");
+        assert_eq!(args, "4@%edi 8@%rsi 8@%rdx 2@%cx");
+    }
+}
@@ -14,6 +14,15 @@
 
 mod inner;
 
+mod telemetry {
+    // The probe macros refer to each other through the module's path from the root of the crate.
+    #[usdt::provider(module = "telemetry::http")]
+    pub mod http {
+        fn ready() {}
+        fn request(_: u8, _: &str) {}
+    }
+}
+
 fn main() {
     usdt::register_probes().expect("Could not register probes");
     // Verify that we can call the probe from its full path.
@@ -23,4 +32,36 @@ fn main() {
     // probe macro, with a link-name for a symbol that the macOS linker will generate for us. This
     // checks that there is no issue defining these locally-scoped extern symbols multiple times.
     inner::probes::am_i_visible!(|| ());
+
+    // Firing a probe without arguments, or with a fallible closure, expands to a call of the probe
+    // macro through its full path.
+    telemetry::http::ready!();
+    telemetry::http::request!(|| (0, "GET"));
+    telemetry::http::request!(try || Ok::<_, ()>((1, "POST")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::main;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_nested_module_path() {
+        let mut probes = usdt::probes()
+            .filter(|probe| probe.provider == "http")
+            .map(|probe| probe.name)
+            .collect::<Vec<_>>();
+        probes.sort();
+        assert_eq!(probes, ["ready", "request"]);
+    }
 }
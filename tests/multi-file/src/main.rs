@@ -0,0 +1,118 @@
+//! Test verifying that several D provider files are generated into a single file by the builder.
+
+// Copyright 2022 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+include!(concat!(env!("OUT_DIR"), "/probes.rs"));
+
+fn main() {
+    usdt::register_probes().unwrap();
+    net::send!(|| (0, "packet"));
+    net::recv!(|| 0);
+    net::drop!(|| 0);
+    disk::read!(|| 0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::main;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    // The `recv` probe is defined in both files, but appears once.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_probes() {
+        let mut probes = usdt::probes()
+            .map(|probe| format!("{}:::{}", probe.provider, probe.name))
+            .collect::<Vec<_>>();
+        probes.sort();
+        assert_eq!(
+            probes,
+            ["disk:::read", "net:::drop", "net:::recv", "net:::send"]
+        );
+    }
+
+    // The test executable contains exactly the probes of the manifest.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_verify_manifest() {
+        let manifest = include_str!(concat!(env!("OUT_DIR"), "/usdt-manifest.json"));
+        let exe = std::env::current_exe().unwrap();
+        let verification = usdt::verify::verify_manifest(&exe, manifest).unwrap();
+        assert!(verification.is_ok(), "{:?}", verification);
+
+        let manifest = manifest.replace("\"drop\"", "\"discard\"");
+        let verification = usdt::verify::verify_manifest(&exe, &manifest).unwrap();
+        assert_eq!(
+            verification.missing,
+            [(String::from("net"), String::from("discard"))]
+        );
+        assert_eq!(
+            verification.unexpected,
+            [(String::from("net"), String::from("drop"))]
+        );
+    }
+
+    // The manifest describes the merged providers, on every platform.
+    #[test]
+    fn test_manifest() {
+        let manifest: serde_json::Value = serde_json::from_str(include_str!(concat!(
+            env!("OUT_DIR"),
+            "/usdt-manifest.json"
+        )))
+        .unwrap();
+        assert_eq!(manifest["version"], 1);
+        let providers = manifest["providers"].as_array().unwrap();
+        let names = providers
+            .iter()
+            .map(|provider| provider["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["net", "disk"]);
+        let send = &providers[0]["probes"][0];
+        assert_eq!(send["name"], "send");
+        assert_eq!(
+            send["arguments"],
+            serde_json::json!([
+                {
+                    "name": "arg0",
+                    "c_type": "uint64_t",
+                    "type": { "kind": "integer", "signed": false, "width": 64 },
+                    "automatic": null,
+                },
+                {
+                    "name": "arg1",
+                    "c_type": "char*",
+                    "type": { "kind": "string" },
+                    "automatic": null,
+                },
+            ])
+        );
+    }
+}
@@ -0,0 +1,78 @@
+//! Integration test verifying that probes may be fired with their arguments named.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Arg {
+    pub value: u64,
+}
+
+#[usdt::provider]
+mod named {
+    use crate::Arg;
+    fn begin(id: u64) {}
+    fn stop(message: &str, arg: &Arg) {}
+    fn unnamed(_: u8, count: u8) {}
+}
+
+#[usdt::provider(auto_args = "tid")]
+mod named_auto {
+    fn tagged(id: u64, message: &str) {}
+}
+
+// Counts how many times the named arguments are evaluated.
+static EVALUATIONS: AtomicUsize = AtomicUsize::new(0);
+
+fn evaluated<T>(value: T) -> T {
+    EVALUATIONS.fetch_add(1, Ordering::SeqCst);
+    value
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let arg = Arg { value: 1 };
+    named::begin!(id = evaluated(1));
+    named::stop!(message = evaluated("done"), arg = &arg);
+    // The arguments may be named in any order, with a trailing comma.
+    named::stop!(arg = evaluated(&arg), message = "done",);
+    // Probes with unnamed parameters are still fired with a closure.
+    named::unnamed!(|| (0, 1));
+    named_auto::tagged!(message = "tagged", id = evaluated(2));
+    // The positional form keeps working.
+    named::stop!(|| ("done", &arg));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    // Without a tracer attached, the probes are disabled and the arguments must not be evaluated.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_named_arguments_not_evaluated_when_disabled() {
+        main();
+        assert_eq!(EVALUATIONS.load(Ordering::SeqCst), 0);
+    }
+}
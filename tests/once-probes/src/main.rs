@@ -0,0 +1,79 @@
+//! Integration test verifying that probes may be fired at most once per process.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[usdt::provider(once_macros = true)]
+mod once {
+    fn started(_: u8, _: &str) {}
+    fn ready() {}
+}
+
+// Counts how many times the argument closure of `started` is called.
+static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+fn fire_started() {
+    once::started_once!(|| {
+        CALLS.fetch_add(1, Ordering::SeqCst);
+        (1, "started")
+    });
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    fire_started();
+    fire_started();
+    once::ready_once!();
+    once::ready_once!(|| ());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    // Without a tracer attached, the probe is disabled, and calls don't use up its one firing.
+    // Once enabled, here by setting its SystemTap semaphore as a tracer would, it fires exactly
+    // once, however many threads race to fire it.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_fires_once_when_enabled() {
+        unsafe extern "C" {
+            static mut __usdt_sema_once_started: u16;
+        }
+        fire_started();
+        assert_eq!(CALLS.load(Ordering::SeqCst), 0);
+
+        unsafe { (&raw mut __usdt_sema_once_started).write_volatile(1) };
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        fire_started();
+                    }
+                });
+            }
+        });
+        unsafe { (&raw mut __usdt_sema_once_started).write_volatile(0) };
+        assert_eq!(CALLS.load(Ordering::SeqCst), 1);
+    }
+}
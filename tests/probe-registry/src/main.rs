@@ -0,0 +1,180 @@
+//! Integration test verifying that the compiled probes can be listed at runtime.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use usdt::UniqueId;
+
+#[usdt::provider]
+mod registry {
+    fn begin(_: &usdt::UniqueId, _: u8) {}
+    fn with__dashes(_: &str, _: &[u64; 2]) {}
+    fn json(_: &Vec<u64>) {}
+    fn never__fired() {}
+}
+
+#[usdt::provider]
+mod other {
+    fn ping(_: i32) {}
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let id = UniqueId::new();
+    registry::begin!(|| (&id, 0));
+    registry::with__dashes!(|| ("foo", [0, 1]));
+    registry::json!(|| vec![0]);
+    other::ping!(|| 0);
+
+    for probe in usdt::probes() {
+        let types = probe
+            .types
+            .iter()
+            .map(|typ| typ.to_c_type())
+            .collect::<Vec<_>>();
+        println!("{}:::{}({})", probe.provider, probe.name, types.join(", "));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use usdt::{DataType, ProbeInfo};
+
+    fn find_probe(provider: &str, name: &str) -> ProbeInfo {
+        usdt::probes()
+            .find(|probe| probe.provider == provider && probe.name == name)
+            .unwrap_or_else(|| panic!("Expected to find probe {}:::{}", provider, name))
+    }
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_probes() {
+        assert_eq!(usdt::probes().count(), 5);
+
+        let probe = find_probe("registry", "begin");
+        assert_eq!(probe.types.len(), 2);
+        assert_eq!(probe.types[0], DataType::UniqueId);
+        assert_eq!(probe.types[1].to_c_type(), "uint8_t");
+
+        let probe = find_probe("registry", "with-dashes");
+        assert_eq!(probe.types[0].to_c_type(), "char*");
+        assert!(matches!(probe.types[0], DataType::Native(_)));
+        assert!(matches!(probe.types[1], DataType::NativeArray(_, 2)));
+
+        let probe = find_probe("registry", "json");
+        let expected: syn::Type = syn::parse_str("&Vec<u64>").unwrap();
+        assert_eq!(probe.types, vec![DataType::Serializable(expected)]);
+
+        let probe = find_probe("other", "ping");
+        assert_eq!(probe.types.len(), 1);
+        assert_eq!(probe.types[0].to_c_type(), "int32_t");
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_register_probes_with_report() {
+        let registration = usdt::register_probes_with_report().unwrap();
+        assert_eq!(
+            registration,
+            usdt::Registration {
+                providers: 2,
+                probes: 5
+            }
+        );
+        // Registering again reports the same probes.
+        assert_eq!(usdt::register_probes_with_report().unwrap(), registration);
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_register_providers() {
+        usdt::register_providers(&["other"]).unwrap();
+        usdt::register_providers(&["registry", "other"]).unwrap();
+        match usdt::register_providers(&["registry", "missing", "also__missing"]) {
+            Err(usdt::Error::UnknownProviders(names)) => {
+                assert_eq!(names, ["missing", "also__missing"]);
+            }
+            result => panic!("Expected unknown providers, found {:?}", result),
+        }
+    }
+
+    // Nothing is tracing the test, so no probe is enabled.
+    #[test]
+    fn test_is_enabled() {
+        usdt::register_probes().unwrap();
+        assert!(!usdt::is_enabled("registry", "begin"));
+        assert!(!usdt::is_enabled("registry", "never-fired"));
+        assert!(!usdt::is_enabled("registry", "never__fired"));
+        assert!(!usdt::is_enabled("registry", "no-such-probe"));
+        assert!(!usdt::is_enabled("no-such-provider", "begin"));
+    }
+
+    #[test]
+    fn test_provider_enabled() {
+        usdt::register_probes().unwrap();
+        assert!(!usdt::provider_enabled("registry"));
+        // See `test_enabled_count`.
+        #[cfg(not(target_os = "linux"))]
+        assert!(!usdt::provider_enabled("other"));
+        assert!(!usdt::provider_enabled("no-such-provider"));
+    }
+
+    // The semaphore of a SystemTap probe counts the tracers attached to it, which is set here as
+    // two tracers would. As that enables the provider `other`, no other test checks it on Linux.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_enabled_count() {
+        unsafe extern "C" {
+            static mut __usdt_sema_other_ping: u16;
+        }
+        assert!(!usdt::provider_enabled("other"));
+        assert_eq!(usdt::enabled_count("other", "ping"), 0);
+
+        unsafe { (&raw mut __usdt_sema_other_ping).write_volatile(2) };
+        let count = usdt::enabled_count("other", "ping");
+        let enabled = usdt::is_enabled("other", "ping");
+        let provider_enabled = usdt::provider_enabled("other");
+        unsafe { (&raw mut __usdt_sema_other_ping).write_volatile(0) };
+        assert_eq!(count, 2);
+        assert!(enabled);
+        assert!(provider_enabled);
+
+        assert_eq!(usdt::enabled_count("other", "no-such-probe"), 0);
+        assert_eq!(usdt::enabled_count("no-such-provider", "ping"), 0);
+        assert_eq!(usdt::enabled_count("registry", "never__fired"), 0);
+    }
+}
@@ -0,0 +1,57 @@
+//! Integration test verifying that the providers of one file may each be generated in a module of
+//! their own.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+// The `disk` provider is generated in a module nested in this one, and the others at the root of
+// the crate.
+mod storage {
+    include!(concat!(env!("OUT_DIR"), "/providers.rs"));
+}
+
+use storage::{cpu, network};
+
+fn main() {
+    usdt::register_probes().unwrap();
+    network::send!(|| (1, "packet"));
+    storage::disk::read!(|| 512);
+    cpu::tick!(|| ());
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_main() {
+        super::main();
+    }
+
+    // The providers keep their names, whatever their modules.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_provider_names() {
+        let mut probes = usdt::probes()
+            .map(|probe| format!("{}:::{}", probe.provider, probe.name))
+            .collect::<Vec<_>>();
+        probes.sort();
+        assert_eq!(probes, ["cpu:::tick", "disk:::read", "net:::send"]);
+    }
+}
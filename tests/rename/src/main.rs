@@ -19,7 +19,45 @@ mod probes {
     fn something() {}
 }
 
+// Both the provider and the generated module may be renamed, independently of the name of the
+// provider module.
+#[usdt::provider(provider = "renamed", module = "renamed_probes")]
+mod _awkward_provider_module {
+    fn ping(_: u8) {}
+}
+
+// Every probe is prefixed, in its name and its macro's.
+#[usdt::provider(prefix = "http_")]
+mod server {
+    fn request(_: &str) {}
+}
+
 fn main() {
     usdt::register_probes().unwrap();
     probes::probe_something!(|| ());
+    renamed_probes::ping!(|| 0);
+    server::http_request!(|| "/");
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_main() {
+        super::main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_prefixed_probe_names() {
+        let probes = usdt::probes()
+            .filter(|probe| probe.provider == "server")
+            .map(|probe| probe.name)
+            .collect::<Vec<_>>();
+        assert_eq!(probes, ["http_request"]);
+    }
 }
@@ -0,0 +1,41 @@
+//! A provider defined once, for this crate and the crates depending on it to fire.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+pub use usdt::{probes, register_probes};
+
+#[cfg(target_os = "linux")]
+pub use usdt::stapsdt;
+
+#[usdt::provider(visibility = "pub")]
+pub mod requests {
+    use crate::Response;
+
+    fn served(_: &str, _: &Response) {}
+}
+
+#[derive(serde::Serialize)]
+pub struct Response {
+    pub status: u16,
+}
+
+/// Serve a request, firing the shared probe from this crate.
+pub fn serve(path: &str) -> Response {
+    let response = Response { status: 200 };
+    requests::served!(|| (path, &response));
+    response
+}
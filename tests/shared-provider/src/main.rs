@@ -0,0 +1,61 @@
+//! Integration test verifying that a provider defined in one crate is fired from several.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use shared_provider_probes::{requests, Response};
+
+fn main() {
+    shared_provider_probes::register_probes().unwrap();
+    shared_provider_probes::serve("/");
+    let response = Response { status: 404 };
+    requests::served!(|| ("/missing", &response));
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn test_main() {
+        super::main();
+    }
+
+    // Both crates fire the probe of the one provider.
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_single_provider() {
+        let probes = shared_provider_probes::probes()
+            .filter(|probe| probe.provider == "requests")
+            .map(|probe| probe.name)
+            .collect::<Vec<_>>();
+        assert_eq!(probes, ["served"]);
+    }
+
+    // The probe has a site in each crate firing it.
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_probe_sites_in_both_crates() {
+        let sites = shared_provider_probes::stapsdt::notes()
+            .into_iter()
+            .filter(|note| note.provider == "requests" && note.name == "served")
+            .count();
+        assert_eq!(sites, 2);
+    }
+}
@@ -0,0 +1,139 @@
+//! Integration test verifying the sign of integer probe arguments in the SystemTap notes.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// Signed integers are marked with a leading `-` in their argument size, so that consumers
+/// sign-extend negative values, while unsigned ones are not.
+#[usdt::provider]
+mod signed {
+    fn s(x: i8) {}
+    fn s16(x: i16) {}
+    fn s32(x: i32) {}
+    fn s64(x: i64) {}
+    fn u(x: u8) {}
+    fn u16(x: u16) {}
+    fn u32(x: u32) {}
+    fn u64(x: u64) {}
+    fn by_reference(x: &i32, y: &i64) {}
+    fn widths(a: u8, b: u16, c: u32, d: u64) {}
+    fn signed_widths(a: i8, b: i16, c: i32, d: i64) {}
+    fn high_registers(a: u64, b: u32, c: u16, d: u8, e: i16, f: i8) {}
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    signed::s!(|| -1);
+    signed::s16!(|| -1);
+    signed::s32!(|| -1);
+    signed::s64!(|| -1);
+    signed::u!(|| 1);
+    signed::u16!(|| 1);
+    signed::u32!(|| 1);
+    signed::u64!(|| 1);
+    let x = -1;
+    signed::by_reference!(|| (&x, &i64::MIN));
+    signed::widths!(|| (u8::MAX, u16::MAX, u32::MAX, u64::MAX));
+    signed::signed_widths!(|| (i8::MIN, i16::MIN, i32::MIN, i64::MIN));
+    signed::high_registers!(|| (1, 2, 8080, 4, -5, -6));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::main;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_integer_widths_round_trip() {
+        for (name, expected) in [
+            ("widths", ["uint8_t", "uint16_t", "uint32_t", "uint64_t"]),
+            ("signed_widths", ["int8_t", "int16_t", "int32_t", "int64_t"]),
+        ] {
+            let probe = usdt::probes()
+                .find(|probe| probe.provider == "signed" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe signed:::{}", name));
+            let types = probe
+                .types
+                .iter()
+                .map(|ty| ty.to_c_type())
+                .collect::<Vec<_>>();
+            assert_eq!(types, expected);
+        }
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_signed_argument_sizes() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+
+        // Collect the "Arguments:" line of each probe of the provider, keyed by the probe name.
+        let mut arguments = std::collections::BTreeMap::new();
+        let mut lines = output.lines().map(str::trim);
+        while let Some(line) = lines.next() {
+            if line != "Provider: signed" {
+                continue;
+            }
+            let name = lines
+                .next()
+                .and_then(|line| line.strip_prefix("Name: "))
+                .expect("Expected a line containing the probe name");
+            let args = lines
+                .find_map(|line| line.strip_prefix("Arguments: "))
+                .expect("Expected a line containing the probe arguments");
+            arguments.insert(name, args);
+        }
+        println!("{:#?}", arguments);
+
+        for (name, args) in [
+            ("s", "-1@%dil"),
+            ("s16", "-2@%di"),
+            ("s32", "-4@%edi"),
+            ("s64", "-8@%rdi"),
+            ("u", "1@%dil"),
+            ("u16", "2@%di"),
+            ("u32", "4@%edi"),
+            ("u64", "8@%rdi"),
+            ("by_reference", "-4@%edi -8@%rsi"),
+            ("widths", "1@%dil 2@%si 4@%edx 8@%rcx"),
+            ("signed_widths", "-1@%dil -2@%si -4@%edx -8@%rcx"),
+            (
+                "high_registers",
+                "8@%rdi 4@%esi 2@%dx 1@%cl -2@%r8w -1@%r9b",
+            ),
+        ] {
+            assert_eq!(
+                arguments.get(name).copied(),
+                Some(args),
+                "Arguments of probe signed:::{} are incorrect",
+                name
+            );
+        }
+    }
+}
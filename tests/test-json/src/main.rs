@@ -112,16 +112,13 @@ mod tests {
                 let mut bytes = vec![0; 128];
                 stdout.read(&mut bytes).await.map(|_| bytes)
             });
-            match read_task.await {
-                Ok(read_result) => {
-                    let chunk = read_result.expect("Failed to read tracer stdout");
-                    output.push_str(std::str::from_utf8(&chunk).expect("Non-UTF8 stdout"));
-                    if output.contains(BEGIN_SENTINEL) {
-                        println!("tracer started up successfully");
-                        return;
-                    }
+            if let Ok(read_result) = read_task.await {
+                let chunk = read_result.expect("Failed to read tracer stdout");
+                output.push_str(std::str::from_utf8(&chunk).expect("Non-UTF8 stdout"));
+                if output.contains(BEGIN_SENTINEL) {
+                    println!("tracer started up successfully");
+                    return;
                 }
-                _ => {}
             }
             println!("tracer not yet ready");
             continue;
@@ -187,7 +184,7 @@ mod tests {
             );
             let stdout = std::str::from_utf8(&output.stdout).expect("Non-UTF8 stdout");
             println!("DTrace output\n{}\n", stdout);
-            let json: Value = serde_json::from_str(&stdout).unwrap();
+            let json: Value = serde_json::from_str(stdout).unwrap();
             json
         }
 
@@ -258,7 +255,7 @@ mod tests {
             // Wait for the process to finish, up to a pretty generous limit.
             let output = tokio::time::timeout_at(now + MAX_WAIT, bpftrace.wait_with_output())
                 .await
-                .expect(&format!("bpftrace did not complete within {:?}", MAX_WAIT))
+                .unwrap_or_else(|_| panic!("bpftrace did not complete within {:?}", MAX_WAIT))
                 .expect("Failed to wait for bpftrace subprocess");
             assert!(
                 output.status.success(),
@@ -267,7 +264,7 @@ mod tests {
             );
             let stdout = std::str::from_utf8(&output.stdout).expect("Non-UTF8 stdout");
             println!("bpftrace output\n{}\n", stdout);
-            let json: Value = serde_json::from_str(&stdout).unwrap();
+            let json: Value = serde_json::from_str(stdout).unwrap();
             json
         }
 
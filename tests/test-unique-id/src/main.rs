@@ -27,6 +27,29 @@ fn main() {}
 #[cfg(test)]
 mod tests {
     use super::with_ids;
+    use usdt::UniqueId;
+
+    // Firing a probe completes before the macro returns, so the borrows of its arguments don't
+    // live across the awaits around it, and the task stays `Send`.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_probes_in_async_task() {
+        let id = UniqueId::new();
+        let task = tokio::spawn(async move {
+            with_ids::start_work!(|| &id);
+            let message = String::from("we're in a task");
+            for _ in 0..10 {
+                with_ids::waypoint_from_thread!(|| (&id, message.as_str()));
+                tokio::task::yield_now().await;
+                with_ids::waypoint_from_thread!(try || {
+                    Ok::<_, ()>((&id, message.as_str()))
+                });
+                tokio::task::yield_now().await;
+            }
+            with_ids::fire_work_finished(&id, id.as_u64());
+            id.as_u64()
+        });
+        assert_ne!(task.await.unwrap(), 0);
+    }
 
     #[cfg(not(target_os = "linux"))]
     mod dtrace {
@@ -199,5 +222,50 @@ mod tests {
 
             assert_eq!(actual_id, id.as_u64());
         }
+
+        // Only double underscores are translated into dashes, so single underscores are kept in
+        // the names of the probes' notes, as bpftrace and others look them up.
+        #[test]
+        fn test_probe_names_keep_single_underscores() {
+            let test_exe = std::env::current_exe().unwrap();
+            let output = std::process::Command::new("readelf")
+                .arg("-n")
+                .arg(&test_exe)
+                .output()
+                .expect("Could not run readelf");
+            let output = String::from_utf8_lossy(&output.stdout);
+            let names = output
+                .lines()
+                .map(str::trim)
+                .filter_map(|line| line.strip_prefix("Name: "))
+                .collect::<Vec<_>>();
+            for name in ["start_work", "waypoint_from_thread", "work_finished"] {
+                assert!(
+                    names.contains(&name),
+                    "Expected a note for the probe {}, found {:?}",
+                    name,
+                    names
+                );
+            }
+            let probes = usdt::probes()
+                .filter(|probe| probe.provider == "with_ids")
+                .map(|probe| probe.name)
+                .collect::<Vec<_>>();
+            assert!(
+                probes.iter().any(|name| name == "start_work"),
+                "{:?}",
+                probes
+            );
+            assert!(
+                probes.iter().any(|name| name == "work_finished"),
+                "{:?}",
+                probes
+            );
+
+            // The macros are named after the probes as they are, which this only compiles if so.
+            let id = UniqueId::new();
+            with_ids::start_work!(|| &id);
+            with_ids::work_finished!(|| (&id, 0));
+        }
     }
 }
@@ -0,0 +1,117 @@
+//! Integration test verifying that probes may be fired from a `Drop` running while unwinding from
+//! a panic.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![deny(warnings)]
+
+use serde::{Serialize, Serializer};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+#[derive(Debug, Serialize)]
+pub struct Arg {
+    pub values: Vec<u64>,
+}
+
+// An argument which fails to serialize, which is passed to the probe as an error message.
+#[derive(Debug)]
+pub struct Failing;
+
+impl Serialize for Failing {
+    fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+        Err(serde::ser::Error::custom("not while unwinding"))
+    }
+}
+
+#[usdt::provider]
+mod unwind {
+    use crate::{Arg, Failing};
+    fn dropped(msg: &str, arg: &Arg, what: &dyn std::fmt::Display) {}
+    fn failed(arg: &Failing) {}
+}
+
+// Counts how many times the guard fired its probes while unwinding.
+static UNWINDING_DROPS: AtomicUsize = AtomicUsize::new(0);
+
+// A guard firing probes when dropped, as a scope guard would.
+struct Guard {
+    name: &'static str,
+}
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            UNWINDING_DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+        unwind::dropped!(|| (
+            self.name,
+            Arg {
+                values: vec![1, 2, 3]
+            },
+            std::thread::panicking()
+        ));
+        unwind::failed!(|| Failing);
+    }
+}
+
+fn run(name: &'static str) {
+    let _guard = Guard { name };
+    panic!("{} panicked", name);
+}
+
+fn main() {
+    usdt::register_probes().unwrap();
+    let err = std::panic::catch_unwind(|| run("main")).unwrap_err();
+    assert_eq!(err.downcast_ref::<String>().unwrap(), "main panicked");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The probes are fired from the guard while unwinding, with their arguments built once they're
+    // enabled, here by setting their SystemTap semaphores as a tracer would. Neither the arguments
+    // nor the probes panic, which would abort the process, and the original panic is caught. This
+    // is one test, so that `main` doesn't fire the probes while they're enabled.
+    #[test]
+    fn test_main() {
+        main();
+        assert_eq!(UNWINDING_DROPS.load(Ordering::SeqCst), 1);
+
+        #[cfg(target_os = "linux")]
+        {
+            unsafe extern "C" {
+                static mut __usdt_sema_unwind_dropped: u16;
+                static mut __usdt_sema_unwind_failed: u16;
+            }
+            unsafe {
+                (&raw mut __usdt_sema_unwind_dropped).write_volatile(1);
+                (&raw mut __usdt_sema_unwind_failed).write_volatile(1);
+            }
+            let err = std::panic::catch_unwind(|| run("enabled")).unwrap_err();
+            unsafe {
+                (&raw mut __usdt_sema_unwind_dropped).write_volatile(0);
+                (&raw mut __usdt_sema_unwind_failed).write_volatile(0);
+            }
+            assert_eq!(err.downcast_ref::<String>().unwrap(), "enabled panicked");
+            assert_eq!(UNWINDING_DROPS.load(Ordering::SeqCst), 2);
+        }
+
+        // Probes may also be fired from a guard dropped on a thread unwinding from a panic which
+        // isn't caught.
+        let err = std::thread::spawn(|| run("thread")).join().unwrap_err();
+        assert_eq!(err.downcast_ref::<String>().unwrap(), "thread panicked");
+    }
+}
@@ -19,6 +19,9 @@ mod usize__test {
     fn emit_usize(_: usize) {}
     fn emit_isize(_: &isize) {}
     fn emit_u8(_: u8) {}
+
+    /// Lengths are passed with the pointer width of the target, as a `uint64_t`.
+    fn len(n: usize) {}
 }
 
 fn main() {
@@ -26,4 +29,61 @@ fn main() {
     usize__test::emit_usize!(|| 1usize);
     usize__test::emit_isize!(|| &1isize);
     usize__test::emit_u8!(|| 1);
+    usize__test::len!(|| usize::MAX);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::main;
+    use usdt::DataType;
+
+    #[test]
+    fn test_main() {
+        main();
+    }
+
+    #[cfg(any(
+        target_os = "linux",
+        target_os = "macos",
+        target_os = "illumos",
+        target_os = "freebsd"
+    ))]
+    #[test]
+    fn test_pointer_sized_types() {
+        let types = |name| {
+            usdt::probes()
+                .find(|probe| probe.provider == "usize__test" && probe.name == name)
+                .unwrap_or_else(|| panic!("Expected to find probe usize__test:::{}", name))
+                .types
+                .iter()
+                .map(DataType::to_c_type)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(types("len"), ["uint64_t"]);
+        assert_eq!(types("emit_usize"), ["uint64_t"]);
+        assert_eq!(types("emit_isize"), ["int64_t"]);
+    }
+
+    #[cfg(all(target_os = "linux", target_arch = "x86_64"))]
+    #[test]
+    fn test_pointer_sized_stapsdt_arguments() {
+        let test_exe = std::env::current_exe().unwrap();
+        let output = std::process::Command::new("readelf")
+            .arg("-n")
+            .arg(&test_exe)
+            .output()
+            .expect("Could not run readelf");
+        let output = String::from_utf8_lossy(&output.stdout);
+        let arguments = |name: &str| {
+            let name = format!("Name: {}", name);
+            output
+                .lines()
+                .map(str::trim)
+                .skip_while(|line| *line != name)
+                .find_map(|line| line.strip_prefix("Arguments: "))
+                .map(String::from)
+        };
+        assert_eq!(arguments("len").as_deref(), Some("8@%rdi"));
+        assert_eq!(arguments("emit_isize").as_deref(), Some("-8@%rdi"));
+    }
 }
@@ -14,45 +14,167 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use dtrace_parser::ProviderAttributes;
 use proc_macro2::TokenStream;
 use quote::quote;
+use serde::Deserialize;
 use serde_tokenstream::from_tokenstream;
+use syn::ext::IdentExt as _;
 use syn::spanned::Spanned;
+use syn::visit_mut::VisitMut;
 use usdt_impl::{CompileProvidersConfig, DataType, Probe, Provider};
 
-/// Generate a provider from functions defined in a Rust module.
+// The parameters of the attribute macro: the configuration used to compile the provider, and its
+// stability attributes, each written as `name/data/class`.
+#[derive(Deserialize)]
+struct ProviderParams {
+    #[serde(flatten)]
+    config: CompileProvidersConfig,
+    stability: Option<String>,
+    provider_stability: Option<String>,
+    module_stability: Option<String>,
+    function_stability: Option<String>,
+    name_stability: Option<String>,
+    args_stability: Option<String>,
+}
+
+impl ProviderParams {
+    // Return the stability attributes of the provider. `stability` sets those of every part of
+    // the provider's interface, and the others override that of a single part.
+    fn attributes(&self) -> Result<ProviderAttributes, dtrace_parser::DTraceError> {
+        let mut attributes = match &self.stability {
+            Some(stability) => ProviderAttributes::all(stability.parse()?),
+            None => ProviderAttributes::default(),
+        };
+        let overrides = [
+            ("provider", &self.provider_stability),
+            ("module", &self.module_stability),
+            ("function", &self.function_stability),
+            ("name", &self.name_stability),
+            ("args", &self.args_stability),
+        ];
+        for (entity, stability) in overrides {
+            if let Some(stability) = stability {
+                // Unwrap safety: These are all valid entities.
+                *attributes.entity_mut(entity).unwrap() = stability.parse()?;
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+/// Generate a provider from functions defined in a Rust module, or from the methods of an inherent
+/// impl block.
 #[proc_macro_attribute]
 pub fn provider(
     attr: proc_macro::TokenStream,
     item: proc_macro::TokenStream,
 ) -> proc_macro::TokenStream {
     let attr = TokenStream::from(attr);
-    match from_tokenstream::<CompileProvidersConfig>(&attr) {
-        Ok(config) => {
-            // Renaming the module via the attribute macro isn't supported.
-            if config.module.is_some() {
-                syn::Error::new(
-                    attr.span(),
-                    "The provider module may not be renamed via the attribute macro",
-                )
-                .to_compile_error()
-                .into()
-            } else {
-                generate_provider_item(TokenStream::from(item), config)
-                    .unwrap_or_else(|e| e.to_compile_error())
-                    .into()
+    match from_tokenstream::<ProviderParams>(&attr) {
+        Ok(params) => match params.attributes() {
+            Ok(attributes) => check_config_names(&params.config, &attr)
+                .and_then(|_| {
+                    generate_provider_item(TokenStream::from(item), params.config, attributes)
+                })
+                .unwrap_or_else(|e| e.to_compile_error())
+                .into(),
+            Err(e) => syn::Error::new(attr.span(), e).to_compile_error().into(),
+        },
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// Derive `usdt::VariantName` for an enum without fields, so that probe arguments of its type
+/// marked `#[variant_name]` are passed as the name of their variant.
+#[proc_macro_derive(VariantName)]
+pub fn derive_variant_name(item: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    generate_variant_name_impl(TokenStream::from(item))
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+// Implement `VariantName` for a field-less enum, matching each variant to its name.
+fn generate_variant_name_impl(item: TokenStream) -> syn::Result<TokenStream> {
+    let input: syn::DeriveInput = syn::parse2(item)?;
+    let syn::Data::Enum(ref data) = input.data else {
+        return Err(syn::Error::new(
+            input.ident.span(),
+            "`VariantName` may only be derived for enums",
+        ));
+    };
+    if let Some(variant) = data
+        .variants
+        .iter()
+        .find(|variant| !matches!(variant.fields, syn::Fields::Unit))
+    {
+        return Err(syn::Error::new(
+            variant.fields.span(),
+            "`VariantName` may only be derived for enums without fields, enums with data are \
+            serialized instead",
+        ));
+    }
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let variants = data.variants.iter().map(|variant| &variant.ident);
+    let names = data
+        .variants
+        .iter()
+        .map(|variant| variant.ident.unraw().to_string());
+    Ok(quote! {
+        impl #impl_generics ::usdt::VariantName for #ident #ty_generics #where_clause {
+            fn variant_name(&self) -> &'static str {
+                match *self {
+                    #(Self::#variants => #names,)*
+                }
             }
         }
-        Err(e) => e.to_compile_error().into(),
+    })
+}
+
+// Check that the provider and module names given as arguments to the macro are valid names for a
+// DTrace provider and a Rust module, respectively.
+fn check_config_names(config: &CompileProvidersConfig, attr: &TokenStream) -> syn::Result<()> {
+    if !config.modules.is_empty() {
+        return Err(syn::Error::new(
+            attr.span(),
+            "`modules` only applies to provider files defining several providers, use `module`",
+        ));
+    }
+    if let Some(provider) = &config.provider {
+        dtrace_parser::validate_provider_name(provider)
+            .map_err(|e| syn::Error::new(attr.span(), e))?;
     }
+    config
+        .check_module()
+        .map_err(|e| syn::Error::new(attr.span(), e))
 }
 
 // Generate the actual provider implementation, include the type-checks and probe macros.
+//
+// The provider is defined by a module of probe functions, or by an inherent impl block whose
+// empty methods are its probes.
 fn generate_provider_item(
     item: TokenStream,
+    config: CompileProvidersConfig,
+    attributes: ProviderAttributes,
+) -> Result<TokenStream, syn::Error> {
+    match syn::parse2::<syn::Item>(item)? {
+        syn::Item::Mod(mod_) => generate_module_provider(mod_, config, attributes),
+        syn::Item::Impl(impl_) => generate_impl_provider(impl_, config, attributes),
+        item => Err(syn::Error::new(
+            item.span(),
+            "Providers may only be defined by a module or an inherent impl block",
+        )),
+    }
+}
+
+// Generate a provider from a module of probe functions.
+fn generate_module_provider(
+    mod_: syn::ItemMod,
     mut config: CompileProvidersConfig,
+    attributes: ProviderAttributes,
 ) -> Result<TokenStream, syn::Error> {
-    let mod_ = syn::parse2::<syn::ItemMod>(item)?;
     if mod_.ident == "provider" {
         return Err(syn::Error::new(
             mod_.ident.span(),
@@ -70,74 +192,378 @@ fn generate_provider_item(
     let mut check_fns = Vec::new();
     let mut probes = Vec::new();
     let mut use_statements = Vec::new();
+    let aliases = collect_type_aliases(content)?;
     for (fn_index, item) in content.iter().enumerate() {
         match item {
             syn::Item::Fn(ref func) => {
-                check_probe_name(&func.sig.ident)?;
-                let signature = check_probe_function_signature(&func.sig)?;
-                let mut item_check_fns = Vec::new();
-                let mut item_types = Vec::new();
-                for (arg_index, arg) in signature.inputs.iter().enumerate() {
-                    match arg {
-                        syn::FnArg::Receiver(item) => {
-                            return Err(syn::Error::new(
-                                item.span(),
-                                "Probe functions may not take Self",
-                            ));
-                        }
-                        syn::FnArg::Typed(ref item) => {
-                            let (maybe_check_fn, item_type) =
-                                parse_probe_argument(&item.ty, fn_index, arg_index)?;
-                            if let Some(check_fn) = maybe_check_fn {
-                                item_check_fns.push(check_fn);
-                            }
-                            item_types.push(item_type);
-                        }
-                    }
-                }
-                check_fns.extend(item_check_fns);
-                probes.push(Probe {
-                    name: signature.ident.to_string(),
-                    types: item_types,
-                });
+                let signature =
+                    resolve_type_aliases(check_probe_function_signature(&func.sig)?, &aliases)?;
+                let (probe_check_fns, probe) =
+                    parse_probe_function(&signature, &func.attrs, fn_index, &config)?;
+                check_fns.extend(probe_check_fns);
+                probes.push(probe);
             }
             syn::Item::Use(ref use_statement) => {
                 verify_use_tree(&use_statement.tree)?;
                 use_statements.push(use_statement.clone());
             }
+            // Type aliases have already been collected, and are resolved in the probe arguments.
+            syn::Item::Type(_) => {}
             _ => {
                 return Err(syn::Error::new(
                     item.span(),
-                    "Provider modules may only include empty functions or use statements",
+                    "Provider modules may only include empty functions, use statements or type \
+                    aliases",
                 ));
             }
         }
     }
 
-    // We're guaranteed that the module name in the config is None. If the user has set the
-    // provider name there, extract it. If they have _not_ set the provider name there, extract the
-    // module name. In both cases, we don't support renaming the module via this path, so the
-    // module name is passed through.
-    let name = match &config.provider {
-        Some(name) => {
-            let name = name.to_string();
-            config.module = Some(mod_.ident.to_string());
-            name
+    // The provider and the generated module are both named after the provider module, unless
+    // renamed in the config. The name of the module itself needn't be a valid provider name when
+    // the provider is renamed.
+    if config.provider.is_none() {
+        let name = mod_.ident.to_string();
+        dtrace_parser::validate_provider_name(&name).map_err(|e| {
+            syn::Error::new(
+                mod_.ident.span(),
+                format!("{}, rename the provider with `provider = \"...\"`", e),
+            )
+        })?;
+        config.provider = Some(name);
+    }
+    if config.module.is_none() {
+        config.module = Some(mod_.ident.to_string());
+    }
+    let (_, compiled) = compile_provider_item(
+        probes,
+        check_fns,
+        use_statements,
+        &config,
+        attributes,
+        mod_.ident.span(),
+    )?;
+    Ok(compiled)
+}
+
+// Generate a provider from the empty methods of an inherent impl block.
+//
+// The provider is named after the type, and its macros are generated in a module named after the
+// type in snake case, next to the impl block. Each method is emitted with a body firing its probe
+// with the method's arguments. A method taking `&self` passes the value as the probe's first
+// argument, serialized like any other reference, so the type must implement `Serialize`. Other
+// receivers aren't supported, as firing a probe shouldn't consume or mutate the value.
+fn generate_impl_provider(
+    impl_: syn::ItemImpl,
+    mut config: CompileProvidersConfig,
+    attributes: ProviderAttributes,
+) -> Result<TokenStream, syn::Error> {
+    if let Some((_, path, _)) = &impl_.trait_ {
+        return Err(syn::Error::new(
+            path.span(),
+            "Providers may only be defined by inherent impl blocks, not trait impls",
+        ));
+    }
+    if !impl_.generics.params.is_empty() {
+        return Err(syn::Error::new(
+            impl_.generics.span(),
+            "Provider impl blocks may not be generic",
+        ));
+    }
+    let type_name = match &*impl_.self_ty {
+        syn::Type::Path(ty) if ty.qself.is_none() => ty.path.segments.last().and_then(|segment| {
+            matches!(segment.arguments, syn::PathArguments::None).then(|| &segment.ident)
+        }),
+        _ => None,
+    }
+    .ok_or_else(|| {
+        syn::Error::new(
+            impl_.self_ty.span(),
+            "Provider impl blocks must be for a named type without generic arguments",
+        )
+    })?;
+
+    let mut check_fns = Vec::new();
+    let mut probes = Vec::new();
+    let mut methods = Vec::new();
+    for (fn_index, item) in impl_.items.iter().enumerate() {
+        let syn::ImplItem::Fn(method) = item else {
+            return Err(syn::Error::new(
+                item.span(),
+                "Provider impl blocks may only include methods with empty bodies",
+            ));
+        };
+        if !method.block.stmts.is_empty() {
+            return Err(syn::Error::new(
+                method.block.span(),
+                "Probe methods must have empty bodies, which fire the probe",
+            ));
+        }
+        let signature =
+            probe_method_signature(check_probe_function_signature(&method.sig)?, &impl_.self_ty)?;
+        let (probe_check_fns, probe) =
+            parse_probe_function(&signature, &method.attrs, fn_index, &config)?;
+        check_fns.extend(probe_check_fns);
+        probes.push(probe);
+        methods.push(method);
+    }
+
+    if config.provider.is_none() {
+        let name = type_name.unraw().to_string();
+        dtrace_parser::validate_provider_name(&name).map_err(|e| {
+            syn::Error::new(
+                type_name.span(),
+                format!("{}, rename the provider with `provider = \"...\"`", e),
+            )
+        })?;
+        config.provider = Some(name);
+    }
+    if config.module.is_none() {
+        config.module = Some(snake_case(&type_name.unraw().to_string()));
+    }
+    let (provider, compiled) = compile_provider_item(
+        probes,
+        check_fns,
+        Vec::new(),
+        &config,
+        attributes,
+        type_name.span(),
+    )?;
+    let compiled = import_parent_items(compiled, &config.module_ident())?;
+
+    // Each method fires its probe through the probe's macro, from the generated module next to
+    // the impl block. Arguments bound by a pattern other than a name are bound to one instead.
+    let module = config.module_ident();
+    let methods = methods
+        .into_iter()
+        .zip(provider.probes.iter())
+        .map(|(method, probe)| {
+            let mut method = method.clone();
+            let macro_name = config.probe_ident(&probe.name);
+            let args = method
+                .sig
+                .inputs
+                .iter_mut()
+                .enumerate()
+                .map(|(i, input)| match input {
+                    syn::FnArg::Receiver(_) => quote! { self },
+                    syn::FnArg::Typed(arg) => {
+                        arg.attrs.clear();
+                        match &*arg.pat {
+                            syn::Pat::Ident(pat) if pat.subpat.is_none() => {
+                                let ident = &pat.ident;
+                                quote! { #ident }
+                            }
+                            _ => {
+                                let ident = quote::format_ident!("__usdt_arg_{}", i);
+                                *arg.pat = syn::parse_quote! { #ident };
+                                quote! { #ident }
+                            }
+                        }
+                    }
+                })
+                .collect::<Vec<_>>();
+            let args = match args.as_slice() {
+                [arg] => quote! { #arg },
+                _ => quote! { (#(#args),*) },
+            };
+            // A deprecated probe's method is deprecated itself, which callers are warned about.
+            method.block = syn::parse_quote! {
+                {
+                    #[allow(deprecated, clippy::redundant_closure_call)]
+                    let () = #module::#macro_name!(|| #args);
+                }
+            };
+            method
+        });
+    let attrs = &impl_.attrs;
+    let self_ty = &impl_.self_ty;
+    Ok(quote! {
+        #compiled
+        #(#attrs)*
+        impl #self_ty {
+            #(#methods)*
+        }
+    })
+}
+
+// Return the signature of a probe method as that of a probe function, with a `&self` receiver
+// taken as an argument of a reference to the type, and `Self` in the arguments' types replaced by
+// the type.
+fn probe_method_signature(
+    signature: &syn::Signature,
+    self_ty: &syn::Type,
+) -> syn::Result<syn::Signature> {
+    let mut signature = signature.clone();
+    if let Some(syn::FnArg::Receiver(receiver)) = signature.inputs.first() {
+        if receiver.reference.is_none()
+            || receiver.mutability.is_some()
+            || receiver.colon_token.is_some()
+        {
+            return Err(syn::Error::new(
+                receiver.span(),
+                "Probe methods may only take `&self`",
+            ));
+        }
+        *signature.inputs.first_mut().unwrap() = syn::parse_quote! { _: &#self_ty };
+    }
+    SelfResolver { self_ty }.visit_signature_mut(&mut signature);
+    Ok(signature)
+}
+
+// Replaces `Self` in the types of a probe method's arguments by the type of the impl block, as the
+// types are also named outside of the block.
+struct SelfResolver<'a> {
+    self_ty: &'a syn::Type,
+}
+
+impl VisitMut for SelfResolver<'_> {
+    fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+        if let syn::Type::Path(path) = ty {
+            if path.qself.is_none() && path.path.is_ident("Self") {
+                *ty = self.self_ty.clone();
+                return;
+            }
+        }
+        syn::visit_mut::visit_type_mut(self, ty);
+    }
+}
+
+// Return a type name in snake case, the name of the module generated for a provider impl block.
+fn snake_case(name: &str) -> String {
+    let mut snake = String::with_capacity(name.len());
+    let mut chars = name.chars().peekable();
+    let mut previous_lower = false;
+    while let Some(c) = chars.next() {
+        if c.is_uppercase() {
+            // A new word starts at an uppercase letter following a lowercase one, or followed by
+            // one, as in `HTTPServer`.
+            let next_lower = chars.peek().is_some_and(|next| next.is_lowercase());
+            if !snake.is_empty() && !snake.ends_with('_') && (previous_lower || next_lower) {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+            previous_lower = false;
+        } else {
+            snake.push(c);
+            previous_lower = c.is_lowercase() || c.is_ascii_digit();
+        }
+    }
+    snake
+}
+
+// Import the items of the enclosing module into the module generated for a provider impl block.
+//
+// Impl blocks can't hold use statements, so the types of the probe methods' arguments are named as
+// they are next to the impl block, which is where the methods fire the probes. The probe's fire
+// function is defined in the generated module though, so its items are imported there.
+fn import_parent_items(compiled: TokenStream, module: &syn::Ident) -> syn::Result<TokenStream> {
+    let mut file = syn::parse2::<syn::File>(compiled)?;
+    for item in file.items.iter_mut() {
+        if let syn::Item::Mod(mod_) = item {
+            if &mod_.ident == module {
+                if let Some((_, content)) = mod_.content.as_mut() {
+                    content.insert(
+                        0,
+                        syn::parse_quote! {
+                            #[allow(unused_imports)]
+                            use super::*;
+                        },
+                    );
+                }
+            }
         }
-        None => {
-            let name = mod_.ident.to_string();
-            config.provider = Some(name.clone());
-            config.module = Some(name.clone());
-            name
+    }
+    Ok(quote! { #file })
+}
+
+// Parse a probe function, returning the functions checking the types of its arguments and the
+// probe itself.
+fn parse_probe_function(
+    signature: &syn::Signature,
+    attrs: &[syn::Attribute],
+    fn_index: usize,
+    config: &CompileProvidersConfig,
+) -> syn::Result<(Vec<TokenStream>, Probe)> {
+    check_probe_name(&signature.ident)?;
+    let mut check_fns = Vec::new();
+    let mut types = Vec::new();
+    let mut names = Vec::new();
+    let mut d_types = Vec::new();
+    let mut defaults = Vec::new();
+    for (arg_index, arg) in signature.inputs.iter().enumerate() {
+        match arg {
+            syn::FnArg::Receiver(item) => {
+                return Err(syn::Error::new(
+                    item.span(),
+                    "Probe functions may not take Self",
+                ));
+            }
+            syn::FnArg::Typed(ref item) => {
+                let (maybe_check_fn, item_type) =
+                    parse_probe_argument(&item.ty, &item.attrs, fn_index, arg_index)?;
+                if let Some(check_fn) = maybe_check_fn {
+                    check_fns.push(check_fn);
+                }
+                d_types.push(parse_d_type_attr(&item_type, &item.attrs)?);
+                let default = parse_default_attr(&item.attrs)?;
+                if default.is_none() && defaults.iter().any(Option::is_some) {
+                    return Err(syn::Error::new(
+                        item.span(),
+                        "Only trailing arguments may have a `#[default]`, \
+                        so this argument needs one too",
+                    ));
+                }
+                defaults.push(default);
+                types.push(item_type);
+                names.push(match &*item.pat {
+                    syn::Pat::Ident(pat) => Some(pat.ident.to_string()),
+                    _ => None,
+                });
+            }
         }
+    }
+    fit_native_arrays(&mut types, &signature.inputs, config.auto_args.len());
+    let probe = Probe {
+        name: signature.ident.to_string(),
+        types,
+        attrs: forwarded_probe_attrs(attrs),
+        arg_names: names,
+        d_types,
+        defaults,
+        span: signature.ident.span(),
     };
+    Ok((check_fns, probe))
+}
 
+// Compile a provider of the given probes, named in the config, along with the functions checking
+// the types of their arguments. Errors are reported at the given span.
+//
+// The provider is returned too, with the names its probes are compiled with.
+fn compile_provider_item(
+    probes: Vec<Probe>,
+    check_fns: Vec<TokenStream>,
+    use_statements: Vec<syn::ItemUse>,
+    config: &CompileProvidersConfig,
+    attributes: ProviderAttributes,
+    span: proc_macro2::Span,
+) -> syn::Result<(Provider, TokenStream)> {
     let provider = Provider {
-        name,
+        // Unwrap safety: The provider is always named before it's compiled.
+        name: config.provider.clone().unwrap(),
         probes,
         use_statements: use_statements.clone(),
-    };
-    let compiled = usdt_impl::compile_provider(&provider, &config);
+        attributes,
+    }
+    .with_prefix(config.prefix.as_deref())
+    .map_err(|e| syn::Error::new(span, e))?;
+    config
+        .check_probe_names(&provider.probes)
+        .and_then(|_| config.check_probe_arguments(&provider.probes))
+        .and_then(|_| config.check_compression())
+        .and_then(|_| config.check_sections())
+        .map_err(|e| syn::Error::new(span, e))?;
+    let compiled = usdt_impl::compile_provider(&provider, config);
     let type_checks = if check_fns.is_empty() {
         quote! { const _: fn() = || {}; }
     } else {
@@ -149,117 +575,423 @@ fn generate_provider_item(
             };
         }
     };
-    Ok(quote! {
-        #type_checks
-        #compiled
-    })
+    Ok((
+        provider,
+        quote! {
+            #type_checks
+            #compiled
+        },
+    ))
 }
 
+// Check that the name of a probe function is a valid DTrace probe name.
 fn check_probe_name(ident: &syn::Ident) -> syn::Result<()> {
-    let check = |name| {
-        if ident == name {
-            Err(syn::Error::new(
-                ident.span(),
-                format!("Probe functions may not be named \"{}\"", name),
-            ))
-        } else {
-            Ok(())
-        }
-    };
-    check("probe").and(check("start"))
+    dtrace_parser::validate_probe_name(&ident.to_string())
+        .map_err(|e| syn::Error::new(ident.span(), e))
+}
+
+// Return the attributes of a probe function which should be placed on the generated probe macro.
+//
+// Doc comments and `#[deprecated]` are meaningful on a macro definition, so that `cargo doc`
+// documents the probe macros and calling a deprecated probe emits a warning. Other attributes are
+// dropped.
+fn forwarded_probe_attrs(attrs: &[syn::Attribute]) -> Vec<syn::Attribute> {
+    attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("doc") || attr.path().is_ident("deprecated"))
+        .cloned()
+        .collect()
 }
 
 fn parse_probe_argument(
     item: &syn::Type,
+    attrs: &[syn::Attribute],
     fn_index: usize,
     arg_index: usize,
 ) -> syn::Result<(Option<TokenStream>, DataType)> {
-    match item {
-        syn::Type::Path(ref path) => {
-            let last_ident = &path
-                .path
-                .segments
-                .last()
-                .ok_or_else(|| {
-                    syn::Error::new(path.span(), "Probe arguments should resolve to path types")
-                })?
-                .ident;
-            if is_simple_type(last_ident) {
-                Ok((None, data_type_from_path(&path.path, false)))
-            } else if last_ident == "UniqueId" {
-                Ok((None, DataType::UniqueId))
-            } else {
-                let check_fn = build_serializable_check_function(item, fn_index, arg_index);
-                Ok((Some(check_fn), DataType::Serializable(item.clone())))
-            }
+    let data_type = apply_argument_attrs(DataType::try_from(item)?, attrs)?;
+    // A smart pointer needn't implement `Serialize` itself, as the value it points to is serialized.
+    let check_fn = match data_type {
+        DataType::Serializable(_) => data_type
+            .serialized_type()
+            .map(|ty| build_serializable_check_function(ty, fn_index, arg_index)),
+        DataType::Discriminant(ref ty, ref int) => Some(build_discriminant_check_function(
+            ty, int, fn_index, arg_index,
+        )),
+        _ => None,
+    };
+    Ok((check_fn, data_type))
+}
+
+// Apply the attributes of a probe argument to its data type.
+//
+// These are `#[signed]`, which passes a timestamp as an `int64_t`, so that times before the epoch
+// may be represented, and `#[repr(...)]`, which passes an enum as its discriminant. The macro
+// can't see the definition of the enum, so its representation is repeated on the argument. With
+// the `prost` feature, `#[protobuf]` passes a message encoded to protobuf rather than serialized,
+// `#[native]` passes a slice of integers as its address, length and element size, and
+// `#[variant_name]` passes a field-less enum as the name of its variant. Other attributes are
+// ignored.
+fn apply_argument_attrs(typ: DataType, attrs: &[syn::Attribute]) -> syn::Result<DataType> {
+    let mut typ = typ;
+    if let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("repr")) {
+        typ = apply_repr_attr(typ, attr)?;
+    }
+    if let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("protobuf")) {
+        typ = apply_protobuf_attr(typ, attr)?;
+    }
+    if let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("native")) {
+        typ = apply_native_attr(typ, attr)?;
+    }
+    if let Some(attr) = attrs
+        .iter()
+        .find(|attr| attr.path().is_ident("variant_name"))
+    {
+        typ = apply_variant_name_attr(typ, attr)?;
+    }
+    let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("signed")) else {
+        return Ok(typ);
+    };
+    attr.meta.require_path_only()?;
+    match typ {
+        DataType::Timestamp(kind, _) => Ok(DataType::Timestamp(kind, dtrace_parser::Sign::Signed)),
+        _ => Err(syn::Error::new(
+            attr.span(),
+            "Only timestamp arguments may be `#[signed]`",
+        )),
+    }
+}
+
+// Pass an enum argument marked `#[repr(...)]` as its discriminant, an integer of the given type.
+//
+// The enum would otherwise be serialized, and a reference to it is passed the same way.
+fn apply_repr_attr(typ: DataType, attr: &syn::Attribute) -> syn::Result<DataType> {
+    let repr = attr.parse_args::<syn::Type>()?;
+    let Ok(DataType::Native(dtrace_parser::DataType::Integer(int))) = DataType::try_from(&repr)
+    else {
+        return Err(syn::Error::new(
+            repr.span(),
+            "Expected the integer type the enum is represented as",
+        ));
+    };
+    let DataType::Serializable(mut ty) = typ else {
+        return Err(syn::Error::new(
+            attr.span(),
+            "Only enum arguments may be passed as their `#[repr(...)]`",
+        ));
+    };
+    while let syn::Type::Reference(reference) = ty {
+        ty = *reference.elem;
+    }
+    if !matches!(ty, syn::Type::Path(_)) {
+        return Err(syn::Error::new(
+            ty.span(),
+            "Only enum arguments may be passed as their `#[repr(...)]`",
+        ));
+    }
+    Ok(DataType::Discriminant(ty, int))
+}
+
+// Pass a message argument marked `#[protobuf]` as its protobuf encoding, with the length of the
+// encoding.
+//
+// The message would otherwise be serialized, and a reference to it is passed the same way.
+fn apply_protobuf_attr(typ: DataType, attr: &syn::Attribute) -> syn::Result<DataType> {
+    attr.meta.require_path_only()?;
+    if !cfg!(feature = "prost") {
+        return Err(syn::Error::new(
+            attr.span(),
+            "`#[protobuf]` arguments require the `prost` feature of the `usdt` crate",
+        ));
+    }
+    let DataType::Serializable(mut ty) = typ else {
+        return Err(syn::Error::new(
+            attr.span(),
+            "Only message arguments may be passed as `#[protobuf]`",
+        ));
+    };
+    while let syn::Type::Reference(reference) = ty {
+        ty = *reference.elem;
+    }
+    if !matches!(ty, syn::Type::Path(_)) {
+        return Err(syn::Error::new(
+            ty.span(),
+            "Only message arguments may be passed as `#[protobuf]`",
+        ));
+    }
+    Ok(DataType::Protobuf(ty))
+}
+
+// Pass an enum argument marked `#[variant_name]` as the name of its variant.
+//
+// The enum would otherwise be serialized, and a reference to it is passed the same way. The enum
+// must implement `usdt::VariantName`, which is checked where the probe is fired.
+fn apply_variant_name_attr(typ: DataType, attr: &syn::Attribute) -> syn::Result<DataType> {
+    attr.meta.require_path_only()?;
+    let DataType::Serializable(mut ty) = typ else {
+        return Err(syn::Error::new(
+            attr.span(),
+            "Only enum arguments may be passed as their `#[variant_name]`",
+        ));
+    };
+    while let syn::Type::Reference(reference) = ty {
+        ty = *reference.elem;
+    }
+    if !matches!(ty, syn::Type::Path(_)) {
+        return Err(syn::Error::new(
+            ty.span(),
+            "Only enum arguments may be passed as their `#[variant_name]`",
+        ));
+    }
+    Ok(DataType::VariantName(ty))
+}
+
+// Return the default value given to an argument with `#[default = ...]`, if any.
+//
+// The default is an expression, which the probe's macro supplies when the argument closure
+// leaves the argument out, so that a provider may add arguments without changing its call sites.
+fn parse_default_attr(attrs: &[syn::Attribute]) -> syn::Result<Option<syn::Expr>> {
+    let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("default")) else {
+        return Ok(None);
+    };
+    match &attr.meta {
+        syn::Meta::NameValue(meta) => Ok(Some(meta.value.clone())),
+        meta => Err(syn::Error::new(
+            meta.span(),
+            "Expected the default value of the argument, as in `#[default = 0]`",
+        )),
+    }
+}
+
+// Return the D type given to an argument with `#[d_type = "..."]`, if any.
+//
+// DTrace presents the argument to scripts as that type, with a translator from the type it's
+// passed as, such as `my_event_t *` for a raw pointer to a struct. Only arguments passed natively,
+// as a single probe argument, may be given a D type. The name is written into the probe's record
+// as it is, so it's limited to identifiers, spaces and `*`.
+fn parse_d_type_attr(typ: &DataType, attrs: &[syn::Attribute]) -> syn::Result<Option<String>> {
+    let Some(attr) = attrs.iter().find(|attr| attr.path().is_ident("d_type")) else {
+        return Ok(None);
+    };
+    let d_type = match &attr.meta.require_name_value()?.value {
+        syn::Expr::Lit(syn::ExprLit {
+            lit: syn::Lit::Str(lit),
+            ..
+        }) => lit.value(),
+        value => {
+            return Err(syn::Error::new(
+                value.span(),
+                "Expected the name of a D type, as in `#[d_type = \"my_event_t *\"]`",
+            ))
         }
-        syn::Type::Ptr(ref pointer) => {
-            if pointer.mutability.is_some() {
-                return Err(syn::Error::new(item.span(), "Pointer types must be const"));
-            }
-            let ty = &*pointer.elem;
-            if let syn::Type::Path(ref path) = ty {
-                let last_ident = &path
-                    .path
-                    .segments
-                    .last()
-                    .ok_or_else(|| {
-                        syn::Error::new(path.span(), "Probe arguments should resolve to path types")
-                    })?
-                    .ident;
-                if !is_integer_type(last_ident) {
-                    return Err(syn::Error::new(
-                        item.span(),
-                        "Only pointers to integer types are supported",
-                    ));
+    };
+    let valid = d_type
+        .chars()
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && d_type
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | ' ' | '*'));
+    if !valid {
+        return Err(syn::Error::new(
+            attr.span(),
+            format!("\"{}\" is not a valid D type name", d_type),
+        ));
+    }
+    if matches!(typ, DataType::Serializable(_)) || typ.expand().len() != 1 {
+        return Err(syn::Error::new(
+            attr.span(),
+            "Only arguments passed natively, as a single probe argument, may have a `#[d_type]`",
+        ));
+    }
+    Ok(Some(d_type))
+}
+
+// Pass a slice or vector of integers marked `#[native]` as the address of its elements, their
+// number, and the size of each.
+//
+// The slice would otherwise be serialized, and a reference to it is passed the same way.
+fn apply_native_attr(typ: DataType, attr: &syn::Attribute) -> syn::Result<DataType> {
+    attr.meta.require_path_only()?;
+    let error = |span| {
+        syn::Error::new(
+            span,
+            "Only slices and vectors of integers may be passed as `#[native]`",
+        )
+    };
+    let DataType::Serializable(mut ty) = typ else {
+        return Err(error(attr.span()));
+    };
+    while let syn::Type::Reference(reference) = ty {
+        ty = *reference.elem;
+    }
+    let elem = match &ty {
+        syn::Type::Slice(slice) => Some(&*slice.elem),
+        syn::Type::Path(path) => path
+            .path
+            .segments
+            .last()
+            .filter(|segment| segment.ident == "Vec")
+            .and_then(|segment| match &segment.arguments {
+                syn::PathArguments::AngleBracketed(args) if args.args.len() == 1 => {
+                    match &args.args[0] {
+                        syn::GenericArgument::Type(elem) => Some(elem),
+                        _ => None,
+                    }
                 }
-                Ok((None, data_type_from_path(&path.path, true)))
+                _ => None,
+            }),
+        _ => None,
+    };
+    match elem.map(DataType::try_from) {
+        Some(Ok(DataType::Native(dtrace_parser::DataType::Integer(int)))) => {
+            Ok(DataType::NativeSlice(int))
+        }
+        _ => Err(error(ty.span())),
+    }
+}
+
+// Serialize arrays and UUIDs that would otherwise be passed natively, if the probe's arguments
+// don't all fit when passing each array element or UUID half separately, after the `reserved`
+// automatic arguments.
+//
+// Since only arrays of integers are passed natively, those always implement `Serialize` and don't
+// need a check function. UUIDs only do with the `serde` feature of the `uuid` crate.
+fn fit_native_arrays(
+    types: &mut [DataType],
+    inputs: &syn::punctuated::Punctuated<syn::FnArg, syn::token::Comma>,
+    reserved: usize,
+) {
+    let n_arguments = types.iter().map(|typ| typ.expand().len()).sum::<usize>() + reserved;
+    if n_arguments <= usdt_impl::MAX_PROBE_ARGUMENTS {
+        return;
+    }
+    for (typ, input) in types.iter_mut().zip(inputs) {
+        if let (DataType::NativeArray(..) | DataType::Uuid, syn::FnArg::Typed(arg)) = (&typ, input)
+        {
+            *typ = DataType::Serializable((*arg.ty).clone());
+        }
+    }
+}
+
+// Collect the type aliases defined in a provider module, which may not be generic.
+fn collect_type_aliases(content: &[syn::Item]) -> syn::Result<Vec<syn::ItemType>> {
+    content
+        .iter()
+        .filter_map(|item| match item {
+            syn::Item::Type(alias) => Some(alias),
+            _ => None,
+        })
+        .map(|alias| {
+            if alias.generics.params.is_empty() && alias.generics.where_clause.is_none() {
+                Ok(alias.clone())
             } else {
                 Err(syn::Error::new(
-                    item.span(),
-                    "Only pointers to path types are supported",
+                    alias.generics.span(),
+                    "Type aliases in provider modules may not be generic",
                 ))
             }
+        })
+        .collect()
+}
+
+// Return a probe function's signature, with the type aliases of the provider module in its
+// arguments replaced by the types they alias.
+//
+// The aliases are only defined in the provider module, and not in the generated one or wherever
+// the probe macros are called, so an aliased argument is handled exactly as if its type were
+// written out.
+fn resolve_type_aliases(
+    signature: &syn::Signature,
+    aliases: &[syn::ItemType],
+) -> syn::Result<syn::Signature> {
+    let mut signature = signature.clone();
+    if aliases.is_empty() {
+        return Ok(signature);
+    }
+    let mut resolver = AliasResolver {
+        aliases,
+        expanding: Vec::new(),
+        error: None,
+    };
+    for input in signature.inputs.iter_mut() {
+        if let syn::FnArg::Typed(arg) = input {
+            resolver.visit_type_mut(&mut arg.ty);
         }
-        syn::Type::Reference(ref reference) => {
-            match parse_probe_argument(&reference.elem, fn_index, arg_index)? {
-                (None, DataType::UniqueId) => Ok((None, DataType::UniqueId)),
-                (None, DataType::Native(ty)) => Ok((None, DataType::Native(ty))),
-                _ => Ok((
-                    Some(build_serializable_check_function(item, fn_index, arg_index)),
-                    DataType::Serializable(item.clone()),
-                )),
-            }
-        }
-        syn::Type::Array(_) | syn::Type::Slice(_) | syn::Type::Tuple(_) => {
-            let check_fn = build_serializable_check_function(item, fn_index, arg_index);
-            Ok((Some(check_fn), DataType::Serializable(item.clone())))
+    }
+    match resolver.error {
+        Some(e) => Err(e),
+        None => Ok(signature),
+    }
+}
+
+struct AliasResolver<'a> {
+    aliases: &'a [syn::ItemType],
+    // The aliases currently being expanded, to catch an alias which refers to itself.
+    expanding: Vec<&'a syn::Ident>,
+    error: Option<syn::Error>,
+}
+
+impl VisitMut for AliasResolver<'_> {
+    fn visit_type_mut(&mut self, ty: &mut syn::Type) {
+        let alias = match ty {
+            syn::Type::Path(path) if path.qself.is_none() => path
+                .path
+                .get_ident()
+                .and_then(|ident| self.aliases.iter().find(|alias| alias.ident == *ident)),
+            _ => None,
+        };
+        let Some(alias) = alias else {
+            return syn::visit_mut::visit_type_mut(self, ty);
+        };
+        if self.expanding.contains(&&alias.ident) {
+            self.error.get_or_insert_with(|| {
+                syn::Error::new(
+                    alias.ident.span(),
+                    format!("Type alias `{}` refers to itself", alias.ident),
+                )
+            });
+            return;
         }
-        _ => Err(syn::Error::new(
-            item.span(),
-            concat!(
-                "Probe arguments must be path types, slices, arrays, tuples, ",
-                "references, or const pointers to integers",
-            ),
-        )),
+        *ty = (*alias.ty).clone();
+        self.expanding.push(&alias.ident);
+        self.visit_type_mut(ty);
+        self.expanding.pop();
     }
 }
 
+// Verify that a use-statement in a provider module only contains absolute paths.
+//
+// The use-statements are emitted into each generated probe macro, and so are resolved wherever the
+// macro is invoked. A relative import would name a different item there, if it resolves at all.
 fn verify_use_tree(tree: &syn::UseTree) -> syn::Result<()> {
+    verify_use_tree_impl(tree, /* is_root = */ true)
+}
+
+fn verify_use_tree_impl(tree: &syn::UseTree, is_root: bool) -> syn::Result<()> {
     match tree {
         syn::UseTree::Path(ref path) => {
-            if path.ident == "super" {
+            // `self` is only relative as the leading segment, e.g., `use self::foo`, since it's
+            // also used to import a module itself in a group, as in `use std::fmt::{self}`.
+            if path.ident == "super" || (is_root && path.ident == "self") {
                 return Err(syn::Error::new(
-                    path.span(),
-                    concat!(
-                        "Use-statements in USDT macros cannot contain relative imports (`super`), ",
-                        "because the generated macros may be called from anywhere in a crate. ",
-                        "Consider using `crate` instead.",
+                    path.ident.span(),
+                    format!(
+                        concat!(
+                            "Use-statements in USDT macros cannot contain relative imports (`{}`), ",
+                            "because the generated macros may be called from anywhere in a crate. ",
+                            "Consider using `crate` instead.",
+                        ),
+                        path.ident,
                     ),
                 ));
             }
-            verify_use_tree(&path.tree)
+            verify_use_tree_impl(&path.tree, false)
         }
+        syn::UseTree::Group(ref group) => group
+            .items
+            .iter()
+            .try_for_each(|item| verify_use_tree_impl(item, is_root)),
         _ => Ok(()),
     }
 }
@@ -279,102 +1011,30 @@ where
     }
 }
 
-// Return `true` if the type is an integer
-fn is_integer_type(ident: &syn::Ident) -> bool {
-    let ident = format!("{}", ident);
-    matches!(
-        ident.as_str(),
-        "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64"
-    )
-}
-
-// Return `true` if this type is "simple", a primitive type with an analog in D, i.e., _not_ a
-// type that implements `Serialize`.
-fn is_simple_type(ident: &syn::Ident) -> bool {
-    let ident = format!("{}", ident);
-    matches!(
-        ident.as_str(),
-        "u8" | "u16"
-            | "u32"
-            | "u64"
-            | "i8"
-            | "i16"
-            | "i32"
-            | "i64"
-            | "String"
-            | "str"
-            | "usize"
-            | "isize"
-    )
-}
-
-// Return the `dtrace_parser::DataType` corresponding to the given `path`
-fn data_type_from_path(path: &syn::Path, pointer: bool) -> DataType {
-    use dtrace_parser::BitWidth;
-    use dtrace_parser::DataType as DType;
-    use dtrace_parser::Integer;
-    use dtrace_parser::Sign;
-
-    let variant = if pointer {
-        DType::Pointer
-    } else {
-        DType::Integer
-    };
-
-    if path.is_ident("u8") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Unsigned,
-            width: BitWidth::Bit8,
-        }))
-    } else if path.is_ident("u16") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Unsigned,
-            width: BitWidth::Bit16,
-        }))
-    } else if path.is_ident("u32") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Unsigned,
-            width: BitWidth::Bit32,
-        }))
-    } else if path.is_ident("u64") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Unsigned,
-            width: BitWidth::Bit64,
-        }))
-    } else if path.is_ident("i8") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Signed,
-            width: BitWidth::Bit8,
-        }))
-    } else if path.is_ident("i16") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Signed,
-            width: BitWidth::Bit16,
-        }))
-    } else if path.is_ident("i32") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Signed,
-            width: BitWidth::Bit32,
-        }))
-    } else if path.is_ident("i64") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Signed,
-            width: BitWidth::Bit64,
-        }))
-    } else if path.is_ident("String") || path.is_ident("str") {
-        DataType::Native(DType::String)
-    } else if path.is_ident("isize") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Signed,
-            width: BitWidth::Pointer,
-        }))
-    } else if path.is_ident("usize") {
-        DataType::Native(variant(Integer {
-            sign: Sign::Unsigned,
-            width: BitWidth::Pointer,
-        }))
-    } else {
-        unreachable!("Tried to parse a non-path data type");
+// Create a function that statically asserts the given type is a field-less enum, the size of the
+// integer it's passed as.
+//
+// Only field-less enums may be cast to an integer, and checking the size catches a representation
+// which doesn't match the one the enum is declared with.
+fn build_discriminant_check_function<T>(
+    ident: &T,
+    int: &dtrace_parser::Integer,
+    fn_index: usize,
+    arg_index: usize,
+) -> TokenStream
+where
+    T: quote::ToTokens,
+{
+    let fn_name = quote::format_ident!("usdt_types_must_be_repr_{}_{}", fn_index, arg_index);
+    let int = DataType::Native(dtrace_parser::DataType::Integer(*int)).to_rust_type();
+    quote! {
+        fn #fn_name(value: #ident) -> #int {
+            const _: () = assert!(
+                ::std::mem::size_of::<#ident>() == ::std::mem::size_of::<#int>(),
+                "The representation of the enum must match its `#[repr(...)]` argument"
+            );
+            value as #int
+        }
     }
 }
 
@@ -416,65 +1076,42 @@ mod tests {
     use dtrace_parser::Sign;
     use rstest::rstest;
 
-    #[test]
-    fn test_is_simple_type() {
-        assert!(is_simple_type(&quote::format_ident!("u8")));
-        assert!(!is_simple_type(&quote::format_ident!("Foo")));
-    }
-
-    #[test]
-    fn test_data_type_from_path() {
-        assert_eq!(
-            data_type_from_path(&syn::parse_str("u8").unwrap(), false),
-            DataType::Native(DType::Integer(Integer {
-                sign: Sign::Unsigned,
-                width: BitWidth::Bit8,
-            })),
-        );
-        assert_eq!(
-            data_type_from_path(&syn::parse_str("u8").unwrap(), true),
-            DataType::Native(DType::Pointer(Integer {
-                sign: Sign::Unsigned,
-                width: BitWidth::Bit8,
-            })),
-        );
-        assert_eq!(
-            data_type_from_path(&syn::parse_str("String").unwrap(), false),
-            DataType::Native(DType::String),
-        );
-        assert_eq!(
-            data_type_from_path(&syn::parse_str("String").unwrap(), false),
-            DataType::Native(DType::String),
-        );
-    }
-
-    #[test]
-    #[should_panic]
-    fn test_data_type_from_path_panics() {
-        data_type_from_path(&syn::parse_str("std::net::IpAddr").unwrap(), false);
-    }
-
     #[rstest]
     #[case("u8", DType::Integer(Integer { sign: Sign::Unsigned, width: BitWidth::Bit8 }))]
-    #[case("*const u8", DType::Pointer(Integer { sign: Sign::Unsigned, width: BitWidth::Bit8}))]
     #[case("&u8", DType::Integer(Integer { sign: Sign::Unsigned, width: BitWidth::Bit8 }))]
     #[case("&str", DType::String)]
     #[case("String", DType::String)]
     #[case("&&str", DType::String)]
     #[case("&String", DType::String)]
+    #[case("Cow<'static, str>", DType::String)]
+    #[case("&std::borrow::Cow<'_, str>", DType::String)]
+    #[case("Box<str>", DType::String)]
+    #[case("std::rc::Rc<str>", DType::String)]
+    #[case("Arc<str>", DType::String)]
     fn test_parse_probe_argument_native(#[case] name: &str, #[case] ty: dtrace_parser::DataType) {
         let arg = syn::parse_str(name).unwrap();
-        let out = parse_probe_argument(&arg, 0, 0).unwrap();
+        let out = parse_probe_argument(&arg, &[], 0, 0).unwrap();
         assert!(out.0.is_none());
         assert_eq!(out.1, DataType::Native(ty));
     }
 
+    #[rstest]
+    #[case("*const u8")]
+    #[case("*mut u8")]
+    #[case("*const std::ffi::c_void")]
+    fn test_parse_probe_argument_address(#[case] name: &str) {
+        let arg = syn::parse_str(name).unwrap();
+        let out = parse_probe_argument(&arg, &[], 0, 0).unwrap();
+        assert!(out.0.is_none());
+        assert_eq!(out.1, DataType::Address(arg));
+    }
+
     #[rstest]
     #[case("usdt::UniqueId")]
     #[case("&usdt::UniqueId")]
     fn test_parse_probe_argument_span(#[case] arg: &str) {
         let ty = syn::parse_str(arg).unwrap();
-        let out = parse_probe_argument(&ty, 0, 0).unwrap();
+        let out = parse_probe_argument(&ty, &[], 0, 0).unwrap();
         assert!(out.0.is_none());
         assert_eq!(out.1, DataType::UniqueId)
     }
@@ -486,7 +1123,7 @@ mod tests {
     #[case("&&[u8]")]
     fn test_parse_probe_argument_serializable(#[case] name: &str) {
         let ty = syn::parse_str(name).unwrap();
-        let out = parse_probe_argument(&ty, 0, 0).unwrap();
+        let out = parse_probe_argument(&ty, &[], 0, 0).unwrap();
         assert!(out.0.is_some());
         assert_eq!(out.1, DataType::Serializable(ty));
         if let (Some(chk), DataType::Serializable(ty)) = out {
@@ -495,6 +1132,287 @@ mod tests {
         }
     }
 
+    #[rstest]
+    #[case("&Arc<Arg>")]
+    #[case("std::rc::Rc<Arg>")]
+    #[case("Box<Arg>")]
+    fn test_parse_probe_argument_smart_pointer(#[case] name: &str) {
+        let ty = syn::parse_str(name).unwrap();
+        let (check_fn, out) = parse_probe_argument(&ty, &[], 0, 0).unwrap();
+        assert_eq!(out, DataType::Serializable(ty));
+        // Only the value pointed to must be serializable.
+        assert!(check_fn
+            .unwrap()
+            .to_string()
+            .contains(&quote! { usdt_types_must_be_serialize::<Arg>() }.to_string()));
+    }
+
+    #[test]
+    fn test_apply_argument_attrs() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(#[signed] _: u8) {}
+        })
+        .unwrap();
+        let syn::FnArg::Typed(ref arg) = func.sig.inputs[0] else {
+            unreachable!()
+        };
+        let timestamp = DataType::Timestamp(usdt_impl::TimestampKind::Chrono, Sign::Unsigned);
+        assert_eq!(
+            apply_argument_attrs(timestamp.clone(), &[]).unwrap(),
+            timestamp
+        );
+        assert_eq!(
+            apply_argument_attrs(timestamp, &arg.attrs).unwrap(),
+            DataType::Timestamp(usdt_impl::TimestampKind::Chrono, Sign::Signed)
+        );
+        assert!(apply_argument_attrs(DataType::UniqueId, &arg.attrs).is_err());
+    }
+
+    #[test]
+    fn test_apply_repr_attr() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(#[repr(i32)] _: &State, #[repr(State)] _: State) {}
+        })
+        .unwrap();
+        let attrs = func
+            .sig
+            .inputs
+            .iter()
+            .map(|input| match input {
+                syn::FnArg::Typed(arg) => arg.attrs.clone(),
+                syn::FnArg::Receiver(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let state: syn::Type = syn::parse_str("State").unwrap();
+        let (check_fn, typ) =
+            parse_probe_argument(&syn::parse_str("&State").unwrap(), &attrs[0], 0, 0).unwrap();
+        assert!(check_fn.is_some());
+        assert_eq!(
+            typ,
+            DataType::Discriminant(
+                state.clone(),
+                Integer {
+                    sign: Sign::Signed,
+                    width: BitWidth::Bit32
+                }
+            )
+        );
+        assert!(parse_probe_argument(&state, &attrs[1], 0, 0).is_err());
+        assert!(parse_probe_argument(&syn::parse_str("u8").unwrap(), &attrs[0], 0, 0).is_err());
+        assert!(
+            parse_probe_argument(&syn::parse_str("(u8, u8)").unwrap(), &attrs[0], 0, 0).is_err()
+        );
+    }
+
+    #[test]
+    fn test_apply_protobuf_attr() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(#[protobuf] _: &Message) {}
+        })
+        .unwrap();
+        let syn::FnArg::Typed(ref arg) = func.sig.inputs[0] else {
+            unreachable!()
+        };
+        let message: syn::Type = syn::parse_str("Message").unwrap();
+        let parsed = parse_probe_argument(&syn::parse_str("&Message").unwrap(), &arg.attrs, 0, 0);
+        if cfg!(feature = "prost") {
+            let (check_fn, typ) = parsed.unwrap();
+            assert!(check_fn.is_none());
+            assert_eq!(typ, DataType::Protobuf(message));
+            assert!(
+                parse_probe_argument(&syn::parse_str("u8").unwrap(), &arg.attrs, 0, 0).is_err()
+            );
+            assert!(
+                parse_probe_argument(&syn::parse_str("[u8]").unwrap(), &arg.attrs, 0, 0).is_err()
+            );
+        } else {
+            assert!(parsed.is_err());
+        }
+    }
+
+    #[test]
+    fn test_apply_variant_name_attr() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(#[variant_name] _: &State, #[variant_name = "x"] _: State) {}
+        })
+        .unwrap();
+        let attrs = func
+            .sig
+            .inputs
+            .iter()
+            .map(|input| match input {
+                syn::FnArg::Typed(arg) => arg.attrs.clone(),
+                syn::FnArg::Receiver(_) => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        let state: syn::Type = syn::parse_str("State").unwrap();
+        for ty in ["State", "&State", "&&State"] {
+            let (check_fn, typ) =
+                parse_probe_argument(&syn::parse_str(ty).unwrap(), &attrs[0], 0, 0).unwrap();
+            assert!(check_fn.is_none());
+            assert_eq!(typ, DataType::VariantName(state.clone()));
+        }
+        assert!(parse_probe_argument(&state, &attrs[1], 0, 0).is_err());
+        assert!(parse_probe_argument(&syn::parse_str("u8").unwrap(), &attrs[0], 0, 0).is_err());
+        assert!(
+            parse_probe_argument(&syn::parse_str("(u8, u8)").unwrap(), &attrs[0], 0, 0).is_err()
+        );
+    }
+
+    #[test]
+    fn test_generate_variant_name_impl() {
+        let generated = generate_variant_name_impl(quote! {
+            enum State { Idle, r#Busy }
+        })
+        .unwrap();
+        let expected = quote! {
+            impl ::usdt::VariantName for State {
+                fn variant_name(&self) -> &'static str {
+                    match *self {
+                        Self::Idle => "Idle",
+                        Self::r#Busy => "Busy",
+                    }
+                }
+            }
+        };
+        assert_eq!(generated.to_string(), expected.to_string());
+        for item in [
+            quote! { enum State { Idle, Busy(u8) } },
+            quote! { enum State { Idle, Busy { count: u8 } } },
+            quote! { struct State; },
+        ] {
+            assert!(generate_variant_name_impl(item).is_err());
+        }
+    }
+
+    #[test]
+    fn test_apply_native_attr() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(#[native] _: &[u32]) {}
+        })
+        .unwrap();
+        let syn::FnArg::Typed(ref arg) = func.sig.inputs[0] else {
+            unreachable!()
+        };
+        let int = Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit32,
+        };
+        for ty in ["&[u32]", "Vec<u32>", "&Vec<u32>", "&std::vec::Vec<u32>"] {
+            let (check_fn, typ) =
+                parse_probe_argument(&syn::parse_str(ty).unwrap(), &arg.attrs, 0, 0).unwrap();
+            assert!(check_fn.is_none());
+            assert_eq!(typ, DataType::NativeSlice(int), "{}", ty);
+        }
+        for ty in [
+            "u32",
+            "&[String]",
+            "Vec<Foo>",
+            "&[&str]",
+            "Option<u32>",
+            "[u32; 4]",
+        ] {
+            assert!(
+                parse_probe_argument(&syn::parse_str(ty).unwrap(), &arg.attrs, 0, 0).is_err(),
+                "{}",
+                ty
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_d_type_attr() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(#[d_type = "my_event_t *"] _: *const Event) {}
+        })
+        .unwrap();
+        let syn::FnArg::Typed(ref arg) = func.sig.inputs[0] else {
+            unreachable!()
+        };
+        for ty in ["*const Event", "u64", "&u32", "usdt::UniqueId"] {
+            let typ = DataType::try_from(&syn::parse_str::<syn::Type>(ty).unwrap()).unwrap();
+            assert_eq!(
+                parse_d_type_attr(&typ, &arg.attrs).unwrap().as_deref(),
+                Some("my_event_t *"),
+                "{}",
+                ty
+            );
+        }
+        for ty in ["&Event", "[u8; 4]"] {
+            let typ = DataType::try_from(&syn::parse_str::<syn::Type>(ty).unwrap()).unwrap();
+            assert!(parse_d_type_attr(&typ, &arg.attrs).is_err(), "{}", ty);
+        }
+        let typ = DataType::try_from(&syn::parse_str::<syn::Type>("u64").unwrap()).unwrap();
+        assert_eq!(parse_d_type_attr(&typ, &[]).unwrap(), None);
+        for attr in [
+            quote! { #[d_type = "my_event_t\\"] },
+            quote! { #[d_type = "*my_event_t"] },
+            quote! { #[d_type = ""] },
+            quote! { #[d_type(my_event_t)] },
+            quote! { #[d_type = 1] },
+        ] {
+            let func: syn::ItemFn = syn::parse2(quote! { fn probe(#attr _: u64) {} }).unwrap();
+            let syn::FnArg::Typed(ref arg) = func.sig.inputs[0] else {
+                unreachable!()
+            };
+            assert!(parse_d_type_attr(&typ, &arg.attrs).is_err(), "{}", attr);
+        }
+    }
+
+    #[test]
+    fn test_parse_default_attr() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(_: u8, #[default = 0] _: u64, #[default = "none"] _: &str, _: u8) {}
+        })
+        .unwrap();
+        let defaults = func
+            .sig
+            .inputs
+            .iter()
+            .map(|arg| {
+                let syn::FnArg::Typed(arg) = arg else {
+                    unreachable!()
+                };
+                parse_default_attr(&arg.attrs)
+                    .unwrap()
+                    .map(|expr| quote! { #expr }.to_string())
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            defaults,
+            [
+                None,
+                Some(String::from("0")),
+                Some(String::from("\"none\"")),
+                None
+            ]
+        );
+
+        let func: syn::ItemFn = syn::parse2(quote! { fn probe(#[default] _: u64) {} }).unwrap();
+        let syn::FnArg::Typed(ref arg) = func.sig.inputs[0] else {
+            unreachable!()
+        };
+        assert!(parse_default_attr(&arg.attrs).is_err());
+    }
+
+    #[test]
+    fn test_defaults_must_be_trailing() {
+        let item = quote! {
+            mod provider_with_defaults {
+                fn served(_: u8, #[default = 0] _: u64, #[default = 1] _: u64) {}
+            }
+        };
+        let result = generate_provider_item(item, Default::default(), Default::default());
+        assert!(!matches!(result, Err(e) if e.to_string().contains("Only trailing arguments")));
+        let item = quote! {
+            mod provider_with_defaults {
+                fn served(#[default = 0] _: u64, _: u8) {}
+            }
+        };
+        let err = generate_provider_item(item, Default::default(), Default::default()).unwrap_err();
+        assert!(err.to_string().contains("Only trailing arguments"));
+    }
+
     #[test]
     fn test_check_probe_function_signature() {
         let signature = syn::parse_str::<syn::Signature>("fn foo(_: u8)").unwrap();
@@ -510,6 +1428,31 @@ mod tests {
         check_is_err("fn foo(_: u8) -> u8");
     }
 
+    #[rstest]
+    #[case("probe")]
+    #[case("start")]
+    #[case("trailing__")]
+    #[case("_leading")]
+    fn test_check_probe_name_invalid(#[case] name: &str) {
+        let ident = syn::Ident::new(name, proc_macro2::Span::call_site());
+        assert!(check_probe_name(&ident).is_err());
+    }
+
+    #[test]
+    fn test_forwarded_probe_attrs() {
+        let func: syn::ItemFn = syn::parse2(quote! {
+            /// Some docs.
+            #[deprecated(note = "use another probe")]
+            #[inline]
+            fn probe() {}
+        })
+        .unwrap();
+        let attrs = forwarded_probe_attrs(&func.attrs);
+        assert_eq!(attrs.len(), 2);
+        assert!(attrs[0].path().is_ident("doc"));
+        assert!(attrs[1].path().is_ident("deprecated"));
+    }
+
     #[test]
     fn test_verify_use_tree() {
         let tokens = quote! { use std::net::IpAddr; };
@@ -523,5 +1466,143 @@ mod tests {
         let tokens = quote! { use crate::super::SomeType; };
         let item: syn::ItemUse = syn::parse2(tokens).unwrap();
         assert!(verify_use_tree(&item.tree).is_err());
+
+        let tokens = quote! { use {crate::Foo, super::SomeType}; };
+        let item: syn::ItemUse = syn::parse2(tokens).unwrap();
+        assert!(verify_use_tree(&item.tree).is_err());
+
+        let tokens = quote! { use self::SomeType; };
+        let item: syn::ItemUse = syn::parse2(tokens).unwrap();
+        assert!(verify_use_tree(&item.tree).is_err());
+
+        let tokens = quote! { use std::fmt::{self, Debug}; };
+        let item: syn::ItemUse = syn::parse2(tokens).unwrap();
+        assert!(verify_use_tree(&item.tree).is_ok());
+    }
+
+    #[test]
+    fn test_resolve_type_aliases() {
+        let module: syn::ItemMod = syn::parse2(quote! {
+            mod provider {
+                type Bytes = Vec<u8>;
+                pub type Nested = [Bytes; 2];
+            }
+        })
+        .unwrap();
+        let aliases = collect_type_aliases(&module.content.unwrap().1).unwrap();
+        let func: syn::ItemFn = syn::parse2(quote! {
+            fn probe(a: Bytes, b: &Bytes, c: Option<Nested>, d: other::Bytes) {}
+        })
+        .unwrap();
+        let signature = resolve_type_aliases(&func.sig, &aliases).unwrap();
+        let types = signature
+            .inputs
+            .iter()
+            .map(|arg| match arg {
+                syn::FnArg::Typed(arg) => {
+                    let ty = &arg.ty;
+                    quote! { #ty }.to_string()
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            vec![
+                quote! { Vec<u8> }.to_string(),
+                quote! { &Vec<u8> }.to_string(),
+                quote! { Option<[Vec<u8>; 2]> }.to_string(),
+                quote! { other::Bytes }.to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_type_aliases_errors() {
+        let module: syn::ItemMod = syn::parse2(quote! {
+            mod provider {
+                type Pair<T> = (T, T);
+            }
+        })
+        .unwrap();
+        assert!(collect_type_aliases(&module.content.unwrap().1).is_err());
+
+        let module: syn::ItemMod = syn::parse2(quote! {
+            mod provider {
+                type A = Vec<B>;
+                type B = Option<A>;
+            }
+        })
+        .unwrap();
+        let aliases = collect_type_aliases(&module.content.unwrap().1).unwrap();
+        let func: syn::ItemFn = syn::parse2(quote! { fn probe(a: A) {} }).unwrap();
+        let err = resolve_type_aliases(&func.sig, &aliases).unwrap_err();
+        assert_eq!(err.to_string(), "Type alias `A` refers to itself");
+    }
+
+    #[test]
+    fn test_probe_method_signature() {
+        let self_ty: syn::Type = syn::parse_str("Connection").unwrap();
+        let method: syn::ImplItemFn = syn::parse2(quote! {
+            fn replaced(&self, other: &Self, ids: Vec<Self>) {}
+        })
+        .unwrap();
+        let signature = probe_method_signature(&method.sig, &self_ty).unwrap();
+        let expected: syn::Signature = syn::parse2(quote! {
+            fn replaced(_: &Connection, other: &Connection, ids: Vec<Connection>)
+        })
+        .unwrap();
+        assert_eq!(signature, expected);
+
+        for receiver in [
+            quote! { self },
+            quote! { &mut self },
+            quote! { self: Box<Self> },
+        ] {
+            let method: syn::ImplItemFn = syn::parse2(quote! { fn closed(#receiver) {} }).unwrap();
+            let err = probe_method_signature(&method.sig, &self_ty).unwrap_err();
+            assert_eq!(err.to_string(), "Probe methods may only take `&self`");
+        }
+    }
+
+    #[rstest]
+    #[case("Connection", "connection")]
+    #[case("HttpServer", "http_server")]
+    #[case("HTTPServer", "http_server")]
+    #[case("Ipv4Pool", "ipv4_pool")]
+    #[case("pool", "pool")]
+    fn test_snake_case(#[case] name: &str, #[case] expected: &str) {
+        assert_eq!(snake_case(name), expected);
+    }
+
+    #[test]
+    fn test_generate_impl_provider() {
+        let item = quote! {
+            impl Connection {
+                fn opened(id: u64) {}
+                fn sent(&self, _: u64) {}
+            }
+        };
+        let tokens = generate_provider_item(item, Default::default(), Default::default())
+            .unwrap()
+            .to_string();
+        assert!(tokens.contains(&quote! { mod connection }.to_string()));
+        assert!(tokens.contains(&quote! { use super::*; }.to_string()));
+        assert!(tokens.contains(&quote! { fn opened(id: u64) }.to_string()));
+        assert!(tokens.contains(&quote! { connection::opened!(|| id) }.to_string()));
+        assert!(tokens.contains(&quote! { fn sent(&self, __usdt_arg_1: u64) }.to_string()));
+        assert!(tokens.contains(&quote! { connection::sent!(|| (self, __usdt_arg_1)) }.to_string()));
+    }
+
+    #[rstest]
+    #[case(quote! { impl Clone for Connection { fn clone(&self) {} } }, "not trait impls")]
+    #[case(quote! { impl<T> Connection<T> { fn opened() {} } }, "may not be generic")]
+    #[case(quote! { impl Connection<u8> { fn opened() {} } }, "without generic arguments")]
+    #[case(quote! { impl Connection { const ID: u8 = 0; } }, "only include methods")]
+    #[case(quote! { impl Connection { fn opened() { todo!() } } }, "must have empty bodies")]
+    #[case(quote! { struct Connection; }, "a module or an inherent impl block")]
+    fn test_generate_impl_provider_errors(#[case] item: TokenStream, #[case] message: &str) {
+        let err = generate_provider_item(item, Default::default(), Default::default()).unwrap_err();
+        assert!(err.to_string().contains(message), "{}", err);
     }
 }
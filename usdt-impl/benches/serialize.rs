@@ -0,0 +1,89 @@
+//! Benchmark serializing probe arguments, as the length of a slice of nested structs grows, and
+//! the same events held in a `serde_json::Value`, passed directly or as a `&dyn ErasedSerialize`.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Serialize;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+use usdt_impl::{to_json_probe_arg, ErasedSerialize};
+
+const ITERATIONS: usize = 100;
+
+#[derive(Serialize)]
+struct Event {
+    id: u64,
+    name: String,
+    tags: Vec<Tag>,
+}
+
+#[derive(Serialize)]
+struct Tag {
+    key: &'static str,
+    value: u32,
+}
+
+// Return a slice of `len` events.
+fn events(len: usize) -> Vec<Event> {
+    (0..len as u64)
+        .map(|id| Event {
+            id,
+            name: format!("event-{id}"),
+            tags: vec![
+                Tag {
+                    key: "first",
+                    value: id as u32,
+                },
+                Tag {
+                    key: "second",
+                    value: !(id as u32),
+                },
+            ],
+        })
+        .collect()
+}
+
+// Serialize `arg` `ITERATIONS` times, returning the elapsed time.
+fn serialize<T: ?Sized + Serialize>(arg: &T) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(to_json_probe_arg(arg, usize::MAX).len());
+    }
+    start.elapsed()
+}
+
+fn main() {
+    println!("  events  elapsed  ns/event  value ns/event  erased ns/event");
+    let mut len = 10;
+    while len <= 100_000 {
+        let events = events(len);
+        let elapsed = serialize(events.as_slice());
+        // A `Value` is written out directly, like any other serializable type, and an erased one
+        // also is, through the dynamic dispatch of `erased_serde`.
+        let value = serde_json::to_value(&events).unwrap();
+        let value_elapsed = serialize(&value);
+        let erased_elapsed = serialize(&value as &dyn ErasedSerialize);
+        let per_event = |elapsed: Duration| elapsed.as_nanos() as f64 / (len * ITERATIONS) as f64;
+        println!(
+            "{:>8}  {:>6.3}s  {:>8.2}  {:>14.2}  {:>15.2}",
+            len,
+            elapsed.as_secs_f64(),
+            per_event(elapsed),
+            per_event(value_elapsed),
+            per_event(erased_elapsed),
+        );
+        len *= 10;
+    }
+}
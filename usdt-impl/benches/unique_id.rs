@@ -0,0 +1,58 @@
+//! Benchmark the creation of unique IDs as the number of threads creating them grows.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::hint::black_box;
+use std::thread;
+use std::time::{Duration, Instant};
+use usdt_impl::UniqueId;
+
+const IDS_PER_THREAD: usize = 10_000_000;
+
+// Create `IDS_PER_THREAD` IDs in each of `n_threads` threads at once, returning the elapsed time.
+fn create_ids(n_threads: usize) -> Duration {
+    let start = Instant::now();
+    let threads = (0..n_threads)
+        .map(|_| {
+            thread::spawn(|| {
+                for _ in 0..IDS_PER_THREAD {
+                    black_box(UniqueId::new().as_u64());
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    start.elapsed()
+}
+
+fn main() {
+    let max_threads = thread::available_parallelism().map_or(1, |n| n.get());
+    println!("threads  elapsed     ns/id   Mids/s");
+    let mut n_threads = 1;
+    while n_threads <= max_threads {
+        let elapsed = create_ids(n_threads);
+        let n_ids = (n_threads * IDS_PER_THREAD) as f64;
+        println!(
+            "{:>7}  {:>7.3}s  {:>7.2}  {:>7.1}",
+            n_threads,
+            elapsed.as_secs_f64(),
+            elapsed.as_nanos() as f64 / n_ids,
+            n_ids / elapsed.as_secs_f64() / 1e6,
+        );
+        n_threads *= 2;
+    }
+}
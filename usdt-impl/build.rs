@@ -23,6 +23,8 @@ enum Backend {
     Linker,
     // SystemTap version 3 probes (read: Linux without dtrace)
     Stap3,
+    // Event Tracing for Windows
+    Etw,
     // Provide probe macros, but probes are no-ops (dtrace-less OSes)
     NoOp,
 }
@@ -33,11 +35,16 @@ fn main() {
     println!("cargo:rustc-check-cfg=cfg(usdt_backend_stapsdt)");
     println!("cargo:rustc-check-cfg=cfg(usdt_backend_linker)");
     println!("cargo:rustc-check-cfg=cfg(usdt_backend_standard)");
+    println!("cargo:rustc-check-cfg=cfg(usdt_backend_etw)");
+    println!("cargo:rustc-check-cfg=cfg(usdt_dof)");
 
+    // With the `disabled` feature, probes compile to nothing, so there's nothing to register.
     let backend = match env::var("CARGO_CFG_TARGET_OS").ok().as_deref() {
+        _ if env::var_os("CARGO_FEATURE_DISABLED").is_some() => Backend::NoOp,
         Some("macos") => Backend::Linker,
         Some("illumos") | Some("solaris") | Some("freebsd") => Backend::Standard,
         Some("linux") => Backend::Stap3,
+        Some("windows") => Backend::Etw,
         _ => Backend::NoOp,
     };
 
@@ -47,12 +54,24 @@ fn main() {
         }
         Backend::Stap3 => {
             println!("cargo:rustc-cfg=usdt_backend_stapsdt");
+            // With the `dtrace-linux` feature, the probes' DOF is registered too, on the
+            // architectures DTrace on Linux supports.
+            let arch = env::var("CARGO_CFG_TARGET_ARCH").ok();
+            if env::var_os("CARGO_FEATURE_DTRACE_LINUX").is_some()
+                && matches!(arch.as_deref(), Some("x86_64") | Some("aarch64"))
+            {
+                println!("cargo:rustc-cfg=usdt_dof");
+            }
         }
         Backend::Linker => {
             println!("cargo:rustc-cfg=usdt_backend_linker");
         }
         Backend::Standard => {
             println!("cargo:rustc-cfg=usdt_backend_standard");
+            println!("cargo:rustc-cfg=usdt_dof");
+        }
+        Backend::Etw => {
+            println!("cargo:rustc-cfg=usdt_backend_etw");
         }
     }
 }
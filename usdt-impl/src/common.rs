@@ -14,9 +14,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::DataType;
-use proc_macro2::TokenStream;
-use quote::{format_ident, quote};
+use crate::target::Arch;
+use crate::{DataType, Probe, Provider, TimestampKind, Visibility};
+use proc_macro2::{Group, Punct, Spacing, TokenStream, TokenTree};
+use quote::{format_ident, quote, quote_spanned};
 
 /// Construct a function to type-check the argument closure.
 ///
@@ -33,55 +34,23 @@ pub fn construct_type_check(
     // result of the closure to ().
     if types.is_empty() {
         return quote! {
-            let _: () = ($args_lambda)();
+            let _: () = (args_lambda)();
         };
     }
-    let type_check_params = types
+    // Arguments are checked through a reference where the parameter is generic, so that a value
+    // which isn't `Copy`, such as a `&mut T`, isn't moved into the closure before the probe reads
+    // it.
+    let (type_check_params, type_check_args): (Vec<_>, Vec<_>) = types
         .iter()
-        .map(|typ| match typ {
-            DataType::Serializable(ty) => {
-                match ty {
-                    syn::Type::Reference(reference) => {
-                        if let Some(elem) = shared_slice_elem_type(reference) {
-                            quote! { _: impl AsRef<[#elem]> }
-                        } else {
-                            let elem = &*reference.elem;
-                            quote! { _: impl ::std::borrow::Borrow<#elem> }
-                        }
-                    }
-                    syn::Type::Slice(slice) => {
-                        let elem = &*slice.elem;
-                        quote! { _: impl AsRef<[#elem]> }
-                    }
-                    syn::Type::Array(array) => {
-                        let elem = &*array.elem;
-                        quote! { _: impl AsRef<[#elem]> }
-                    }
-                    syn::Type::Path(_) => {
-                        quote! { _: impl ::std::borrow::Borrow<#ty> }
-                    }
-                    _ => {
-                        // Any other type must be specified exactly as given in the probe parameter
-                        quote! { _: #ty }
-                    }
-                }
-            }
-            DataType::Native(dtrace_parser::DataType::String) => quote! { _: impl AsRef<str> },
-            _ => {
-                let arg = typ.to_rust_type();
-                quote! { _: impl ::std::borrow::Borrow<#arg> }
-            }
-        })
-        .collect::<Vec<_>>();
-
-    // Create a list of arguments `arg.0`, `arg.1`, ... to pass to the check
-    // function.
-    let type_check_args = (0..types.len())
-        .map(|i| {
+        .enumerate()
+        .map(|(i, typ)| {
             let index = syn::Index::from(i);
-            quote! { args.#index }
+            match type_check_param(typ) {
+                (param, true) => (quote! { _: &#param }, quote! { &args.#index }),
+                (param, false) => (quote! { _: #param }, quote! { args.#index }),
+            }
         })
-        .collect::<Vec<_>>();
+        .unzip();
 
     let type_check_fn = format_ident!("__usdt_private_{}_{}_type_check", provider_name, probe_name);
     quote! {
@@ -93,6 +62,60 @@ pub fn construct_type_check(
     }
 }
 
+// Return an expression checking whether a probe is enabled by the given check, and probes weren't
+// disabled when they were registered.
+//
+// Backends whose probes may be enabled without being registered, such as SystemTap's, check this
+// themselves. The check is only made once the probe is enabled, so it costs nothing otherwise.
+pub(crate) fn unless_probes_disabled(check: TokenStream) -> TokenStream {
+    quote! { (#check && !::usdt::probes_disabled()) }
+}
+
+// Return the type of the parameter checking an argument of the given type, and whether it's
+// generic, in which case the argument is checked through a reference.
+fn type_check_param(typ: &DataType) -> (TokenStream, bool) {
+    match typ {
+        DataType::Serializable(ty) | DataType::SizedSerializable(ty) => match ty {
+            // A trait object is passed by coercing a reference to it.
+            syn::Type::Reference(reference)
+                if matches!(*reference.elem, syn::Type::TraitObject(_)) =>
+            {
+                (quote! { #ty }, false)
+            }
+            syn::Type::Reference(reference) => {
+                if let Some(elem) = shared_slice_elem_type(reference) {
+                    (quote! { impl AsRef<[#elem]> }, true)
+                } else {
+                    let elem = &*reference.elem;
+                    (quote! { impl ::std::borrow::Borrow<#elem> }, true)
+                }
+            }
+            syn::Type::Slice(slice) => {
+                let elem = &*slice.elem;
+                (quote! { impl AsRef<[#elem]> }, true)
+            }
+            syn::Type::Array(array) => {
+                let elem = &*array.elem;
+                (quote! { impl AsRef<[#elem]> }, true)
+            }
+            syn::Type::Path(_) => (quote! { impl ::std::borrow::Borrow<#ty> }, true),
+            // Any other type must be specified exactly as given in the probe parameter
+            _ => (quote! { #ty }, false),
+        },
+        DataType::Native(dtrace_parser::DataType::String) => (quote! { impl AsRef<str> }, true),
+        DataType::Path => (quote! { impl AsRef<::std::path::Path> }, true),
+        DataType::Display => (quote! { impl ::std::fmt::Display }, true),
+        DataType::NativeSlice(int) => {
+            let elem = DataType::Native(dtrace_parser::DataType::Integer(*int)).to_rust_type();
+            (quote! { impl AsRef<[#elem]> }, true)
+        }
+        _ => {
+            let arg = typ.to_rust_type();
+            (quote! { impl ::std::borrow::Borrow<#arg> }, true)
+        }
+    }
+}
+
 fn shared_slice_elem_type(reference: &syn::TypeReference) -> Option<&syn::Type> {
     if let syn::Type::Slice(slice) = &*reference.elem {
         Some(&*slice.elem)
@@ -103,185 +126,1150 @@ fn shared_slice_elem_type(reference: &syn::TypeReference) -> Option<&syn::Type>
 
 // Return code to destructure a probe arguments into identifiers, and to pass those to ASM
 // registers.
-pub fn construct_probe_args(types: &[DataType]) -> (TokenStream, TokenStream) {
+pub fn construct_probe_args(
+    types: &[DataType],
+    arch: Arch,
+    config: &crate::CompileProvidersConfig,
+) -> (TokenStream, TokenStream) {
+    let mut registers = abi_registers(types, arch).iter();
+    let mut spills = Vec::new();
+    // Here, we convert the argument to store it within a register, or one
+    // register per element for arrays.
+    let (unpacked_args, in_regs) =
+        unpack_probe_args(types, config, &register_type(arch), |typ, value| {
+            let reg = registers.next().unwrap();
+            let value = pass_in_register(typ, value, arch, &mut spills);
+            quote! { in(#reg) #value }
+        });
+    let in_regs = quote! { #(#in_regs,)* };
+    let unpacked_args = quote! {
+        #unpacked_args
+        #(#spills)*
+    };
+    (unpacked_args, in_regs)
+}
+
+// Return the type of the values passed in registers, into which integers are converted.
+//
+// Registers are 64 bits wide on all supported architectures but 32-bit Arm, where the integers are
+// converted to `u64` instead, so that 64-bit values are passed in full.
+fn register_type(arch: Arch) -> TokenStream {
+    match arch {
+        Arch::Arm => quote! { u64 },
+        _ => quote! { usize },
+    }
+}
+
+// Return the expression passing a value of the given type, of the architecture's register type, in
+// a register.
+//
+// A 64-bit value doesn't fit in a register on 32-bit Arm, so it's spilled to the stack, and its
+// address passed in its register instead, for the tracer to read it from memory. The statements
+// spilling the values are appended to `spills`, and must precede the ASM firing the probe.
+fn pass_in_register(
+    typ: &DataType,
+    value: TokenStream,
+    arch: Arch,
+    spills: &mut Vec<TokenStream>,
+) -> TokenStream {
+    match arch {
+        Arch::Arm if crate::stapsdt::args::is_spilled(typ, arch) => {
+            let spill = format_ident!("spill_{}", spills.len());
+            spills.push(quote! { let #spill: u64 = #value as u64; });
+            quote! { (&raw const #spill as usize) }
+        }
+        Arch::Arm => quote! { (#value as usize) },
+        _ => value,
+    }
+}
+
+/// Return the options of the ASM firing a probe with arguments of the given types.
+///
+/// These are a promise to the compiler about what the ASM does, which each lowering must keep:
+///
+/// - The ASM doesn't write to memory, push to the stack, or change the flags.
+/// - If it reads no memory, it's `nomem`. Otherwise it's `readonly`, so that the compiler writes
+///   any memory the ASM reads before it runs, rather than assuming it's unread and eliding or
+///   deferring the writes.
+///
+/// The probe's ASM itself is a `nop`, but a tracer reads the memory its arguments point to when
+/// the probe fires: strings, JSON, and the integers behind pointers. Probes passing any of these
+/// are therefore `readonly`, while those passing only values are `nomem`. A lowering whose ASM
+/// does more than fire the probe must declare options covering that too.
+pub fn asm_options(types: &[DataType]) -> TokenStream {
+    if types.iter().any(reads_memory) {
+        quote! { options(readonly, nostack, preserves_flags) }
+    } else {
+        quote! { options(nomem, nostack, preserves_flags) }
+    }
+}
+
+// Return `true` if a tracer reads memory an argument of this type points to.
+//
+// The pointee of a raw pointer passed as its address is never read.
+fn reads_memory(typ: &DataType) -> bool {
+    match typ {
+        DataType::Native(dtrace_parser::DataType::Integer(_))
+        | DataType::NativeArray(..)
+        | DataType::UniqueId
+        | DataType::Timestamp(..)
+        | DataType::Duration
+        | DataType::Uuid
+        | DataType::Address(_)
+        | DataType::Discriminant(..)
+        | DataType::NonZero(_) => false,
+        DataType::Native(dtrace_parser::DataType::Pointer(_))
+        | DataType::Native(dtrace_parser::DataType::String)
+        | DataType::Path
+        | DataType::Display
+        | DataType::VariantName(_)
+        | DataType::Serializable(_)
+        | DataType::SizedSerializable(_)
+        | DataType::Protobuf(_)
+        | DataType::NativeSlice(_) => true,
+    }
+}
+
+// Return code to destructure probe arguments into identifiers, and code firing the probe, given
+// the code passing those to ASM registers, and the options of the ASM, to `fire`.
+//
+// When `dedupe_probes` is set in the config, the probe is fired from a function of its own. As that function isn't
+// generic, even when the probe is fired in a generic function, its ASM, and so the probe's record,
+// is emitted once, rather than once per monomorphization of the enclosing function.
+pub fn construct_probe_firing(
+    types: &[DataType],
+    arch: Arch,
+    config: &crate::CompileProvidersConfig,
+    fire: impl FnOnce(TokenStream, TokenStream) -> TokenStream,
+) -> (TokenStream, TokenStream) {
+    // The tracer reads any values spilled to the stack from memory.
+    let spills = types
+        .iter()
+        .flat_map(DataType::expand)
+        .any(|typ| crate::stapsdt::args::is_spilled(&typ, arch));
+    let options = if spills {
+        quote! { options(readonly, nostack, preserves_flags) }
+    } else {
+        asm_options(types)
+    };
+    if !config.dedupe_probes {
+        let (unpacked_args, in_regs) = construct_probe_args(types, arch, config);
+        return (unpacked_args, fire(in_regs, options));
+    }
+    let registers = abi_registers(types, arch);
+    let mut spills = Vec::new();
+    let (unpacked_args, passed_args) =
+        unpack_probe_args(types, config, &register_type(arch), |typ, value| {
+            pass_in_register(typ, value, arch, &mut spills)
+        });
+    let unpacked_args = quote! {
+        #unpacked_args
+        #(#spills)*
+    };
+    let params = (0..passed_args.len())
+        .map(|i| format_ident!("arg_{}", i))
+        .collect::<Vec<_>>();
+    let in_regs = registers
+        .iter()
+        .zip(&params)
+        .map(|(reg, param)| quote! { in(#reg) #param });
+    let fire = fire(quote! { #(#in_regs,)* }, options);
+    let firing = quote! {
+        #[inline(never)]
+        fn __usdt_fire_probe(#(#params: usize),*) {
+            #fire
+        }
+        __usdt_fire_probe(#(#passed_args),*);
+    };
+    (unpacked_args, firing)
+}
+
+// Return the registers the arguments of a probe are passed in.
+fn abi_registers(types: &[DataType], arch: Arch) -> &'static [&'static str] {
     // x86_64 passes the first 6 arguments in registers, with the rest on the stack.
     // We limit this to 6 arguments in all cases for now, as handling those stack
     // arguments would be challenging with the current `asm!` macro implementation.
-    #[cfg(target_arch = "x86_64")]
-    let abi_regs = ["rdi", "rsi", "rdx", "rcx", "r8", "r9"];
-    #[cfg(target_arch = "aarch64")]
-    let abi_regs = ["x0", "x1", "x2", "x3", "x4", "x5"];
-    #[cfg(not(any(target_arch = "aarch64", target_arch = "x86_64")))]
-    compile_error!("USDT only supports x86_64 and ARM64 architectures");
+    let abi_regs: &[&str] = match arch {
+        Arch::X86_64 => &["rdi", "rsi", "rdx", "rcx", "r8", "r9"],
+        Arch::AArch64 => &["x0", "x1", "x2", "x3", "x4", "x5"],
+        Arch::RiscV64 => &["a0", "a1", "a2", "a3", "a4", "a5"],
+        // 32-bit Arm passes only 4 arguments in registers. Unlike a call though, the probe's
+        // operands may be in any register its record names, so the rest are passed in registers
+        // too, rather than on the stack.
+        Arch::Arm => &["r0", "r1", "r2", "r3", "r4", "r5"],
+    };
 
+    let n_registers = types.iter().map(|typ| typ.expand().len()).sum::<usize>();
     assert!(
-        types.len() <= abi_regs.len(),
+        n_registers <= crate::MAX_PROBE_ARGUMENTS,
         "Up to 6 probe arguments are currently supported"
     );
-    let (unpacked_args, in_regs): (Vec<_>, Vec<_>) = types
-        .iter()
-        .zip(&abi_regs)
-        .enumerate()
-        .map(|(i, (typ, reg))| {
-            let arg = format_ident!("arg_{}", i);
-            let index = syn::Index::from(i);
-            let input = quote! { args.#index };
-            let (value, at_use) = asm_type_convert(typ, input);
-
-            // These values must refer to the actual traced data and prevent it
-            // from being dropped until after we've completed the probe
-            // invocation.
-            let destructured_arg = quote! {
-                let #arg = #value;
-            };
-            // Here, we convert the argument to store it within a register.
-            let register_arg = quote! { in(#reg) (#arg #at_use) };
+    abi_regs
+}
 
-            (destructured_arg, register_arg)
-        })
-        .unzip();
+// Return code to destructure probe arguments into identifiers, and the expressions passing those
+// to a function firing the probe via FFI, one per parameter of the function.
+pub fn construct_probe_ffi_args(
+    types: &[DataType],
+    config: &crate::CompileProvidersConfig,
+) -> (TokenStream, Vec<TokenStream>) {
+    unpack_probe_args(types, config, &quote! { usize }, |typ, value| {
+        let ty = typ.to_rust_ffi_type();
+        quote! { #value as #ty }
+    })
+}
+
+// Destructure the probe arguments into identifiers, returning that code and the result of
+// `pass_arg` for each argument the probe is fired with, given its type and its value as a `word`.
+pub fn unpack_probe_args(
+    types: &[DataType],
+    config: &crate::CompileProvidersConfig,
+    word: &TokenStream,
+    mut pass_arg: impl FnMut(&DataType, TokenStream) -> TokenStream,
+) -> (TokenStream, Vec<TokenStream>) {
+    let mut unpacked_args = Vec::new();
+    let mut passed_args = Vec::new();
+    for (i, typ) in types.iter().enumerate() {
+        let arg = format_ident!("arg_{}", i);
+        let index = syn::Index::from(i);
+        let input = quote! { args.#index };
+        let (value, at_uses) = asm_type_convert(
+            typ,
+            input,
+            config.max_serialized_len(),
+            config.compression,
+            config.frame_arguments,
+            &config.rename_json_keys,
+            word,
+        );
+
+        // A value passed in a single register as it is needn't be kept alive, and is passed
+        // directly, so that a probe with only native arguments does nothing but move them into
+        // registers.
+        if let [at_use] = at_uses.as_slice() {
+            if at_use.is_empty() {
+                passed_args.push(pass_arg(typ, value));
+                continue;
+            }
+        }
+
+        // These values must refer to the actual traced data and prevent it
+        // from being dropped until after we've completed the probe
+        // invocation.
+        unpacked_args.push(quote! {
+            let #arg = #value;
+        });
+        for (elem, at_use) in typ.expand().iter().zip(at_uses) {
+            passed_args.push(pass_arg(elem, quote! { (#arg #at_use) }));
+        }
+    }
     let arg_lambda = call_argument_closure(types);
     let unpacked_args = quote! {
         #arg_lambda
         #(#unpacked_args)*
     };
-    let in_regs = quote! { #(#in_regs,)* };
-    (unpacked_args, in_regs)
+    (unpacked_args, passed_args)
 }
 
-/// Call the argument closure, assigning its output to `args`.
+/// Call the argument closure bound to `args_lambda`, assigning its output to `args`.
 pub fn call_argument_closure(types: &[DataType]) -> TokenStream {
     match types.len() {
         // Don't bother with any closure if there are no arguments.
         0 => quote! {},
         // Wrap a single argument in a tuple.
-        1 => quote! { let args = (($args_lambda)(),); },
+        1 => quote! { let args = ((args_lambda)(),); },
         // General case.
-        _ => quote! { let args = ($args_lambda)(); },
+        _ => quote! { let args = (args_lambda)(); },
     }
 }
 
 // Convert a supported data type to 1. a type to store for the duration of the
 // probe invocation and 2. a transformation for compatibility with an asm
-// register.
-fn asm_type_convert(typ: &DataType, input: TokenStream) -> (TokenStream, TokenStream) {
+// register, for each register the type is passed in.
+//
+// Serializable types are converted to JSON of at most `max_serialized_len` bytes, which is then
+// compressed if `compression` is given, or else framed if `frame` is set, with the keys in
+// `renames` renamed. Protobuf messages are encoded in full, and never compressed. Integers are
+// converted to `word`, the type of a register, or `u64` where 64-bit values don't fit in one.
+fn asm_type_convert(
+    typ: &DataType,
+    input: TokenStream,
+    max_serialized_len: usize,
+    compression: Option<crate::Compression>,
+    frame: bool,
+    renames: &[(String, String)],
+    word: &TokenStream,
+) -> (TokenStream, Vec<TokenStream>) {
     match typ {
-        DataType::Serializable(_) => (
-            // Convert the input to JSON. This is a fallible operation, however, so we wrap the
-            // data in a result-like JSON blob, mapping the `Result`'s variants to the keys "ok"
+        DataType::Serializable(_) | DataType::SizedSerializable(_) => {
+            // Convert the input to JSON. This is a fallible operation, however, so the data is
+            // wrapped in a result-like JSON blob, mapping the `Result`'s variants to the keys "ok"
             // and "err".
+            // A smart pointer is serialized as the value it points to, in place, and key/value
+            // pairs as an object.
+            let value = match crate::smart_pointer_type(&typ.to_rust_type()) {
+                Some((pointer, _)) => {
+                    quote! { &**<_ as ::std::borrow::Borrow<#pointer>>::borrow(&#input) }
+                }
+                None if crate::is_fields_type(&typ.to_rust_type()) => {
+                    quote! { &::usdt::SerializeFields(&#input) }
+                }
+                None => quote! { &#input },
+            };
+            let value = if renames.is_empty() {
+                value
+            } else {
+                let (from, to): (Vec<_>, Vec<_>) = renames.iter().cloned().unzip();
+                quote! { &::usdt::RenameKeys(#value, &[#((#from, #to)),*]) }
+            };
+            let json = quote! {
+                ::usdt::to_json_probe_arg(#value, #max_serialized_len)
+            };
+            let mut at_uses = vec![quote! { .as_ptr() as #word }];
+            if matches!(typ, DataType::SizedSerializable(_)) {
+                // The length of the argument, framed, compressed or not, leaves out any null byte.
+                at_uses.push(quote! { .arg_len() });
+            }
+            (
+                match compression {
+                    Some(compression) => compression.compress(json),
+                    None if frame => quote! {
+                        ::usdt::to_framed_json_probe_arg(#value, #max_serialized_len)
+                    },
+                    None => json,
+                },
+                at_uses,
+            )
+        }
+        DataType::Protobuf(ty) => (
             quote! {
-                [
-                    match ::usdt::to_json(&#input) {
-                        Ok(json) => format!("{{\"ok\":{}}}", json),
-                        Err(e) => format!("{{\"err\":\"{}\"}}", e.to_string()),
-                    }.as_bytes(),
-                    &[0_u8]
-                ].concat()
+                ::prost::Message::encode_to_vec(<_ as ::std::borrow::Borrow<#ty>>::borrow(&#input))
             },
-            quote! { .as_ptr() as usize },
+            vec![quote! { .as_ptr() as #word }, quote! { .len() as #word }],
         ),
+        // The tracer copies in the elements, the length times the element size in bytes.
+        DataType::NativeSlice(int) => {
+            let elem = DataType::Native(dtrace_parser::DataType::Integer(*int)).to_rust_type();
+            (
+                quote! {
+                    {
+                        let slice = <_ as AsRef<[#elem]>>::as_ref(&#input);
+                        (slice.as_ptr(), slice.len(), ::std::mem::size_of::<#elem>())
+                    }
+                },
+                vec![quote! { .0 as #word }, quote! { .1 }, quote! { .2 }],
+            )
+        }
         DataType::Native(dtrace_parser::DataType::String) => (
             quote! {
-                [(#input.as_ref() as &str).as_bytes(), &[0_u8]].concat()
+                ::usdt::to_str_probe_arg(#input.as_ref() as &str)
             },
-            quote! { .as_ptr() as usize },
+            vec![quote! { .as_ptr() as #word }],
+        ),
+        DataType::Path => (
+            quote! {
+                ::usdt::to_path_probe_arg(#input.as_ref() as &::std::path::Path)
+            },
+            vec![quote! { .as_ptr() as #word }],
+        ),
+        DataType::Display => (
+            quote! {
+                ::usdt::to_display_probe_arg(&#input)
+            },
+            vec![quote! { .as_ptr() as #word }],
+        ),
+        DataType::VariantName(ty) => (
+            quote! {
+                ::usdt::to_str_probe_arg(::usdt::VariantName::variant_name(
+                    <_ as ::std::borrow::Borrow<#ty>>::borrow(&#input)
+                ))
+            },
+            vec![quote! { .as_ptr() as #word }],
         ),
         DataType::Native(_) => {
             let ty = typ.to_rust_type();
             (
-                quote! { (*<_ as ::std::borrow::Borrow<#ty>>::borrow(&#input) as usize) },
-                quote! {},
+                quote! { (*<_ as ::std::borrow::Borrow<#ty>>::borrow(&#input) as #word) },
+                vec![quote! {}],
+            )
+        }
+        DataType::NativeArray(_, len) => {
+            let ty = typ.to_rust_type();
+            (
+                quote! { *<_ as ::std::borrow::Borrow<#ty>>::borrow(&#input) },
+                (0..*len)
+                    .map(|i| {
+                        let index = syn::Index::from(i);
+                        quote! { [#index] as #word }
+                    })
+                    .collect(),
+            )
+        }
+        DataType::UniqueId => (quote! { (#input.as_u64() as #word) }, vec![quote! {}]),
+        DataType::NonZero(_) => {
+            let ty = typ.to_rust_type();
+            (
+                quote! { (<_ as ::std::borrow::Borrow<#ty>>::borrow(&#input).get() as #word) },
+                vec![quote! {}],
+            )
+        }
+        // Only the address is passed, so the pointee is never read.
+        DataType::Address(_) => {
+            let ty = typ.to_rust_type();
+            (
+                quote! { ((*<_ as ::std::borrow::Borrow<#ty>>::borrow(&#input)).cast::<()>() as #word) },
+                vec![quote! {}],
+            )
+        }
+        // The discriminant is read through a reference, so that the enum needn't be `Copy`. The
+        // provider checks that the enum is field-less, and the size of its representation. It's
+        // read before firing the probe, outside the unsafe block of the ASM.
+        DataType::Discriminant(_, int) => {
+            let ty = typ.to_rust_type();
+            let int = DataType::Native(dtrace_parser::DataType::Integer(*int)).to_rust_type();
+            (
+                quote! {
+                    unsafe {
+                        ::std::ptr::read(
+                            (<_ as ::std::borrow::Borrow<#ty>>::borrow(&#input) as *const #ty)
+                                .cast::<#int>()
+                        )
+                    }
+                },
+                vec![quote! { as #word }],
             )
         }
-        DataType::UniqueId => (quote! { #input.as_u64() as usize }, quote! {}),
+        DataType::Uuid => (
+            // The most and least significant halves of the UUID, as a big-endian integer.
+            quote! {
+                {
+                    let uuid = #input.as_u128();
+                    ((uuid >> 64) as u64, uuid as u64)
+                }
+            },
+            vec![quote! { .0 as #word }, quote! { .1 as #word }],
+        ),
+        DataType::Timestamp(kind, sign) => {
+            // The nanoseconds since the epoch, saturated to an `i64`.
+            let nanos = match kind {
+                TimestampKind::Chrono => quote! {
+                    (match #input.timestamp_nanos_opt() {
+                        Some(nanos) => nanos,
+                        None if #input.timestamp() < 0 => i64::MIN,
+                        None => i64::MAX,
+                    })
+                },
+                TimestampKind::Time => quote! {
+                    (#input.unix_timestamp_nanos().clamp(i64::MIN as i128, i64::MAX as i128) as i64)
+                },
+            };
+            let nanos = match sign {
+                dtrace_parser::Sign::Signed => nanos,
+                dtrace_parser::Sign::Unsigned => quote! { (#nanos.max(0) as u64) },
+            };
+            (quote! { (#nanos as #word) }, vec![quote! {}])
+        }
+        // The nanoseconds of the duration, saturated to a `u64`, which holds over 584 years.
+        DataType::Duration => (
+            quote! {
+                (u64::try_from(
+                    <_ as ::std::borrow::Borrow<::std::time::Duration>>::borrow(&#input).as_nanos()
+                )
+                .unwrap_or(u64::MAX) as #word)
+            },
+            vec![quote! {}],
+        ),
     }
 }
 
 /// Create the top-level probe macro.
 ///
 /// This takes the implementation block constructed elsewhere, and builds out
-/// the actual macro users call in their code to fire the probe.
+/// the actual macro users call in their code to fire the probe. Any attributes
+/// of the probe, such as doc comments, are placed on the macro definition.
+///
+/// The `is_enabled` expression is used to only call fallible argument closures,
+/// passed as `probe!(try || ...)`, when the probe is enabled.
 pub(crate) fn build_probe_macro(
     config: &crate::CompileProvidersConfig,
-    probe_name: &str,
-    types: &[DataType],
+    probe: &Probe,
+    is_enabled: TokenStream,
     impl_block: TokenStream,
 ) -> TokenStream {
-    let module = config.module_ident();
-    let macro_name = config.probe_ident(probe_name);
-    let no_args_match = if types.is_empty() {
-        quote! { () => { crate::#module::#macro_name!(|| ()) }; }
+    let module = config.module_path();
+    let macro_name = config.probe_ident(&probe.name);
+    let attrs = &probe.attrs;
+    // The automatic arguments follow the probe's own, which are all the caller passes.
+    let n_args = probe.types.len() - config.auto_args.len();
+    let no_args_match = if n_args == 0 {
+        // The probe may be deprecated, but the call site has already been warned about that.
+        quote! {
+            () => {
+                {
+                    #[allow(deprecated, clippy::redundant_closure_call)]
+                    let () = crate::#module::#macro_name!(|| ());
+                }
+            };
+        }
     } else {
         quote! {}
     };
-    quote! {
-        #[allow(unused_macros)]
-        macro_rules! #macro_name {
-            #no_args_match
-            ($tree:tt) => {
-                compile_error!("USDT probe macros should be invoked with a closure returning the arguments");
+    // With `warn_unused_probes`, firing the probe with its macro refers to its fire function, which
+    // is then left to the dead code lint, so that a probe fired neither way is reported.
+    let fire_fn_name = fire_fn_ident(config, probe);
+    let impl_block = if config.warn_unused_probes {
+        quote! {
+            let _ = crate::#module::#fire_fn_name;
+            #impl_block
+        }
+    } else {
+        impl_block
+    };
+    // The probe is fired with a closure returning its arguments, which is only called when the
+    // probe is enabled, or with the arguments themselves, which are then wrapped in a closure. The
+    // two are told apart by the type of the expression, see `ProbeArgs`, so that any function
+    // returning the arguments is called lazily too. The implementation calls the closure bound to
+    // `args_lambda`.
+    let args_lambda = quote! {
+        #[allow(unused_imports)]
+        use ::usdt::{ClosureProbeArgs as _, ValueProbeArgs as _};
+        let args = ::usdt::ProbeArgs($args);
+        let args_lambda = (&args).usdt_probe_args_kind().into_closure(args.0);
+    };
+    // Each arm expands to a block which fires the probe before returning, so that no temporaries
+    // of the arguments outlive the macro, say across an `.await` in an async caller.
+    let fire_match = if config.auto_args.is_empty() {
+        quote! {
+            ($args:expr) => {
+                {
+                    #args_lambda
+                    #impl_block
+                }
             };
-            ($args_lambda:expr) => {
+        }
+    } else {
+        // The argument closure is wrapped in one appending the automatic arguments, so that they
+        // are only captured when the probe is enabled.
+        let call = match n_args {
+            0 => quote! { let () = (args_lambda)(); },
+            _ => call_argument_closure(&probe.types[..n_args]),
+        };
+        let args = (0..n_args)
+            .map(|i| {
+                let index = syn::Index::from(i);
+                quote! { args.#index }
+            })
+            .chain(config.auto_args.iter().map(|arg| arg.capture()))
+            .collect::<Vec<_>>();
+        let args = if args.len() == 1 {
+            quote! { #(#args)* }
+        } else {
+            quote! { (#(#args),*) }
+        };
+        quote! {
+            ($args:expr) => {
                 {
+                    #args_lambda
+                    let args_lambda = || {
+                        #call
+                        #args
+                    };
                     #impl_block
                 }
             };
         }
-        #[allow(unused_imports)]
-        pub(crate) use #macro_name;
+    };
+    // A fallible closure is called when the probe is enabled, and the probe is only fired with
+    // the arguments it returns on success. Errors are discarded, skipping the probe entirely.
+    let n_required = probe
+        .defaults
+        .iter()
+        .position(Option::is_some)
+        .unwrap_or(n_args);
+    let arity_match = arity_check_arms(&probe.name, n_required, n_args);
+    let default_match = default_argument_arms(config, probe, n_required, n_args);
+    let named_match = named_argument_arms(config, probe, n_args);
+    let fire_fn = build_probe_fire_fn(config, probe);
+    let once_macro = if config.once_macros {
+        build_probe_once_macro(config, probe, &is_enabled)
+    } else {
+        quote! {}
+    };
+    let if_macro = if config.if_macros {
+        build_probe_if_macro(config, probe, &is_enabled)
+    } else {
+        quote! {}
+    };
+    let try_match = quote! {
+        (try $args_lambda:expr) => {
+            {
+                if #is_enabled {
+                    #[allow(clippy::redundant_closure_call)]
+                    let result = ($args_lambda)();
+                    if let ::std::result::Result::Ok(args) = result {
+                        #[allow(deprecated, clippy::redundant_closure_call)]
+                        let () = crate::#module::#macro_name!(move || args);
+                    }
+                }
+            }
+        };
+    };
+    let arms = quote! {
+        #no_args_match
+        #try_match
+        #named_match
+        #default_match
+        #arity_match
+        #fire_match
+    };
+    let probe_macro = define_macro(config, attrs, &macro_name, arms);
+    quote! {
+        #probe_macro
+        #fire_fn
+        #once_macro
+        #if_macro
     }
 }
 
-#[cfg(test)]
-mod tests {
+// Define a macro of a probe, with the given arms, and bring it into the provider's module.
+//
+// A macro can't be re-exported from a crate with `use`, so the macro of a public provider is
+// exported with `#[macro_export]` under a name unique to the crate, and brought into the module
+// under its own. Its paths from the root of the crate then start from `$crate`, to refer to the
+// crate defining the macro wherever it's called, and its paths into `usdt` go through the
+// module's re-export of it, so that the crates calling the macro needn't depend on `usdt`.
+fn define_macro(
+    config: &crate::CompileProvidersConfig,
+    attrs: &[syn::Attribute],
+    name: &proc_macro2::Ident,
+    arms: TokenStream,
+) -> TokenStream {
+    match config.visibility {
+        Visibility::Crate => quote! {
+            #(#attrs)*
+            #[allow(unused_macros)]
+            macro_rules! #name {
+                #arms
+            }
+            #[allow(unused_imports, deprecated)]
+            pub(crate) use #name;
+        },
+        Visibility::Public => {
+            let module_path = config.module_path();
+            let module = module_path
+                .segments
+                .iter()
+                .map(|segment| segment.ident.to_string())
+                .collect::<Vec<_>>()
+                .join("_");
+            let exported_name = format_ident!("__usdt_{}_{}", module, name);
+            let usdt = quote! { crate::#module_path::__usdt };
+            let arms = dollar_crate_paths(arms, &usdt);
+            quote! {
+                #(#attrs)*
+                #[doc(hidden)]
+                #[macro_export]
+                #[allow(unused_macros)]
+                macro_rules! #exported_name {
+                    #arms
+                }
+                #[doc(inline)]
+                #[allow(unused_imports, deprecated)]
+                pub use #exported_name as #name;
+            }
+        }
+    }
+}
 
-    use super::*;
-    use dtrace_parser::BitWidth;
-    use dtrace_parser::DataType as DType;
-    use dtrace_parser::Integer;
-    use dtrace_parser::Sign;
+// Return the tokens with each path from the root of the crate, such as `crate::Arg`, starting
+// from `$crate` instead, and each path into `usdt`, such as `::usdt::Error`, starting from
+// `$crate` and the path `usdt` is re-exported at instead, `usdt` from the root of the crate.
+fn dollar_crate_paths(tokens: TokenStream, usdt: &TokenStream) -> TokenStream {
+    let mut out = Vec::new();
+    let mut tokens = tokens.into_iter().peekable();
+    while let Some(token) = tokens.next() {
+        let after_dollar =
+            matches!(out.last(), Some(TokenTree::Punct(punct)) if punct.as_char() == '$');
+        match token {
+            TokenTree::Group(group) => {
+                let mut new =
+                    Group::new(group.delimiter(), dollar_crate_paths(group.stream(), usdt));
+                new.set_span(group.span());
+                out.push(TokenTree::Group(new));
+            }
+            TokenTree::Ident(ident) if ident == "usdt" && is_global_path(&out) => {
+                out.truncate(out.len() - 2);
+                out.push(TokenTree::Punct(Punct::new('$', Spacing::Alone)));
+                out.extend(usdt.clone());
+            }
+            TokenTree::Ident(ident)
+                if ident == "crate"
+                    && !after_dollar
+                    && matches!(tokens.peek(), Some(TokenTree::Punct(punct)) if punct.as_char() == ':') =>
+            {
+                out.push(TokenTree::Punct(Punct::new('$', Spacing::Alone)));
+                out.push(TokenTree::Ident(ident));
+            }
+            other => out.push(other),
+        }
+    }
+    out.into_iter().collect()
+}
 
-    #[test]
-    fn test_construct_type_check_empty() {
-        let expected = quote! {
-            let _ : () = ($args_lambda)();
-        };
-        let block = construct_type_check("", "", &[], &[]);
-        assert_eq!(block.to_string(), expected.to_string());
+// Return `true` if the tokens end with the `::` starting a path from the extern prelude, such as
+// `::usdt`, rather than one separating the segments of a path, such as `crate::usdt`.
+fn is_global_path(tokens: &[TokenTree]) -> bool {
+    match tokens {
+        [.., TokenTree::Punct(first), TokenTree::Punct(second)]
+            if first.as_char() == ':' && second.as_char() == ':' => {}
+        _ => return false,
     }
+    match tokens.len().checked_sub(3).map(|i| &tokens[i]) {
+        Some(TokenTree::Ident(ident)) => ["as", "dyn", "impl", "use"].iter().any(|kw| ident == kw),
+        Some(TokenTree::Punct(punct)) => punct.as_char() != '>',
+        _ => true,
+    }
+}
 
-    #[test]
-    fn test_construct_type_check_native() {
-        let provider = "provider";
-        let probe = "probe";
-        let types = &[
-            DataType::Native(DType::Integer(Integer {
-                sign: Sign::Unsigned,
-                width: BitWidth::Bit8,
-            })),
-            DataType::Native(DType::Integer(Integer {
-                sign: Sign::Signed,
-                width: BitWidth::Bit64,
-            })),
-        ];
-        let expected = quote! {
-            #[allow(unused_imports)]
-            #[allow(non_snake_case)]
-            fn __usdt_private_provider_probe_type_check(
-                _: impl ::std::borrow::Borrow<u8>,
-                _: impl ::std::borrow::Borrow<i64>
-            ) { }
-            let _ = || {
-                __usdt_private_provider_probe_type_check(args.0, args.1);
+// Create a macro firing the probe at most once per process, `<probe>_once!`, with `once_macros`.
+//
+// The probe's flag is set by the first call finding the probe enabled, which then fires it through
+// the probe's macro, so that calls while the probe is disabled don't use up the one firing. As the
+// flag is swapped atomically, exactly one call sets it, whichever thread it's on. The flag is a
+// static of the provider's module, rather than of the macro's expansion, so that the probe fires
+// once however many places call the macro.
+//
+// The tokens passed to the macro are forwarded as they are, rather than as an expression, so that
+// the probe's macro still checks the arity of the closure.
+fn build_probe_once_macro(
+    config: &crate::CompileProvidersConfig,
+    probe: &Probe,
+    is_enabled: &TokenStream,
+) -> TokenStream {
+    let module = config.module_path();
+    let macro_name = config.probe_ident(&probe.name);
+    let once_name = config.once_probe_ident(&probe.name);
+    let flag = format_ident!("__usdt_once_{}", macro_name);
+    let attrs = &probe.attrs;
+    let no_args_match = if probe.types.len() == config.auto_args.len() {
+        quote! {
+            () => {
+                {
+                    #[allow(deprecated, clippy::redundant_closure_call)]
+                    let () = crate::#module::#once_name!(|| ());
+                }
             };
+        }
+    } else {
+        quote! {}
+    };
+    let visibility = config.visibility.tokens();
+    let arms = quote! {
+        #no_args_match
+        ($($args:tt)+) => {
+            {
+                if #is_enabled
+                    && !crate::#module::#flag.swap(true, ::std::sync::atomic::Ordering::Relaxed)
+                {
+                    #[allow(deprecated, clippy::redundant_closure_call)]
+                    let () = crate::#module::#macro_name!($($args)+);
+                }
+            }
         };
-        let block = construct_type_check(provider, probe, &[], types);
-        assert_eq!(block.to_string(), expected.to_string());
+    };
+    let once_macro = define_macro(config, attrs, &once_name, arms);
+    quote! {
+        #[doc(hidden)]
+        #[allow(dead_code, non_upper_case_globals)]
+        #visibility static #flag: ::std::sync::atomic::AtomicBool =
+            ::std::sync::atomic::AtomicBool::new(false);
+        #once_macro
     }
+}
 
-    #[test]
-    fn test_construct_type_check_with_string() {
+// Create a macro firing the probe only when a predicate holds, `<probe>_if!`, with `if_macros`.
+//
+// The predicate, a `FnOnce() -> bool`, is only called once the probe is found to be enabled, and
+// the probe's macro is only called, building the arguments, when it returns `true`. As with the
+// `_once` macro, the remaining tokens are forwarded to the probe's macro as they are.
+fn build_probe_if_macro(
+    config: &crate::CompileProvidersConfig,
+    probe: &Probe,
+    is_enabled: &TokenStream,
+) -> TokenStream {
+    let module = config.module_path();
+    let macro_name = config.probe_ident(&probe.name);
+    let if_name = config.if_probe_ident(&probe.name);
+    let attrs = &probe.attrs;
+    let no_args_match = if probe.types.len() == config.auto_args.len() {
+        quote! {
+            ($predicate:expr $(,)?) => {
+                {
+                    #[allow(deprecated, clippy::redundant_closure_call)]
+                    let () = crate::#module::#if_name!($predicate, || ());
+                }
+            };
+        }
+    } else {
+        quote! {}
+    };
+    let arms = quote! {
+        #no_args_match
+        ($predicate:expr, $($args:tt)+) => {
+            {
+                #[allow(clippy::redundant_closure_call)]
+                if #is_enabled && ($predicate)() {
+                    #[allow(deprecated, clippy::redundant_closure_call)]
+                    let () = crate::#module::#macro_name!($($args)+);
+                }
+            }
+        };
+    };
+    define_macro(config, attrs, &if_name, arms)
+}
+
+// Create a function firing the probe, `fire_<probe>`, taking the probe's own arguments rather
+// than a closure returning them.
+//
+// The function fires the probe through its macro, so nothing is done with the arguments when the
+// probe isn't enabled, though the caller has evaluated them by then. The types of the arguments
+// are those of the provider module, so its use statements must be in scope, see
+// `provider_use_statements`.
+//
+// The macro is called by its name alone, as it's defined just before the function, in the same
+// module.
+fn build_probe_fire_fn(config: &crate::CompileProvidersConfig, probe: &Probe) -> TokenStream {
+    let macro_name = config.probe_ident(&probe.name);
+    let fn_name = fire_fn_ident(config, probe);
+    let attrs = &probe.attrs;
+    let types = &probe.types[..probe.types.len() - config.auto_args.len()];
+    let params = (0..types.len())
+        .map(|i| format_ident!("arg_{}", i))
+        .collect::<Vec<_>>();
+    let param_types = types.iter().map(fire_fn_param_type);
+    let args = match params.as_slice() {
+        [] => quote! { () },
+        [param] => quote! { #param },
+        _ => quote! { (#(#params),*) },
+    };
+    let allow = if config.warn_unused_probes {
+        quote! { #[allow(non_snake_case)] }
+    } else {
+        quote! { #[allow(dead_code, non_snake_case)] }
+    };
+    // The function has the span of the probe's definition, since lints such as dead code aren't
+    // reported for items generated by a macro.
+    quote_spanned! { probe.span=>
+        #(#attrs)*
+        #allow
+        #[inline]
+        pub fn #fn_name(#(#params: #param_types),*) {
+            #[allow(deprecated, clippy::redundant_closure_call)]
+            let () = #macro_name!(move || #args);
+        }
+    }
+}
+
+// Return the name of a probe's fire function, `fire_` followed by the name of its macro.
+fn fire_fn_ident(config: &crate::CompileProvidersConfig, probe: &Probe) -> proc_macro2::Ident {
+    format_ident!(
+        "fire_{}",
+        config.probe_ident(&probe.name),
+        span = probe.span
+    )
+}
+
+// Return the type of the parameter of a probe's fire function passing an argument of this type.
+fn fire_fn_param_type(typ: &DataType) -> TokenStream {
+    match typ {
+        DataType::UniqueId => quote! { &::usdt::UniqueId },
+        // An unsized slice is passed by reference, as it would be returned from the closure.
+        DataType::Serializable(ty @ syn::Type::Slice(_))
+        | DataType::SizedSerializable(ty @ syn::Type::Slice(_)) => quote! { &#ty },
+        _ => {
+            let ty = typ.to_rust_type();
+            quote! { #ty }
+        }
+    }
+}
+
+/// Return the use statements of a provider module, to place in the module generated for it.
+///
+/// These bring the types of the probes' arguments into scope for their fire functions. A public
+/// provider also re-exports `usdt`, for its probe macros to refer to from other crates.
+pub(crate) fn provider_use_statements(
+    provider: &Provider,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    let use_statements = &provider.use_statements;
+    let reexport = match config.visibility {
+        Visibility::Crate => quote! {},
+        Visibility::Public => quote! {
+            #[doc(hidden)]
+            pub use ::usdt as __usdt;
+        },
+    };
+    quote! {
+        #(
+            #[allow(unused_imports)]
+            #use_statements
+        )*
+        #reexport
+    }
+}
+
+// The largest number of elements of a tuple literal returned by an argument closure that's
+// checked against the probe's arguments. Longer tuples fail the type check instead.
+const MAX_CHECKED_ARITY: usize = 2 * crate::MAX_PROBE_ARGUMENTS;
+
+/// Return macro arms rejecting argument closures which return a tuple literal of the wrong length,
+/// such as `|| (a, b, c)` or `|| { (a, b, c) }` for a probe taking two arguments.
+///
+/// These only match the canonical shape of an argument closure, and any other closure is still
+/// checked by its type. A probe taking one argument may be passed a tuple, so isn't checked. The
+/// arguments from the `n_required`th on have defaults, and may be left out.
+fn arity_check_arms(probe_name: &str, n_required: usize, n_args: usize) -> TokenStream {
+    if n_args == 1 {
+        return quote! {};
+    }
+    let arms = (0..=MAX_CHECKED_ARITY)
+        .filter(|&count| !(n_required..=n_args).contains(&count))
+        .map(|count| {
+            let args = (0..count)
+                .map(|i| format_ident!("arg_{}", i))
+                .collect::<Vec<_>>();
+            // The comma of a single-element tuple is required, as without it the closure returns a
+            // single value, which may itself be a tuple.
+            let tuple = match count {
+                0 => quote! { () },
+                1 => quote! { (#($#args:expr),*,) },
+                _ => quote! { (#($#args:expr),* $(,)?) },
+            };
+            let message = format!(
+                "probe `{}` expects {} argument{}, closure returned {}",
+                probe_name,
+                n_args,
+                if n_args == 1 { "" } else { "s" },
+                count,
+            );
+            quote! {
+                ($(move)? || #tuple) => {
+                    compile_error!(#message);
+                };
+                ($(move)? || { #tuple }) => {
+                    compile_error!(#message);
+                };
+            }
+        });
+    quote! { #(#arms)* }
+}
+
+// Create the arms of a probe's macro supplying the defaults of its trailing arguments, given with
+// `#[default = ...]`, when the argument closure returns a tuple literal leaving them out, as in
+// `probe!(|| (a, b))` for a probe taking three arguments.
+//
+// Each arm fires the probe with a closure returning the arguments given followed by the defaults
+// of the rest, so that the defaults are only evaluated when the probe is enabled. Any other closure
+// must return all of the arguments, as how many it returns can't be told from its tokens. A closure
+// returning a single argument must return it as a tuple, `|| (a,)`, for the same reason.
+fn default_argument_arms(
+    config: &crate::CompileProvidersConfig,
+    probe: &Probe,
+    n_required: usize,
+    n_args: usize,
+) -> TokenStream {
+    if n_required == n_args {
+        return quote! {};
+    }
+    let module = config.module_path();
+    let macro_name = config.probe_ident(&probe.name);
+    let arms = (n_required..n_args).map(|count| {
+        let args = (0..count)
+            .map(|i| format_ident!("arg_{}", i))
+            .collect::<Vec<_>>();
+        let tuple = match count {
+            0 => quote! { () },
+            1 => quote! { (#($#args:expr),*,) },
+            _ => quote! { (#($#args:expr),* $(,)?) },
+        };
+        let defaults = probe.defaults[count..n_args].iter().flatten();
+        let all_args = match n_args {
+            1 => quote! { #(#defaults)* },
+            _ => quote! { (#($#args,)* #(#defaults),*) },
+        };
+        let fire = quote! {
+            {
+                #[allow(deprecated, clippy::redundant_closure_call)]
+                let () = crate::#module::#macro_name!(|| #all_args);
+            }
+        };
+        let no_args = if count == 0 {
+            quote! { () => #fire; }
+        } else {
+            quote! {}
+        };
+        quote! {
+            #no_args
+            ($(move)? || #tuple) => #fire;
+            ($(move)? || { #tuple }) => #fire;
+        }
+    });
+    quote! { #(#arms)* }
+}
+
+// Create the arms of a probe's macro firing it with its arguments named after the parameters of
+// the probe function, as in `probe!(id = 1, name = "x")`, in any order.
+//
+// The named arguments are sorted into a slot for each parameter, one at a time, and the probe is
+// then fired with a closure returning them in order. A slot may only be filled once, so that an
+// argument given twice, or one of a name the probe doesn't have, matches none of the arms sorting
+// them. The arguments are only evaluated within the closure, when the probe is enabled. Once the
+// named arguments are sorted, any slot left empty is filled with its default, if the parameter
+// has one. Probes with any unnamed parameters, including all those defined in D, have no such
+// arms.
+fn named_argument_arms(
+    config: &crate::CompileProvidersConfig,
+    probe: &Probe,
+    n_args: usize,
+) -> TokenStream {
+    let names = match probe.arg_names.iter().cloned().collect::<Option<Vec<_>>>() {
+        Some(names) if n_args > 0 && names.len() == n_args => names,
+        _ => return quote! {},
+    };
+    let module = config.module_path();
+    let macro_name = config.probe_ident(&probe.name);
+    let slots = (0..n_args)
+        .map(|i| format_ident!("arg_{}", i))
+        .collect::<Vec<_>>();
+    let sort_arms = names.iter().enumerate().map(|(i, name)| {
+        let name = format_ident!("{}", name);
+        let before = slots[..i].iter().map(|slot| quote! { [$($#slot:expr)?] });
+        let after = slots[i + 1..]
+            .iter()
+            .map(|slot| quote! { [$($#slot:expr)?] });
+        let forward_before = slots[..i].iter().map(|slot| quote! { [$($#slot)?] });
+        let forward_after = slots[i + 1..].iter().map(|slot| quote! { [$($#slot)?] });
+        quote! {
+            (@usdt_named #(#before)* [] #(#after)* ; #name = $value:expr $(, $($rest:tt)*)?) => {
+                {
+                    #[allow(deprecated)]
+                    let () = crate::#module::#macro_name!(
+                        @usdt_named #(#forward_before)* [$value] #(#forward_after)* ; $($($rest)*)?
+                    );
+                }
+            };
+        }
+    });
+    let default_arms = probe
+        .defaults
+        .iter()
+        .enumerate()
+        .filter_map(|(i, default)| {
+            let default = default.as_ref()?;
+            let before = slots[..i].iter().map(|slot| quote! { [$($#slot:expr)?] });
+            let after = slots[i + 1..]
+                .iter()
+                .map(|slot| quote! { [$($#slot:expr)?] });
+            let forward_before = slots[..i].iter().map(|slot| quote! { [$($#slot)?] });
+            let forward_after = slots[i + 1..].iter().map(|slot| quote! { [$($#slot)?] });
+            Some(quote! {
+                (@usdt_named #(#before)* [] #(#after)* ;) => {
+                    {
+                        #[allow(deprecated)]
+                        let () = crate::#module::#macro_name!(
+                            @usdt_named #(#forward_before)* [#default] #(#forward_after)* ;
+                        );
+                    }
+                };
+            })
+        });
+    let empty_slots = slots.iter().map(|_| quote! { [] });
+    let args = match slots.as_slice() {
+        [slot] => quote! { $#slot },
+        _ => quote! { (#($#slots),*) },
+    };
+    let message = format!(
+        "probe `{}` expects the named argument{} {}, each given once",
+        probe.name,
+        if n_args == 1 { "" } else { "s" },
+        names
+            .iter()
+            .map(|name| format!("`{}`", name))
+            .collect::<Vec<_>>()
+            .join(", "),
+    );
+    quote! {
+        ($($name:ident = $value:expr),+ $(,)?) => {
+            {
+                #[allow(deprecated)]
+                let () = crate::#module::#macro_name!(
+                    @usdt_named #(#empty_slots)* ; $($name = $value),+
+                );
+            }
+        };
+        #(#sort_arms)*
+        (@usdt_named #([$#slots:expr])* ;) => {
+            {
+                #[allow(deprecated, clippy::redundant_closure_call)]
+                let () = crate::#module::#macro_name!(|| #args);
+            }
+        };
+        #(#default_arms)*
+        (@usdt_named $($rest:tt)*) => {
+            compile_error!(#message)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use dtrace_parser::BitWidth;
+    use dtrace_parser::DataType as DType;
+    use dtrace_parser::Integer;
+    use dtrace_parser::Sign;
+
+    #[test]
+    fn test_construct_type_check_empty() {
+        let expected = quote! {
+            let _ : () = (args_lambda)();
+        };
+        let block = construct_type_check("", "", &[], &[]);
+        assert_eq!(block.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn test_construct_type_check_native() {
+        let provider = "provider";
+        let probe = "probe";
+        let types = &[
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit8,
+            })),
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Signed,
+                width: BitWidth::Bit64,
+            })),
+        ];
+        let expected = quote! {
+            #[allow(unused_imports)]
+            #[allow(non_snake_case)]
+            fn __usdt_private_provider_probe_type_check(
+                _: &impl ::std::borrow::Borrow<u8>,
+                _: &impl ::std::borrow::Borrow<i64>
+            ) { }
+            let _ = || {
+                __usdt_private_provider_probe_type_check(&args.0, &args.1);
+            };
+        };
+        let block = construct_type_check(provider, probe, &[], types);
+        assert_eq!(block.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn test_construct_type_check_with_string() {
         let provider = "provider";
         let probe = "probe";
         let types = &[DataType::Native(dtrace_parser::DataType::String)];
@@ -289,9 +1277,9 @@ mod tests {
         let expected = quote! {
             #[allow(unused_imports)]
             #[allow(non_snake_case)]
-            fn __usdt_private_provider_probe_type_check(_: impl AsRef<str>) { }
+            fn __usdt_private_provider_probe_type_check(_: &impl AsRef<str>) { }
             let _ = || {
-                __usdt_private_provider_probe_type_check(args.0);
+                __usdt_private_provider_probe_type_check(&args.0);
             };
         };
         let block = construct_type_check(provider, probe, &use_statements, types);
@@ -307,9 +1295,9 @@ mod tests {
         let expected = quote! {
             #[allow(unused_imports)]
             #[allow(non_snake_case)]
-            fn __usdt_private_provider_probe_type_check(_: impl AsRef<[u8]>) { }
+            fn __usdt_private_provider_probe_type_check(_: &impl AsRef<[u8]>) { }
             let _ = || {
-                __usdt_private_provider_probe_type_check(args.0);
+                __usdt_private_provider_probe_type_check(&args.0);
             };
         };
         let block = construct_type_check(provider, probe, &use_statements, types);
@@ -326,9 +1314,11 @@ mod tests {
             #[allow(unused_imports)]
             use my_module::MyType;
             #[allow(non_snake_case)]
-            fn __usdt_private_provider_probe_type_check(_: impl ::std::borrow::Borrow<MyType>) { }
+            fn __usdt_private_provider_probe_type_check(
+                _: &impl ::std::borrow::Borrow<MyType>
+            ) { }
             let _ = || {
-                __usdt_private_provider_probe_type_check(args.0);
+                __usdt_private_provider_probe_type_check(&args.0);
             };
         };
         let block = construct_type_check(provider, probe, &use_statements, types);
@@ -344,34 +1334,528 @@ mod tests {
             })),
             DataType::Native(dtrace_parser::DataType::String),
         ];
-        #[cfg(target_arch = "x86_64")]
-        let registers = ["rdi", "rsi"];
-        #[cfg(target_arch = "aarch64")]
-        let registers = ["x0", "x1"];
-        let (args, regs) = construct_probe_args(types);
+        let (args, regs) = construct_probe_args(types, Arch::X86_64, &Default::default());
+        // Only the string must be kept alive while the probe fires, while the pointer is passed
+        // directly.
         let expected = quote! {
-            let args = ($args_lambda)();
-            let arg_0 = (*<_ as ::std::borrow::Borrow<*const u8>>::borrow(&args.0) as usize);
-            let arg_1 = [(args.1.as_ref() as &str).as_bytes(), &[0_u8]].concat();
+            let args = (args_lambda)();
+            let arg_1 = ::usdt::to_str_probe_arg(args.1.as_ref() as &str);
         };
         assert_eq!(args.to_string(), expected.to_string());
+        let expected = quote! {
+            in("rdi") (*<_ as ::std::borrow::Borrow<*const u8>>::borrow(&args.0) as usize),
+            in("rsi") (arg_1.as_ptr() as usize),
+        };
+        assert_eq!(regs.to_string(), expected.to_string());
+    }
 
-        for (i, (expected, actual)) in registers
-            .iter()
-            .zip(regs.to_string().split(','))
-            .enumerate()
-        {
-            let reg = actual.replace(' ', "");
-            let expected = format!("in(\"{}\")(arg_{}", expected, i);
-            assert!(
-                reg.starts_with(&expected),
-                "reg: {}; expected {}",
-                reg,
-                expected,
-            );
+    // A probe with only native arguments passes them straight to the registers.
+    #[test]
+    fn test_construct_probe_args_native() {
+        let types = &[
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Signed,
+                width: BitWidth::Bit32,
+            })),
+            DataType::UniqueId,
+        ];
+        let (args, regs) = construct_probe_args(types, Arch::X86_64, &Default::default());
+        assert_eq!(
+            args.to_string(),
+            quote! { let args = (args_lambda)(); }.to_string()
+        );
+        let expected = quote! {
+            in("rdi") (*<_ as ::std::borrow::Borrow<i32>>::borrow(&args.0) as usize),
+            in("rsi") (args.1.as_u64() as usize),
+        };
+        assert_eq!(regs.to_string(), expected.to_string());
+    }
+
+    // Native and serializable arguments may come in any order, each taking the next registers.
+    #[test]
+    fn test_construct_probe_args_interleaved() {
+        let types = &[
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit64,
+            })),
+            DataType::SizedSerializable(syn::parse_str("&Arg").unwrap()),
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit32,
+            })),
+        ];
+        let (args, regs) = construct_probe_args(types, Arch::X86_64, &Default::default());
+        let expected = quote! {
+            let args = (args_lambda)();
+            let arg_1 = ::usdt::to_json_probe_arg(&args.1, 65536usize);
+        };
+        assert_eq!(args.to_string(), expected.to_string());
+        let expected = quote! {
+            in("rdi") (*<_ as ::std::borrow::Borrow<u64>>::borrow(&args.0) as usize),
+            in("rsi") (arg_1.as_ptr() as usize),
+            in("rdx") (arg_1.arg_len()),
+            in("rcx") (*<_ as ::std::borrow::Borrow<u32>>::borrow(&args.2) as usize),
+        };
+        assert_eq!(regs.to_string(), expected.to_string());
+    }
+
+    // On 32-bit Arm, 64-bit values are spilled to the stack and passed by address, and the ASM
+    // reads memory.
+    #[test]
+    fn test_construct_probe_firing_arm() {
+        let types = &[
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Signed,
+                width: BitWidth::Bit64,
+            })),
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit32,
+            })),
+        ];
+        let fire = |in_regs, options| quote! { fire(#in_regs #options) };
+        let (args, firing) = construct_probe_firing(types, Arch::Arm, &Default::default(), fire);
+        let expected = quote! {
+            let args = (args_lambda)();
+            let spill_0: u64 = (*<_ as ::std::borrow::Borrow<i64>>::borrow(&args.0) as u64) as u64;
+        };
+        assert_eq!(args.to_string(), expected.to_string());
+        let expected = quote! {
+            fire(
+                in("r0") (&raw const spill_0 as usize),
+                in("r1") ((*<_ as ::std::borrow::Borrow<u32>>::borrow(&args.1) as u64) as usize),
+                options(readonly, nostack, preserves_flags)
+            )
+        };
+        assert_eq!(firing.to_string(), expected.to_string());
+    }
+
+    // Only probes passing memory a tracer reads may have it read by the ASM.
+    #[test]
+    fn test_asm_options() {
+        let int = DataType::Native(DType::Integer(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        }));
+        let nomem = quote! { options(nomem, nostack, preserves_flags) }.to_string();
+        let readonly = quote! { options(readonly, nostack, preserves_flags) }.to_string();
+        assert_eq!(asm_options(&[]).to_string(), nomem);
+        assert_eq!(
+            asm_options(&[
+                int.clone(),
+                DataType::UniqueId,
+                DataType::Address(syn::parse_str("*const u8").unwrap()),
+            ])
+            .to_string(),
+            nomem
+        );
+        for typ in [
+            DataType::Native(DType::String),
+            DataType::Native(DType::Pointer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit8,
+            })),
+            DataType::Serializable(syn::parse_str("Foo").unwrap()),
+        ] {
+            assert_eq!(asm_options(&[int.clone(), typ]).to_string(), readonly);
         }
     }
 
+    // Each string is copied into a null-terminated buffer of its own, passed in its own register.
+    #[test]
+    fn test_construct_probe_args_several_strings() {
+        let types = &[
+            DataType::Native(DType::String),
+            DataType::Native(DType::String),
+            DataType::Native(DType::String),
+        ];
+        let (args, regs) = construct_probe_args(types, Arch::X86_64, &Default::default());
+        let expected = quote! {
+            let args = (args_lambda)();
+            let arg_0 = ::usdt::to_str_probe_arg(args.0.as_ref() as &str);
+            let arg_1 = ::usdt::to_str_probe_arg(args.1.as_ref() as &str);
+            let arg_2 = ::usdt::to_str_probe_arg(args.2.as_ref() as &str);
+        };
+        assert_eq!(args.to_string(), expected.to_string());
+        let expected = quote! {
+            in("rdi") (arg_0.as_ptr() as usize),
+            in("rsi") (arg_1.as_ptr() as usize),
+            in("rdx") (arg_2.as_ptr() as usize),
+        };
+        assert_eq!(regs.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn test_construct_probe_firing_outlined() {
+        let types = &[
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit8,
+            })),
+            DataType::Native(dtrace_parser::DataType::String),
+        ];
+        let fire = |in_regs, options| quote! { fire(#in_regs #options) };
+        let config = crate::CompileProvidersConfig::default();
+        let dedupe_config = crate::CompileProvidersConfig {
+            dedupe_probes: true,
+            ..Default::default()
+        };
+        let (inline_args, inline_firing) =
+            construct_probe_firing(types, Arch::X86_64, &config, fire);
+        let (args, firing) = construct_probe_firing(types, Arch::X86_64, &dedupe_config, fire);
+        assert_eq!(args.to_string(), inline_args.to_string());
+        assert!(!inline_firing.to_string().contains("__usdt_fire_probe"));
+        let expected = quote! {
+            #[inline(never)]
+            fn __usdt_fire_probe(arg_0: usize, arg_1: usize) {
+                fire(in("rdi") arg_0, in("rsi") arg_1, options(readonly, nostack, preserves_flags))
+            }
+            __usdt_fire_probe(
+                (*<_ as ::std::borrow::Borrow<u8>>::borrow(&args.0) as usize),
+                (arg_1.as_ptr() as usize)
+            );
+        };
+        assert_eq!(firing.to_string(), expected.to_string());
+    }
+
+    #[test]
+    fn test_build_probe_macro_forwards_attrs() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("provider")),
+            module: Some(String::from("provider")),
+            ..Default::default()
+        };
+        let probe = Probe {
+            name: String::from("probe"),
+            types: vec![],
+            attrs: vec![
+                syn::parse_quote! { #[doc = " Some docs."] },
+                syn::parse_quote! { #[deprecated] },
+            ],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let tokens = build_probe_macro(&config, &probe, quote! { true }, quote! {});
+        let expected = quote! {
+            #[doc = " Some docs."]
+            #[deprecated]
+            #[allow(unused_macros)]
+            macro_rules! probe
+        };
+        assert!(tokens.to_string().starts_with(&expected.to_string()));
+    }
+
+    #[test]
+    fn test_build_probe_macro_public() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("provider")),
+            module: Some(String::from("telemetry::provider")),
+            visibility: Visibility::Public,
+            ..Default::default()
+        };
+        let probe = Probe {
+            name: String::from("probe"),
+            types: vec![DataType::Native(DType::String)],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let tokens = build_probe_macro(&config, &probe, quote! { true }, quote! {}).to_string();
+        let expected = quote! {
+            #[doc(hidden)]
+            #[macro_export]
+            #[allow(unused_macros)]
+            macro_rules! __usdt_telemetry_provider_probe
+        };
+        assert!(tokens.starts_with(&expected.to_string()));
+        let expected = quote! {
+            #[doc(inline)]
+            #[allow(unused_imports, deprecated)]
+            pub use __usdt_telemetry_provider_probe as probe;
+        };
+        assert!(tokens.contains(&expected.to_string()));
+        assert!(tokens.contains(&quote! { $crate::telemetry::provider::probe! }.to_string()));
+        assert!(!tokens.contains("pub (crate)"));
+    }
+
+    #[test]
+    fn test_dollar_crate_paths() {
+        let tokens = quote! {
+            use crate::Arg;
+            let _: crate::a::B<crate::C> = $crate::d();
+            pub(crate) fn crate_() {}
+        };
+        let expected = quote! {
+            use $crate::Arg;
+            let _: $crate::a::B<$crate::C> = $crate::d();
+            pub(crate) fn crate_() {}
+        };
+        let usdt = quote! { crate::provider::__usdt };
+        assert_eq!(
+            dollar_crate_paths(tokens, &usdt).to_string(),
+            expected.to_string()
+        );
+
+        // Paths into `usdt` go through the provider's re-export of it.
+        let tokens = quote! {
+            let _ = ::usdt::to_json_probe_arg(&x, 1);
+            let _ = <_ as ::usdt::ErasedSerialize>::f(&x as &dyn ::usdt::ErasedSerialize);
+            let _: crate::usdt::Arg = (::usdt::f)();
+            use ::usdt::{A as _, B as _};
+        };
+        let expected = quote! {
+            let _ = $crate::provider::__usdt::to_json_probe_arg(&x, 1);
+            let _ = <_ as $crate::provider::__usdt::ErasedSerialize>::f(
+                &x as &dyn $crate::provider::__usdt::ErasedSerialize
+            );
+            let _: $crate::usdt::Arg = ($crate::provider::__usdt::f)();
+            use $crate::provider::__usdt::{A as _, B as _};
+        };
+        assert_eq!(
+            dollar_crate_paths(tokens, &usdt).to_string(),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn test_arity_check_arms() {
+        let arms = arity_check_arms("stop", 2, 2).to_string();
+        let expected = quote! {
+            ($(move)? || { ($arg_0:expr, $arg_1:expr, $arg_2:expr $(,)?) }) => {
+                compile_error!("probe `stop` expects 2 arguments, closure returned 3");
+            };
+        };
+        assert!(arms.contains(&expected.to_string()));
+        let expected = quote! {
+            ($(move)? || ($arg_0:expr,)) => {
+                compile_error!("probe `stop` expects 2 arguments, closure returned 1");
+            };
+        };
+        assert!(arms.contains(&expected.to_string()));
+        assert!(!arms.contains("closure returned 2"));
+        // A single argument may itself be a tuple.
+        assert!(arity_check_arms("start", 1, 1).is_empty());
+
+        // Arguments with defaults may be left out.
+        let arms = arity_check_arms("stop", 1, 3).to_string();
+        assert!(arms.contains("closure returned 0\""));
+        assert!(!arms.contains("closure returned 1\""));
+        assert!(!arms.contains("closure returned 2\""));
+        assert!(!arms.contains("closure returned 3\""));
+        assert!(arms.contains("closure returned 4\""));
+    }
+
+    #[test]
+    fn test_build_probe_macro_auto_args() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("provider")),
+            module: Some(String::from("provider")),
+            auto_args: vec![crate::AutoArg::ThreadId, crate::AutoArg::Cpu],
+            ..Default::default()
+        };
+        let probe = Probe {
+            name: String::from("probe"),
+            types: vec![
+                DataType::Native(DType::String),
+                crate::AutoArg::ThreadId.data_type(),
+                crate::AutoArg::Cpu.data_type(),
+            ],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let tokens =
+            build_probe_macro(&config, &probe, quote! { true }, quote! { fire(); }).to_string();
+        let expected = quote! {
+            ($args:expr) => {
+                {
+                    #[allow(unused_imports)]
+                    use ::usdt::{ClosureProbeArgs as _, ValueProbeArgs as _};
+                    let args = ::usdt::ProbeArgs($args);
+                    let args_lambda = (&args).usdt_probe_args_kind().into_closure(args.0);
+                    let args_lambda = || {
+                        let args = ((args_lambda)(),);
+                        (args.0, ::usdt::current_thread_id(), ::usdt::current_cpu())
+                    };
+                    fire();
+                }
+            };
+        };
+        assert!(tokens.contains(&expected.to_string()));
+        // The probe takes no arguments of its own, so may be fired without a closure.
+        let probe = Probe {
+            types: probe.types[1..].to_vec(),
+            ..probe
+        };
+        let tokens = build_probe_macro(&config, &probe, quote! { true }, quote! {}).to_string();
+        assert!(tokens.contains(&quote! { () => }.to_string()));
+        assert!(tokens.contains(&quote! { let () = (args_lambda)(); }.to_string()));
+    }
+
+    #[test]
+    fn test_build_probe_macro_try() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("provider")),
+            module: Some(String::from("provider")),
+            ..Default::default()
+        };
+        let probe = Probe {
+            name: String::from("probe"),
+            types: vec![DataType::Native(DType::String)],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let tokens = build_probe_macro(&config, &probe, quote! { enabled() }, quote! {});
+        let expected = quote! {
+            (try $args_lambda:expr) => {
+                {
+                    if enabled() {
+                        #[allow(clippy::redundant_closure_call)]
+                        let result = ($args_lambda)();
+                        if let ::std::result::Result::Ok(args) = result {
+                            #[allow(deprecated, clippy::redundant_closure_call)]
+                            let () = crate::provider::probe!(move || args);
+                        }
+                    }
+                }
+            };
+        };
+        assert!(tokens.to_string().contains(&expected.to_string()));
+    }
+
+    #[test]
+    fn test_build_probe_macro_named_arguments() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("provider")),
+            module: Some(String::from("provider")),
+            ..Default::default()
+        };
+        let probe = Probe {
+            name: String::from("probe"),
+            types: vec![
+                DataType::Native(DType::String),
+                DataType::Native(DType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                })),
+            ],
+            attrs: vec![],
+            arg_names: vec![Some(String::from("message")), Some(String::from("code"))],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let tokens = build_probe_macro(&config, &probe, quote! { true }, quote! {}).to_string();
+        let sort_code = quote! {
+            (@usdt_named [$($arg_0:expr)?] [] ; code = $value:expr $(, $($rest:tt)*)?) => {
+                {
+                    #[allow(deprecated)]
+                    let () = crate::provider::probe!(
+                        @usdt_named [$($arg_0)?] [$value] ; $($($rest)*)?
+                    );
+                }
+            };
+        };
+        assert!(tokens.contains(&sort_code.to_string()));
+        let fire = quote! {
+            (@usdt_named [$arg_0:expr] [$arg_1:expr] ;) => {
+                {
+                    #[allow(deprecated, clippy::redundant_closure_call)]
+                    let () = crate::provider::probe!(|| ($arg_0, $arg_1));
+                }
+            };
+        };
+        assert!(tokens.contains(&fire.to_string()));
+
+        // A probe with an unnamed parameter may only be fired with a closure.
+        let probe = Probe {
+            arg_names: vec![Some(String::from("message")), None],
+            d_types: vec![],
+            defaults: vec![],
+            ..probe
+        };
+        let tokens = build_probe_macro(&config, &probe, quote! { true }, quote! {}).to_string();
+        assert!(!tokens.contains("usdt_named"));
+    }
+
+    #[test]
+    fn test_build_probe_fire_fn() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("provider")),
+            auto_args: vec![crate::AutoArg::ThreadId],
+            ..Default::default()
+        };
+        let probe = Probe {
+            name: String::from("probe"),
+            types: vec![
+                DataType::Native(DType::String),
+                DataType::UniqueId,
+                crate::AutoArg::ThreadId.data_type(),
+            ],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let expected = quote! {
+            #[allow(dead_code, non_snake_case)]
+            #[inline]
+            pub fn fire_probe(arg_0: &str, arg_1: &::usdt::UniqueId) {
+                #[allow(deprecated, clippy::redundant_closure_call)]
+                let () = probe!(move || (arg_0, arg_1));
+            }
+        };
+        assert_eq!(
+            build_probe_fire_fn(&config, &probe).to_string(),
+            expected.to_string()
+        );
+    }
+
+    #[test]
+    fn test_build_probe_macro_warn_unused_probes() {
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("provider")),
+            warn_unused_probes: true,
+            ..Default::default()
+        };
+        let probe = Probe {
+            name: String::from("probe"),
+            types: vec![],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let tokens = build_probe_macro(&config, &probe, quote! { true }, quote! { fire(); });
+        let expected = quote! {
+            ($args:expr) => {
+                {
+                    #[allow(unused_imports)]
+                    use ::usdt::{ClosureProbeArgs as _, ValueProbeArgs as _};
+                    let args = ::usdt::ProbeArgs($args);
+                    let args_lambda = (&args).usdt_probe_args_kind().into_closure(args.0);
+                    let _ = crate::provider::fire_probe;
+                    fire();
+                }
+            };
+        };
+        let tokens = tokens.to_string();
+        assert!(tokens.contains(&expected.to_string()));
+        assert!(!tokens.contains("dead_code"));
+    }
+
     #[test]
     fn test_asm_type_convert() {
         use std::str::FromStr;
@@ -381,21 +1865,295 @@ mod tests {
                 width: BitWidth::Bit8,
             })),
             TokenStream::from_str("foo").unwrap(),
+            0,
+            None,
+            false,
+            &[],
+            &quote! { usize },
         );
         assert_eq!(
             out.to_string(),
             quote! {(*<_ as ::std::borrow::Borrow<u8>>::borrow(&foo) as usize)}.to_string()
         );
-        assert_eq!(post.to_string(), quote! {}.to_string());
+        assert_eq!(post.len(), 1);
+        assert_eq!(post[0].to_string(), quote! {}.to_string());
+
+        // A smart pointer is serialized as the value it points to.
+        let (out, _) = asm_type_convert(
+            &DataType::Serializable(syn::parse_str("&Arc<Foo>").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            None,
+            false,
+            &[],
+            &quote! { usize },
+        );
+        let pointer: syn::Type = syn::parse_str("Arc<Foo>").unwrap();
+        assert_eq!(
+            out.to_string(),
+            quote! {
+                ::usdt::to_json_probe_arg(
+                    &**<_ as ::std::borrow::Borrow<#pointer>>::borrow(&foo),
+                    16usize
+                )
+            }
+            .to_string()
+        );
+
+        let (out, _) = asm_type_convert(
+            &DataType::Serializable(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            None,
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert!(out
+            .to_string()
+            .contains(&quote! { ::usdt::to_json_probe_arg(&foo, 16usize) }.to_string()));
+
+        // Renamed keys wrap the value serialized.
+        let (out, _) = asm_type_convert(
+            &DataType::Serializable(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            None,
+            false,
+            &[(String::from("request_id"), String::from("id"))],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! {
+                ::usdt::to_json_probe_arg(
+                    &::usdt::RenameKeys(&foo, &[("request_id", "id")]),
+                    16usize
+                )
+            }
+            .to_string()
+        );
+
+        let (out, post) = asm_type_convert(
+            &DataType::Serializable(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            Some(crate::Compression::Gzip),
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { ::usdt::gzip_probe_arg(&::usdt::to_json_probe_arg(&foo, 16usize)) }
+                .to_string()
+        );
+        assert_eq!(
+            post[0].to_string(),
+            quote! { .as_ptr() as usize }.to_string()
+        );
+
+        // The length of the JSON follows the pointer to it, without the null byte.
+        let (out, post) = asm_type_convert(
+            &DataType::SizedSerializable(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            None,
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { ::usdt::to_json_probe_arg(&foo, 16usize) }.to_string()
+        );
+        assert_eq!(
+            post.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                quote! { .as_ptr() as usize }.to_string(),
+                quote! { .arg_len() }.to_string()
+            ]
+        );
+        let (_, post) = asm_type_convert(
+            &DataType::SizedSerializable(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            Some(crate::Compression::Zstd),
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(post[1].to_string(), quote! { .arg_len() }.to_string());
+
+        // Framed JSON keeps its null byte, and isn't framed when compressed.
+        let (out, post) = asm_type_convert(
+            &DataType::SizedSerializable(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            None,
+            true,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { ::usdt::to_framed_json_probe_arg(&foo, 16usize) }.to_string()
+        );
+        assert_eq!(post[1].to_string(), quote! { .arg_len() }.to_string());
+        let (out, _) = asm_type_convert(
+            &DataType::Serializable(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            Some(crate::Compression::Gzip),
+            true,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { ::usdt::gzip_probe_arg(&::usdt::to_json_probe_arg(&foo, 16usize)) }
+                .to_string()
+        );
+
+        // Key/value pairs are serialized as an object.
+        let (out, _) = asm_type_convert(
+            &DataType::Serializable(syn::parse_str("impl ::usdt::Fields").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            None,
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { ::usdt::to_json_probe_arg(&::usdt::SerializeFields(&foo), 16usize) }
+                .to_string()
+        );
+
+        // A protobuf message is encoded in full, even with compression.
+        let (out, post) = asm_type_convert(
+            &DataType::Protobuf(syn::parse_str("Foo").unwrap()),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            Some(crate::Compression::Zstd),
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! {
+                ::prost::Message::encode_to_vec(<_ as ::std::borrow::Borrow<Foo>>::borrow(&foo))
+            }
+            .to_string()
+        );
+        assert_eq!(
+            post.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            [
+                quote! { .as_ptr() as usize }.to_string(),
+                quote! { .len() as usize }.to_string()
+            ]
+        );
+
+        // A slice is passed as its address, length and element size.
+        let (out, post) = asm_type_convert(
+            &DataType::NativeSlice(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit16,
+            }),
+            TokenStream::from_str("foo").unwrap(),
+            16,
+            None,
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! {
+                {
+                    let slice = <_ as AsRef<[u16]>>::as_ref(&foo);
+                    (slice.as_ptr(), slice.len(), ::std::mem::size_of::<u16>())
+                }
+            }
+            .to_string()
+        );
+        assert_eq!(post.len(), 3);
 
         let (out, post) = asm_type_convert(
             &DataType::Native(dtrace_parser::DataType::String),
             TokenStream::from_str("foo").unwrap(),
+            0,
+            None,
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { ::usdt::to_str_probe_arg(foo.as_ref() as &str) }.to_string()
+        );
+        assert_eq!(post.len(), 1);
+        assert_eq!(
+            post[0].to_string(),
+            quote! { .as_ptr() as usize }.to_string()
+        );
+
+        let (out, post) = asm_type_convert(
+            &DataType::NativeArray(
+                Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64,
+                },
+                2,
+            ),
+            TokenStream::from_str("foo").unwrap(),
+            0,
+            None,
+            false,
+            &[],
+            &quote! { usize },
+        );
+        assert_eq!(
+            out.to_string(),
+            quote! { *<_ as ::std::borrow::Borrow<[u64; 2usize]>>::borrow(&foo) }.to_string()
+        );
+        let post = post.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            post,
+            [
+                quote! { [0] as usize }.to_string(),
+                quote! { [1] as usize }.to_string()
+            ]
+        );
+
+        let (out, post) = asm_type_convert(
+            &DataType::Uuid,
+            TokenStream::from_str("foo").unwrap(),
+            0,
+            None,
+            false,
+            &[],
+            &quote! { usize },
         );
         assert_eq!(
             out.to_string(),
-            quote! { [(foo.as_ref() as &str).as_bytes(), &[0_u8]].concat() }.to_string()
+            quote! {
+                {
+                    let uuid = foo.as_u128();
+                    ((uuid >> 64) as u64, uuid as u64)
+                }
+            }
+            .to_string()
+        );
+        let post = post.iter().map(|p| p.to_string()).collect::<Vec<_>>();
+        assert_eq!(
+            post,
+            [
+                quote! { .0 as usize }.to_string(),
+                quote! { .1 as usize }.to_string()
+            ]
         );
-        assert_eq!(post.to_string(), quote! { .as_ptr() as usize }.to_string());
     }
 }
@@ -0,0 +1,269 @@
+//! Compression of serialized probe arguments.
+//!
+//! With the `gzip` or `zstd` features, a provider may be compiled with `compression = "gzip"` or
+//! `compression = "zstd"`, which compresses the JSON of each serializable argument before passing
+//! it to the probe. Rather than a null-terminated string, the argument then points to a header of
+//! [`COMPRESSED_ARG_HEADER_LEN`] bytes, followed by the compressed JSON:
+//!
+//! | Offset | Size | Contents                                                     |
+//! |--------|------|--------------------------------------------------------------|
+//! | 0      | 1    | [`COMPRESSED_ARG_MAGIC`], `0xff`                             |
+//! | 1      | 1    | The encoding, 1 for gzip and 2 for zstd                      |
+//! | 2      | 1    | [`COMPRESSED_ARG_VERSION`] of the header, currently 1        |
+//! | 3      | 1    | Reserved, always zero                                        |
+//! | 4      | 4    | The length of the compressed data, as a little-endian `u32`  |
+//!
+//! As `0xff` never appears in UTF-8, the first byte tells a consumer whether an argument is
+//! compressed. Should compressing an argument fail, its JSON is passed as a null-terminated
+//! string, as it would be without compression. With `json_len`, the length passed is then that of
+//! the JSON without its null byte, as it is without compression, and otherwise that of the header
+//! and the compressed data.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use proc_macro2::TokenStream;
+use quote::quote;
+use serde::Deserialize;
+
+/// The first byte of a compressed probe argument.
+pub const COMPRESSED_ARG_MAGIC: u8 = 0xff;
+
+/// The version of the header of compressed probe arguments.
+pub const COMPRESSED_ARG_VERSION: u8 = 1;
+
+/// The length of the header preceding the data of a compressed probe argument.
+pub const COMPRESSED_ARG_HEADER_LEN: usize = 8;
+
+/// The algorithm used to compress serialized probe arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    /// Compress with gzip, which requires the `gzip` feature. Written `gzip`.
+    Gzip,
+    /// Compress with Zstandard, which requires the `zstd` feature. Written `zstd`.
+    Zstd,
+}
+
+impl Compression {
+    /// Return the byte identifying the encoding in the header of a compressed argument.
+    pub fn encoding(&self) -> u8 {
+        match self {
+            Compression::Gzip => 1,
+            Compression::Zstd => 2,
+        }
+    }
+
+    /// Return the name of the feature required to compress arguments this way, which is also how
+    /// it's written in the config.
+    pub fn feature(&self) -> &'static str {
+        match self {
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        }
+    }
+
+    /// Return `true` if the feature required to compress arguments this way is enabled.
+    pub fn is_available(&self) -> bool {
+        match self {
+            Compression::Gzip => cfg!(feature = "gzip"),
+            Compression::Zstd => cfg!(feature = "zstd"),
+        }
+    }
+
+    /// Return the code compressing the null-terminated JSON of an argument.
+    pub(crate) fn compress(&self, json: TokenStream) -> TokenStream {
+        match self {
+            Compression::Gzip => quote! { ::usdt::gzip_probe_arg(&#json) },
+            Compression::Zstd => quote! { ::usdt::zstd_probe_arg(&#json) },
+        }
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!(
+                "Unknown compression \"{}\", expected \"gzip\" or \"zstd\"",
+                s
+            )),
+        }
+    }
+}
+
+// Deserialize the name of a compression algorithm.
+pub(crate) fn deserialize_compression<'de, D>(
+    deserializer: D,
+) -> Result<Option<Compression>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+/// A compressed probe argument, or the null-terminated JSON it falls back to.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+#[doc(hidden)]
+pub struct CompressedProbeArg {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl CompressedProbeArg {
+    /// Return the length of the argument without any null byte, as passed with `json_len`.
+    pub fn arg_len(&self) -> usize {
+        self.len
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+impl std::ops::Deref for CompressedProbeArg {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf
+    }
+}
+
+/// Compress the null-terminated JSON of a probe argument with gzip.
+#[cfg(feature = "gzip")]
+#[doc(hidden)]
+pub fn gzip_probe_arg(json: &[u8]) -> CompressedProbeArg {
+    compress_probe_arg(json, Compression::Gzip, |json, out| {
+        use std::io::Write as _;
+        let mut encoder = flate2::write::GzEncoder::new(out, flate2::Compression::fast());
+        encoder.write_all(json)?;
+        encoder.finish().map(|_| ())
+    })
+}
+
+/// Compress the null-terminated JSON of a probe argument with Zstandard.
+#[cfg(feature = "zstd")]
+#[doc(hidden)]
+pub fn zstd_probe_arg(json: &[u8]) -> CompressedProbeArg {
+    compress_probe_arg(json, Compression::Zstd, |json, out| {
+        zstd::stream::copy_encode(json, out, 0)
+    })
+}
+
+// Compress the null-terminated JSON of a probe argument into a buffer starting with the header,
+// falling back to the JSON itself if that fails.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn compress_probe_arg(
+    json: &[u8],
+    compression: Compression,
+    compress: impl FnOnce(&[u8], &mut Vec<u8>) -> std::io::Result<()>,
+) -> CompressedProbeArg {
+    let json = json.strip_suffix(&[0]).unwrap_or(json);
+    let mut out = vec![
+        COMPRESSED_ARG_MAGIC,
+        compression.encoding(),
+        COMPRESSED_ARG_VERSION,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ];
+    let len = compress(json, &mut out)
+        .ok()
+        .and_then(|_| u32::try_from(out.len() - COMPRESSED_ARG_HEADER_LEN).ok());
+    match len {
+        Some(len) => {
+            out[4..COMPRESSED_ARG_HEADER_LEN].copy_from_slice(&len.to_le_bytes());
+            CompressedProbeArg {
+                len: out.len(),
+                buf: out,
+            }
+        }
+        None => CompressedProbeArg {
+            buf: [json, &[0]].concat(),
+            len: json.len(),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_compression() {
+        assert_eq!("gzip".parse(), Ok(Compression::Gzip));
+        assert_eq!("zstd".parse(), Ok(Compression::Zstd));
+        assert!("lz4".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn test_compress() {
+        let json = quote! { ::usdt::to_json_probe_arg(&args.0, 1024) };
+        assert_eq!(
+            Compression::Zstd.compress(json).to_string(),
+            quote! { ::usdt::zstd_probe_arg(&::usdt::to_json_probe_arg(&args.0, 1024)) }
+                .to_string()
+        );
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    fn check_header(arg: &[u8], compression: Compression) -> &[u8] {
+        let (header, data) = arg.split_at(COMPRESSED_ARG_HEADER_LEN);
+        assert_eq!(
+            header[..4],
+            [COMPRESSED_ARG_MAGIC, compression.encoding(), 1, 0]
+        );
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap());
+        assert_eq!(len as usize, data.len());
+        data
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip_probe_arg() {
+        use std::io::Read as _;
+        let json = b"{\"ok\":[1,2,3]}\0";
+        let arg = gzip_probe_arg(json);
+        let mut decoded = String::new();
+        flate2::read::GzDecoder::new(check_header(&arg, Compression::Gzip))
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "{\"ok\":[1,2,3]}");
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_probe_arg() {
+        let json = b"{\"ok\":[1,2,3]}\0";
+        let arg = zstd_probe_arg(json);
+        let decoded = zstd::stream::decode_all(check_header(&arg, Compression::Zstd)).unwrap();
+        assert_eq!(decoded, b"{\"ok\":[1,2,3]}");
+    }
+
+    #[cfg(any(feature = "gzip", feature = "zstd"))]
+    #[test]
+    fn test_compress_probe_arg_fallback() {
+        let json = b"{\"ok\":1}\0";
+        let arg = compress_probe_arg(json, Compression::Gzip, |_, _| {
+            Err(std::io::Error::other("failed"))
+        });
+        assert_eq!(&*arg, json);
+        assert_eq!(arg.arg_len(), json.len() - 1);
+    }
+}
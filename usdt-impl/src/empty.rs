@@ -20,52 +20,41 @@ use crate::common;
 use crate::{Probe, Provider};
 use proc_macro2::TokenStream;
 use quote::quote;
-use std::convert::TryFrom;
 
-pub fn compile_provider_source(
-    source: &str,
+pub(crate) fn compile_provider(
+    provider: &Provider,
     config: &crate::CompileProvidersConfig,
-) -> Result<TokenStream, crate::Error> {
-    let dfile = dtrace_parser::File::try_from(source)?;
-    let providers = dfile
-        .providers()
-        .into_iter()
-        .map(|provider| {
-            let provider = Provider::from(provider);
-            // Ensure that the name of the module in the config is set, either by the caller or
-            // defaulting to the provider name.
-            let config = crate::CompileProvidersConfig {
-                provider: Some(provider.name.clone()),
-                probe_format: config.probe_format.clone(),
-                module: match &config.module {
-                    None => Some(provider.name.clone()),
-                    other => other.clone(),
-                },
-            };
-            compile_provider(&provider, &config)
-        })
-        .collect::<Vec<_>>();
-    Ok(quote! {
-        #(#providers)*
-    })
+) -> TokenStream {
+    compile_provider_with(provider, config, compile_probe)
 }
 
-pub fn compile_provider_from_definition(
+/// Compile a provider whose probes are disabled outright, with the `disabled` feature.
+///
+/// Unlike the probes of targets without a backend, these never call the argument closure, which
+/// is only type-checked.
+pub(crate) fn compile_disabled_provider(
     provider: &Provider,
     config: &crate::CompileProvidersConfig,
 ) -> TokenStream {
-    compile_provider(provider, config)
+    compile_provider_with(provider, config, compile_disabled_probe)
 }
 
-fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig) -> TokenStream {
+fn compile_provider_with(
+    provider: &Provider,
+    config: &crate::CompileProvidersConfig,
+    compile_probe: fn(&Provider, &Probe, &crate::CompileProvidersConfig) -> TokenStream,
+) -> TokenStream {
     let probe_impls = provider
         .probes
         .iter()
         .map(|probe| compile_probe(provider, probe, config))
         .collect::<Vec<_>>();
     let module = config.module_ident();
+    let visibility = config.visibility.tokens();
+    let use_statements = common::provider_use_statements(provider, config);
     quote! {
-        pub(crate) mod #module {
+        #visibility mod #module {
+            #use_statements
             #(#probe_impls)*
         }
     }
@@ -89,9 +78,37 @@ fn compile_probe(
         #args
         #type_check_fn
     };
-    common::build_probe_macro(config, &probe.name, &probe.types, impl_block)
+    common::build_probe_macro(config, probe, quote! { true }, impl_block)
 }
 
+fn compile_disabled_probe(
+    provider: &Provider,
+    probe: &Probe,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    let args = common::call_argument_closure(&probe.types);
+    let type_check_fn = common::construct_type_check(
+        &provider.name,
+        &probe.name,
+        &provider.use_statements,
+        &probe.types,
+    );
+    let impl_block = quote! {
+        if false {
+            #args
+            #type_check_fn
+        }
+    };
+    common::build_probe_macro(config, probe, quote! { false }, impl_block)
+}
+
+#[cfg(usdt_backend_noop)]
 pub fn register_probes() -> Result<(), crate::Error> {
     Ok(())
 }
+
+// No providers are compiled into the program, so none of the names are checked.
+#[cfg(usdt_backend_noop)]
+pub fn register_providers(_names: &[&str]) -> Result<(), crate::Error> {
+    Ok(())
+}
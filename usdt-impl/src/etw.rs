@@ -0,0 +1,706 @@
+//! The Event Tracing for Windows (ETW) implementation of the USDT crate.
+//!
+//! Each provider is an ETW provider writing self-describing [TraceLogging] events, so that no
+//! manifest needs to be installed for the events to be decoded. Every probe is an event of the
+//! same name, with the probe's arguments as its fields, named `arg0`, `arg1` and so on. Integers
+//! are written as the integer fields of the same width, pointers to integers as the hexadecimal
+//! address they point to, strings as UTF-8 string fields and serializable arguments as string
+//! fields holding their JSON.
+//!
+//! As with .NET's `EventSource`, the GUID of a provider is derived from its name, so that tools
+//! accepting provider names prefixed by `*`, like `wpr` profiles, find it from the name alone.
+//! The providers are registered with ETW by [`register_probes`](crate::register_probes), and are
+//! listed in the linker section `.usdt` along with the records of their probes.
+//!
+//! [TraceLogging]: https://learn.microsoft.com/en-us/windows/win32/tracelogging/trace-logging-portal
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{common, registry, DataType, Probe, Provider};
+use dtrace_parser::{BitWidth, DataType as DType, Sign};
+use proc_macro2::{Literal, TokenStream};
+use quote::quote;
+
+/// The name of the linker section listing the providers, sorted between the bounds of the list.
+const PROVIDER_SECTION: &str = ".usdt$m";
+
+/// Compile a provider into Rust tokens that implement its probes by writing ETW events.
+pub(crate) fn compile_provider(
+    provider: &Provider,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    // Events record the JSON of serialized arguments as strings, so it's never compressed
+    // nor framed.
+    let config = &crate::CompileProvidersConfig {
+        compression: None,
+        frame_arguments: false,
+        ..config.clone()
+    };
+    let probe_impls = provider
+        .probes
+        .iter()
+        .map(|probe| compile_probe(provider, probe, config))
+        .collect::<Vec<_>>();
+    let records = registry::registry_records(provider, |_| is_enabled_check(provider), |_| None);
+    let n_records = provider.probes.len();
+    let [d0, d1, d2, d3, d4, d5, d6, d7, d8, d9, d10, d11, d12, d13, d14, d15] =
+        provider_guid(&provider.name);
+    let data1 = u32::from_le_bytes([d0, d1, d2, d3]);
+    let data2 = u16::from_le_bytes([d4, d5]);
+    let data3 = u16::from_le_bytes([d6, d7]);
+    let name = &provider.name;
+    let metadata = Literal::byte_string(&provider_metadata(&provider.name));
+    let module = config.module_ident();
+    let visibility = config.visibility.tokens();
+    let use_statements = common::provider_use_statements(provider, config);
+    quote! {
+        #visibility mod #module {
+            #use_statements
+            static __USDT_ETW_PROVIDER: ::usdt::etw::Provider = {
+                static PROBE_RECORDS: [::usdt::ProbeRecord; #n_records] = #records;
+                ::usdt::etw::Provider::new(
+                    #name,
+                    ::usdt::etw::Guid {
+                        data1: #data1,
+                        data2: #data2,
+                        data3: #data3,
+                        data4: [#d8, #d9, #d10, #d11, #d12, #d13, #d14, #d15],
+                    },
+                    #metadata,
+                    &PROBE_RECORDS,
+                )
+            };
+            const _: () = {
+                #[link_section = #PROVIDER_SECTION]
+                #[used]
+                static PROVIDER: ::std::option::Option<&::usdt::etw::Provider> =
+                    ::std::option::Option::Some(&__USDT_ETW_PROVIDER);
+            };
+            #(#probe_impls)*
+        }
+    }
+}
+
+fn compile_probe(
+    provider: &Provider,
+    probe: &Probe,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    let (unpacked_args, event_data) = common::unpack_probe_args(
+        &probe.types,
+        config,
+        &quote! { usize },
+        |typ, value| match typ {
+            DataType::Native(DType::String)
+            | DataType::Path
+            | DataType::Display
+            | DataType::VariantName(_)
+            | DataType::Serializable(_) => {
+                quote! {
+                    unsafe {
+                        ::usdt::etw::EventData::c_str(#value as *const ::std::os::raw::c_char)
+                    }
+                }
+            }
+            _ => {
+                let ty = typ.to_rust_ffi_type();
+                quote! { ::usdt::etw::EventData::value(&(#value as #ty)) }
+            }
+        },
+    );
+    let type_check_fn = common::construct_type_check(
+        &provider.name,
+        &probe.name,
+        &provider.use_statements,
+        &probe.types,
+    );
+    let site = site(provider);
+    let metadata = Literal::byte_string(&event_metadata(probe));
+    let impl_block = quote! {
+        #site
+        if __USDT_ETW_SITE.is_enabled() {
+            #unpacked_args
+            #type_check_fn
+            __USDT_ETW_SITE.write(#metadata, &[#(#event_data),*]);
+        }
+    };
+    common::build_probe_macro(config, probe, is_enabled_check(provider), impl_block)
+}
+
+// Return the definition of the site of a probe, `__USDT_ETW_SITE`, through which its provider is
+// found.
+//
+// The probe macros may be called from anywhere in the crate, so rather than referring to the
+// provider's definition by its path, each site looks the provider up by name the first time it's
+// used.
+fn site(provider: &Provider) -> TokenStream {
+    let name = &provider.name;
+    quote! {
+        static __USDT_ETW_SITE: ::usdt::etw::Site = ::usdt::etw::Site::new(#name);
+    }
+}
+
+// Return an expression checking whether the provider is enabled. All of its events are written
+// at the same level and without keywords, so they're enabled or disabled together.
+fn is_enabled_check(provider: &Provider) -> TokenStream {
+    let site = site(provider);
+    quote! {
+        {
+            #site
+            __USDT_ETW_SITE.is_enabled()
+        }
+    }
+}
+
+// The TraceLogging field types.
+const IN_ANSISTRING: u8 = 2;
+const IN_INT8: u8 = 3;
+const IN_UINT8: u8 = 4;
+const IN_INT16: u8 = 5;
+const IN_UINT16: u8 = 6;
+const IN_INT32: u8 = 7;
+const IN_UINT32: u8 = 8;
+const IN_INT64: u8 = 9;
+const IN_UINT64: u8 = 10;
+const IN_HEXINT64: u8 = 21;
+const OUT_JSON: u8 = 12;
+const OUT_UTF8: u8 = 35;
+// Set on the field's input type when it's followed by an output type.
+const CHAIN_FLAG: u8 = 0x80;
+
+/// Return the TraceLogging input type of a probe argument, and the output type it's formatted
+/// with, if any.
+///
+/// Arrays and UUIDs are passed as one argument per element, and must have been expanded.
+fn field_type(typ: &DataType) -> (u8, Option<u8>) {
+    match typ {
+        DataType::Native(DType::Integer(int))
+        | DataType::Discriminant(_, int)
+        | DataType::NonZero(int) => {
+            let types = match int.width {
+                BitWidth::Bit8 => (IN_INT8, IN_UINT8),
+                BitWidth::Bit16 => (IN_INT16, IN_UINT16),
+                BitWidth::Bit32 => (IN_INT32, IN_UINT32),
+                // Windows targets with a probe backend are all 64-bit.
+                BitWidth::Bit64 | BitWidth::Pointer => (IN_INT64, IN_UINT64),
+            };
+            match int.sign {
+                Sign::Signed => (types.0, None),
+                Sign::Unsigned => (types.1, None),
+            }
+        }
+        DataType::Native(DType::Pointer(_)) | DataType::Address(_) => (IN_HEXINT64, None),
+        DataType::Native(DType::String)
+        | DataType::Path
+        | DataType::Display
+        | DataType::VariantName(_) => (IN_ANSISTRING, Some(OUT_UTF8)),
+        DataType::Serializable(_) => (IN_ANSISTRING, Some(OUT_JSON)),
+        DataType::UniqueId | DataType::Duration => (IN_UINT64, None),
+        DataType::Timestamp(_, Sign::Signed) => (IN_INT64, None),
+        DataType::Timestamp(_, Sign::Unsigned) => (IN_UINT64, None),
+        DataType::NativeArray(..) => unreachable!("Arrays are passed as one argument per element"),
+        DataType::Uuid => unreachable!("UUIDs are passed as two arguments"),
+        DataType::SizedSerializable(_) => {
+            unreachable!("Serializable types are passed with their length as two arguments")
+        }
+        DataType::Protobuf(_) => unreachable!("Protobuf messages are passed as two arguments"),
+        DataType::NativeSlice(_) => unreachable!("Slices are passed as three arguments"),
+    }
+}
+
+/// Return the TraceLogging metadata of a provider, which is its size and its name.
+fn provider_metadata(name: &str) -> Vec<u8> {
+    let mut metadata = vec![0, 0];
+    metadata.extend_from_slice(name.as_bytes());
+    metadata.push(0);
+    let len = u16::try_from(metadata.len()).expect("Provider name is too long");
+    metadata[..2].copy_from_slice(&len.to_le_bytes());
+    metadata
+}
+
+/// Return the TraceLogging metadata of the event written by a probe, describing its name and
+/// fields.
+fn event_metadata(probe: &Probe) -> Vec<u8> {
+    // The size, filled in last, and the event's tags.
+    let mut metadata = vec![0, 0, 0];
+    metadata.extend_from_slice(probe.name.replace("__", "-").as_bytes());
+    metadata.push(0);
+    for (i, typ) in probe.argument_types().iter().enumerate() {
+        metadata.extend_from_slice(format!("arg{}", i).as_bytes());
+        metadata.push(0);
+        match field_type(typ) {
+            (in_type, None) => metadata.push(in_type),
+            (in_type, Some(out_type)) => {
+                metadata.extend_from_slice(&[in_type | CHAIN_FLAG, out_type])
+            }
+        }
+    }
+    let len = u16::try_from(metadata.len()).expect("Probe metadata is too long");
+    metadata[..2].copy_from_slice(&len.to_le_bytes());
+    metadata
+}
+
+/// Return the bytes of the GUID of a provider, derived from its name as `EventSource` does.
+///
+/// This is the SHA-1 hash of a fixed namespace followed by the upper-cased name, in big-endian
+/// UTF-16, truncated to 16 bytes and marked as a version 5 GUID. The first three fields of the
+/// GUID are read from the bytes in little-endian order.
+fn provider_guid(name: &str) -> [u8; 16] {
+    const NAMESPACE: [u8; 16] = [
+        0x48, 0x2c, 0x2d, 0xb2, 0xc3, 0x90, 0x47, 0xc8, 0x87, 0xf8, 0x1a, 0x15, 0xbf, 0xc1, 0x30,
+        0xfb,
+    ];
+    let mut input = NAMESPACE.to_vec();
+    for unit in name.to_uppercase().encode_utf16() {
+        input.extend_from_slice(&unit.to_be_bytes());
+    }
+    let mut guid = [0; 16];
+    guid.copy_from_slice(&sha1(&input)[..16]);
+    guid[7] = (guid[7] & 0x0f) | 0x50;
+    guid
+}
+
+// Return the SHA-1 hash of the input.
+fn sha1(input: &[u8]) -> [u8; 20] {
+    let mut state: [u32; 5] = [0x67452301, 0xefcdab89, 0x98badcfe, 0x10325476, 0xc3d2e1f0];
+    let mut message = input.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend_from_slice(&(input.len() as u64 * 8).to_be_bytes());
+    for block in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for (i, word) in block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = state;
+        for (i, word) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5a827999),
+                20..=39 => (b ^ c ^ d, 0x6ed9eba1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8f1bbcdc),
+                _ => (b ^ c ^ d, 0xca62c1d6),
+            };
+            let temp = a
+                .rotate_left(5)
+                .wrapping_add(f)
+                .wrapping_add(e)
+                .wrapping_add(k)
+                .wrapping_add(*word);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    let mut hash = [0; 20];
+    for (chunk, s) in hash.chunks_mut(4).zip(state) {
+        chunk.copy_from_slice(&s.to_be_bytes());
+    }
+    hash
+}
+
+#[cfg(usdt_backend_etw)]
+pub(crate) use runtime::{register_probes, register_providers};
+
+/// The types used by the code generated for ETW providers, re-exported as `usdt::etw`.
+#[cfg(usdt_backend_etw)]
+pub mod runtime {
+    use crate::registry::ProbeRecord;
+    use std::ffi::{c_void, CStr};
+    use std::os::raw::c_char;
+    use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+    use std::sync::Mutex;
+
+    /// The GUID identifying an ETW provider.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct Guid {
+        pub data1: u32,
+        pub data2: u16,
+        pub data3: u16,
+        pub data4: [u8; 8],
+    }
+
+    #[repr(C)]
+    struct EventDescriptor {
+        id: u16,
+        version: u8,
+        channel: u8,
+        level: u8,
+        opcode: u8,
+        task: u16,
+        keyword: u64,
+    }
+
+    // Every event is written at the verbose level, without any keywords, on the channel marking
+    // it as a TraceLogging event.
+    const EVENT_DESCRIPTOR: EventDescriptor = EventDescriptor {
+        id: 0,
+        version: 0,
+        channel: 11,
+        level: 5,
+        opcode: 0,
+        task: 0,
+        keyword: 0,
+    };
+
+    const EVENT_PROVIDER_SET_TRAITS: i32 = 2;
+    const DESCRIPTOR_TYPE_EVENT_METADATA: u32 = 1;
+    const DESCRIPTOR_TYPE_PROVIDER_METADATA: u32 = 2;
+
+    #[link(name = "advapi32")]
+    unsafe extern "system" {
+        fn EventRegister(
+            provider_id: *const Guid,
+            enable_callback: *const c_void,
+            callback_context: *mut c_void,
+            reg_handle: *mut u64,
+        ) -> u32;
+        fn EventSetInformation(
+            reg_handle: u64,
+            information_class: i32,
+            event_information: *const c_void,
+            information_length: u32,
+        ) -> u32;
+        fn EventEnabled(reg_handle: u64, event_descriptor: *const EventDescriptor) -> u8;
+        fn EventWriteTransfer(
+            reg_handle: u64,
+            event_descriptor: *const EventDescriptor,
+            activity_id: *const Guid,
+            related_activity_id: *const Guid,
+            user_data_count: u32,
+            user_data: *const EventData,
+        ) -> u32;
+    }
+
+    /// An ETW provider, and the records of its probes.
+    #[derive(Debug)]
+    pub struct Provider {
+        name: &'static str,
+        guid: Guid,
+        metadata: &'static [u8],
+        records: &'static [ProbeRecord],
+        handle: AtomicU64,
+    }
+
+    impl Provider {
+        pub const fn new(
+            name: &'static str,
+            guid: Guid,
+            metadata: &'static [u8],
+            records: &'static [ProbeRecord],
+        ) -> Self {
+            Self {
+                name,
+                guid,
+                metadata,
+                records,
+                handle: AtomicU64::new(0),
+            }
+        }
+
+        // Register the provider with ETW, unless it already is.
+        fn register(&self) -> Result<(), crate::Error> {
+            if self.handle.load(Ordering::Acquire) != 0 {
+                return Ok(());
+            }
+            let mut handle = 0;
+            let status = unsafe {
+                EventRegister(
+                    &self.guid,
+                    std::ptr::null(),
+                    std::ptr::null_mut(),
+                    &mut handle,
+                )
+            };
+            if status != 0 {
+                return Err(std::io::Error::from_raw_os_error(status as i32).into());
+            }
+            // The traits only name the provider, so failing to set them doesn't prevent events
+            // from being written.
+            unsafe {
+                EventSetInformation(
+                    handle,
+                    EVENT_PROVIDER_SET_TRAITS,
+                    self.metadata.as_ptr().cast(),
+                    self.metadata.len() as u32,
+                );
+            }
+            self.handle.store(handle, Ordering::Release);
+            Ok(())
+        }
+
+        fn is_enabled(&self) -> bool {
+            let handle = self.handle.load(Ordering::Acquire);
+            handle != 0 && unsafe { EventEnabled(handle, &EVENT_DESCRIPTOR) } != 0
+        }
+
+        fn write(&self, metadata: &'static [u8], fields: &[EventData]) {
+            const MAX_DATA: usize = 2 + crate::MAX_PROBE_ARGUMENTS;
+            let mut data = [EventData::EMPTY; MAX_DATA];
+            data[0] = EventData::metadata(self.metadata, DESCRIPTOR_TYPE_PROVIDER_METADATA);
+            data[1] = EventData::metadata(metadata, DESCRIPTOR_TYPE_EVENT_METADATA);
+            data[2..2 + fields.len()].copy_from_slice(fields);
+            let handle = self.handle.load(Ordering::Acquire);
+            unsafe {
+                EventWriteTransfer(
+                    handle,
+                    &EVENT_DESCRIPTOR,
+                    std::ptr::null(),
+                    std::ptr::null(),
+                    (2 + fields.len()) as u32,
+                    data.as_ptr(),
+                );
+            }
+        }
+    }
+
+    /// The site of a probe, writing the events of the provider of the given name.
+    #[derive(Debug)]
+    pub struct Site {
+        provider: &'static str,
+        cached: AtomicPtr<Provider>,
+    }
+
+    impl Site {
+        pub const fn new(provider: &'static str) -> Self {
+            Self {
+                provider,
+                cached: AtomicPtr::new(std::ptr::null_mut()),
+            }
+        }
+
+        // Return the provider written to, looking it up the first time.
+        fn provider(&self) -> Option<&'static Provider> {
+            let cached = self.cached.load(Ordering::Acquire);
+            if !cached.is_null() {
+                return Some(unsafe { &*cached });
+            }
+            let provider = providers().find(|provider| provider.name == self.provider)?;
+            self.cached
+                .store((provider as *const Provider).cast_mut(), Ordering::Release);
+            Some(provider)
+        }
+
+        /// Return `true` if an ETW session is collecting the events of the provider.
+        pub fn is_enabled(&self) -> bool {
+            self.provider().is_some_and(Provider::is_enabled)
+        }
+
+        /// Write the event described by the given metadata, with the given fields.
+        pub fn write(&self, metadata: &'static [u8], fields: &[EventData]) {
+            if let Some(provider) = self.provider() {
+                provider.write(metadata, fields);
+            }
+        }
+    }
+
+    /// A field of an ETW event, pointing to its data.
+    #[repr(C)]
+    #[derive(Debug, Clone, Copy)]
+    pub struct EventData {
+        ptr: u64,
+        size: u32,
+        kind: u32,
+    }
+
+    impl EventData {
+        const EMPTY: Self = Self {
+            ptr: 0,
+            size: 0,
+            kind: 0,
+        };
+
+        fn metadata(metadata: &'static [u8], kind: u32) -> Self {
+            Self {
+                ptr: metadata.as_ptr() as u64,
+                size: metadata.len() as u32,
+                kind,
+            }
+        }
+
+        /// A field holding the bytes of the given value.
+        pub fn value<T: Copy>(value: &T) -> Self {
+            Self {
+                ptr: (value as *const T) as u64,
+                size: std::mem::size_of::<T>() as u32,
+                kind: 0,
+            }
+        }
+
+        /// A field holding a null-terminated string, including its terminator.
+        ///
+        /// # Safety
+        ///
+        /// The pointer must point to a null-terminated string, which outlives the field.
+        pub unsafe fn c_str(ptr: *const c_char) -> Self {
+            Self {
+                ptr: ptr as u64,
+                size: unsafe { CStr::from_ptr(ptr) }.to_bytes_with_nul().len() as u32,
+                kind: 0,
+            }
+        }
+    }
+
+    // The list of providers is sorted by the linker between these bounds, which are merged into
+    // the `.usdt` section along with it. The list may be padded with zeros.
+    #[link_section = ".usdt$a"]
+    #[used]
+    static PROVIDERS_START: [Option<&Provider>; 0] = [];
+
+    #[link_section = ".usdt$z"]
+    #[used]
+    static PROVIDERS_STOP: [Option<&Provider>; 0] = [];
+
+    // Return the providers compiled into the module.
+    fn providers() -> impl Iterator<Item = &'static Provider> {
+        let start = PROVIDERS_START.as_ptr();
+        let stop = PROVIDERS_STOP.as_ptr();
+        let providers =
+            unsafe { std::slice::from_raw_parts(start, stop.offset_from(start) as usize) };
+        providers.iter().flatten().copied()
+    }
+
+    /// Return the records of the probes of every provider compiled into the module.
+    pub(crate) fn probe_records() -> &'static [ProbeRecord] {
+        static RECORDS: std::sync::OnceLock<Vec<ProbeRecord>> = std::sync::OnceLock::new();
+        RECORDS.get_or_init(|| {
+            providers()
+                .flat_map(|provider| provider.records.iter().cloned())
+                .collect()
+        })
+    }
+
+    // Serialize registration, so that a provider is only registered once.
+    static REGISTRATION: Mutex<()> = Mutex::new(());
+
+    pub(crate) fn register_probes() -> Result<(), crate::Error> {
+        let _guard = REGISTRATION.lock().unwrap_or_else(|e| e.into_inner());
+        providers().try_for_each(Provider::register)
+    }
+
+    pub(crate) fn register_providers(names: &[&str]) -> Result<(), crate::Error> {
+        crate::registry::check_providers(names)?;
+        let _guard = REGISTRATION.lock().unwrap_or_else(|e| e.into_inner());
+        providers()
+            .filter(|provider| names.contains(&provider.name))
+            .try_for_each(Provider::register)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtrace_parser::Integer;
+
+    fn test_probe() -> Probe {
+        Probe {
+            name: String::from("start__work"),
+            types: vec![
+                DataType::Native(DType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                })),
+                DataType::Native(DType::String),
+                DataType::Serializable(syn::parse_str("&Foo").unwrap()),
+                DataType::Uuid,
+            ],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        }
+    }
+
+    #[test]
+    fn test_sha1() {
+        let hex = |hash: [u8; 20]| {
+            hash.iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<Vec<_>>()
+                .concat()
+        };
+        assert_eq!(hex(sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(
+            hex(sha1(b"abc")),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            hex(sha1(&[b'a'; 1000])),
+            "291e9a6c66994949b57ba5e650361e98fc36b1ba"
+        );
+    }
+
+    // The GUID of .NET's `DiagnosticSource` event source, which is derived from its name.
+    #[test]
+    fn test_provider_guid() {
+        assert_eq!(
+            provider_guid("Microsoft-Diagnostics-DiagnosticSource"),
+            [
+                0xe1, 0x01, 0xb4, 0xad, 0x96, 0x52, 0xf8, 0x51, 0xc1, 0x25, 0x5f, 0xda, 0x75, 0x82,
+                0x61, 0x44
+            ]
+        );
+    }
+
+    #[test]
+    fn test_provider_metadata() {
+        assert_eq!(provider_metadata("prov"), b"\x07\x00prov\x00");
+    }
+
+    #[test]
+    fn test_event_metadata() {
+        let expected = b"\x2e\x00\x00start-work\x00\
+            arg0\x00\x04\
+            arg1\x00\x82\x23\
+            arg2\x00\x82\x0c\
+            arg3\x00\x0a\
+            arg4\x00\x0a";
+        assert_eq!(event_metadata(&test_probe()), expected);
+    }
+
+    #[test]
+    fn test_compile_probe_writes_event() {
+        let provider = Provider {
+            name: String::from("prov"),
+            probes: vec![test_probe()],
+            use_statements: vec![],
+            attributes: Default::default(),
+        };
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("prov")),
+            module: Some(String::from("prov")),
+            ..Default::default()
+        };
+        let output = compile_provider(&provider, &config).to_string();
+        assert!(output.contains(&quote! { #[link_section = ".usdt$m"] }.to_string()));
+        assert!(output.contains(&quote! { __USDT_ETW_SITE.write }.to_string()));
+        assert!(output.contains(&quote! { ::usdt::etw::EventData::c_str }.to_string()));
+        assert!(output.contains(
+            &quote! { ::usdt::etw::EventData::value(&((arg_3 .0 as usize) as ::std::os::raw::c_ulonglong)) }
+                .to_string()
+        ));
+    }
+}
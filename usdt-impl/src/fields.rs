@@ -0,0 +1,120 @@
+//! Key/value pairs passed to probes as JSON objects.
+//!
+//! A probe argument declared as `impl usdt::Fields` accepts an array, slice or vector of pairs of
+//! a key and a serializable value, such as `[("method", "GET"), ("path", "/")]`. The pairs are
+//! passed to the probe as the JSON of an object with one member per pair, in the order they're
+//! given, like any other serializable argument: `{"ok":{"method":"GET","path":"/"}}`. This suits
+//! log-like probes, whose events needn't each be described by a struct of their own.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::{Serialize, Serializer};
+
+/// Key/value pairs passed to a probe as the members of a JSON object.
+///
+/// This is implemented for arrays, slices and vectors of pairs of a string key and a serializable
+/// value, and references to them. The values of the pairs all have the same type, so values of
+/// different types may be passed as `&dyn usdt::ErasedSerialize` or `serde_json::Value`. Keys are
+/// written in the order they're given, and repeated keys aren't merged.
+pub trait Fields {
+    /// The type of the keys.
+    type Key: AsRef<str>;
+    /// The type of the values.
+    type Value: Serialize;
+
+    /// Return the pairs of keys and values.
+    fn fields(&self) -> &[(Self::Key, Self::Value)];
+}
+
+impl<K: AsRef<str>, V: Serialize> Fields for [(K, V)] {
+    type Key = K;
+    type Value = V;
+
+    fn fields(&self) -> &[(K, V)] {
+        self
+    }
+}
+
+impl<K: AsRef<str>, V: Serialize, const N: usize> Fields for [(K, V); N] {
+    type Key = K;
+    type Value = V;
+
+    fn fields(&self) -> &[(K, V)] {
+        self
+    }
+}
+
+impl<K: AsRef<str>, V: Serialize> Fields for Vec<(K, V)> {
+    type Key = K;
+    type Value = V;
+
+    fn fields(&self) -> &[(K, V)] {
+        self
+    }
+}
+
+impl<T: Fields + ?Sized> Fields for &T {
+    type Key = T::Key;
+    type Value = T::Value;
+
+    fn fields(&self) -> &[(T::Key, T::Value)] {
+        (**self).fields()
+    }
+}
+
+/// Key/value pairs serialized as a map.
+#[doc(hidden)]
+pub struct SerializeFields<'a, T: ?Sized>(pub &'a T);
+
+impl<T: Fields + ?Sized> Serialize for SerializeFields<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.fields().iter().map(|(k, v)| (k.as_ref(), v)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErasedSerialize;
+
+    #[test]
+    fn test_serialize_fields() {
+        let fields = [("b", 1), ("a", 2)];
+        assert_eq!(
+            serde_json::to_string(&SerializeFields(&fields)).unwrap(),
+            r#"{"b":1,"a":2}"#
+        );
+        let fields = vec![(String::from("id"), 3)];
+        assert_eq!(
+            serde_json::to_string(&SerializeFields(&&fields)).unwrap(),
+            r#"{"id":3}"#
+        );
+        let fields: &[(&str, u8)] = &[];
+        assert_eq!(
+            serde_json::to_string(&SerializeFields(fields)).unwrap(),
+            "{}"
+        );
+    }
+
+    #[test]
+    fn test_serialize_fields_of_different_types() {
+        let fields: [(&str, &dyn ErasedSerialize); 3] =
+            [("method", &"GET"), ("status", &200), ("tags", &["a", "b"])];
+        assert_eq!(
+            serde_json::to_string(&SerializeFields(&fields)).unwrap(),
+            r#"{"method":"GET","status":200,"tags":["a","b"]}"#
+        );
+    }
+}
@@ -0,0 +1,83 @@
+//! Framing of serialized probe arguments.
+//!
+//! A provider compiled with `frame_arguments = true` prepends a header of [`FRAMED_ARG_HEADER_LEN`]
+//! bytes to the JSON of each serializable argument, so that a consumer can tell which encoding an
+//! argument is in, and which version of it, before decoding it:
+//!
+//! | Offset | Size | Contents                                                     |
+//! |--------|------|--------------------------------------------------------------|
+//! | 0      | 1    | [`FRAMED_ARG_MAGIC`], `0xfe`                                 |
+//! | 1      | 1    | The format, [`FRAMED_ARG_FORMAT_JSON`] for JSON              |
+//! | 2      | 1    | [`FRAMED_ARG_VERSION`] of the format, currently 1            |
+//! | 3      | 1    | Reserved, always zero                                        |
+//!
+//! The header is followed by the null-terminated payload, so that a JSON argument may still be
+//! read with `copyinstr(arg0 + 4)`. The header is written into the buffer the JSON is serialized
+//! into, ahead of it, so framing doesn't copy the JSON. With `json_len`, the length passed is that
+//! of the header and the JSON, without the null byte. As `0xfe` never appears in UTF-8, the first byte tells a
+//! framed argument from a bare JSON string, and from a compressed argument, whose own header
+//! starts with `0xff` and which is never framed. The layout of the header and the meaning of
+//! format 1, version 1 won't change: a new encoding gets a format of its own, and a change to an
+//! encoding a new version.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// The first byte of a framed probe argument.
+pub const FRAMED_ARG_MAGIC: u8 = 0xfe;
+
+/// The format of a framed probe argument holding JSON wrapped in `{"ok":...}` or `{"err":...}`.
+pub const FRAMED_ARG_FORMAT_JSON: u8 = 1;
+
+/// The version of the format of framed JSON arguments.
+pub const FRAMED_ARG_VERSION: u8 = 1;
+
+/// The length of the header preceding the payload of a framed probe argument.
+pub const FRAMED_ARG_HEADER_LEN: usize = 4;
+
+// The header of framed JSON.
+const FRAMED_JSON_HEADER: [u8; FRAMED_ARG_HEADER_LEN] = [
+    FRAMED_ARG_MAGIC,
+    FRAMED_ARG_FORMAT_JSON,
+    FRAMED_ARG_VERSION,
+    0,
+];
+
+/// Serialize a probe argument into framed JSON, the header followed by the null-terminated JSON
+/// [`to_json_probe_arg`](crate::to_json_probe_arg) passes, in the same buffer.
+#[doc(hidden)]
+pub fn to_framed_json_probe_arg<T>(x: &T, max_len: usize) -> crate::JsonProbeArg
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    crate::json_probe_arg(x, max_len, &FRAMED_JSON_HEADER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_framed_json_probe_arg() {
+        let arg = to_framed_json_probe_arg(&[1, 2, 3], 1024);
+        assert_eq!(&*arg, b"\xfe\x01\x01\x00{\"ok\":[1,2,3]}\0");
+        assert_eq!(arg.arg_len(), FRAMED_ARG_HEADER_LEN + 14);
+        assert!(std::str::from_utf8(&arg).is_err());
+
+        // A truncated argument keeps its header.
+        let arg = to_framed_json_probe_arg(&[1, 2, 3], 6);
+        assert_eq!(&arg[..FRAMED_ARG_HEADER_LEN], FRAMED_JSON_HEADER);
+        assert!(arg[FRAMED_ARG_HEADER_LEN..].starts_with(b"{\"ok\":{\"__truncated\":true"));
+    }
+}
@@ -0,0 +1,166 @@
+//! Serializing probe arguments without allocating, with the `json-core` feature.
+//!
+//! Rather than `serde_json` and a growable buffer, the JSON of each serializable argument is
+//! written by `serde-json-core` into a buffer of [`JSON_CORE_BUFFER_LEN`] bytes on the stack. The
+//! JSON is wrapped in an `{"ok":...}` object, as it is by default. When it doesn't fit, or is
+//! longer than the provider's maximum serialized length, it's replaced by
+//! `{"ok":{"__truncated":true}}`. Unlike the marker of the default path, this has no `len`, since
+//! serialization stops as soon as the buffer is full.
+//!
+//! `serde-json-core` supports fewer types than `serde_json`: it panics on `char`s, tuple variants,
+//! maps with keys other than strings, and `Serialize` implementations returning an error. Probes
+//! taking such arguments must not be fired with this feature enabled.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+/// The length of the buffer the JSON of a probe argument is written into, its wrapping object
+/// and null byte included.
+pub const JSON_CORE_BUFFER_LEN: usize = 1024;
+
+/// Serialize a probe argument into the null-terminated JSON passed to the probe.
+///
+/// The JSON is wrapped in an object mapping it to the key "ok", and written into a buffer on the
+/// stack. Arguments longer than `max_len`, or than the buffer, are replaced by a marker.
+#[doc(hidden)]
+pub fn to_json_probe_arg<T>(x: &T, max_len: usize) -> JsonProbeArg
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    json_probe_arg(x, max_len, &[])
+}
+
+// Serialize a probe argument as by `to_json_probe_arg`, following the given header in the same
+// buffer.
+pub(crate) fn json_probe_arg<T>(x: &T, max_len: usize, header: &[u8]) -> JsonProbeArg
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    const PREFIX: &[u8] = b"{\"ok\":";
+    const SUFFIX: &[u8] = b"}\0";
+    const TRUNCATED: &[u8] = b"{\"ok\":{\"__truncated\":true}}\0";
+    let mut arg = JsonProbeArg {
+        buf: [0; JSON_CORE_BUFFER_LEN],
+        len: 0,
+    };
+    arg.buf[..header.len()].copy_from_slice(header);
+    let start = header.len() + PREFIX.len();
+    arg.buf[header.len()..start].copy_from_slice(PREFIX);
+    let end = start
+        .saturating_add(max_len)
+        .min(JSON_CORE_BUFFER_LEN - SUFFIX.len());
+    match ::serde_json_core::to_slice(x, &mut arg.buf[start..end]) {
+        Ok(len) => {
+            let len = start + len;
+            arg.buf[len..][..SUFFIX.len()].copy_from_slice(SUFFIX);
+            arg.len = len + SUFFIX.len();
+        }
+        // The only error is a full buffer.
+        Err(_) => {
+            arg.buf[header.len()..][..TRUNCATED.len()].copy_from_slice(TRUNCATED);
+            arg.len = header.len() + TRUNCATED.len();
+        }
+    }
+    arg
+}
+
+/// The null-terminated JSON of a probe argument, in a buffer on the stack.
+#[doc(hidden)]
+pub struct JsonProbeArg {
+    buf: [u8; JSON_CORE_BUFFER_LEN],
+    len: usize,
+}
+
+impl JsonProbeArg {
+    /// Return the length of the argument without its null byte, as passed with `json_len`.
+    pub fn arg_len(&self) -> usize {
+        self.len - 1
+    }
+}
+
+impl std::ops::Deref for JsonProbeArg {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(serde::Serialize)]
+    struct Request<'a> {
+        id: u64,
+        path: &'a str,
+        tags: [&'a str; 2],
+    }
+
+    #[test]
+    fn test_to_json_probe_arg() {
+        let request = Request {
+            id: 7,
+            path: "/a\"b",
+            tags: ["x", "y"],
+        };
+        assert_eq!(
+            &*to_json_probe_arg(&request, 1024),
+            b"{\"ok\":{\"id\":7,\"path\":\"/a\\\"b\",\"tags\":[\"x\",\"y\"]}}\0"
+        );
+    }
+
+    #[derive(serde::Serialize)]
+    struct Attributed {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        user: Option<u64>,
+        #[serde(serialize_with = "serialize_negated")]
+        flag: bool,
+    }
+
+    fn serialize_negated<S: serde::Serializer>(
+        flag: &bool,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bool(!flag)
+    }
+
+    #[test]
+    fn test_to_json_probe_arg_serde_attributes() {
+        let value = Attributed {
+            user: None,
+            flag: true,
+        };
+        assert_eq!(
+            &*to_json_probe_arg(&value, 1024),
+            b"{\"ok\":{\"flag\":false}}\0"
+        );
+    }
+
+    #[test]
+    fn test_to_json_probe_arg_truncated() {
+        const TRUNCATED: &[u8] = b"{\"ok\":{\"__truncated\":true}}\0";
+        assert_eq!(&*to_json_probe_arg(&[1, 2, 3], 7), b"{\"ok\":[1,2,3]}\0");
+        assert_eq!(&*to_json_probe_arg(&[1, 2, 3], 6), TRUNCATED);
+
+        // The buffer bounds the JSON, whatever the maximum length.
+        let long = "x".repeat(JSON_CORE_BUFFER_LEN);
+        assert_eq!(&*to_json_probe_arg(&long, usize::MAX), TRUNCATED);
+        let fits = "x".repeat(JSON_CORE_BUFFER_LEN - 10);
+        assert_eq!(
+            to_json_probe_arg(&fits, usize::MAX).len(),
+            JSON_CORE_BUFFER_LEN
+        );
+    }
+}
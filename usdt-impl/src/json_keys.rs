@@ -0,0 +1,474 @@
+//! Renaming the keys of serializable probe arguments.
+//!
+//! A provider compiled with `rename_json_keys`, such as `"request_id=id,timestamp=ts"`, serializes
+//! each of its serializable arguments through [`RenameKeys`], which renames the fields of structs
+//! and struct variants, and the string keys of maps, as they're serialized. Nested values are
+//! renamed too, while string values and other keys are left as they are. The type's own
+//! `Serialize` implementation, and so its JSON anywhere but in probes, is unchanged.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::ser::{
+    Serialize, SerializeMap, SerializeSeq, SerializeStruct, SerializeStructVariant, SerializeTuple,
+    SerializeTupleStruct, SerializeTupleVariant, Serializer,
+};
+
+// Deserialize a comma-separated list of keys and the keys they're renamed to, such as
+// `"request_id=id,timestamp=ts"`.
+pub(crate) fn deserialize_renames<'de, D>(
+    deserializer: D,
+) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let list = <String as serde::Deserialize>::deserialize(deserializer)?;
+    let mut renames: Vec<(String, String)> = Vec::new();
+    for rename in list.split(',') {
+        let (from, to) = match rename.split_once('=') {
+            Some((from, to)) if !from.trim().is_empty() && !to.trim().is_empty() => {
+                (from.trim(), to.trim())
+            }
+            _ => {
+                return Err(serde::de::Error::custom(format!(
+                    "Invalid key renaming \"{}\", expected \"key=renamed\"",
+                    rename.trim()
+                )))
+            }
+        };
+        if renames.iter().any(|(other, _)| other == from) {
+            return Err(serde::de::Error::custom(format!(
+                "The key \"{}\" is renamed more than once",
+                from
+            )));
+        }
+        renames.push((from.to_string(), to.to_string()));
+    }
+    Ok(renames)
+}
+
+/// The renamed keys, as pairs of a key and the key it's renamed to.
+pub type Renames = &'static [(&'static str, &'static str)];
+
+/// A value serialized with some of its keys renamed.
+#[doc(hidden)]
+pub struct RenameKeys<'a, T: ?Sized>(pub &'a T, pub Renames);
+
+impl<T: ?Sized + Serialize> Serialize for RenameKeys<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(Renaming {
+            inner: serializer,
+            renames: self.1,
+            key: false,
+        })
+    }
+}
+
+// Return the name a key is renamed to, or the key itself.
+fn rename(renames: Renames, key: &str) -> &str {
+    renames
+        .iter()
+        .find(|(from, _)| *from == key)
+        .map_or(key, |(_, to)| to)
+}
+
+// A value nested in another, serialized with the same renames. `key` is set for the keys of maps.
+struct Nested<'a, T: ?Sized> {
+    value: &'a T,
+    renames: Renames,
+    key: bool,
+}
+
+impl<T: ?Sized + Serialize> Serialize for Nested<'_, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.value.serialize(Renaming {
+            inner: serializer,
+            renames: self.renames,
+            key: self.key,
+        })
+    }
+}
+
+// A serializer forwarding to another, renaming keys along the way.
+struct Renaming<S> {
+    inner: S,
+    renames: Renames,
+    key: bool,
+}
+
+impl<S> Renaming<S> {
+    fn nested<'a, T: ?Sized>(&self, value: &'a T) -> Nested<'a, T> {
+        Nested {
+            value,
+            renames: self.renames,
+            key: false,
+        }
+    }
+}
+
+// A compound value being serialized, whose elements are serialized with the same renames.
+struct Compound<C> {
+    inner: C,
+    renames: Renames,
+}
+
+impl<C> Compound<C> {
+    fn nested<'a, T: ?Sized>(&self, value: &'a T, key: bool) -> Nested<'a, T> {
+        Nested {
+            value,
+            renames: self.renames,
+            key,
+        }
+    }
+}
+
+macro_rules! forward {
+    ($($method:ident($ty:ty)),* $(,)?) => {
+        $(
+            fn $method(self, v: $ty) -> Result<S::Ok, S::Error> {
+                self.inner.$method(v)
+            }
+        )*
+    };
+}
+
+impl<S: Serializer> Serializer for Renaming<S> {
+    type Ok = S::Ok;
+    type Error = S::Error;
+    type SerializeSeq = Compound<S::SerializeSeq>;
+    type SerializeTuple = Compound<S::SerializeTuple>;
+    type SerializeTupleStruct = Compound<S::SerializeTupleStruct>;
+    type SerializeTupleVariant = Compound<S::SerializeTupleVariant>;
+    type SerializeMap = Compound<S::SerializeMap>;
+    type SerializeStruct = Compound<S::SerializeStruct>;
+    type SerializeStructVariant = Compound<S::SerializeStructVariant>;
+
+    forward! {
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_i128(i128),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_u128(u128),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_bytes(&[u8]),
+    }
+
+    fn serialize_str(self, v: &str) -> Result<S::Ok, S::Error> {
+        match self.key {
+            true => self.inner.serialize_str(rename(self.renames, v)),
+            false => self.inner.serialize_str(v),
+        }
+    }
+
+    fn serialize_none(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_none()
+    }
+
+    fn serialize_some<T: ?Sized + Serialize>(self, value: &T) -> Result<S::Ok, S::Error> {
+        let value = self.nested(value);
+        self.inner.serialize_some(&value)
+    }
+
+    fn serialize_unit(self) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit()
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<S::Ok, S::Error> {
+        self.inner.serialize_unit_struct(name)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+    ) -> Result<S::Ok, S::Error> {
+        self.inner
+            .serialize_unit_variant(name, variant_index, variant)
+    }
+
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        // A newtype around a key is serialized as the key itself.
+        let value = Nested {
+            value,
+            renames: self.renames,
+            key: self.key,
+        };
+        self.inner.serialize_newtype_struct(name, &value)
+    }
+
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<S::Ok, S::Error> {
+        let value = self.nested(value);
+        self.inner
+            .serialize_newtype_variant(name, variant_index, variant, &value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<Self::SerializeSeq, S::Error> {
+        let renames = self.renames;
+        let inner = self.inner.serialize_seq(len)?;
+        Ok(Compound { inner, renames })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, S::Error> {
+        let renames = self.renames;
+        let inner = self.inner.serialize_tuple(len)?;
+        Ok(Compound { inner, renames })
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, S::Error> {
+        let renames = self.renames;
+        let inner = self.inner.serialize_tuple_struct(name, len)?;
+        Ok(Compound { inner, renames })
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, S::Error> {
+        let renames = self.renames;
+        let inner = self
+            .inner
+            .serialize_tuple_variant(name, variant_index, variant, len)?;
+        Ok(Compound { inner, renames })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, S::Error> {
+        let renames = self.renames;
+        let inner = self.inner.serialize_map(len)?;
+        Ok(Compound { inner, renames })
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, S::Error> {
+        let renames = self.renames;
+        let inner = self.inner.serialize_struct(name, len)?;
+        Ok(Compound { inner, renames })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, S::Error> {
+        let renames = self.renames;
+        let inner = self
+            .inner
+            .serialize_struct_variant(name, variant_index, variant, len)?;
+        Ok(Compound { inner, renames })
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+impl<C: SerializeSeq> SerializeSeq for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.nested(value, false);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTuple> SerializeTuple for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_element<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.nested(value, false);
+        self.inner.serialize_element(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleStruct> SerializeTupleStruct for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.nested(value, false);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeTupleVariant> SerializeTupleVariant for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.nested(value, false);
+        self.inner.serialize_field(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeMap> SerializeMap for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_key<T: ?Sized + Serialize>(&mut self, key: &T) -> Result<(), C::Error> {
+        let key = self.nested(key, true);
+        self.inner.serialize_key(&key)
+    }
+
+    fn serialize_value<T: ?Sized + Serialize>(&mut self, value: &T) -> Result<(), C::Error> {
+        let value = self.nested(value, false);
+        self.inner.serialize_value(&value)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStruct> SerializeStruct for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let key = rename(self.renames, key);
+        let value = self.nested(value, false);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        let key = rename(self.renames, key);
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+impl<C: SerializeStructVariant> SerializeStructVariant for Compound<C> {
+    type Ok = C::Ok;
+    type Error = C::Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), C::Error> {
+        let key = rename(self.renames, key);
+        let value = self.nested(value, false);
+        self.inner.serialize_field(key, &value)
+    }
+
+    fn skip_field(&mut self, key: &'static str) -> Result<(), C::Error> {
+        let key = rename(self.renames, key);
+        self.inner.skip_field(key)
+    }
+
+    fn end(self) -> Result<C::Ok, C::Error> {
+        self.inner.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serde::Serialize;
+    use std::collections::BTreeMap;
+
+    #[derive(Serialize)]
+    struct Inner {
+        request_id: u64,
+        name: &'static str,
+    }
+
+    #[derive(Serialize)]
+    enum Event {
+        Done { request_id: u64 },
+    }
+
+    #[derive(Serialize)]
+    struct Outer {
+        inner: Inner,
+        events: Vec<Event>,
+        #[serde(flatten)]
+        extra: BTreeMap<&'static str, &'static str>,
+    }
+
+    #[test]
+    fn test_rename_keys() {
+        const RENAMES: Renames = &[("request_id", "id"), ("inner", "in"), ("region", "r")];
+        let value = Outer {
+            inner: Inner {
+                request_id: 1,
+                name: "request_id",
+            },
+            events: vec![Event::Done { request_id: 2 }],
+            extra: BTreeMap::from([("region", "inner")]),
+        };
+        assert_eq!(
+            serde_json::to_string(&RenameKeys(&value, RENAMES)).unwrap(),
+            r#"{"in":{"id":1,"name":"request_id"},"events":[{"Done":{"id":2}}],"r":"inner"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"inner":{"request_id":1,"name":"request_id"},"events":[{"Done":{"request_id":2}}],"region":"inner"}"#
+        );
+    }
+}
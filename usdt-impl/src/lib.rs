@@ -15,22 +15,73 @@
 // limitations under the License.
 
 use serde::Deserialize;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use syn::spanned::Spanned;
 use thiserror::Error;
 
-// Probe record parsing required for standard backend (and `des` feature used by `dusty util)
-#[cfg(any(usdt_backend_standard, usdt_backend_stapsdt, feature = "des"))]
+pub mod compression;
+mod fields;
+pub mod framing;
+#[cfg(feature = "json-core")]
+pub mod json_core;
+mod json_keys;
+pub mod manifest;
 pub mod record;
+pub mod registry;
+pub mod runtime;
+#[cfg(feature = "backtrace")]
+pub mod stack_trace;
 
-#[cfg_attr(usdt_backend_noop, path = "empty.rs")]
-#[cfg_attr(usdt_backend_linker, path = "linker.rs")]
-#[cfg_attr(usdt_backend_standard, path = "no-linker.rs")]
-#[cfg_attr(usdt_backend_stapsdt, path = "stapsdt.rs")]
-mod internal;
+// The code generation of every backend is always compiled, since probes are generated for the
+// compilation target, which need not be the host running the code generation. See `target` for
+// details.
+mod empty;
+#[doc(hidden)]
+pub mod etw;
+mod linker;
+mod lttng;
+#[path = "no-linker.rs"]
+mod no_linker;
+mod stapsdt;
+mod target;
+
+#[cfg(feature = "gzip")]
+pub use compression::gzip_probe_arg;
+#[cfg(feature = "zstd")]
+pub use compression::zstd_probe_arg;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+pub use compression::CompressedProbeArg;
+pub use compression::Compression;
+pub use fields::{Fields, SerializeFields};
+pub use framing::to_framed_json_probe_arg;
+#[cfg(feature = "json-core")]
+use json_core::json_probe_arg;
+#[cfg(feature = "json-core")]
+pub use json_core::{to_json_probe_arg, JsonProbeArg};
+pub use json_keys::RenameKeys;
+#[cfg(target_os = "linux")]
+pub use registry::enabled_count;
+pub use registry::{is_enabled, probes, provider_enabled, ProbeInfo, Registration};
+#[cfg(feature = "backtrace")]
+pub use stack_trace::{Backtrace, MAX_BACKTRACE_FRAMES};
+pub use target::Arch;
+
+// Registration of probes happens in the target process itself, so it's only compiled for the
+// backend selected for the target of this crate.
+#[cfg(usdt_backend_noop)]
+use empty as internal;
+#[cfg(usdt_backend_etw)]
+use etw as internal;
+#[cfg(usdt_backend_linker)]
+use linker as internal;
+#[cfg(usdt_backend_standard)]
+use no_linker as internal;
+#[cfg(usdt_backend_stapsdt)]
+use stapsdt as internal;
 
-// Since the `empty` is mostly a no-op, parts of the common code will go unused when it is
-// selected for use.
-#[cfg_attr(usdt_backend_noop, allow(dead_code))]
 mod common;
 
 /// Register an application's probe points with DTrace.
@@ -45,10 +96,81 @@ mod common;
 /// problem, but each comes with significant tradeoffs. Library developers are encouraged to
 /// re-export this function and document to their users that this function should be called to
 /// guarantee that the library's probes are registered.
+///
+/// A provider of the same name defined in more than one place, say by two crates, is an
+/// [`Error::DuplicateProvider`], and nothing is registered. Without the DTrace helper device, the
+/// error is [`Error::HelperUnavailable`], and the probes are never enabled, so firing them does
+/// nothing.
+///
+/// Setting the [`DISABLE_ENV_VAR`] environment variable, `USDT_DISABLE`, to anything but an empty
+/// string or `0` disables all probes instead: nothing is registered, and the probes are never
+/// enabled, see [`probes_disabled`].
 pub fn register_probes() -> Result<(), Error> {
+    if probes_disabled_by_env() {
+        return Ok(());
+    }
+    registry::check_unique_providers(|_| true)?;
     crate::internal::register_probes()
 }
 
+/// The environment variable disabling all probes when they're registered, `USDT_DISABLE`.
+///
+/// When it's set to anything but an empty string or `0`, [`register_probes`] and
+/// [`register_providers`] register nothing, and every probe is reported disabled, whether or not a
+/// tracer enabled it, so that its arguments are never built. The variable is read once, by the
+/// first call registering probes, so changing it later has no effect.
+pub const DISABLE_ENV_VAR: &str = "USDT_DISABLE";
+
+// Whether probes are disabled, read from the environment by the first registration.
+static PROBES_DISABLED: OnceLock<bool> = OnceLock::new();
+
+// Return whether probes are disabled by `USDT_DISABLE`, reading it if probes weren't registered yet.
+fn probes_disabled_by_env() -> bool {
+    *PROBES_DISABLED.get_or_init(|| {
+        std::env::var_os(DISABLE_ENV_VAR).is_some_and(|value| !value.is_empty() && value != "0")
+    })
+}
+
+/// Return `true` if all probes were disabled with [`DISABLE_ENV_VAR`] when they were registered.
+///
+/// Disabled probes are never fired, and their arguments never built. This is `false` until probes
+/// are first registered.
+#[inline]
+pub fn probes_disabled() -> bool {
+    PROBES_DISABLED.get().is_some_and(|disabled| *disabled)
+}
+
+/// Register an application's probe points with DTrace, as [`register_probes`] does, returning the
+/// number of providers and probes registered.
+///
+/// As with the SystemTap notes on Linux, where nothing needs registering, these are the providers
+/// and probes compiled into the program, or into the shared library calling this. On platforms
+/// without a probe backend, or with the `disabled` feature, both are zero.
+pub fn register_probes_with_report() -> Result<Registration, Error> {
+    register_probes()?;
+    Ok(registry::registration())
+}
+
+/// Register the probes of only the named providers with DTrace, leaving those of any other
+/// provider unregistered.
+///
+/// Other providers may be registered by a later call, or by [`register_probes`]. Naming a provider
+/// which isn't compiled into the program, or into the shared library calling this, is an
+/// [`Error::UnknownProviders`], and registers none of them. On platforms without a probe backend,
+/// or with the `disabled` feature, no names are checked.
+///
+/// Providers compiled with the linker on macOS, and SystemTap notes on Linux, are always visible,
+/// so only the names are checked there. ETW providers are registered just as with DTrace. As with
+/// [`register_probes`], each of the named providers must be defined in only one place. Nothing is
+/// registered when probes are disabled with [`DISABLE_ENV_VAR`].
+pub fn register_providers(names: &[&str]) -> Result<(), Error> {
+    if probes_disabled_by_env() {
+        return Ok(());
+    }
+    registry::check_unique_providers(|name| names.contains(&name))?;
+    crate::internal::register_providers(names)
+}
+
 /// Errors related to building DTrace probes into Rust code
 #[derive(Error, Debug)]
 pub enum Error {
@@ -58,6 +180,14 @@ pub enum Error {
     /// Error reading or writing files, or registering DTrace probes
     #[error(transparent)]
     IO(#[from] std::io::Error),
+    /// The DTrace helper device, through which probes are registered, doesn't exist, as when
+    /// DTrace isn't loaded, or in a container without the device
+    #[error("The DTrace helper device \"{path}\" is unavailable")]
+    HelperUnavailable {
+        path: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
     /// Error related to environment variables, e.g., while running a build script
     #[error(transparent)]
     Env(#[from] std::env::VarError),
@@ -70,15 +200,321 @@ pub enum Error {
     /// Error converting input to JSON
     #[error(transparent)]
     Json(#[from] serde_json::Error),
+    /// Error related to compiling an LTTng-UST tracepoint provider
+    #[error("Failed to compile the LTTng-UST tracepoint provider")]
+    LttngError,
+    /// A section given for SystemTap probes isn't a valid section name
+    #[error(
+        "The section \"{0}\" is not a valid section name, \
+        which may only contain ASCII letters, digits, '_', '.' and '$', and not start with a digit"
+    )]
+    InvalidSectionName(String),
+    /// The module isn't a valid Rust identifier, nor a path of them
+    #[error(
+        "The module \"{0}\" is not a valid Rust identifier, \
+        nor a path of them from the root of the crate"
+    )]
+    InvalidModulePath(String),
+    /// The probe format produced a probe macro name which isn't a valid Rust identifier
+    #[error("The probe macro name \"{0}\" is not a valid Rust identifier, check the probe format")]
+    InvalidProbeMacroName(String),
+    /// The probe format produced the same probe macro name for two probes of a provider
+    #[error(
+        "The probes \"{}\" and \"{}\" would both be named \"{name}\", \
+         the probe format should include \"{{probe}}\"",
+        probes.0,
+        probes.1
+    )]
+    ProbeMacroNameCollision {
+        name: String,
+        probes: (String, String),
+    },
+    /// Two sources define a probe of the same provider with different arguments
+    #[error("The probe \"{provider}:{probe}\" is defined with conflicting arguments")]
+    ConflictingProbe { provider: String, probe: String },
+    /// Two sources declare different stability attributes for the same provider
+    #[error("The provider \"{0}\" is declared with conflicting stability attributes")]
+    ConflictingProviderAttributes(String),
+    /// A probe takes more arguments than can be passed, counting the automatic ones
+    #[error(
+        "The probe \"{probe}\" takes {count} arguments, including automatic ones, \
+        but at most {max} are supported",
+        max = MAX_PROBE_ARGUMENTS
+    )]
+    TooManyArguments { probe: String, count: usize },
+    /// Serialized arguments are compressed without the feature implementing the compression
+    #[error(
+        "Compressing arguments with {} requires the \"{}\" feature",
+        .0.feature(),
+        .0.feature()
+    )]
+    CompressionUnavailable(Compression),
+    /// Providers were named for registration which aren't compiled into the program, or given
+    /// modules which the provider source doesn't define
+    #[error("Unknown providers: {}", .0.join(", "))]
+    UnknownProviders(Vec<String>),
+    /// Providers can't be registered at runtime on this platform
+    #[error("Providers can't be registered at runtime on this platform")]
+    RuntimeProvidersUnsupported,
+    /// A provider registered at runtime has the name of one registered before
+    #[error("The provider \"{0}\" is already registered at runtime")]
+    DuplicateRuntimeProvider(String),
+    /// A provider of the same name is defined in several places, whose probes DTrace would merge
+    #[error(
+        "The provider \"{name}\" is defined in more than one place; rename all but one of them, \
+        say with `provider = \"...\"`, or namespace their names, say with that of their crate"
+    )]
+    DuplicateProvider { name: String },
+    /// Registering providers at runtime would exceed the sites their probes are fired from
+    #[error(
+        "At most {max} probes may be registered at runtime",
+        max = runtime::MAX_RUNTIME_PROBES
+    )]
+    TooManyRuntimeProbes,
+    /// A probe was fired by name which isn't registered at runtime
+    #[error("The probe \"{provider}:{probe}\" isn't registered at runtime")]
+    UnknownProbe { provider: String, probe: String },
+    /// A probe registered at runtime was fired with arguments not matching its types
+    #[error("The arguments don't match the types of the probe \"{provider}:{probe}\"")]
+    InvalidProbeArguments { provider: String, probe: String },
 }
 
-#[derive(Default, Debug, Deserialize)]
+#[derive(Default, Debug, Clone, Deserialize)]
 pub struct CompileProvidersConfig {
     pub provider: Option<String>,
     pub probe_format: Option<String>,
+    /// A prefix prepended to the name of every probe of the provider, such as `http_`, so that
+    /// the probe `request` is named `http_request` by DTrace and SystemTap, and its macro
+    /// `http_request!`.
+    pub prefix: Option<String>,
     pub module: Option<String>,
+    /// The modules of individual providers of a source defining several, as pairs of a provider
+    /// name and the module generated for it. These are written as a comma-separated list, such as
+    /// `"net=telemetry::net,disk=storage_probes"`, and take precedence over `module`. Each path is
+    /// checked as `module` is.
+    #[serde(default, deserialize_with = "deserialize_modules")]
+    pub modules: Vec<(String, String)>,
+    /// Fire each probe from a function of its own, so that a probe fired in a generic function has
+    /// a single call site, rather than one per monomorphization of that function.
+    #[serde(default)]
+    pub dedupe_probes: bool,
+    /// The maximum length of the JSON a serializable probe argument is converted into, beyond
+    /// which it's replaced by a marker. Defaults to [`DEFAULT_MAX_SERIALIZED_LEN`].
+    pub max_serialized_len: Option<usize>,
+    /// The values captured automatically when each probe fires, passed after its own arguments.
+    /// These are written as a comma-separated list, such as `"tid,cpu"`, and are always passed in
+    /// the order of [`AutoArg`]'s variants.
+    #[serde(default, deserialize_with = "deserialize_auto_args")]
+    pub auto_args: Vec<AutoArg>,
+    /// The compression applied to the JSON of serializable arguments, written `"gzip"` or
+    /// `"zstd"`, which requires the feature of the same name. See [`compression`] for the format
+    /// of compressed arguments. LTTng-UST tracepoints and ETW events record the JSON itself, and
+    /// ignore this.
+    #[serde(default, deserialize_with = "compression::deserialize_compression")]
+    pub compression: Option<Compression>,
+    /// Pass each serializable argument as two probe arguments, a pointer to its JSON followed by
+    /// the length of the JSON in bytes, so that it may be copied in full whatever DTrace's
+    /// `strsize`. LTTng-UST tracepoints record the JSON itself, and ignore this.
+    #[serde(default)]
+    pub json_len: bool,
+    /// Prepend a header identifying the format and its version to the JSON of serializable
+    /// arguments which aren't compressed. See [`framing`] for the format of framed arguments.
+    /// LTTng-UST tracepoints and ETW events record the JSON itself, and ignore this.
+    #[serde(default)]
+    pub frame_arguments: bool,
+    /// Report probes which are never fired, neither with their macro nor their fire function, as
+    /// dead code. Off by default, since a library may define probes it doesn't fire itself.
+    #[serde(default)]
+    pub warn_unused_probes: bool,
+    /// Also generate a macro firing each probe at most once per process, named after the probe's
+    /// macro with an `_once` suffix, such as `start_once!`.
+    #[serde(default)]
+    pub once_macros: bool,
+    /// Also generate a macro firing each probe only when a predicate holds, named after the
+    /// probe's macro with an `_if` suffix, such as `start_if!`. The predicate is called after the
+    /// probe is found to be enabled, and the arguments are only built if it returns `true`.
+    #[serde(default)]
+    pub if_macros: bool,
+    /// Keys renamed in the JSON of serializable arguments, as pairs of a key and the key it's
+    /// renamed to. These are written as a comma-separated list, such as
+    /// `"request_id=id,timestamp=ts"`, and rename the fields of structs and the string keys of
+    /// maps, leaving the types' own `Serialize` implementations unchanged.
+    #[serde(default, deserialize_with = "json_keys::deserialize_renames")]
+    pub rename_json_keys: Vec<(String, String)>,
+    /// The section of SystemTap probes' notes. Defaults to [`DEFAULT_STAPSDT_NOTE_SECTION`].
+    pub stapsdt_note_section: Option<String>,
+    /// The section of SystemTap probes' semaphores. Defaults to
+    /// [`DEFAULT_STAPSDT_SEMAPHORE_SECTION`].
+    pub stapsdt_semaphore_section: Option<String>,
+    /// The section whose address SystemTap probes' notes are relocated by. Defaults to
+    /// [`DEFAULT_STAPSDT_BASE_SECTION`].
+    pub stapsdt_base_section: Option<String>,
+    /// The visibility of the generated module, its probe macros and fire functions, written
+    /// `"pub(crate)"`, the default, or `"pub"` to export the probes for other crates to fire.
+    #[serde(default, deserialize_with = "deserialize_visibility")]
+    pub visibility: Visibility,
+}
+
+/// The visibility of a provider's generated module, and of the macros and functions firing its
+/// probes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Visibility {
+    /// Visible within the crate defining the provider. Written `pub(crate)`.
+    #[default]
+    Crate,
+    /// Visible to other crates, with the probe macros exported with `#[macro_export]`. Written
+    /// `pub`.
+    Public,
+}
+
+impl Visibility {
+    /// Return the visibility as written on an item.
+    pub(crate) fn tokens(&self) -> proc_macro2::TokenStream {
+        match self {
+            Visibility::Crate => quote::quote! { pub(crate) },
+            Visibility::Public => quote::quote! { pub },
+        }
+    }
+}
+
+impl std::str::FromStr for Visibility {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.replace(' ', "").as_str() {
+            "pub(crate)" => Ok(Visibility::Crate),
+            "pub" => Ok(Visibility::Public),
+            _ => Err(format!(
+                "Unknown visibility \"{}\", expected \"pub(crate)\" or \"pub\"",
+                s
+            )),
+        }
+    }
+}
+
+// Deserialize a comma-separated list of providers and their modules, such as
+// `"net=telemetry::net,disk=storage_probes"`.
+fn deserialize_modules<'de, D>(deserializer: D) -> Result<Vec<(String, String)>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let list = String::deserialize(deserializer)?;
+    let mut modules: Vec<(String, String)> = Vec::new();
+    for entry in list.split(',') {
+        let (provider, module) = match entry.split_once('=') {
+            Some((provider, module))
+                if !provider.trim().is_empty() && !module.trim().is_empty() =>
+            {
+                (provider.trim(), module.trim())
+            }
+            _ => {
+                return Err(serde::de::Error::custom(format!(
+                    "Invalid provider module \"{}\", expected \"provider=module\"",
+                    entry.trim()
+                )))
+            }
+        };
+        if modules.iter().any(|(other, _)| other == provider) {
+            return Err(serde::de::Error::custom(format!(
+                "The provider \"{}\" is given more than one module",
+                provider
+            )));
+        }
+        modules.push((provider.to_string(), module.to_string()));
+    }
+    Ok(modules)
+}
+
+// Deserialize the visibility of the generated module.
+fn deserialize_visibility<'de, D>(deserializer: D) -> Result<Visibility, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    String::deserialize(deserializer)?
+        .parse()
+        .map_err(serde::de::Error::custom)
+}
+
+/// A value captured automatically when a probe fires, and passed after the probe's own arguments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AutoArg {
+    /// The ID of the firing thread, as given by the OS, passed as a `uint64_t`. Written `tid`.
+    ThreadId,
+    /// The CPU the firing thread runs on, passed as an `int32_t`, or -1 where the OS doesn't tell.
+    /// Written `cpu`.
+    Cpu,
+}
+
+impl AutoArg {
+    /// Return the type the value is passed as.
+    pub fn data_type(&self) -> DataType {
+        let sign = match self {
+            AutoArg::ThreadId => dtrace_parser::Sign::Unsigned,
+            AutoArg::Cpu => dtrace_parser::Sign::Signed,
+        };
+        let width = match self {
+            AutoArg::ThreadId => dtrace_parser::BitWidth::Bit64,
+            AutoArg::Cpu => dtrace_parser::BitWidth::Bit32,
+        };
+        DataType::Native(dtrace_parser::DataType::Integer(dtrace_parser::Integer {
+            sign,
+            width,
+        }))
+    }
+
+    /// Return the code capturing the value when a probe fires.
+    pub(crate) fn capture(&self) -> proc_macro2::TokenStream {
+        match self {
+            AutoArg::ThreadId => quote::quote! { ::usdt::current_thread_id() },
+            AutoArg::Cpu => quote::quote! { ::usdt::current_cpu() },
+        }
+    }
+}
+
+impl std::str::FromStr for AutoArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tid" => Ok(AutoArg::ThreadId),
+            "cpu" => Ok(AutoArg::Cpu),
+            _ => Err(format!(
+                "Unknown automatic argument \"{}\", expected \"tid\" or \"cpu\"",
+                s
+            )),
+        }
+    }
+}
+
+// Deserialize a comma-separated list of automatic arguments, in the order they're passed in.
+fn deserialize_auto_args<'de, D>(deserializer: D) -> Result<Vec<AutoArg>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let list = String::deserialize(deserializer)?;
+    let mut args = list
+        .split(',')
+        .map(|arg| arg.trim().parse())
+        .collect::<Result<Vec<AutoArg>, _>>()
+        .map_err(serde::de::Error::custom)?;
+    args.sort();
+    args.dedup();
+    Ok(args)
 }
 
+/// The default maximum length of the JSON a serializable probe argument is converted into.
+pub const DEFAULT_MAX_SERIALIZED_LEN: usize = 64 * 1024;
+
+/// The default section of SystemTap probes' notes, where consumers look for them.
+pub const DEFAULT_STAPSDT_NOTE_SECTION: &str = ".note.stapsdt";
+
+/// The default section of SystemTap probes' semaphores.
+pub const DEFAULT_STAPSDT_SEMAPHORE_SECTION: &str = ".probes";
+
+/// The default section whose address SystemTap probes' notes are relocated by, where consumers
+/// look for it.
+pub const DEFAULT_STAPSDT_BASE_SECTION: &str = ".stapsdt.base";
+
 impl CompileProvidersConfig {
     /// Return the formatted name of a probe.
     pub fn format_probe(&self, probe_name: &str) -> String {
@@ -100,29 +536,368 @@ impl CompileProvidersConfig {
         quote::format_ident!("{}", self.format_probe(probe_name))
     }
 
+    /// Check that the formatted names of the probes of a provider are valid identifiers, and that
+    /// no two probes share a name.
+    ///
+    /// With `once_macros` or `if_macros`, the name of each probe's `_once` or `_if` macro mustn't be
+    /// that of another macro either.
+    pub fn check_probe_names(&self, probes: &[Probe]) -> Result<(), Error> {
+        let mut names = std::collections::BTreeMap::new();
+        for probe in probes.iter() {
+            let name = self.format_probe(&probe.name);
+            if syn::parse_str::<syn::Ident>(&name).is_err() {
+                return Err(Error::InvalidProbeMacroName(name));
+            }
+            let once_name = self.once_macros.then(|| format!("{}_once", name));
+            let if_name = self.if_macros.then(|| format!("{}_if", name));
+            for name in std::iter::once(name).chain(once_name).chain(if_name) {
+                if let Some(other) = names.insert(name.clone(), &probe.name) {
+                    return Err(Error::ProbeMacroNameCollision {
+                        name,
+                        probes: (other.clone(), probe.name.clone()),
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Return the name of the macro firing a probe at most once, with `once_macros`.
+    pub(crate) fn once_probe_ident(&self, probe_name: &str) -> proc_macro2::Ident {
+        quote::format_ident!("{}_once", self.format_probe(probe_name))
+    }
+
+    /// Return the name of the macro firing a probe when a predicate holds, with `if_macros`.
+    pub(crate) fn if_probe_ident(&self, probe_name: &str) -> proc_macro2::Ident {
+        quote::format_ident!("{}_if", self.format_probe(probe_name))
+    }
+
+    /// Check that each probe's arguments, along with the automatic ones, fit in the registers they
+    /// are passed in.
+    pub fn check_probe_arguments(&self, probes: &[Probe]) -> Result<(), Error> {
+        for probe in probes.iter() {
+            let count = probe
+                .types
+                .iter()
+                .map(|typ| match typ {
+                    DataType::Serializable(_) if self.json_len => 2,
+                    _ => typ.expand().len(),
+                })
+                .sum::<usize>()
+                + self.auto_args.len();
+            if count > MAX_PROBE_ARGUMENTS {
+                return Err(Error::TooManyArguments {
+                    probe: probe.name.clone(),
+                    count,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Check that the compression of serialized arguments, if any, is available.
+    pub fn check_compression(&self) -> Result<(), Error> {
+        match self.compression {
+            Some(compression) if !compression.is_available() => {
+                Err(Error::CompressionUnavailable(compression))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the sections of SystemTap probes, if given, are valid section names.
+    ///
+    /// A section name may contain ASCII letters, digits, `_`, `.` and `$`, and mustn't be empty
+    /// nor start with a digit, so that it may be written in assembly without quoting.
+    pub fn check_sections(&self) -> Result<(), Error> {
+        [
+            &self.stapsdt_note_section,
+            &self.stapsdt_semaphore_section,
+            &self.stapsdt_base_section,
+        ]
+        .into_iter()
+        .flatten()
+        .try_for_each(|name| {
+            let valid = !name.starts_with(|c: char| c.is_ascii_digit())
+                && !name.is_empty()
+                && name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '.' | '$'));
+            if valid {
+                Ok(())
+            } else {
+                Err(Error::InvalidSectionName(name.clone()))
+            }
+        })
+    }
+
+    /// Return the section of SystemTap probes' notes.
+    pub fn stapsdt_note_section(&self) -> &str {
+        self.stapsdt_note_section
+            .as_deref()
+            .unwrap_or(DEFAULT_STAPSDT_NOTE_SECTION)
+    }
+
+    /// Return the section of SystemTap probes' semaphores.
+    pub fn stapsdt_semaphore_section(&self) -> &str {
+        self.stapsdt_semaphore_section
+            .as_deref()
+            .unwrap_or(DEFAULT_STAPSDT_SEMAPHORE_SECTION)
+    }
+
+    /// Return the section whose address SystemTap probes' notes are relocated by.
+    pub fn stapsdt_base_section(&self) -> &str {
+        self.stapsdt_base_section
+            .as_deref()
+            .unwrap_or(DEFAULT_STAPSDT_BASE_SECTION)
+    }
+
+    /// Return the maximum length of the JSON a serializable probe argument is converted into.
+    pub fn max_serialized_len(&self) -> usize {
+        self.max_serialized_len
+            .unwrap_or(DEFAULT_MAX_SERIALIZED_LEN)
+    }
+
     /// Return the formatted module name as an identifier.
+    ///
+    /// For a module given as a path, such as `telemetry::http`, this is the last segment of the
+    /// path, which is the name of the module generated in place of the provider.
     pub fn module_ident(&self) -> proc_macro2::Ident {
+        self.module_path()
+            .segments
+            .pop()
+            .expect("Expected a module path with at least one segment")
+            .into_value()
+            .ident
+    }
+
+    /// Return the path of the generated module from the root of the crate, through which the probe
+    /// macros refer to each other.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the module isn't a valid path, see [`CompileProvidersConfig::check_module`].
+    pub fn module_path(&self) -> syn::Path {
         let name = self.module.as_ref().unwrap_or_else(|| {
             self.provider
                 .as_ref()
                 .expect("Expected a provider name when making a module ident")
         });
-        quote::format_ident!("{}", name)
+        parse_module_path(name)
+            .unwrap_or_else(|| panic!("The module \"{}\" is not a valid module path", name))
+    }
+
+    /// Check that the module, if given, and those of individual providers, are Rust identifiers,
+    /// or paths of identifiers from the root of the crate such as `telemetry::http`.
+    pub fn check_module(&self) -> Result<(), Error> {
+        match self
+            .module
+            .iter()
+            .chain(self.modules.iter().map(|(_, module)| module))
+            .find(|module| parse_module_path(module).is_none())
+        {
+            Some(module) => Err(Error::InvalidModulePath(module.clone())),
+            None => Ok(()),
+        }
     }
 }
 
+// Parse a module path relative to the root of the crate, made of identifiers separated by `::`.
+// Keywords such as `crate` or `super` aren't identifiers, so they are rejected.
+fn parse_module_path(module: &str) -> Option<syn::Path> {
+    let segments = module
+        .split("::")
+        .map(|segment| syn::parse_str::<syn::Ident>(segment.trim()).ok())
+        .collect::<Option<Vec<_>>>()?;
+    Some(syn::parse_quote! { #(#segments)::* })
+}
+
 // Compile DTrace provider source code into Rust.
 //
 // This function parses a provider definition, and, for each probe, a corresponding Rust macro is
 // returned. This macro may be called throughout Rust code to fire the corresponding DTrace probe
 // (if it's enabled). See [probe_test_macro] for a detailed example.
 //
+// Providers, and the probes of each, are emitted in the order they're defined in the source, so
+// that compiling the same source always generates the same code, and builds are reproducible.
+//
 // [probe_test_macro]: https://github.com/oxidecomputer/usdt/tree/master/probe-test-macro
 pub fn compile_provider_source(
     source: &str,
     config: &CompileProvidersConfig,
 ) -> Result<proc_macro2::TokenStream, Error> {
-    crate::internal::compile_provider_source(source, config)
+    let providers = providers_from_source(source, config)?;
+    crate::target::compile_providers(source, &providers)
+}
+
+/// Compile DTrace provider source code into formatted Rust source, to be checked in rather than
+/// generated by a build script.
+///
+/// The code is that of [`compile_provider_source`], generated for every target, each variant
+/// gated on its `cfg`, even when called from a build script. It's built like the code generated
+/// at build time: the crate depends on `usdt`, and the generated modules are included at the
+/// root of the crate, or their path given as the `module`. The code for macOS targets can only be
+/// generated on macOS, and is otherwise a `compile_error!`. This requires the `generate` feature.
+#[cfg(feature = "generate")]
+pub fn format_provider_source(
+    source: &str,
+    config: &CompileProvidersConfig,
+) -> Result<String, Error> {
+    let providers = providers_from_source(source, config)?;
+    let tokens = crate::target::compile_portable_providers(source, &providers)?;
+    // Unwrap safety: The generated code is valid Rust.
+    let file = syn::parse2::<syn::File>(tokens).unwrap();
+    Ok(prettyplease::unparse(&file))
+}
+
+/// The output of compiling providers into LTTng-UST tracepoints.
+#[derive(Debug, Clone)]
+pub struct LttngProviders {
+    /// The Rust code implementing the probes.
+    pub tokens: proc_macro2::TokenStream,
+    /// The tracepoint provider header of each provider, as its file name and contents.
+    pub headers: Vec<(String, String)>,
+    /// The C source of the tracepoint providers, which must be compiled with the headers in the
+    /// include path, and linked along with `liblttng-ust`.
+    pub source: String,
+}
+
+// Compile DTrace provider source code into Rust code firing LTTng-UST tracepoints, rather than
+// the probes of the target's backend, along with the C source of the tracepoint providers.
+//
+// This is only meaningful when building for Linux.
+pub fn compile_lttng_provider_source(
+    source: &str,
+    config: &CompileProvidersConfig,
+) -> Result<LttngProviders, Error> {
+    let providers = providers_from_source(source, config)?
+        .into_iter()
+        .map(|(provider, config)| (provider.with_auto_args(&config.auto_args), config))
+        .collect::<Vec<_>>();
+    let items = providers
+        .iter()
+        .map(|(provider, config)| lttng::compile_provider(provider, config))
+        .collect::<Vec<_>>();
+    let headers = providers
+        .iter()
+        .map(|(provider, _)| {
+            (
+                lttng::header_file_name(provider),
+                lttng::tracepoint_provider_header(provider),
+            )
+        })
+        .collect();
+    let providers = providers
+        .into_iter()
+        .map(|(provider, _)| provider)
+        .collect::<Vec<_>>();
+    Ok(LttngProviders {
+        tokens: quote::quote! { #(#items)* },
+        headers,
+        source: lttng::tracepoint_provider_source(&providers),
+    })
+}
+
+// Parse the providers of DTrace provider source code, along with the configuration used to
+// compile each.
+fn providers_from_source(
+    source: &str,
+    config: &CompileProvidersConfig,
+) -> Result<Vec<(Provider, CompileProvidersConfig)>, Error> {
+    let dfile = dtrace_parser::File::try_from(source)?;
+    let unknown = config
+        .modules
+        .iter()
+        .filter(|(name, _)| !dfile.providers().iter().any(|p| p.name == *name))
+        .map(|(name, _)| name.clone())
+        .collect::<Vec<_>>();
+    if !unknown.is_empty() {
+        return Err(Error::UnknownProviders(unknown));
+    }
+    dfile
+        .providers()
+        .iter()
+        .map(|provider| {
+            let provider = Provider::from(provider).with_prefix(config.prefix.as_deref())?;
+            // Ensure that the name of the module in the config is set, either by the caller for
+            // this provider or all of them, or defaulting to the provider name.
+            let module = config
+                .modules
+                .iter()
+                .find(|(name, _)| *name == provider.name)
+                .map(|(_, module)| module)
+                .or(config.module.as_ref())
+                .unwrap_or(&provider.name);
+            let config = CompileProvidersConfig {
+                provider: Some(provider.name.clone()),
+                probe_format: config.probe_format.clone(),
+                prefix: config.prefix.clone(),
+                module: Some(module.clone()),
+                modules: Vec::new(),
+                dedupe_probes: config.dedupe_probes,
+                max_serialized_len: config.max_serialized_len,
+                auto_args: config.auto_args.clone(),
+                compression: config.compression,
+                json_len: config.json_len,
+                frame_arguments: config.frame_arguments,
+                warn_unused_probes: config.warn_unused_probes,
+                once_macros: config.once_macros,
+                if_macros: config.if_macros,
+                rename_json_keys: config.rename_json_keys.clone(),
+                stapsdt_note_section: config.stapsdt_note_section.clone(),
+                stapsdt_semaphore_section: config.stapsdt_semaphore_section.clone(),
+                stapsdt_base_section: config.stapsdt_base_section.clone(),
+                visibility: config.visibility,
+            };
+            config.check_module()?;
+            config.check_probe_names(&provider.probes)?;
+            config.check_probe_arguments(&provider.probes)?;
+            config.check_compression()?;
+            config.check_sections()?;
+            Ok((provider, config))
+        })
+        .collect()
+}
+
+/// Merge several DTrace provider source files into one.
+///
+/// Providers of the same name are merged into one with the probes of each, and probes defined
+/// identically in several sources appear once. It's an error for two sources to define a probe of
+/// the same provider with different arguments, or to declare different stability attributes for it.
+pub fn merge_provider_sources(sources: &[&str]) -> Result<String, Error> {
+    let mut providers: Vec<dtrace_parser::Provider> = Vec::new();
+    for source in sources.iter() {
+        let dfile = dtrace_parser::File::try_from(*source)?;
+        for provider in dfile.providers().iter() {
+            let Some(merged) = providers.iter_mut().find(|p| p.name == provider.name) else {
+                providers.push(provider.clone());
+                continue;
+            };
+            let default = dtrace_parser::ProviderAttributes::default();
+            if merged.attributes == default {
+                merged.attributes = provider.attributes;
+            } else if provider.attributes != default && provider.attributes != merged.attributes {
+                return Err(Error::ConflictingProviderAttributes(provider.name.clone()));
+            }
+            for probe in provider.probes.iter() {
+                match merged.probes.iter().find(|p| p.name == probe.name) {
+                    Some(existing) if existing == probe => {}
+                    Some(_) => {
+                        return Err(Error::ConflictingProbe {
+                            provider: provider.name.clone(),
+                            probe: probe.name.clone(),
+                        })
+                    }
+                    None => merged.probes.push(probe.clone()),
+                }
+            }
+        }
+    }
+    Ok(providers
+        .into_iter()
+        .map(|provider| Provider::from(provider).to_d_source())
+        .collect::<Vec<_>>()
+        .join("\n"))
 }
 
 // Compile a DTrace provider from its representation in the USDT crate.
@@ -130,33 +905,202 @@ pub fn compile_provider(
     provider: &Provider,
     config: &CompileProvidersConfig,
 ) -> proc_macro2::TokenStream {
-    crate::internal::compile_provider_from_definition(provider, config)
+    // Unwrap safety: The type signature confirms that `provider` is valid.
+    crate::target::compile_providers(
+        &provider.to_d_source(),
+        &[(provider.clone(), config.clone())],
+    )
+    .unwrap()
+}
+
+/// The maximum number of arguments a probe may be fired with.
+///
+/// Arguments are passed to the probe in registers, and only 6 of them are used on all supported
+/// architectures.
+pub const MAX_PROBE_ARGUMENTS: usize = 6;
+
+/// The type of a timestamp passed as a probe argument.
+///
+/// Each is only recognized in probe functions when the feature of the same name is enabled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TimestampKind {
+    /// A `chrono::DateTime<chrono::Utc>`.
+    Chrono,
+    /// A `time::OffsetDateTime`.
+    Time,
 }
 
 /// A data type supported by the `usdt` crate.
+///
+/// Probe arguments are either passed to DTrace directly, as _native_ types with an analog in D, or
+/// serialized to JSON and passed as strings.
 #[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
+    /// A native type: an integer, a pointer to an integer, or a string.
     Native(dtrace_parser::DataType),
+    /// A fixed-size array of integers, with the given length, which is passed natively as one
+    /// probe argument per element.
+    ///
+    /// Only arrays of up to [`MAX_PROBE_ARGUMENTS`] elements are passed this way, and only when
+    /// all the arguments of the probe still fit. Other arrays are serialized.
+    NativeArray(dtrace_parser::Integer, usize),
+    /// A [`UniqueId`], which is passed natively as a `uint64_t`.
     UniqueId,
+    /// A `Path` or `PathBuf`, which is passed natively as a `char *`.
+    ///
+    /// On Unix, the path's bytes are passed as they are, even when they aren't valid UTF-8. On
+    /// other platforms, the path is converted to UTF-8 lossily, with any invalid sequence replaced
+    /// by U+FFFD.
+    Path,
+    /// A timestamp, which is passed natively as the nanoseconds since the Unix epoch.
+    ///
+    /// Unsigned timestamps are passed as a `uint64_t`, with times before the epoch passed as
+    /// zero, and signed timestamps as an `int64_t`. Times which don't fit are saturated.
+    Timestamp(TimestampKind, dtrace_parser::Sign),
+    /// A `std::time::Duration`, such as the difference between two `Instant`s, which is passed
+    /// natively as its nanoseconds in a `uint64_t`. Durations which don't fit are saturated.
+    Duration,
+    /// A `&dyn std::fmt::Display` or `impl std::fmt::Display`, which is formatted and passed
+    /// natively as a `char *`.
+    ///
+    /// The string is formatted into a buffer reused by later probes fired on the same thread, see
+    /// [`to_display_probe_arg`].
+    Display,
+    /// A `uuid::Uuid`, which is passed natively as two `uint64_t` probe arguments.
+    ///
+    /// Reading the 16 bytes of the UUID as a big-endian integer, the first argument is its most
+    /// significant half, and the second its least significant half. That is, the first argument
+    /// holds the first 8 bytes of the UUID, with the first byte in its most significant byte.
+    Uuid,
+    /// A raw pointer, `*const T` or `*mut T`, which is passed as its address in a `uint64_t`. The
+    /// pointee is never read, nor serialized.
+    Address(syn::Type),
+    /// A field-less enum with a primitive representation, such as `#[repr(u8)]`, which is passed
+    /// natively as its discriminant, an integer of that representation.
+    Discriminant(syn::Type, dtrace_parser::Integer),
+    /// A field-less enum implementing [`VariantName`], which is passed natively as the name of its
+    /// variant in a `char *`.
+    ///
+    /// Only arguments marked `#[variant_name]` are passed this way. The type is that of the enum,
+    /// without any reference.
+    VariantName(syn::Type),
+    /// Any other type implementing `serde::Serialize`, which is serialized to JSON and passed as
+    /// a `char *`.
+    ///
+    /// A `Box<T>`, `Rc<T>` or `Arc<T>`, or a reference to one, is serialized as its inner `T`, see
+    /// [`DataType::serialized_type`]. An `impl Fields` is serialized as an object with a member
+    /// per key/value pair, see [`Fields`].
     Serializable(syn::Type),
+    /// A serializable type of a provider compiled with `json_len`, which is passed as two probe
+    /// arguments: a `char *` pointing to its JSON, and the length of the JSON as a `uint64_t`.
+    ///
+    /// The length excludes the terminating null byte. Compressed JSON isn't null-terminated, and
+    /// its length is that of the compressed argument, header included.
+    SizedSerializable(syn::Type),
+    /// A `prost::Message`, which is encoded to protobuf and passed as two probe arguments: the
+    /// address of the encoded bytes as a `uint64_t`, and their length as a `uint64_t`.
+    ///
+    /// Only arguments marked `#[protobuf]` are passed this way, with the `prost` feature of the
+    /// `usdt` crate. The type is that of the message, without any reference.
+    Protobuf(syn::Type),
+    /// A slice of integers, which is passed natively as three probe arguments: the address of its
+    /// first element, the number of elements, and the size of each element in bytes, each as a
+    /// `uint64_t`.
+    ///
+    /// Only arguments marked `#[native]` are passed this way, such as `#[native] _: &[u32]`, and
+    /// any value implementing `AsRef<[T]>` may be passed to them.
+    NativeSlice(dtrace_parser::Integer),
+    /// A non-zero integer, such as `NonZeroU32` or `NonZero<u32>`, which is passed natively as the
+    /// integer it wraps.
+    NonZero(dtrace_parser::Integer),
 }
 
 impl DataType {
     /// Convert a data type to its C type representation as a string.
+    ///
+    /// As an array is passed as several arguments, its representation is the comma-separated
+    /// list of its elements' types.
+    ///
+    /// Pointer-sized integers, `usize` and `isize`, have the pointer width of the architecture
+    /// this code runs on. Use [`DataType::to_c_type_for`] for the types on another architecture.
     pub fn to_c_type(&self) -> String {
+        self.c_type(Arch::host())
+    }
+
+    /// Convert a data type to its C type representation on the given architecture, where
+    /// pointer-sized integers have the pointer width of that architecture, such as `uint32_t` for
+    /// a `usize` on 32-bit Arm.
+    pub fn to_c_type_for(&self, arch: Arch) -> String {
+        self.c_type(Some(arch))
+    }
+
+    // Convert a data type to its C type on the given architecture, or on the host if it has no
+    // probe backend.
+    pub(crate) fn c_type(&self, arch: Option<Arch>) -> String {
         match self {
-            DataType::Native(ty) => ty.to_c_type(),
-            DataType::UniqueId => String::from("uint64_t"),
-            DataType::Serializable(_) => String::from("char*"),
+            DataType::Native(ty) => target_native_type(ty, arch).to_c_type(),
+            DataType::Discriminant(_, int) | DataType::NonZero(int) => {
+                target_integer(int, arch).to_c_type()
+            }
+            DataType::NativeArray(..)
+            | DataType::Uuid
+            | DataType::SizedSerializable(_)
+            | DataType::Protobuf(_)
+            | DataType::NativeSlice(_) => self
+                .expand()
+                .iter()
+                .map(|typ| typ.c_type(arch))
+                .collect::<Vec<_>>()
+                .join(", "),
+            DataType::UniqueId | DataType::Duration | DataType::Address(_) => {
+                String::from("uint64_t")
+            }
+            DataType::Timestamp(_, sign) => integer64(*sign).to_c_type(),
+            DataType::Path
+            | DataType::Display
+            | DataType::VariantName(_)
+            | DataType::Serializable(_) => String::from("char*"),
         }
     }
 
     /// Return the Rust FFI type representation of this data type.
     pub fn to_rust_ffi_type(&self) -> syn::Type {
         match self {
-            DataType::Native(ty) => syn::parse_str(&ty.to_rust_ffi_type()).unwrap(),
-            DataType::UniqueId => syn::parse_str("::std::os::raw::c_ulonglong").unwrap(),
-            DataType::Serializable(_) => syn::parse_str("*const ::std::os::raw::c_char").unwrap(),
+            DataType::Native(ty) => {
+                syn::parse_str(&ffi_native_type(ty).to_rust_ffi_type()).unwrap()
+            }
+            DataType::Discriminant(_, int) | DataType::NonZero(int) => {
+                DataType::Native(dtrace_parser::DataType::Integer(*int)).to_rust_ffi_type()
+            }
+            DataType::NativeArray(int, len) => {
+                let elem = DataType::Native(dtrace_parser::DataType::Integer(*int));
+                let elem = elem.to_rust_ffi_type();
+                syn::parse2(quote::quote! { [#elem; #len] }).unwrap()
+            }
+            DataType::UniqueId | DataType::Duration | DataType::Address(_) => {
+                syn::parse_str("::std::os::raw::c_ulonglong").unwrap()
+            }
+            DataType::Timestamp(_, sign) => {
+                syn::parse_str(&integer64(*sign).to_rust_ffi_type()).unwrap()
+            }
+            DataType::Uuid => syn::parse_str("[::std::os::raw::c_ulonglong; 2]").unwrap(),
+            DataType::Path
+            | DataType::Display
+            | DataType::VariantName(_)
+            | DataType::Serializable(_) => syn::parse_str("*const ::std::os::raw::c_char").unwrap(),
+            DataType::SizedSerializable(_) => {
+                syn::parse_str("(*const ::std::os::raw::c_char, ::std::os::raw::c_ulonglong)")
+                    .unwrap()
+            }
+            DataType::Protobuf(_) => {
+                syn::parse_str("(::std::os::raw::c_ulonglong, ::std::os::raw::c_ulonglong)")
+                    .unwrap()
+            }
+            DataType::NativeSlice(_) => syn::parse_str(
+                "(::std::os::raw::c_ulonglong, ::std::os::raw::c_ulonglong, \
+                ::std::os::raw::c_ulonglong)",
+            )
+            .unwrap(),
         }
     }
 
@@ -164,10 +1108,125 @@ impl DataType {
     pub fn to_rust_type(&self) -> syn::Type {
         match self {
             DataType::Native(ty) => syn::parse_str(&ty.to_rust_type()).unwrap(),
+            DataType::NativeArray(int, len) => {
+                let elem = DataType::Native(dtrace_parser::DataType::Integer(*int));
+                let elem = elem.to_rust_type();
+                syn::parse2(quote::quote! { [#elem; #len] }).unwrap()
+            }
             DataType::UniqueId => syn::parse_str("::usdt::UniqueId").unwrap(),
-            DataType::Serializable(ref inner) => inner.clone(),
+            DataType::Path => syn::parse_str("&::std::path::Path").unwrap(),
+            DataType::Timestamp(TimestampKind::Chrono, _) => {
+                syn::parse_str("::chrono::DateTime<::chrono::Utc>").unwrap()
+            }
+            DataType::Timestamp(TimestampKind::Time, _) => {
+                syn::parse_str("::time::OffsetDateTime").unwrap()
+            }
+            DataType::Duration => syn::parse_str("::std::time::Duration").unwrap(),
+            DataType::Display => syn::parse_str("&dyn ::std::fmt::Display").unwrap(),
+            DataType::Uuid => syn::parse_str("::uuid::Uuid").unwrap(),
+            DataType::NativeSlice(int) => {
+                let elem = DataType::Native(dtrace_parser::DataType::Integer(*int));
+                let elem = elem.to_rust_type();
+                syn::parse2(quote::quote! { &[#elem] }).unwrap()
+            }
+            DataType::NonZero(int) => {
+                let int = DataType::Native(dtrace_parser::DataType::Integer(*int));
+                let int = int.to_rust_type();
+                syn::parse2(quote::quote! { ::std::num::NonZero<#int> }).unwrap()
+            }
+            DataType::Address(ref inner)
+            | DataType::Discriminant(ref inner, _)
+            | DataType::VariantName(ref inner)
+            | DataType::Serializable(ref inner)
+            | DataType::SizedSerializable(ref inner)
+            | DataType::Protobuf(ref inner) => inner.clone(),
+        }
+    }
+
+    /// Return the type whose JSON a serializable argument is passed as, or `None` for a type which
+    /// isn't serialized.
+    ///
+    /// This is the argument's own type, except for a `Box<T>`, `Rc<T>` or `Arc<T>`, or a reference
+    /// to one, which is serialized as the `T` it points to, without cloning it. `T` must then
+    /// implement `Serialize`, rather than the pointer, which only does with serde's `rc` feature.
+    pub fn serialized_type(&self) -> Option<&syn::Type> {
+        match self {
+            // Key/value pairs are checked by their trait, rather than as serializable types.
+            DataType::Serializable(ty) | DataType::SizedSerializable(ty) if is_fields_type(ty) => {
+                None
+            }
+            DataType::Serializable(ty) | DataType::SizedSerializable(ty) => {
+                Some(smart_pointer_type(ty).map_or(ty, |(_, elem)| elem))
+            }
+            _ => None,
+        }
+    }
+
+    /// Return the data types of the probe arguments this data type is passed as.
+    ///
+    /// This is a single argument for all types except arrays, which are passed as one argument
+    /// per element, UUIDs, which are passed as two `uint64_t` arguments, serializable types and
+    /// protobuf messages passed with the length of their JSON or encoding, and slices, which are
+    /// passed as their address, length and element size.
+    pub fn expand(&self) -> Vec<DataType> {
+        match self {
+            DataType::NativeArray(int, len) => {
+                vec![DataType::Native(dtrace_parser::DataType::Integer(*int)); *len]
+            }
+            DataType::Uuid => vec![
+                DataType::Native(dtrace_parser::DataType::Integer(integer64(
+                    dtrace_parser::Sign::Unsigned
+                )));
+                2
+            ],
+            DataType::SizedSerializable(ty) => vec![
+                DataType::Serializable(ty.clone()),
+                DataType::Native(dtrace_parser::DataType::Integer(integer64(
+                    dtrace_parser::Sign::Unsigned,
+                ))),
+            ],
+            // The encoded bytes aren't a C string, so they're passed by address, like a raw
+            // pointer, for the tracer to copy in as many bytes as the length.
+            DataType::Protobuf(_) => vec![
+                DataType::Address(syn::parse_str("*const u8").unwrap()),
+                DataType::Native(dtrace_parser::DataType::Integer(integer64(
+                    dtrace_parser::Sign::Unsigned,
+                ))),
+            ],
+            DataType::NativeSlice(int) => {
+                let elem = DataType::Native(dtrace_parser::DataType::Integer(*int));
+                let elem = elem.to_rust_type();
+                let len = DataType::Native(dtrace_parser::DataType::Integer(integer64(
+                    dtrace_parser::Sign::Unsigned,
+                )));
+                vec![
+                    DataType::Address(syn::parse2(quote::quote! { *const #elem }).unwrap()),
+                    len.clone(),
+                    len,
+                ]
+            }
+            _ => vec![self.clone()],
         }
     }
+
+    /// Return the size of this data type in a SystemTap probe argument, such as `-4` for an
+    /// `int32_t`. For arrays, this is the size of each element, and for pointers to integers, the
+    /// size of the integer read through the pointer. Pointers, and pointer-sized integers, have
+    /// the pointer width of the given architecture.
+    pub fn to_asm_size(&self, arch: Arch) -> &'static str {
+        stapsdt::args::data_type_to_arg_size(self, arch)
+    }
+
+    /// Return the GNU Assembler operand reading this data type from the argument register with the
+    /// given index, as used in a SystemTap probe argument, such as `%edi` or `(%rsi)`. For arrays,
+    /// this is the operand of the element in that register.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `reg_index` is greater than 5, as only 6 probe arguments are supported.
+    pub fn to_asm_op(&self, reg_index: u8, arch: Arch) -> String {
+        stapsdt::args::data_type_to_asm_op(self, reg_index, arch)
+    }
 }
 
 impl From<dtrace_parser::DataType> for DataType {
@@ -176,122 +1235,1117 @@ impl From<dtrace_parser::DataType> for DataType {
     }
 }
 
-impl From<&syn::Type> for DataType {
-    fn from(t: &syn::Type) -> Self {
-        DataType::Serializable(t.clone())
+impl TryFrom<&syn::Type> for DataType {
+    type Error = syn::Error;
+
+    /// Determine the data type used for a probe argument of the given Rust type.
+    ///
+    /// Integers, strings and `UniqueId`s, as well as references to those, are native types, where
+    /// strings include `Cow<str>`, `Box<str>`, `Rc<str>` and `Arc<str>`. `Path` and `PathBuf` are
+    /// passed as strings, and raw pointers as their address.
+    /// So are non-zero integers, arrays of up to [`MAX_PROBE_ARGUMENTS`] integers with a literal
+    /// length, and, with the `chrono`, `time` and `uuid` features, unsigned timestamps and UUIDs.
+    /// Other paths, references, arrays, slices and tuples are assumed to be serializable, and any
+    /// other type is an error. A mutable reference is read like a shared one, and never mutated.
+    /// The probe accepts the same arguments as it would for `&T`.
+    fn try_from(item: &syn::Type) -> Result<Self, Self::Error> {
+        match item {
+            syn::Type::Path(ref path) => {
+                let last_ident = &path
+                    .path
+                    .segments
+                    .last()
+                    .ok_or_else(|| {
+                        syn::Error::new(path.span(), "Probe arguments should resolve to path types")
+                    })?
+                    .ident;
+                if is_simple_type(last_ident) {
+                    Ok(data_type_from_path(&path.path, false))
+                } else if is_str_pointer_type(&path.path) {
+                    Ok(DataType::Native(dtrace_parser::DataType::String))
+                } else if is_path_type(&path.path) {
+                    Ok(DataType::Path)
+                } else if last_ident == "UniqueId" {
+                    Ok(DataType::UniqueId)
+                } else if let Some(kind) = timestamp_kind(&path.path) {
+                    Ok(DataType::Timestamp(kind, dtrace_parser::Sign::Unsigned))
+                } else if is_duration_type(&path.path) {
+                    Ok(DataType::Duration)
+                } else if last_ident == "Instant" {
+                    Err(syn::Error::new(
+                        path.span(),
+                        concat!(
+                            "An `Instant` has no absolute value to pass to a probe, ",
+                            "pass the `Duration` since another `Instant` instead",
+                        ),
+                    ))
+                } else if cfg!(feature = "uuid") && last_ident == "Uuid" {
+                    Ok(DataType::Uuid)
+                } else if let Some(int) = non_zero_integer(&path.path) {
+                    Ok(DataType::NonZero(int))
+                } else {
+                    Ok(DataType::Serializable(item.clone()))
+                }
+            }
+            // The pointee of a raw pointer is never read, only its address is passed.
+            syn::Type::Ptr(_) => Ok(DataType::Address(item.clone())),
+            syn::Type::ImplTrait(ref impl_trait) if is_display_bounds(&impl_trait.bounds) => {
+                Ok(DataType::Display)
+            }
+            // Key/value pairs are serialized as an object, rather than as the array holding them.
+            syn::Type::ImplTrait(_) if is_fields_type(item) => Ok(DataType::Serializable(
+                syn::parse_quote! { impl ::usdt::Fields },
+            )),
+            syn::Type::Reference(ref reference) => {
+                if let syn::Type::TraitObject(ref object) = *reference.elem {
+                    if is_display_bounds(&object.bounds) && reference.mutability.is_none() {
+                        return Ok(DataType::Display);
+                    }
+                    return erased_serialize_type(reference, object);
+                }
+                match DataType::try_from(&*reference.elem)? {
+                    DataType::Serializable(_) => Ok(DataType::Serializable(syn::Type::Reference(
+                        syn::TypeReference {
+                            mutability: None,
+                            ..reference.clone()
+                        },
+                    ))),
+                    native => Ok(native),
+                }
+            }
+            syn::Type::Array(ref array) => match native_array_type(array) {
+                Some((int, len)) => Ok(DataType::NativeArray(int, len)),
+                None => Ok(DataType::Serializable(item.clone())),
+            },
+            syn::Type::Slice(_) | syn::Type::Tuple(_) => Ok(DataType::Serializable(item.clone())),
+            _ => Err(syn::Error::new(
+                item.span(),
+                concat!(
+                    "Probe arguments must be path types, slices, arrays, tuples, ",
+                    "references, or raw pointers",
+                ),
+            )),
+        }
     }
 }
 
-/// A single DTrace probe function
-#[derive(Debug, Clone)]
-pub struct Probe {
-    pub name: String,
-    pub types: Vec<DataType>,
+// Return the element type and length of an array which can be passed natively, if any.
+fn native_array_type(array: &syn::TypeArray) -> Option<(dtrace_parser::Integer, usize)> {
+    let syn::Type::Path(ref path) = *array.elem else {
+        return None;
+    };
+    if !is_integer_type(&path.path.segments.last()?.ident) {
+        return None;
+    }
+    let syn::Expr::Lit(syn::ExprLit {
+        lit: syn::Lit::Int(ref len),
+        ..
+    }) = array.len
+    else {
+        return None;
+    };
+    let len = len.base10_parse::<usize>().ok()?;
+    if len == 0 || len > MAX_PROBE_ARGUMENTS {
+        return None;
+    }
+    match data_type_from_path(&path.path, false) {
+        DataType::Native(dtrace_parser::DataType::Integer(int)) => Some((int, len)),
+        _ => None,
+    }
 }
 
-impl From<dtrace_parser::Probe> for Probe {
-    fn from(p: dtrace_parser::Probe) -> Self {
-        Self {
-            name: p.name,
-            types: p.types.into_iter().map(DataType::from).collect(),
+// Return the integer wrapped by a non-zero integer type, such as `NonZeroU32` or `NonZero<u32>`.
+fn non_zero_integer(path: &syn::Path) -> Option<dtrace_parser::Integer> {
+    let last = path.segments.last()?;
+    let name = last.ident.to_string();
+    let int = match (name.strip_prefix("NonZero")?, &last.arguments) {
+        ("", syn::PathArguments::AngleBracketed(args)) => {
+            match args.args.iter().collect::<Vec<_>>()[..] {
+                [syn::GenericArgument::Type(syn::Type::Path(ty))] if ty.qself.is_none() => {
+                    ty.path.get_ident()?.to_string()
+                }
+                _ => return None,
+            }
         }
+        (int, syn::PathArguments::None) => int.to_lowercase(),
+        _ => return None,
+    };
+    let ident = syn::Ident::new(&int, last.ident.span());
+    if !is_integer_type(&ident) && int != "usize" && int != "isize" {
+        return None;
+    }
+    match data_type_from_path(&ident.into(), false) {
+        DataType::Native(dtrace_parser::DataType::Integer(int)) => Some(int),
+        _ => None,
     }
 }
 
-impl Probe {
-    /// Return the representation of this probe in D source code.
-    pub fn to_d_source(&self) -> String {
-        let types = self
-            .types
-            .iter()
-            .map(|typ| typ.to_c_type())
-            .collect::<Vec<_>>()
-            .join(", ");
-        format!("probe {name}({types});", name = self.name, types = types)
+// Return `true` if the type is `impl Fields`, the key/value pairs passed as a JSON object.
+pub(crate) fn is_fields_type(ty: &syn::Type) -> bool {
+    let syn::Type::ImplTrait(impl_trait) = ty else {
+        return false;
+    };
+    match impl_trait.bounds.iter().collect::<Vec<_>>()[..] {
+        [syn::TypeParamBound::Trait(bound)] => bound
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Fields" && segment.arguments.is_none()),
+        _ => false,
     }
 }
 
-/// The `Provider` represents a single DTrace provider, with a collection of probes.
-#[derive(Debug, Clone)]
-pub struct Provider {
-    pub name: String,
-    pub probes: Vec<Probe>,
-    pub use_statements: Vec<syn::ItemUse>,
+// Return `true` if the bounds of a trait object or `impl Trait` are only `Display`.
+fn is_display_bounds(
+    bounds: &syn::punctuated::Punctuated<syn::TypeParamBound, syn::Token![+]>,
+) -> bool {
+    match bounds.iter().collect::<Vec<_>>()[..] {
+        [syn::TypeParamBound::Trait(bound)] => bound
+            .path
+            .segments
+            .last()
+            .is_some_and(|segment| segment.ident == "Display" && segment.arguments.is_none()),
+        _ => false,
+    }
 }
 
-impl Provider {
-    /// Return the representation of this provider in D source code.
-    pub fn to_d_source(&self) -> String {
-        let probes = self
-            .probes
-            .iter()
-            .map(|probe| format!("\t{}", probe.to_d_source()))
-            .collect::<Vec<_>>()
-            .join("\n");
-        format!(
-            "provider {provider_name} {{\n{probes}\n}};",
-            provider_name = self.name,
-            probes = probes
-        )
+// Return the data type of a reference to a `dyn Serialize` trait object.
+//
+// As `Serialize` can't be made into a trait object, such arguments are passed as an
+// `ErasedSerialize` trait object, and serialized like any other type.
+fn erased_serialize_type(
+    reference: &syn::TypeReference,
+    object: &syn::TypeTraitObject,
+) -> syn::Result<DataType> {
+    let is_serialize = object.bounds.iter().any(|bound| match bound {
+        syn::TypeParamBound::Trait(bound) => bound.path.segments.last().is_some_and(|segment| {
+            segment.ident == "Serialize" || segment.ident == "ErasedSerialize"
+        }),
+        _ => false,
+    });
+    if !is_serialize || object.bounds.len() > 1 || reference.mutability.is_some() {
+        return Err(syn::Error::new(
+            reference.span(),
+            concat!(
+                "The only trait objects supported as probe arguments are ",
+                "`&dyn Serialize` and `&dyn Display`",
+            ),
+        ));
     }
+    Ok(DataType::Serializable(syn::parse_quote! {
+        &dyn ::usdt::ErasedSerialize
+    }))
 }
 
-impl From<dtrace_parser::Provider> for Provider {
-    fn from(p: dtrace_parser::Provider) -> Self {
-        Self {
-            name: p.name,
-            probes: p.probes.into_iter().map(Probe::from).collect(),
-            use_statements: vec![],
+// Return the kind of timestamp the given path names, if any, and if its feature is enabled.
+fn timestamp_kind(path: &syn::Path) -> Option<TimestampKind> {
+    let last = path.segments.last()?;
+    if cfg!(feature = "chrono") && last.ident == "DateTime" {
+        // Only UTC timestamps are supported, as `DateTime<Utc>`.
+        let syn::PathArguments::AngleBracketed(ref args) = last.arguments else {
+            return None;
+        };
+        match args.args.iter().collect::<Vec<_>>()[..] {
+            [syn::GenericArgument::Type(syn::Type::Path(ref tz))]
+                if tz.path.segments.last()?.ident == "Utc" =>
+            {
+                Some(TimestampKind::Chrono)
+            }
+            _ => None,
         }
+    } else if cfg!(feature = "time") && last.ident == "OffsetDateTime" && last.arguments.is_none() {
+        Some(TimestampKind::Time)
+    } else {
+        None
     }
 }
 
-impl From<&dtrace_parser::Provider> for Provider {
-    fn from(p: &dtrace_parser::Provider) -> Self {
-        Self::from(p.clone())
+// Return the 64-bit integer type with the given sign, which timestamps and UUIDs are passed as.
+fn integer64(sign: dtrace_parser::Sign) -> dtrace_parser::Integer {
+    dtrace_parser::Integer {
+        sign,
+        width: dtrace_parser::BitWidth::Bit64,
     }
 }
 
-/// Convert a serializable type into a JSON string, if possible.
-///
-/// NOTE: This is essentially a re-export of the `serde_json::to_string` function, used to avoid
-/// foisting an explicity dependency on that crate in user's `Cargo.toml`.
-pub fn to_json<T>(x: &T) -> Result<String, Error>
-where
-    T: ?Sized + ::serde::Serialize,
-{
-    ::serde_json::to_string(x).map_err(Error::from)
+// Resolve a pointer-sized integer to the pointer width of the target's architecture, rather than
+// leaving it to `dtrace_parser`, which uses that of the host generating the probes. Without an
+// architecture, the integer is left to be resolved on the host.
+pub(crate) fn target_integer(
+    int: &dtrace_parser::Integer,
+    arch: Option<Arch>,
+) -> dtrace_parser::Integer {
+    let width = match (int.width, arch.map(|arch| arch.pointer_width())) {
+        (dtrace_parser::BitWidth::Pointer, Some("32")) => dtrace_parser::BitWidth::Bit32,
+        (dtrace_parser::BitWidth::Pointer, Some(_)) => dtrace_parser::BitWidth::Bit64,
+        (width, _) => width,
+    };
+    dtrace_parser::Integer {
+        sign: int.sign,
+        width,
+    }
 }
 
-thread_local! {
-    static CURRENT_ID: RefCell<u32> = const { RefCell::new(0) };
-    static THREAD_ID: RefCell<usize> = RefCell::new(thread_id::get());
+// Resolve a pointer-sized integer as it's passed to the functions firing probes through FFI, which
+// take them as 64-bit integers on every target.
+pub(crate) fn ffi_integer(int: &dtrace_parser::Integer) -> dtrace_parser::Integer {
+    target_integer(int, Some(Arch::X86_64))
 }
 
-/// A unique identifier that can be used to correlate multiple USDT probes together.
-///
-/// It's a common pattern in DTrace scripts to correlate multiple probes. For example, one can time
-/// system calls by storing a timestamp on the `syscall:::entry` probe and then computing the
-/// elapsed time in the `syscall:::return` probe. This requires some way to "match up" these two
-/// probes, to ensure that the elapsed time is correctly attributed to a single system call. Doing
-/// so requires an identifier. User code may already have an ID appropriate for this use case, but
-/// the `UniqueId` type may be used when one is not already available. These unique IDs can be used
-/// to correlate multiple probes occurring in a section or span of user code.
-///
-/// A probe function may accept a `UniqueId`, which appears in a D as a `u64`. The value is
-/// guaranteed to be unique, even if multiple threads run the same traced section of code. (See the
-/// [notes] for caveats.) The value may be shared between threads by calling `clone()` on a
-/// constructed span -- in this case, the cloned object shares the same value, so that a traced
-/// span running in multiple threads (or asynchronous tasks) shares the same identifier.
-///
-/// A `UniqueId` is very cheap to construct. The internal value is "materialized" in two
-/// situations:
-///
-/// - When an _enabled_ probe fires
-/// - When the value is cloned (e.g., for sharing with another thread)
-///
-/// This minimizes the disabled-probe effect, but still allows sharing a consistent ID in the case
-/// of multithreaded work.
+// Resolve the pointer-sized integers of a native type, as by `target_integer`.
+fn target_native_type(ty: &dtrace_parser::DataType, arch: Option<Arch>) -> dtrace_parser::DataType {
+    match ty {
+        dtrace_parser::DataType::Integer(int) => {
+            dtrace_parser::DataType::Integer(target_integer(int, arch))
+        }
+        dtrace_parser::DataType::Pointer(int) => {
+            dtrace_parser::DataType::Pointer(target_integer(int, arch))
+        }
+        dtrace_parser::DataType::String => dtrace_parser::DataType::String,
+    }
+}
+
+// Resolve the pointer-sized integers of a native type, as by `ffi_integer`.
+fn ffi_native_type(ty: &dtrace_parser::DataType) -> dtrace_parser::DataType {
+    target_native_type(ty, Some(Arch::X86_64))
+}
+
+// Return `true` if the type is an integer
+fn is_integer_type(ident: &syn::Ident) -> bool {
+    let ident = format!("{}", ident);
+    matches!(
+        ident.as_str(),
+        "u8" | "u16" | "u32" | "u64" | "i8" | "i16" | "i32" | "i64"
+    )
+}
+
+// Return `true` if this type is "simple", a primitive type with an analog in D, i.e., _not_ a
+// type that implements `Serialize`.
+fn is_simple_type(ident: &syn::Ident) -> bool {
+    let ident = format!("{}", ident);
+    matches!(
+        ident.as_str(),
+        "u8" | "u16"
+            | "u32"
+            | "u64"
+            | "i8"
+            | "i16"
+            | "i32"
+            | "i64"
+            | "String"
+            | "str"
+            | "usize"
+            | "isize"
+    )
+}
+
+// Return `true` if this type is a smart pointer to a `str`, such as `Cow<str>` or `Arc<str>`, which
+// is passed as a string like `&str` itself.
+fn is_str_pointer_type(path: &syn::Path) -> bool {
+    let Some(segment) = path.segments.last() else {
+        return false;
+    };
+    if !matches!(
+        segment.ident.to_string().as_str(),
+        "Cow" | "Box" | "Rc" | "Arc"
+    ) {
+        return false;
+    }
+    let syn::PathArguments::AngleBracketed(ref args) = segment.arguments else {
+        return false;
+    };
+    let mut types = args.args.iter().filter_map(|arg| match arg {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+    matches!(
+        (types.next(), types.next()),
+        (Some(syn::Type::Path(ty)), None) if ty.qself.is_none() && ty.path.is_ident("str")
+    )
+}
+
+// Return `true` if the path names a `Path` or `PathBuf`, which are passed as strings.
+fn is_path_type(path: &syn::Path) -> bool {
+    path.segments.last().is_some_and(|segment| {
+        (segment.ident == "Path" || segment.ident == "PathBuf") && segment.arguments.is_none()
+    })
+}
+
+fn is_duration_type(path: &syn::Path) -> bool {
+    path.segments
+        .last()
+        .is_some_and(|segment| segment.ident == "Duration" && segment.arguments.is_none())
+}
+
+// Return a `Box<T>`, `Rc<T>` or `Arc<T>` type, behind any references, and the `T` it points to.
+pub(crate) fn smart_pointer_type(ty: &syn::Type) -> Option<(&syn::Type, &syn::Type)> {
+    let mut ty = ty;
+    while let syn::Type::Reference(reference) = ty {
+        ty = &reference.elem;
+    }
+    let syn::Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last()?;
+    if !matches!(segment.ident.to_string().as_str(), "Box" | "Rc" | "Arc") {
+        return None;
+    }
+    let syn::PathArguments::AngleBracketed(ref args) = segment.arguments else {
+        return None;
+    };
+    let mut types = args.args.iter().filter_map(|arg| match arg {
+        syn::GenericArgument::Type(ty) => Some(ty),
+        _ => None,
+    });
+    match (types.next(), types.next()) {
+        (Some(elem), None) => Some((ty, elem)),
+        _ => None,
+    }
+}
+
+// Return the `dtrace_parser::DataType` corresponding to the given `path`, by its last segment, so
+// that qualified paths such as `core::primitive::u8` or `std::string::String` are accepted too.
+fn data_type_from_path(path: &syn::Path, pointer: bool) -> DataType {
+    use dtrace_parser::BitWidth;
+    use dtrace_parser::DataType as DType;
+    use dtrace_parser::Integer;
+    use dtrace_parser::Sign;
+
+    let variant = if pointer {
+        DType::Pointer
+    } else {
+        DType::Integer
+    };
+    let ident = &path
+        .segments
+        .last()
+        .expect("Paths have at least one segment")
+        .ident;
+
+    if ident == "u8" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        }))
+    } else if ident == "u16" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit16,
+        }))
+    } else if ident == "u32" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit32,
+        }))
+    } else if ident == "u64" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit64,
+        }))
+    } else if ident == "i8" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Signed,
+            width: BitWidth::Bit8,
+        }))
+    } else if ident == "i16" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Signed,
+            width: BitWidth::Bit16,
+        }))
+    } else if ident == "i32" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Signed,
+            width: BitWidth::Bit32,
+        }))
+    } else if ident == "i64" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Signed,
+            width: BitWidth::Bit64,
+        }))
+    } else if ident == "String" || ident == "str" {
+        DataType::Native(DType::String)
+    } else if ident == "isize" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Signed,
+            width: BitWidth::Pointer,
+        }))
+    } else if ident == "usize" {
+        DataType::Native(variant(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Pointer,
+        }))
+    } else {
+        unreachable!("Tried to parse a non-path data type");
+    }
+}
+
+/// A single DTrace probe function
+#[derive(Debug, Clone)]
+pub struct Probe {
+    pub name: String,
+    pub types: Vec<DataType>,
+    /// Attributes forwarded onto the generated probe macro, such as doc comments or
+    /// `#[deprecated]`.
+    pub attrs: Vec<syn::Attribute>,
+    /// The names of the probe's own parameters, or `None` for one named `_`. Probes defined in D
+    /// have no names, and this is empty.
+    pub arg_names: Vec<Option<String>>,
+    /// The D types given to the probe's own parameters with `#[d_type = "..."]`, or `None` for
+    /// those without one. Probes defined in D have none, and this is empty.
+    ///
+    /// DTrace presents such an argument to scripts as that type, its translated type, rather than
+    /// as the integer or pointer it's passed as.
+    pub d_types: Vec<Option<String>>,
+    /// The defaults given to the probe's trailing parameters with `#[default = ...]`, or `None`
+    /// for those without one. Probes defined in D have none, and this is empty.
+    ///
+    /// The probe's macro supplies these when the argument closure leaves the arguments out.
+    pub defaults: Vec<Option<syn::Expr>>,
+    /// The span of the probe's definition, where warnings about the probe are reported. Probes
+    /// defined in D have no span of their own, and use the call site of the macro.
+    pub span: proc_macro2::Span,
+}
+
+impl From<dtrace_parser::Probe> for Probe {
+    fn from(p: dtrace_parser::Probe) -> Self {
+        Self {
+            name: p.name,
+            types: p.types.into_iter().map(DataType::from).collect(),
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        }
+    }
+}
+
+impl Probe {
+    /// Return the data types of the arguments this probe is fired with.
+    ///
+    /// Arrays passed natively are expanded into one argument per element, so these may be more
+    /// than the types of the probe function's parameters.
+    pub fn argument_types(&self) -> Vec<DataType> {
+        self.types.iter().flat_map(DataType::expand).collect()
+    }
+
+    /// Return the D type each argument of this probe is presented to scripts as.
+    ///
+    /// This is the type given with `#[d_type = "..."]`, if any, or the C type the argument is
+    /// passed as on the given architecture. There's one type for each of the
+    /// [`Probe::argument_types`].
+    pub fn translated_argument_types(&self, arch: Arch) -> Vec<String> {
+        self.types
+            .iter()
+            .enumerate()
+            .flat_map(|(i, typ)| match self.d_types.get(i) {
+                Some(Some(d_type)) => vec![d_type.clone()],
+                _ => typ
+                    .expand()
+                    .iter()
+                    .map(|typ| typ.to_c_type_for(arch))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Return the representation of this probe in D source code.
+    pub fn to_d_source(&self) -> String {
+        format!("probe {}({});", self.name, self.dtrace_arg_spec())
+    }
+
+    /// Return the C types of the arguments of this probe, separated by commas, as they're
+    /// declared in the D source of its provider, such as `uint8_t, char*`.
+    pub fn dtrace_arg_spec(&self) -> String {
+        self.argument_types()
+            .iter()
+            .map(|typ| typ.to_c_type())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    /// Return the arguments of this probe as recorded in its SystemTap note on the given
+    /// architecture, such as `1@%dil 8@%rsi`, which `readelf -n` shows as its `Arguments`.
+    pub fn stapsdt_arg_spec(&self, arch: Arch) -> String {
+        self.argument_types()
+            .iter()
+            .enumerate()
+            .map(|(reg_index, typ)| stapsdt::args::format_argument(arch, reg_index, typ))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// The `Provider` represents a single DTrace provider, with a collection of probes.
+#[derive(Debug, Clone)]
+pub struct Provider {
+    pub name: String,
+    pub probes: Vec<Probe>,
+    pub use_statements: Vec<syn::ItemUse>,
+    /// The stability attributes of the provider, which are `Internal/Internal/Unknown` unless
+    /// declared otherwise.
+    pub attributes: dtrace_parser::ProviderAttributes,
+}
+
+impl Provider {
+    /// Return this provider with the types of the given automatic arguments appended to those of
+    /// each probe, as they're passed when the probe fires.
+    pub(crate) fn with_auto_args(&self, auto_args: &[AutoArg]) -> Provider {
+        let mut provider = self.clone();
+        for probe in provider.probes.iter_mut() {
+            probe
+                .types
+                .extend(auto_args.iter().map(|arg| arg.data_type()));
+        }
+        provider
+    }
+
+    /// Return this provider with the prefix, if any, prepended to the name of each of its probes.
+    ///
+    /// It's an error for a prefixed name not to be a valid probe name, say for being too long.
+    pub fn with_prefix(mut self, prefix: Option<&str>) -> Result<Provider, Error> {
+        let Some(prefix) = prefix else {
+            return Ok(self);
+        };
+        for probe in self.probes.iter_mut() {
+            probe.name = format!("{}{}", prefix, probe.name);
+            dtrace_parser::validate_probe_name(&probe.name)?;
+        }
+        Ok(self)
+    }
+
+    /// Return this provider with its serializable arguments passed with the length of their JSON,
+    /// if `json_len` is set.
+    pub(crate) fn with_json_len(self, json_len: bool) -> Provider {
+        if !json_len {
+            return self;
+        }
+        let mut provider = self;
+        for typ in provider
+            .probes
+            .iter_mut()
+            .flat_map(|probe| probe.types.iter_mut())
+        {
+            if let DataType::Serializable(ty) = typ {
+                *typ = DataType::SizedSerializable(ty.clone());
+            }
+        }
+        provider
+    }
+
+    /// Return the representation of this provider in D source code.
+    ///
+    /// Any stability attributes are declared with pragmas following the provider.
+    pub fn to_d_source(&self) -> String {
+        let probes = self
+            .probes
+            .iter()
+            .map(|probe| format!("\t{}", probe.to_d_source()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        let pragmas = self
+            .attributes
+            .to_pragmas(&self.name)
+            .into_iter()
+            .map(|pragma| format!("\n{}\n", pragma))
+            .collect::<Vec<_>>()
+            .concat();
+        format!(
+            "provider {provider_name} {{\n{probes}\n}};{pragmas}",
+            provider_name = self.name,
+            probes = probes,
+            pragmas = pragmas,
+        )
+    }
+}
+
+impl From<dtrace_parser::Provider> for Provider {
+    fn from(p: dtrace_parser::Provider) -> Self {
+        Self {
+            name: p.name,
+            probes: p.probes.into_iter().map(Probe::from).collect(),
+            use_statements: vec![],
+            attributes: p.attributes,
+        }
+    }
+}
+
+impl From<&dtrace_parser::Provider> for Provider {
+    fn from(p: &dtrace_parser::Provider) -> Self {
+        Self::from(p.clone())
+    }
+}
+
+/// Return the ID of the current thread, as given by the OS, passed to probes with the `tid`
+/// automatic argument.
+pub fn current_thread_id() -> u64 {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        unsafe { libc::gettid() as u64 }
+    }
+    #[cfg(target_os = "macos")]
+    {
+        let mut id = 0;
+        unsafe { libc::pthread_threadid_np(libc::pthread_self(), &mut id) };
+        id
+    }
+    #[cfg(target_os = "freebsd")]
+    {
+        unsafe { libc::pthread_getthreadid_np() as u64 }
+    }
+    #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+    {
+        // Threads are identified by their LWP ID, which is also what `pthread_self` returns.
+        unsafe { libc::pthread_self() as u64 }
+    }
+    #[cfg(windows)]
+    {
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetCurrentThreadId() -> u32;
+        }
+        unsafe { u64::from(GetCurrentThreadId()) }
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "macos",
+        target_os = "freebsd",
+        target_os = "illumos",
+        target_os = "solaris",
+        windows
+    )))]
+    {
+        0
+    }
+}
+
+/// Return the CPU the current thread runs on, passed to probes with the `cpu` automatic argument,
+/// or -1 where the OS doesn't tell.
+pub fn current_cpu() -> i32 {
+    #[cfg(any(target_os = "linux", target_os = "android", target_os = "freebsd"))]
+    {
+        unsafe { libc::sched_getcpu() }
+    }
+    #[cfg(any(target_os = "illumos", target_os = "solaris"))]
+    {
+        extern "C" {
+            fn getcpuid() -> libc::c_int;
+        }
+        unsafe { getcpuid() }
+    }
+    #[cfg(windows)]
+    {
+        #[link(name = "kernel32")]
+        extern "system" {
+            fn GetCurrentProcessorNumber() -> u32;
+        }
+        unsafe { GetCurrentProcessorNumber() as i32 }
+    }
+    #[cfg(not(any(
+        target_os = "linux",
+        target_os = "android",
+        target_os = "freebsd",
+        target_os = "illumos",
+        target_os = "solaris",
+        windows
+    )))]
+    {
+        -1
+    }
+}
+
+/// Convert a serializable type into a JSON string, if possible.
+///
+/// NOTE: This is essentially a re-export of the `serde_json::to_string` function, used to avoid
+/// foisting an explicity dependency on that crate in user's `Cargo.toml`.
+pub fn to_json<T>(x: &T) -> Result<String, Error>
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    ::serde_json::to_string(x).map_err(Error::from)
+}
+
+/// Convert a serializable type into a JSON string of at most `max_len` bytes, if possible.
+///
+/// Longer JSON is replaced by an object marking it as truncated, with its full length, as in
+/// `{"__truncated":true,"len":1234567}`. Only the first `max_len` bytes are kept while serializing.
+pub fn to_json_bounded<T>(x: &T, max_len: usize) -> Result<String, Error>
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    let mut writer = BoundedWriter {
+        buf: Vec::new(),
+        len: 0,
+        max_len,
+    };
+    ::serde_json::to_writer(&mut writer, x)?;
+    if writer.len > max_len {
+        Ok(format!("{{\"__truncated\":true,\"len\":{}}}", writer.len))
+    } else {
+        // Unwrap safety: serde_json only writes valid UTF-8, and all of it was kept.
+        Ok(String::from_utf8(writer.buf).unwrap())
+    }
+}
+
+/// Serialize a probe argument into the null-terminated JSON passed to the probe.
+///
+/// The JSON is wrapped in an object mapping the result of serialization to the keys "ok" and
+/// "err", and bounded as by [`to_json_bounded`]. It's written directly into a single buffer, which
+/// is reused by later probes fired on the same thread, so that large arguments are encoded in
+/// linear time without repeatedly growing or copying it.
+#[cfg(not(feature = "json-core"))]
+#[doc(hidden)]
+pub fn to_json_probe_arg<T>(x: &T, max_len: usize) -> JsonProbeArg
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    json_probe_arg(x, max_len, &[])
+}
+
+// Serialize a probe argument as by `to_json_probe_arg`, following the given header in the same
+// buffer.
+#[cfg(not(feature = "json-core"))]
+fn json_probe_arg<T>(x: &T, max_len: usize, header: &[u8]) -> JsonProbeArg
+where
+    T: ?Sized + ::serde::Serialize,
+{
+    use std::io::Write as _;
+
+    const PREFIX: &[u8] = b"{\"ok\":";
+    let mut writer = BoundedWriter {
+        buf: take_probe_arg_buffer(&JSON_BUFFER),
+        len: 0,
+        max_len,
+    };
+    writer.buf.extend_from_slice(header);
+    writer.buf.extend_from_slice(PREFIX);
+    match ::serde_json::to_writer(&mut writer, x) {
+        Ok(()) if writer.len > max_len => {
+            writer.buf.truncate(header.len() + PREFIX.len());
+            write!(
+                writer.buf,
+                "{{\"__truncated\":true,\"len\":{}}}",
+                writer.len
+            )
+            .unwrap();
+        }
+        Ok(()) => {}
+        Err(e) => {
+            writer.buf.truncate(header.len());
+            write!(writer.buf, "{{\"err\":\"{}\"", Error::from(e)).unwrap();
+        }
+    }
+    writer.buf.extend_from_slice(b"}\0");
+    JsonProbeArg(writer.buf)
+}
+
+/// The null-terminated JSON of a probe argument, returning its buffer for reuse when dropped.
+#[cfg(not(feature = "json-core"))]
+#[doc(hidden)]
+pub struct JsonProbeArg(Vec<u8>);
+
+#[cfg(not(feature = "json-core"))]
+impl JsonProbeArg {
+    /// Return the length of the argument without its null byte, as passed with `json_len`.
+    pub fn arg_len(&self) -> usize {
+        self.0.len() - 1
+    }
+}
+
+#[cfg(not(feature = "json-core"))]
+impl std::ops::Deref for JsonProbeArg {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+#[cfg(not(feature = "json-core"))]
+impl Drop for JsonProbeArg {
+    fn drop(&mut self) {
+        return_probe_arg_buffer(&JSON_BUFFER, std::mem::take(&mut self.0));
+    }
+}
+
+#[cfg(not(feature = "json-core"))]
+thread_local! {
+    // The buffer reused to serialize probe arguments on this thread.
+    static JSON_BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+// Take the buffer from one of the thread-local caches of probe arguments.
+//
+// Probes may be fired from the destructor of another thread-local, after the cache is gone, in
+// which case a new buffer is used rather than panicking.
+fn take_probe_arg_buffer(cache: &'static std::thread::LocalKey<Cell<Vec<u8>>>) -> Vec<u8> {
+    cache.try_with(Cell::take).unwrap_or_default()
+}
+
+// Return a buffer taken from one of the thread-local caches of probe arguments to it.
+//
+// The larger buffer is kept, if another was returned while this one was in use. The cache may
+// already be gone if the thread is exiting.
+fn return_probe_arg_buffer(cache: &'static std::thread::LocalKey<Cell<Vec<u8>>>, mut buf: Vec<u8>) {
+    buf.clear();
+    let _ = cache.try_with(|cached| {
+        let other = cached.take();
+        cached.set(if other.capacity() > buf.capacity() {
+            other
+        } else {
+            buf
+        });
+    });
+}
+
+/// Format a value passed to a probe as a `Display` into a null-terminated string.
+///
+/// As with serialized arguments, the string is written directly into a buffer which is reused by
+/// later probes fired on the same thread. If formatting fails, the string holds what was written
+/// before the error.
+#[doc(hidden)]
+pub fn to_display_probe_arg(value: &dyn std::fmt::Display) -> DisplayProbeArg {
+    use std::io::Write as _;
+
+    let mut buf = take_probe_arg_buffer(&DISPLAY_BUFFER);
+    let _ = write!(buf, "{value}");
+    buf.push(0);
+    DisplayProbeArg(buf)
+}
+
+/// The null-terminated string of a formatted probe argument, returning its buffer for reuse when
+/// dropped.
+#[doc(hidden)]
+pub struct DisplayProbeArg(Vec<u8>);
+
+impl std::ops::Deref for DisplayProbeArg {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Drop for DisplayProbeArg {
+    fn drop(&mut self) {
+        return_probe_arg_buffer(&DISPLAY_BUFFER, std::mem::take(&mut self.0));
+    }
+}
+
+thread_local! {
+    // The buffer reused to format probe arguments on this thread.
+    static DISPLAY_BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) };
+}
+
+/// The argument of a probe's macro, either a closure returning the probe's arguments or the
+/// arguments themselves.
+///
+/// The two are told apart with autoref-based specialization: calling `usdt_probe_args_kind` on a
+/// `&ProbeArgs<T>` finds [`ClosureProbeArgs`] without any autoref when `T` is a closure, or any
+/// other `FnOnce() -> R`, and [`ValueProbeArgs`] with one otherwise. The kind returned then turns
+/// the argument into a closure, which is only called when the probe is enabled.
+#[doc(hidden)]
+pub struct ProbeArgs<T>(pub T);
+
+#[doc(hidden)]
+pub trait ClosureProbeArgs {
+    fn usdt_probe_args_kind(&self) -> ClosureProbeArgsKind {
+        ClosureProbeArgsKind
+    }
+}
+
+impl<F: FnOnce() -> R, R> ClosureProbeArgs for ProbeArgs<F> {}
+
+#[doc(hidden)]
+pub trait ValueProbeArgs {
+    fn usdt_probe_args_kind(&self) -> ValueProbeArgsKind {
+        ValueProbeArgsKind
+    }
+}
+
+impl<T> ValueProbeArgs for &ProbeArgs<T> {}
+
+/// The kind of a probe's argument which is already a closure returning its arguments.
+#[doc(hidden)]
+pub struct ClosureProbeArgsKind;
+
+impl ClosureProbeArgsKind {
+    pub fn into_closure<F: FnOnce() -> R, R>(self, f: F) -> impl FnOnce() -> R {
+        f
+    }
+}
+
+/// The kind of a probe's argument which is the probe's arguments themselves.
+#[doc(hidden)]
+pub struct ValueProbeArgsKind;
+
+impl ValueProbeArgsKind {
+    pub fn into_closure<T>(self, value: T) -> impl FnOnce() -> T {
+        move || value
+    }
+}
+
+/// The length of the longest string passed to a probe without allocating, its null byte included.
+const INLINE_STR_PROBE_ARG_LEN: usize = 128;
+
+/// Null-terminate a string passed to a probe.
+///
+/// Short strings are copied into a buffer on the stack, so that firing a probe with only native
+/// arguments doesn't allocate.
+#[doc(hidden)]
+pub fn to_str_probe_arg(s: &str) -> StrProbeArg {
+    bytes_to_str_probe_arg(s.as_bytes())
+}
+
+/// Null-terminate a path passed to a probe, as [`to_str_probe_arg`] does a string.
+///
+/// On Unix, the bytes of the path are passed as they are, whether or not they're UTF-8. Elsewhere,
+/// the path is converted to UTF-8 lossily.
+#[doc(hidden)]
+pub fn to_path_probe_arg(path: &std::path::Path) -> StrProbeArg {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt as _;
+        bytes_to_str_probe_arg(path.as_os_str().as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        bytes_to_str_probe_arg(path.to_string_lossy().as_bytes())
+    }
+}
+
+fn bytes_to_str_probe_arg(bytes: &[u8]) -> StrProbeArg {
+    if bytes.len() < INLINE_STR_PROBE_ARG_LEN {
+        let mut buf = [0; INLINE_STR_PROBE_ARG_LEN];
+        buf[..bytes.len()].copy_from_slice(bytes);
+        StrProbeArg::Inline(buf, bytes.len() + 1)
+    } else {
+        StrProbeArg::Heap([bytes, &[0]].concat())
+    }
+}
+
+/// The null-terminated string of a probe argument.
+#[doc(hidden)]
+pub enum StrProbeArg {
+    Inline([u8; INLINE_STR_PROBE_ARG_LEN], usize),
+    Heap(Vec<u8>),
+}
+
+impl std::ops::Deref for StrProbeArg {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            StrProbeArg::Inline(buf, len) => &buf[..*len],
+            StrProbeArg::Heap(buf) => buf,
+        }
+    }
+}
+
+// A writer keeping the first `max_len` bytes written to it, and counting the rest.
+//
+// Any bytes already in the buffer aren't counted towards `max_len`.
+struct BoundedWriter {
+    buf: Vec<u8>,
+    len: usize,
+    max_len: usize,
+}
+
+impl std::io::Write for BoundedWriter {
+    // Serializers write a few bytes at a time, so this is inlined into them.
+    #[inline]
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.len += buf.len();
+        if self.len <= self.max_len {
+            self.buf.extend_from_slice(buf);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// An object-safe version of `serde::Serialize`, used to pass probe arguments declared as
+/// `&dyn Serialize`.
+///
+/// `Serialize` can't be made into a trait object, as its method is generic. Instead, this trait is
+/// implemented for every sized serializable type, and a probe argument declared as
+/// `&dyn Serialize` accepts a reference to any of those, or a `&dyn ErasedSerialize`. Such values
+/// are serialized through `erased_serde`, straight into the probe's buffer, and their JSON is the
+/// same as that of the value's own type.
+pub trait ErasedSerialize: erased_serde::Serialize {}
+
+impl<T: ::serde::Serialize> ErasedSerialize for T {}
+
+erased_serde::serialize_trait_object!(ErasedSerialize);
+
+/// A field-less enum whose probe arguments may be passed as the name of their variant.
+///
+/// A probe argument marked `#[variant_name]`, as in `fn transition(#[variant_name] _: State) {}`,
+/// is passed as a `char *` holding the name of the variant, rather than serialized to JSON. The
+/// trait is usually derived with `#[derive(usdt::VariantName)]`, which only accepts enums without
+/// fields.
+pub trait VariantName {
+    /// Return the name of the variant, as it's written in the enum.
+    fn variant_name(&self) -> &'static str;
+}
+
+// The prefix of the next shard of unique IDs handed to a thread.
+static NEXT_ID_PREFIX: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // The prefix of the current thread's shard of unique IDs, and the last ID used from it.
+    static ID_SHARD: Cell<Option<(u32, u32)>> = const { Cell::new(None) };
+}
+
+// Return a new unique ID, from the current thread's shard of IDs.
+//
+// Each shard is the 2^32 IDs sharing their upper 32 bits, the prefix. A thread takes a new shard
+// when it creates its first ID, or exhausts its previous shard, and otherwise creates IDs without
+// any synchronization at all. The prefixes wrap around past `u32::MAX`, so that a large seed never
+// runs out of them, and a prefix is only reused once all 2^32 of them have been taken.
+fn next_unique_id() -> u64 {
+    ID_SHARD.with(|shard| {
+        let (prefix, counter) = match shard.get() {
+            Some((prefix, counter)) if counter < u32::MAX => (prefix, counter + 1),
+            _ => {
+                // Truncating wraps the prefix around, rather than panicking in a probe.
+                let prefix = NEXT_ID_PREFIX.fetch_add(1, Ordering::Relaxed) as u32;
+                (prefix, 1)
+            }
+        };
+        shard.set(Some((prefix, counter)));
+        (u64::from(prefix) << 32) | u64::from(counter)
+    })
+}
+
+/// A unique identifier that can be used to correlate multiple USDT probes together.
+///
+/// It's a common pattern in DTrace scripts to correlate multiple probes. For example, one can time
+/// system calls by storing a timestamp on the `syscall:::entry` probe and then computing the
+/// elapsed time in the `syscall:::return` probe. This requires some way to "match up" these two
+/// probes, to ensure that the elapsed time is correctly attributed to a single system call. Doing
+/// so requires an identifier. User code may already have an ID appropriate for this use case, but
+/// the `UniqueId` type may be used when one is not already available. These unique IDs can be used
+/// to correlate multiple probes occurring in a section or span of user code.
+///
+/// A probe function may accept a `UniqueId`, which appears in a D as a `u64`. The value is
+/// guaranteed to be unique, even if multiple threads run the same traced section of code. (See the
+/// [notes] for caveats.) The value may be shared between threads by calling `clone()` on a
+/// constructed span -- in this case, the cloned object shares the same value, so that a traced
+/// span running in multiple threads (or asynchronous tasks) shares the same identifier.
+///
+/// A `UniqueId` is very cheap to construct. The internal value is "materialized" in two
+/// situations:
+///
+/// - When an _enabled_ probe fires
+/// - When the value is cloned (e.g., for sharing with another thread)
+///
+/// This minimizes the disabled-probe effect, but still allows sharing a consistent ID in the case
+/// of multithreaded work.
 ///
 /// Example
 /// -------
@@ -358,11 +2412,12 @@ thread_local! {
 /// Notes
 /// -----
 ///
-/// In any practical situation, the generated ID is unique. Its value is assigned on the basis of
-/// the thread that creates the `UniqueId` object, plus a monotonic thread-local counter. However,
-/// the counter is 32 bits, and so wraps around after about 4 billion unique values. So
-/// theoretically, multiple `UniqueId`s could manifest as the same value to DTrace, if they are
-/// exceptionally long-lived or generated very often.
+/// Each thread takes IDs from a shard of its own, the 2^32 IDs sharing their upper 32 bits, and
+/// only takes a new shard from a global counter once it has exhausted the previous one. Creating
+/// IDs therefore doesn't contend between threads, and no two IDs materialized by a process are the
+/// same. The shards are handed out from 0 unless seeded with [`UniqueId::seed`], wrapping around to
+/// 0 after `u32::MAX`. IDs could only repeat once all the shards are taken, which would take 2^64
+/// IDs or 2^32 threads.
 #[derive(Debug)]
 pub struct UniqueId {
     id: RefCell<Option<u64>>,
@@ -379,10 +2434,32 @@ impl UniqueId {
         }
     }
 
+    /// Seed the prefixes of the shards of IDs handed to each thread, returning whether it succeeded.
+    ///
+    /// The upper 32 bits of an ID are the prefix of the shard it was taken from, and threads take
+    /// shards in the order they materialize their first ID. Shards otherwise start from prefix 0,
+    /// so that seeding lets tests produce reproducible IDs, or distinct ones between runs. It's only
+    /// possible to seed the prefixes before any ID has been materialized, as the IDs could
+    /// otherwise collide, and this returns `false` without any effect afterwards. Any prefix may be
+    /// given: the prefixes of later shards wrap around from `u32::MAX` to 0.
+    ///
+    /// ```
+    /// # use usdt_impl::UniqueId;
+    /// assert!(UniqueId::seed(42));
+    /// let id = UniqueId::new();
+    /// assert_eq!(id.as_u64(), 42 << 32 | 1);
+    /// assert!(!UniqueId::seed(7));
+    /// ```
+    pub fn seed(prefix: u32) -> bool {
+        NEXT_ID_PREFIX
+            .compare_exchange(0, u64::from(prefix), Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+    }
+
     // Helper function to actually materialize a u64 value internally.
     //
-    // This method assigns a value on the basis of the current thread and a monotonic counter, in
-    // the upper and lower 32-bits of a u64, respectively.
+    // This method assigns a value from the shard of IDs of the current thread, see
+    // `next_unique_id`.
     fn materialize(&self) {
         // Safety: This type is not Sync, which means the current thread maintains the only
         // reference to the contained ID. A `UniqueId` in another thread is a clone, at which
@@ -390,44 +2467,469 @@ impl UniqueId {
         // different `RefCell` -- that type is here just to enable interior mutability.
         let mut inner = self.id.borrow_mut();
         if inner.is_none() {
-            let id = CURRENT_ID.with(|id| {
-                let thread_id = THREAD_ID.with(|id| *id.borrow_mut() as u64);
-                let mut inner = id.borrow_mut();
-                *inner = inner.wrapping_add(1);
-                (thread_id << 32) | (*inner as u64)
-            });
-            inner.replace(id);
+            inner.replace(next_unique_id());
+        }
+    }
+
+    /// Return the internal `u64` value, materializing it if needed.
+    #[doc(hidden)]
+    pub fn as_u64(&self) -> u64 {
+        self.materialize();
+        // Safety: This is an immutable borrow, so is safe from multiple threads. The cell cannot
+        // be borrowed mutably at the same time, as that only occurs within the scope of the
+        // `materialize` method. This method can't be called on the _same_ `UniqueId` from multiple
+        // threads, because the type is not `Sync`.
+        self.id.borrow().unwrap()
+    }
+}
+
+impl Default for UniqueId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for UniqueId {
+    fn clone(&self) -> Self {
+        self.materialize();
+        Self {
+            id: self.id.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use dtrace_parser::BitWidth;
+    use dtrace_parser::DataType as DType;
+    use dtrace_parser::Integer;
+    use dtrace_parser::Sign;
+
+    #[test]
+    fn test_is_simple_type() {
+        assert!(is_simple_type(&quote::format_ident!("u8")));
+        assert!(!is_simple_type(&quote::format_ident!("Foo")));
+    }
+
+    #[test]
+    fn test_data_type_from_path() {
+        assert_eq!(
+            data_type_from_path(&syn::parse_str("u8").unwrap(), false),
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit8,
+            })),
+        );
+        assert_eq!(
+            data_type_from_path(&syn::parse_str("u8").unwrap(), true),
+            DataType::Native(DType::Pointer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit8,
+            })),
+        );
+        assert_eq!(
+            data_type_from_path(&syn::parse_str("String").unwrap(), false),
+            DataType::Native(DType::String),
+        );
+        assert_eq!(
+            data_type_from_path(&syn::parse_str("std::string::String").unwrap(), false),
+            DataType::Native(DType::String),
+        );
+        assert_eq!(
+            data_type_from_path(&syn::parse_str("core::primitive::u8").unwrap(), false),
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit8,
+            })),
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_data_type_from_path_panics() {
+        data_type_from_path(&syn::parse_str("std::net::IpAddr").unwrap(), false);
+    }
+
+    #[test]
+    fn test_data_type_try_from_syn_type() {
+        let u8_type = DataType::Native(DType::Integer(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit8,
+        }));
+        let cases = [
+            ("u8", u8_type.clone()),
+            ("core::primitive::u8", u8_type),
+            ("std::string::String", DataType::Native(DType::String)),
+            ("&&str", DataType::Native(DType::String)),
+            ("&usdt::UniqueId", DataType::UniqueId),
+            ("&std::path::Path", DataType::Path),
+            ("PathBuf", DataType::Path),
+            ("std::time::Duration", DataType::Duration),
+            ("&Duration", DataType::Duration),
+            ("&dyn std::fmt::Display", DataType::Display),
+            ("impl Display", DataType::Display),
+            (
+                "impl usdt::Fields",
+                DataType::Serializable(syn::parse_str("impl ::usdt::Fields").unwrap()),
+            ),
+            (
+                "[u8; 4]",
+                DataType::NativeArray(
+                    Integer {
+                        sign: Sign::Unsigned,
+                        width: BitWidth::Bit8,
+                    },
+                    4,
+                ),
+            ),
+            (
+                "&[u64; 16]",
+                DataType::Serializable(syn::parse_str("&[u64; 16]").unwrap()),
+            ),
+            (
+                "[u8; N]",
+                DataType::Serializable(syn::parse_str("[u8; N]").unwrap()),
+            ),
+            (
+                "&Foo",
+                DataType::Serializable(syn::parse_str("&Foo").unwrap()),
+            ),
+            (
+                "&mut Foo",
+                DataType::Serializable(syn::parse_str("&Foo").unwrap()),
+            ),
+            (
+                "&mut u8",
+                DataType::Native(DType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                })),
+            ),
+            (
+                "*const u8",
+                DataType::Address(syn::parse_str("*const u8").unwrap()),
+            ),
+            (
+                "*mut u8",
+                DataType::Address(syn::parse_str("*mut u8").unwrap()),
+            ),
+            (
+                "*const String",
+                DataType::Address(syn::parse_str("*const String").unwrap()),
+            ),
+            (
+                "*const [u8]",
+                DataType::Address(syn::parse_str("*const [u8]").unwrap()),
+            ),
+            (
+                "std::num::NonZeroU64",
+                DataType::NonZero(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64,
+                }),
+            ),
+            (
+                "&NonZero<i16>",
+                DataType::NonZero(Integer {
+                    sign: Sign::Signed,
+                    width: BitWidth::Bit16,
+                }),
+            ),
+            (
+                "NonZeroUsize",
+                DataType::NonZero(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Pointer,
+                }),
+            ),
+            (
+                "NonZeroFoo",
+                DataType::Serializable(syn::parse_str("NonZeroFoo").unwrap()),
+            ),
+            (
+                "NonZero<Foo>",
+                DataType::Serializable(syn::parse_str("NonZero<Foo>").unwrap()),
+            ),
+        ];
+        for (name, expected) in cases {
+            let ty: syn::Type = syn::parse_str(name).unwrap();
+            assert_eq!(DataType::try_from(&ty).unwrap(), expected, "{}", name);
         }
+        let ty: syn::Type = syn::parse_str("&dyn serde::Serialize").unwrap();
+        assert_eq!(
+            DataType::try_from(&ty).unwrap(),
+            DataType::Serializable(syn::parse_str("&dyn ::usdt::ErasedSerialize").unwrap())
+        );
+        for name in [
+            "fn()",
+            "&dyn std::fmt::Debug",
+            "&mut dyn Serialize",
+            "&(dyn Serialize + Send)",
+            "&(dyn Display + Send)",
+            "impl std::fmt::Debug",
+            "std::time::Instant",
+        ] {
+            let ty: syn::Type = syn::parse_str(name).unwrap();
+            assert!(DataType::try_from(&ty).is_err(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_timestamp_kind() {
+        let kind = |name| timestamp_kind(&syn::parse_str(name).unwrap());
+        assert_eq!(
+            kind("chrono::DateTime<chrono::Utc>"),
+            cfg!(feature = "chrono").then_some(TimestampKind::Chrono)
+        );
+        assert_eq!(
+            kind("DateTime<Utc>"),
+            cfg!(feature = "chrono").then_some(TimestampKind::Chrono)
+        );
+        assert_eq!(
+            kind("time::OffsetDateTime"),
+            cfg!(feature = "time").then_some(TimestampKind::Time)
+        );
+        assert_eq!(kind("chrono::DateTime<chrono::Local>"), None);
+        assert_eq!(kind("chrono::NaiveDateTime"), None);
+        assert_eq!(kind("std::time::SystemTime"), None);
+    }
+
+    #[test]
+    fn test_data_type_timestamp() {
+        let ty = DataType::Timestamp(TimestampKind::Chrono, Sign::Unsigned);
+        assert_eq!(ty.to_c_type(), "uint64_t");
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "8");
+        assert_eq!(ty.to_asm_op(0, Arch::X86_64), "%rdi");
+        assert_eq!(
+            ty.to_rust_type(),
+            syn::parse_str::<syn::Type>("::chrono::DateTime<::chrono::Utc>").unwrap()
+        );
+        let ty = DataType::Timestamp(TimestampKind::Time, Sign::Signed);
+        assert_eq!(ty.to_c_type(), "int64_t");
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "-8");
+        assert_eq!(
+            ty.to_rust_ffi_type(),
+            syn::parse_str::<syn::Type>("::std::os::raw::c_longlong").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_data_type_duration() {
+        let ty = DataType::Duration;
+        assert_eq!(ty.to_c_type(), "uint64_t");
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "8");
+        assert_eq!(ty.to_asm_op(1, Arch::X86_64), "%rsi");
+        assert_eq!(
+            ty.to_rust_type(),
+            syn::parse_str::<syn::Type>("::std::time::Duration").unwrap()
+        );
+        // Only a difference between instants may be passed.
+        let err = DataType::try_from(&syn::parse_str::<syn::Type>("&Instant").unwrap());
+        assert!(err.unwrap_err().to_string().contains("`Duration`"));
+    }
+
+    #[test]
+    fn test_data_type_display() {
+        let ty = DataType::Display;
+        assert_eq!(ty.to_c_type(), "char*");
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "8");
+        assert_eq!(
+            ty.to_rust_type(),
+            syn::parse_str::<syn::Type>("&dyn ::std::fmt::Display").unwrap()
+        );
+    }
+
+    // Pointer-sized integers keep their Rust type, but are passed with the pointers of the
+    // target's architecture, whatever the host generating the probes.
+    #[test]
+    fn test_data_type_pointer_width() {
+        let ty = DataType::try_from(&syn::parse_str::<syn::Type>("usize").unwrap()).unwrap();
+        assert_eq!(
+            ty,
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Pointer,
+            }))
+        );
+        assert_eq!(ty.to_c_type_for(Arch::X86_64), "uint64_t");
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "8");
+        assert_eq!(ty.to_asm_op(0, Arch::X86_64), "%rdi");
+        assert_eq!(
+            ty.to_rust_type(),
+            syn::parse_str::<syn::Type>("usize").unwrap()
+        );
+        assert_eq!(
+            ty.to_rust_ffi_type(),
+            syn::parse_str::<syn::Type>("::std::os::raw::c_ulonglong").unwrap()
+        );
+
+        let ty = DataType::try_from(&syn::parse_str::<syn::Type>("&isize").unwrap()).unwrap();
+        assert_eq!(ty.to_c_type_for(Arch::X86_64), "int64_t");
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "-8");
+        assert_eq!(
+            ty.to_rust_type(),
+            syn::parse_str::<syn::Type>("isize").unwrap()
+        );
+        let ty = DataType::Native(DType::Pointer(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Pointer,
+        }));
+        assert_eq!(ty.to_c_type_for(Arch::X86_64), "uint64_t*");
+    }
+
+    // The C type of a pointer-sized integer has the size of its SystemTap argument on each
+    // architecture, including the 32-bit pointers of Arm.
+    #[test]
+    fn test_data_type_pointer_width_matches_arg_size() {
+        for (name, expected) in [
+            ("usize", [("uint64_t", "8"), ("uint32_t", "4")]),
+            ("isize", [("int64_t", "-8"), ("int32_t", "-4")]),
+            ("&usize", [("uint64_t", "8"), ("uint32_t", "4")]),
+        ] {
+            let ty = DataType::try_from(&syn::parse_str::<syn::Type>(name).unwrap()).unwrap();
+            for (arch, (c_type, size)) in [Arch::X86_64, Arch::Arm].into_iter().zip(expected) {
+                assert_eq!(ty.to_c_type_for(arch), c_type);
+                assert_eq!(ty.to_asm_size(arch), size);
+            }
+        }
+    }
+
+    #[test]
+    fn test_data_type_uuid() {
+        let ty: syn::Type = syn::parse_str("uuid::Uuid").unwrap();
+        let expected = if cfg!(feature = "uuid") {
+            DataType::Uuid
+        } else {
+            DataType::Serializable(ty.clone())
+        };
+        assert_eq!(DataType::try_from(&ty).unwrap(), expected);
+
+        let half = DataType::Native(DType::Integer(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit64,
+        }));
+        assert_eq!(DataType::Uuid.expand(), [half.clone(), half]);
+        assert_eq!(DataType::Uuid.to_c_type(), "uint64_t, uint64_t");
+        assert_eq!(DataType::Uuid.to_asm_op(1, Arch::X86_64), "%rsi");
+    }
+
+    #[test]
+    fn test_data_type_to_asm() {
+        let ty = DataType::Native(DType::Pointer(Integer {
+            sign: Sign::Signed,
+            width: BitWidth::Bit32,
+        }));
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "-4");
+        assert_eq!(ty.to_asm_op(1, Arch::X86_64), "(%rsi)");
+        assert_eq!(ty.to_asm_op(1, Arch::AArch64), "[x1]");
+        assert_eq!(DataType::UniqueId.to_asm_op(0, Arch::AArch64), "x0");
+        assert_eq!(ty.to_asm_op(2, Arch::RiscV64), "0(a2)");
+        assert_eq!(DataType::UniqueId.to_asm_op(5, Arch::RiscV64), "a5");
+    }
+
+    #[test]
+    fn test_data_type_address() {
+        let ty = DataType::Address(syn::parse_str("*mut Foo").unwrap());
+        assert_eq!(ty.to_c_type(), "uint64_t");
+        assert_eq!(ty.to_rust_type(), syn::parse_str("*mut Foo").unwrap());
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "8");
+        assert_eq!(ty.to_asm_op(0, Arch::X86_64), "%rdi");
+        assert_eq!(ty.to_asm_op(0, Arch::AArch64), "x0");
+    }
+
+    #[test]
+    fn test_data_type_non_zero() {
+        let ty = DataType::NonZero(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit64,
+        });
+        assert_eq!(ty.expand(), [ty.clone()]);
+        assert_eq!(ty.to_c_type(), "uint64_t");
+        assert_eq!(
+            ty.to_rust_type(),
+            syn::parse_str("::std::num::NonZero<u64>").unwrap()
+        );
+        assert_eq!(
+            ty.to_rust_ffi_type(),
+            syn::parse_str("::std::os::raw::c_ulonglong").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_data_type_native_slice() {
+        let ty = DataType::NativeSlice(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit32,
+        });
+        let len = DataType::Native(DType::Integer(Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit64,
+        }));
+        assert_eq!(
+            ty.expand(),
+            [
+                DataType::Address(syn::parse_str("*const u32").unwrap()),
+                len.clone(),
+                len,
+            ]
+        );
+        assert_eq!(ty.to_c_type(), "uint64_t, uint64_t, uint64_t");
+        assert_eq!(ty.to_rust_type(), syn::parse_str("&[u32]").unwrap());
+        assert_eq!(ty.serialized_type(), None);
     }
 
-    /// Return the internal `u64` value, materializing it if needed.
-    #[doc(hidden)]
-    pub fn as_u64(&self) -> u64 {
-        self.materialize();
-        // Safety: This is an immutable borrow, so is safe from multiple threads. The cell cannot
-        // be borrowed mutably at the same time, as that only occurs within the scope of the
-        // `materialize` method. This method can't be called on the _same_ `UniqueId` from multiple
-        // threads, because the type is not `Sync`.
-        self.id.borrow().unwrap()
+    #[test]
+    fn test_data_type_protobuf() {
+        let ty = DataType::Protobuf(syn::parse_str("Foo").unwrap());
+        assert_eq!(
+            ty.expand(),
+            [
+                DataType::Address(syn::parse_str("*const u8").unwrap()),
+                DataType::Native(DType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64,
+                }))
+            ]
+        );
+        assert_eq!(ty.to_c_type(), "uint64_t, uint64_t");
+        assert_eq!(ty.to_rust_type(), syn::parse_str("Foo").unwrap());
+        assert_eq!(ty.serialized_type(), None);
     }
-}
 
-impl Clone for UniqueId {
-    fn clone(&self) -> Self {
-        self.materialize();
-        Self {
-            id: self.id.clone(),
-        }
+    #[test]
+    fn test_serialized_type() {
+        let serialized = |ty: &str| {
+            DataType::Serializable(syn::parse_str(ty).unwrap())
+                .serialized_type()
+                .map(|ty| quote::quote! { #ty }.to_string())
+        };
+        let foo = Some(String::from("Foo"));
+        assert_eq!(serialized("Foo"), foo);
+        assert_eq!(serialized("&Arc<Foo>"), foo);
+        assert_eq!(serialized("std::rc::Rc<Foo>"), foo);
+        assert_eq!(serialized("&&Box<Foo>"), foo);
+        assert_eq!(serialized("Vec<Foo>"), Some(String::from("Vec < Foo >")));
+        assert_eq!(DataType::UniqueId.serialized_type(), None);
+        assert_eq!(serialized("impl ::usdt::Fields"), None);
     }
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
-    use dtrace_parser::BitWidth;
-    use dtrace_parser::DataType as DType;
-    use dtrace_parser::Integer;
-    use dtrace_parser::Sign;
+    #[test]
+    fn test_data_type_discriminant() {
+        let int = Integer {
+            sign: Sign::Signed,
+            width: BitWidth::Bit16,
+        };
+        let ty = DataType::Discriminant(syn::parse_str("State").unwrap(), int);
+        assert_eq!(ty.to_c_type(), "int16_t");
+        assert_eq!(ty.to_rust_type(), syn::parse_str("State").unwrap());
+        assert_eq!(
+            ty.to_rust_ffi_type(),
+            syn::parse_str("::std::os::raw::c_short").unwrap()
+        );
+        assert_eq!(ty.to_asm_size(Arch::X86_64), "-2");
+        assert_eq!(ty.to_asm_op(0, Arch::X86_64), "%di");
+    }
 
     #[test]
     fn test_probe_to_d_source() {
@@ -437,10 +2939,65 @@ mod test {
                 sign: Sign::Unsigned,
                 width: BitWidth::Bit8,
             }))],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
         };
         assert_eq!(probe.to_d_source(), "probe my_probe(uint8_t*);");
     }
 
+    #[test]
+    fn test_probe_arg_specs() {
+        let probe = Probe {
+            name: String::from("work"),
+            types: vec![
+                DataType::Native(DType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                })),
+                DataType::Native(DType::String),
+            ],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        assert_eq!(probe.dtrace_arg_spec(), "uint8_t, char*");
+        assert_eq!(probe.stapsdt_arg_spec(Arch::X86_64), "1@%dil 8@%rsi");
+        assert_eq!(probe.stapsdt_arg_spec(Arch::AArch64), "1@x0 8@x1");
+        assert_eq!(probe.stapsdt_arg_spec(Arch::RiscV64), "1@a0 8@a1");
+        assert_eq!(probe.stapsdt_arg_spec(Arch::Arm), "1@r0 4@r1");
+    }
+
+    // Arguments without a D type of their own are presented as the type they're passed as.
+    #[test]
+    fn test_probe_translated_argument_types() {
+        let probe = Probe {
+            name: String::from("my_probe"),
+            types: vec![
+                DataType::Address(syn::parse_str("*const Event").unwrap()),
+                DataType::Uuid,
+                DataType::Native(DType::String),
+            ],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![Some(String::from("my_event_t *")), None],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        assert_eq!(
+            probe.translated_argument_types(Arch::X86_64),
+            ["my_event_t *", "uint64_t", "uint64_t", "char*"]
+        );
+        assert_eq!(
+            probe.to_d_source(),
+            "probe my_probe(uint64_t, uint64_t, uint64_t, char*);"
+        );
+    }
+
     #[test]
     fn test_provider_to_d_source() {
         let probe = Probe {
@@ -449,16 +3006,30 @@ mod test {
                 sign: Sign::Unsigned,
                 width: BitWidth::Bit8,
             }))],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
         };
-        let provider = Provider {
+        let mut provider = Provider {
             name: String::from("my_provider"),
             probes: vec![probe],
             use_statements: vec![],
+            attributes: Default::default(),
         };
         assert_eq!(
             provider.to_d_source(),
             "provider my_provider {\n\tprobe my_probe(uint8_t);\n};"
         );
+
+        provider.attributes.args = "Evolving/Evolving/Common".parse().unwrap();
+        let source = provider.to_d_source();
+        assert!(source.ends_with(
+            "};\n#pragma D attributes Evolving/Evolving/Common provider my_provider args\n"
+        ));
+        let file = dtrace_parser::File::try_from(source.as_str()).unwrap();
+        assert_eq!(file.providers()[0].attributes, provider.attributes);
     }
 
     #[test]
@@ -479,6 +3050,205 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_to_json_bounded() {
+        let value = vec![1, 2, 3];
+        assert_eq!(to_json_bounded(&value, 7).unwrap(), "[1,2,3]");
+        assert_eq!(
+            to_json_bounded(&value, 6).unwrap(),
+            r#"{"__truncated":true,"len":7}"#
+        );
+        let value = vec![0u8; 1 << 20];
+        assert_eq!(
+            to_json_bounded(&value, DEFAULT_MAX_SERIALIZED_LEN).unwrap(),
+            format!(r#"{{"__truncated":true,"len":{}}}"#, 2 * value.len() + 1)
+        );
+    }
+
+    #[test]
+    fn test_to_str_probe_arg() {
+        let arg = to_str_probe_arg("short");
+        assert!(matches!(arg, StrProbeArg::Inline(..)));
+        assert_eq!(&*arg, b"short\0");
+        assert_eq!(&*to_str_probe_arg(""), b"\0");
+        let long = "x".repeat(INLINE_STR_PROBE_ARG_LEN);
+        let arg = to_str_probe_arg(&long);
+        assert!(matches!(arg, StrProbeArg::Heap(_)));
+        assert_eq!(&arg[..INLINE_STR_PROBE_ARG_LEN], long.as_bytes());
+        assert_eq!(arg[INLINE_STR_PROBE_ARG_LEN..], [0]);
+    }
+
+    #[test]
+    fn test_to_display_probe_arg() {
+        let arg = to_display_probe_arg(&format_args!("{}-{}", 1, "two"));
+        assert_eq!(&*arg, b"1-two\0");
+        let ptr = arg.as_ptr();
+        drop(arg);
+        // The buffer is reused by the next argument formatted on the thread.
+        let arg = to_display_probe_arg(&std::net::Ipv4Addr::LOCALHOST);
+        assert_eq!(&*arg, b"127.0.0.1\0");
+        assert_eq!(arg.as_ptr(), ptr);
+    }
+
+    // Probes fired from the destructor of a thread-local may run after the buffers are destroyed.
+    #[test]
+    fn test_probe_args_after_thread_local_destruction() {
+        struct FireOnDrop(std::sync::mpsc::Sender<Vec<u8>>);
+
+        impl Drop for FireOnDrop {
+            fn drop(&mut self) {
+                let arg = to_display_probe_arg(&"late");
+                let _ = self.0.send(arg.to_vec());
+                #[cfg(not(feature = "json-core"))]
+                {
+                    let arg = to_json_probe_arg(&[1, 2], DEFAULT_MAX_SERIALIZED_LEN);
+                    let _ = self.0.send(arg.to_vec());
+                }
+            }
+        }
+
+        thread_local! {
+            static FIRE_ON_DROP: RefCell<Option<FireOnDrop>> = const { RefCell::new(None) };
+        }
+
+        let (tx, rx) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            // Thread-locals are destroyed in the reverse order of their first use, so that the
+            // buffers used after the guard is set are destroyed before it.
+            FIRE_ON_DROP.with(|guard| *guard.borrow_mut() = Some(FireOnDrop(tx)));
+            drop(to_display_probe_arg(&"early"));
+            #[cfg(not(feature = "json-core"))]
+            drop(to_json_probe_arg(&0, DEFAULT_MAX_SERIALIZED_LEN));
+        })
+        .join()
+        .unwrap();
+        assert_eq!(rx.recv().unwrap(), b"late\0");
+        #[cfg(not(feature = "json-core"))]
+        assert_eq!(rx.recv().unwrap(), b"{\"ok\":[1,2]}\0");
+    }
+
+    #[test]
+    fn test_to_path_probe_arg() {
+        let arg = to_path_probe_arg(std::path::Path::new("/tmp/file"));
+        assert_eq!(&*arg, b"/tmp/file\0");
+    }
+
+    // Paths which aren't UTF-8 are passed as their bytes on Unix.
+    #[cfg(unix)]
+    #[test]
+    fn test_to_path_probe_arg_not_utf8() {
+        use std::os::unix::ffi::OsStrExt as _;
+        let path = std::path::Path::new(std::ffi::OsStr::from_bytes(b"/tmp/\xff"));
+        assert_eq!(&*to_path_probe_arg(path), b"/tmp/\xff\0");
+    }
+
+    #[cfg(not(feature = "json-core"))]
+    #[test]
+    fn test_to_json_probe_arg() {
+        let value = vec![1, 2, 3];
+        assert_eq!(&*to_json_probe_arg(&value, 7), b"{\"ok\":[1,2,3]}\0");
+        assert_eq!(
+            &*to_json_probe_arg(&value, 6),
+            b"{\"ok\":{\"__truncated\":true,\"len\":7}}\0"
+        );
+        let value = std::collections::BTreeMap::from([((1, 2), 3)]);
+        assert_eq!(
+            &*to_json_probe_arg(&value, 1024),
+            b"{\"err\":\"key must be a string\"}\0"
+        );
+
+        // The buffer is returned for the next argument serialized on this thread.
+        let value = vec![0u8; 1024];
+        let capacity = to_json_probe_arg(&value, usize::MAX).0.capacity();
+        let arg = to_json_probe_arg(&1, 1024);
+        assert_eq!(&*arg, b"{\"ok\":1}\0");
+        assert_eq!(arg.0.capacity(), capacity);
+    }
+
+    // A `Value` is written out as it is, rather than via another `Value`.
+    #[cfg(not(feature = "json-core"))]
+    #[test]
+    fn test_to_json_probe_arg_value() {
+        let value = serde_json::json!({"id": 1, "tags": ["a", "b"], "nested": {"ok": null}});
+        let expected = format!("{{\"ok\":{}}}\0", serde_json::to_string(&value).unwrap());
+        assert_eq!(&*to_json_probe_arg(&value, 1024), expected.as_bytes());
+    }
+
+    // Arguments are serialized by their own `Serialize` impl, so serde's attributes apply as they
+    // do for `serde_json::to_string`.
+    #[cfg(not(feature = "json-core"))]
+    #[test]
+    fn test_to_json_probe_arg_serde_attributes() {
+        #[derive(serde::Serialize)]
+        struct Attributed {
+            request_id: u64,
+            #[serde(skip_serializing_if = "Option::is_none")]
+            user: Option<String>,
+            #[serde(serialize_with = "serialize_hex")]
+            flags: u32,
+            #[serde(rename = "kind", skip_serializing_if = "str::is_empty")]
+            class: &'static str,
+        }
+
+        fn serialize_hex<S: serde::Serializer>(
+            flags: &u32,
+            serializer: S,
+        ) -> Result<S::Ok, S::Error> {
+            serializer.serialize_str(&format!("{:#x}", flags))
+        }
+
+        let values = [
+            Attributed {
+                request_id: 1,
+                user: None,
+                flags: 0x2a,
+                class: "",
+            },
+            Attributed {
+                request_id: 2,
+                user: Some(String::from("ann")),
+                flags: 0,
+                class: "get",
+            },
+        ];
+        for value in &values {
+            let expected = format!("{{\"ok\":{}}}\0", serde_json::to_string(value).unwrap());
+            assert_eq!(&*to_json_probe_arg(value, 1024), expected.as_bytes());
+        }
+        assert_eq!(
+            &*to_json_probe_arg(&values[0], 1024),
+            b"{\"ok\":{\"request_id\":1,\"flags\":\"0x2a\"}}\0"
+        );
+        // Renaming keys keeps the fields skipped.
+        let renamed = RenameKeys(&values[0], &[("request_id", "id")]);
+        assert_eq!(
+            &*to_json_probe_arg(&renamed, 1024),
+            b"{\"ok\":{\"id\":1,\"flags\":\"0x2a\"}}\0"
+        );
+    }
+
+    #[test]
+    fn test_erased_serialize() {
+        let values: [&dyn ErasedSerialize; 2] = [&vec![1, 2], &"foo"];
+        assert_eq!(to_json(&values).unwrap(), r#"[[1,2],"foo"]"#);
+
+        // Fields keep the order they're declared in, as when serialized through their own type.
+        #[derive(serde::Serialize)]
+        struct Unsorted {
+            b: u8,
+            a: u8,
+        }
+        let value = Unsorted { b: 1, a: 2 };
+        assert_eq!(
+            &*to_json_probe_arg(&value as &dyn ErasedSerialize, 1024),
+            &*to_json_probe_arg(&value, 1024),
+        );
+        assert_eq!(
+            &*to_json_probe_arg(&value as &dyn ErasedSerialize, 1024),
+            b"{\"ok\":{\"b\":1,\"a\":2}}\0"
+        );
+    }
+
     #[test]
     fn test_unique_id() {
         let id = UniqueId::new();
@@ -504,12 +3274,46 @@ mod test {
         assert_ne!(id.id.as_ptr(), id2.id.as_ptr());
     }
 
+    #[test]
+    fn test_unique_ids_across_threads() {
+        let threads = (0..8)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    (0..1000)
+                        .map(|_| UniqueId::new().as_u64())
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let ids = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(ids.len(), 8000);
+    }
+
+    #[test]
+    fn test_unique_id_shard_exhausted() {
+        let first = UniqueId::new().as_u64();
+        let prefix = (first >> 32) as u32;
+        ID_SHARD.with(|shard| shard.set(Some((prefix, u32::MAX - 1))));
+        assert_eq!(
+            UniqueId::new().as_u64(),
+            (u64::from(prefix) << 32) | u64::from(u32::MAX)
+        );
+        // The shard is exhausted, so the next ID is from a new one.
+        let next = UniqueId::new().as_u64();
+        assert_ne!(next >> 32, first >> 32);
+        assert_eq!(next & 0xFFFF_FFFF, 1);
+    }
+
     #[test]
     fn test_compile_providers_config() {
         let config = CompileProvidersConfig {
             provider: Some(String::from("prov")),
             probe_format: Some(String::from("probe_{probe}")),
             module: Some(String::from("not_prov")),
+            ..Default::default()
         };
         assert_eq!(config.format_probe("prob"), "probe_prob");
         let module = config.module_ident();
@@ -518,4 +3322,535 @@ mod test {
             quote::quote! { not_prov }.to_string(),
         );
     }
+
+    #[test]
+    fn test_module_path() {
+        let config = CompileProvidersConfig {
+            provider: Some(String::from("http")),
+            module: Some(String::from("telemetry::http")),
+            ..Default::default()
+        };
+        assert!(config.check_module().is_ok());
+        let (path, ident) = (config.module_path(), config.module_ident());
+        assert_eq!(
+            quote::quote! { #path #ident }.to_string(),
+            quote::quote! { telemetry::http http }.to_string(),
+        );
+        for module in [
+            "crate::http",
+            "telemetry::",
+            "::http",
+            "telemetry::http-v2",
+            "",
+        ] {
+            let config = CompileProvidersConfig {
+                module: Some(String::from(module)),
+                ..Default::default()
+            };
+            assert!(
+                matches!(config.check_module(), Err(Error::InvalidModulePath(m)) if m == module),
+                "{}",
+                module
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_sections() {
+        let config = CompileProvidersConfig::default();
+        assert!(config.check_sections().is_ok());
+        assert_eq!(config.stapsdt_note_section(), ".note.stapsdt");
+        assert_eq!(config.stapsdt_semaphore_section(), ".probes");
+        assert_eq!(config.stapsdt_base_section(), ".stapsdt.base");
+        let config = CompileProvidersConfig {
+            stapsdt_note_section: Some(String::from(".note.app_probes")),
+            stapsdt_semaphore_section: Some(String::from("app$semaphores")),
+            ..Default::default()
+        };
+        assert!(config.check_sections().is_ok());
+        assert_eq!(config.stapsdt_note_section(), ".note.app_probes");
+        assert_eq!(config.stapsdt_base_section(), ".stapsdt.base");
+        for section in [
+            "",
+            ".note stapsdt",
+            "\".probes\"",
+            "1probes",
+            ".probes,\"aw\"",
+        ] {
+            let config = CompileProvidersConfig {
+                stapsdt_base_section: Some(String::from(section)),
+                ..Default::default()
+            };
+            assert!(
+                matches!(config.check_sections(), Err(Error::InvalidSectionName(s)) if s == section),
+                "{}",
+                section
+            );
+        }
+    }
+
+    #[test]
+    fn test_auto_args() {
+        let parse = |json: &str| serde_json::from_str::<CompileProvidersConfig>(json);
+        assert!(parse("{}").unwrap().auto_args.is_empty());
+        assert_eq!(
+            parse(r#"{"auto_args": "cpu, tid, cpu"}"#)
+                .unwrap()
+                .auto_args,
+            [AutoArg::ThreadId, AutoArg::Cpu]
+        );
+        assert!(parse(r#"{"auto_args": "pid"}"#).is_err());
+
+        let probe = |n_args: usize| Probe {
+            name: String::from("probe"),
+            types: vec![
+                DataType::Native(dtrace_parser::DataType::Integer(integer64(
+                    dtrace_parser::Sign::Unsigned
+                )));
+                n_args
+            ],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let config = parse(r#"{"auto_args": "tid,cpu"}"#).unwrap();
+        assert!(config.check_probe_arguments(&[probe(4)]).is_ok());
+        assert!(matches!(
+            config.check_probe_arguments(&[probe(5)]),
+            Err(Error::TooManyArguments { count: 7, .. })
+        ));
+
+        let provider = Provider {
+            name: String::from("prov"),
+            probes: vec![probe(1)],
+            use_statements: vec![],
+            attributes: Default::default(),
+        };
+        assert_eq!(
+            provider.with_auto_args(&config.auto_args).to_d_source(),
+            "provider prov {\n\tprobe probe(uint64_t, uint64_t, int32_t);\n};"
+        );
+    }
+
+    #[test]
+    fn test_visibility() {
+        let parse = |json: &str| serde_json::from_str::<CompileProvidersConfig>(json);
+        assert_eq!(parse("{}").unwrap().visibility, Visibility::Crate);
+        assert_eq!(
+            parse(r#"{"visibility": "pub(crate)"}"#).unwrap().visibility,
+            Visibility::Crate
+        );
+        assert_eq!(
+            parse(r#"{"visibility": "pub"}"#).unwrap().visibility,
+            Visibility::Public
+        );
+        assert!(parse(r#"{"visibility": "pub(super)"}"#).is_err());
+
+        let source = "provider prov { probe ping(); };";
+        let config = CompileProvidersConfig {
+            visibility: Visibility::Public,
+            ..Default::default()
+        };
+        let providers = providers_from_source(source, &config).unwrap();
+        assert_eq!(providers[0].1.visibility, Visibility::Public);
+    }
+
+    #[test]
+    fn test_provider_modules() {
+        let config = serde_json::from_str::<CompileProvidersConfig>(
+            r#"{"modules": "net=net_probes, disk = storage::disk"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.modules,
+            vec![
+                (String::from("net"), String::from("net_probes")),
+                (String::from("disk"), String::from("storage::disk")),
+            ]
+        );
+        for invalid in ["net", "net=", "=net_probes", "net=a,net=b"] {
+            let json = format!(r#"{{"modules": "{}"}}"#, invalid);
+            assert!(serde_json::from_str::<CompileProvidersConfig>(&json).is_err());
+        }
+
+        // Providers left out use the global module, or their own name.
+        let source = r#"
+            provider net { probe send(); };
+            provider disk { probe read(); };
+            provider cpu { probe tick(); };
+        "#;
+        let modules = |config: &CompileProvidersConfig| {
+            providers_from_source(source, config)
+                .unwrap()
+                .into_iter()
+                .map(|(_, config)| config.module.unwrap())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(modules(&config), ["net_probes", "storage::disk", "cpu"]);
+        let config = CompileProvidersConfig {
+            module: Some(String::from("probes")),
+            ..config
+        };
+        assert_eq!(modules(&config), ["net_probes", "storage::disk", "probes"]);
+
+        // Each provider is generated in its own module.
+        let source = "provider net { probe send(); }; provider disk { probe read(); };";
+        let config = CompileProvidersConfig {
+            modules: vec![
+                (String::from("net"), String::from("network")),
+                (String::from("disk"), String::from("storage")),
+            ],
+            ..Default::default()
+        };
+        let output = compile_provider_source(source, &config)
+            .unwrap()
+            .to_string();
+        assert!(output.contains("mod network "));
+        assert!(output.contains("mod storage "));
+        assert!(!output.contains("mod net "));
+        assert!(!output.contains("mod disk "));
+
+        // The modules must name providers of the source, and be valid paths.
+        let config = CompileProvidersConfig {
+            modules: vec![(String::from("gpu"), String::from("gpu_probes"))],
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile_provider_source(source, &config),
+            Err(Error::UnknownProviders(names)) if names == ["gpu"]
+        ));
+        let config = CompileProvidersConfig {
+            modules: vec![(String::from("net"), String::from("crate::net"))],
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.check_module(),
+            Err(Error::InvalidModulePath(module)) if module == "crate::net"
+        ));
+    }
+
+    #[test]
+    fn test_rename_json_keys() {
+        let config = serde_json::from_str::<CompileProvidersConfig>(
+            r#"{"rename_json_keys": "request_id=id, timestamp = ts"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.rename_json_keys,
+            vec![
+                (String::from("request_id"), String::from("id")),
+                (String::from("timestamp"), String::from("ts")),
+            ]
+        );
+        for invalid in ["request_id", "request_id=", "=id", "a=b,a=c"] {
+            let json = format!(r#"{{"rename_json_keys": "{}"}}"#, invalid);
+            assert!(serde_json::from_str::<CompileProvidersConfig>(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn test_with_prefix() {
+        let provider = Provider::from(
+            &dtrace_parser::File::try_from(
+                "provider test { probe begin(uint8_t); probe stop(); };",
+            )
+            .unwrap()
+            .providers()[0],
+        );
+        let prefixed = provider.clone().with_prefix(Some("http_")).unwrap();
+        assert_eq!(
+            prefixed
+                .probes
+                .iter()
+                .map(|probe| probe.name.as_str())
+                .collect::<Vec<_>>(),
+            ["http_begin", "http_stop"]
+        );
+        assert!(prefixed
+            .to_d_source()
+            .contains("probe http_begin(uint8_t);"));
+        assert_eq!(
+            provider.clone().with_prefix(None).unwrap().probes[0].name,
+            "begin"
+        );
+        // A prefixed name which isn't a valid probe name is an error.
+        assert!(provider.clone().with_prefix(Some("http-")).is_err());
+        assert!(provider.with_prefix(Some(&"x".repeat(64))).is_err());
+    }
+
+    #[test]
+    fn test_json_len() {
+        let config =
+            serde_json::from_str::<CompileProvidersConfig>(r#"{"json_len": true}"#).unwrap();
+        assert!(config.json_len);
+        let probe = |n_args: usize| Probe {
+            name: String::from("probe"),
+            types: vec![DataType::Serializable(syn::parse_str("Foo").unwrap()); n_args],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        assert!(config.check_probe_arguments(&[probe(3)]).is_ok());
+        assert!(matches!(
+            config.check_probe_arguments(&[probe(4)]),
+            Err(Error::TooManyArguments { count: 8, .. })
+        ));
+
+        let provider = Provider {
+            name: String::from("prov"),
+            probes: vec![probe(1)],
+            use_statements: vec![],
+            attributes: Default::default(),
+        };
+        assert_eq!(
+            provider.clone().with_json_len(false).to_d_source(),
+            "provider prov {\n\tprobe probe(char*);\n};"
+        );
+        let provider = provider.with_json_len(true);
+        assert_eq!(
+            provider.probes[0].types,
+            [DataType::SizedSerializable(syn::parse_str("Foo").unwrap())]
+        );
+        assert_eq!(
+            provider.to_d_source(),
+            "provider prov {\n\tprobe probe(char*, uint64_t);\n};"
+        );
+    }
+
+    #[test]
+    fn test_probe_formats() {
+        let formats = [
+            (None, "start"),
+            (Some("{probe}"), "start"),
+            (Some("{provider}_{probe}"), "test_start"),
+            (Some("__{probe}_{provider}__"), "__start_test__"),
+        ];
+        for (format, expected) in formats {
+            let config = CompileProvidersConfig {
+                provider: Some(String::from("test")),
+                probe_format: format.map(String::from),
+                module: None,
+                ..Default::default()
+            };
+            assert_eq!(config.format_probe("start"), expected);
+            assert_eq!(config.probe_ident("start").to_string(), expected);
+        }
+    }
+
+    #[test]
+    fn test_check_probe_names() {
+        let probe = |name: &str| Probe {
+            name: String::from(name),
+            types: vec![],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let probes = [probe("start"), probe("stop")];
+        let config = |format: &str| CompileProvidersConfig {
+            provider: Some(String::from("test")),
+            probe_format: Some(String::from(format)),
+            module: None,
+            ..Default::default()
+        };
+        assert!(config("{provider}_{probe}")
+            .check_probe_names(&probes)
+            .is_ok());
+        assert!(CompileProvidersConfig::default()
+            .check_probe_names(&probes)
+            .is_ok());
+        assert!(matches!(
+            config("{provider}").check_probe_names(&probes),
+            Err(Error::ProbeMacroNameCollision { name, probes })
+                if name == "test" && probes == (String::from("start"), String::from("stop"))
+        ));
+        assert!(matches!(
+            config("{provider}-{probe}").check_probe_names(&probes),
+            Err(Error::InvalidProbeMacroName(name)) if name == "test-start"
+        ));
+        // A single probe can't collide, but the name must still be valid.
+        assert!(config("{provider}").check_probe_names(&probes[..1]).is_ok());
+        assert!(config("{provider").check_probe_names(&probes[..1]).is_err());
+    }
+
+    // The `_once` macro of a probe mustn't be named like the macro of another.
+    #[test]
+    fn test_check_probe_names_once_macros() {
+        let probe = |name: &str| Probe {
+            name: String::from(name),
+            types: vec![],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let probes = [probe("start"), probe("start_once")];
+        assert!(CompileProvidersConfig::default()
+            .check_probe_names(&probes)
+            .is_ok());
+        let config = CompileProvidersConfig {
+            once_macros: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.check_probe_names(&probes),
+            Err(Error::ProbeMacroNameCollision { name, probes })
+                if name == "start_once"
+                    && probes == (String::from("start"), String::from("start_once"))
+        ));
+        assert!(config.check_probe_names(&probes[..1]).is_ok());
+    }
+
+    // The `_if` macro of a probe mustn't be named like the macro of another.
+    #[test]
+    fn test_check_probe_names_if_macros() {
+        let probe = |name: &str| Probe {
+            name: String::from(name),
+            types: vec![],
+            attrs: vec![],
+            arg_names: vec![],
+            d_types: vec![],
+            defaults: vec![],
+            span: proc_macro2::Span::call_site(),
+        };
+        let probes = [probe("request"), probe("request_if")];
+        assert!(CompileProvidersConfig::default()
+            .check_probe_names(&probes)
+            .is_ok());
+        let config = CompileProvidersConfig {
+            if_macros: true,
+            ..Default::default()
+        };
+        assert!(matches!(
+            config.check_probe_names(&probes),
+            Err(Error::ProbeMacroNameCollision { name, .. }) if name == "request_if"
+        ));
+        assert!(config.check_probe_names(&probes[..1]).is_ok());
+    }
+
+    #[test]
+    fn test_compile_provider_source_is_deterministic() {
+        let source = r#"
+            provider zeta {
+                probe begin(uint8_t, char*);
+                probe end();
+            };
+            provider alpha {
+                probe work(uint64_t, int32_t);
+                probe done(char*);
+            };
+            provider mid {
+                probe tick();
+            };
+        "#;
+        let config = CompileProvidersConfig::default();
+        let first = compile_provider_source(source, &config)
+            .unwrap()
+            .to_string();
+        let second = compile_provider_source(source, &config)
+            .unwrap()
+            .to_string();
+        assert_eq!(first, second);
+
+        // The providers are emitted in the order of the source, rather than sorted.
+        let position = |name: &str| {
+            first
+                .find(&format!("mod {} ", name))
+                .unwrap_or_else(|| panic!("Expected a module for provider {}", name))
+        };
+        assert!(position("zeta") < position("alpha"));
+        assert!(position("alpha") < position("mid"));
+    }
+
+    #[cfg(feature = "generate")]
+    #[test]
+    fn test_format_provider_source() {
+        let source = "provider vendored { probe work(uint8_t); };";
+        let config = CompileProvidersConfig {
+            module: Some(String::from("probes")),
+            ..Default::default()
+        };
+        let formatted = format_provider_source(source, &config).unwrap();
+        assert!(syn::parse_file(&formatted).is_ok());
+        // The code is formatted, one item per line, rather than as a single line of tokens.
+        assert!(formatted.lines().count() > 1);
+        assert!(formatted.contains("pub(crate) mod probes {\n"));
+        // Each target is gated on its `cfg`, including those without a backend.
+        for os in ["linux", "macos", "illumos", "freebsd", "windows"] {
+            assert!(
+                formatted.contains(&format!("#[cfg(all(target_os = \"{}\"", os)),
+                "Expected code for {}",
+                os
+            );
+        }
+        let unformatted = formatted.split_whitespace().collect::<String>();
+        assert!(unformatted.contains("#[cfg(not(any(target_os=\"linux\""));
+        assert!(format_provider_source("provider {", &config).is_err());
+    }
+
+    #[test]
+    fn test_compile_provider_source_name_collision() {
+        let source = "provider test { probe begin(); probe end(); };";
+        let config = CompileProvidersConfig {
+            probe_format: Some(String::from("{provider}_probe")),
+            ..Default::default()
+        };
+        assert!(matches!(
+            compile_provider_source(source, &config),
+            Err(Error::ProbeMacroNameCollision { .. })
+        ));
+    }
+
+    #[test]
+    fn test_merge_provider_sources() {
+        let first = "provider foo { probe begin(uint8_t); };";
+        let second = r#"
+            provider foo { probe begin(uint8_t); probe end(char*); };
+            provider bar { probe baz(); };
+            #pragma D attributes Evolving/Evolving/Common provider foo provider
+        "#;
+        let merged = merge_provider_sources(&[first, second]).unwrap();
+        let dfile = dtrace_parser::File::try_from(merged.as_str()).unwrap();
+        let providers = dfile.providers();
+        assert_eq!(providers.len(), 2);
+        assert_eq!(providers[0].name, "foo");
+        let probes = providers[0]
+            .probes
+            .iter()
+            .map(|probe| probe.name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(probes, ["begin", "end"]);
+        assert_eq!(
+            providers[0].attributes.provider,
+            "Evolving/Evolving/Common".parse().unwrap()
+        );
+        assert_eq!(providers[1].name, "bar");
+    }
+
+    #[test]
+    fn test_merge_provider_sources_conflicts() {
+        let first = "provider foo { probe begin(uint8_t); };";
+        let second = "provider foo { probe begin(uint16_t); };";
+        assert!(matches!(
+            merge_provider_sources(&[first, second]),
+            Err(Error::ConflictingProbe { provider, probe })
+                if provider == "foo" && probe == "begin"
+        ));
+
+        let first = "provider foo { probe begin(); };\n\
+            #pragma D attributes Stable/Stable/Common provider foo provider\n";
+        let second = "provider foo { probe begin(); };\n\
+            #pragma D attributes Evolving/Evolving/Common provider foo provider\n";
+        assert!(matches!(
+            merge_provider_sources(&[first, second]),
+            Err(Error::ConflictingProviderAttributes(name)) if name == "foo"
+        ));
+    }
 }
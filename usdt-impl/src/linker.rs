@@ -15,11 +15,11 @@
 //!
 //! #if !defined(DTRACE_PROBES_DISABLED) || !DTRACE_PROBES_DISABLED
 //!
-//! #define	FOO_BAR() \
+//! #define FOO_BAR() \
 //! do { \
-//! 	__asm__ volatile(".reference " FOO_TYPEDEFS); \
-//! 	__dtrace_probe$foo$bar$v1(); \
-//! 	__asm__ volatile(".reference " FOO_STABILITY); \
+//!     __asm__ volatile(".reference " FOO_TYPEDEFS); \
+//!     __dtrace_probe$foo$bar$v1(); \
+//!     __asm__ volatile(".reference " FOO_STABILITY); \
 //! } while (0)
 //! ```
 //!
@@ -65,90 +65,74 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{common, DataType, Provider};
+use crate::target::Arch;
+use crate::{common, registry, Probe, Provider};
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
 use std::{
     collections::BTreeMap,
-    convert::TryFrom,
     io::Write,
     process::{Command, Stdio},
 };
 
-/// Compile a DTrace provider definition into Rust tokens that implement its probes.
-pub fn compile_provider_source(
+/// Compile DTrace providers into Rust tokens that implement their probes, returning one item per
+/// provider.
+///
+/// The `source` is the D source of all the providers, which is passed through `dtrace(1)` to
+/// learn the names of the symbols the linker will provide.
+pub(crate) fn compile_providers(
     source: &str,
-    config: &crate::CompileProvidersConfig,
-) -> Result<TokenStream, crate::Error> {
-    let dfile = dtrace_parser::File::try_from(source)?;
-    let header = build_header_from_provider(&source)?;
+    providers: &[(Provider, crate::CompileProvidersConfig)],
+    arch: Arch,
+) -> Result<Vec<TokenStream>, crate::Error> {
+    let header = build_header_from_provider(source)?;
     let provider_info = extract_providers(&header);
-    let providers = dfile
-        .providers()
-        .into_iter()
-        .map(|provider| {
-            let provider = Provider::from(provider);
-            // Ensure that the name of the module in the config is set, either by the caller or
-            // defaulting to the provider name.
-            let config = crate::CompileProvidersConfig {
-                provider: Some(provider.name.clone()),
-                probe_format: config.probe_format.clone(),
-                module: match &config.module {
-                    None => Some(provider.name.clone()),
-                    other => other.clone(),
-                },
-            };
-            compile_provider(&provider, &provider_info[&provider.name], &config)
+    Ok(providers
+        .iter()
+        .map(|(provider, config)| {
+            compile_provider(provider, &provider_info[&provider.name], config, arch)
         })
-        .collect::<Vec<_>>();
-    Ok(quote! {
-        #(#providers)*
-    })
-}
-
-pub fn compile_provider_from_definition(
-    provider: &Provider,
-    config: &crate::CompileProvidersConfig,
-) -> TokenStream {
-    // Unwrap safety: The type signature confirms that `provider` is valid.
-    let header = build_header_from_provider(&provider.to_d_source()).unwrap();
-    let provider_info = extract_providers(&header);
-    let provider_tokens = compile_provider(provider, &provider_info[&provider.name], config);
-    quote! {
-        #provider_tokens
-    }
+        .collect())
 }
 
 fn compile_provider(
     provider: &Provider,
     provider_info: &ProviderInfo,
     config: &crate::CompileProvidersConfig,
+    arch: Arch,
 ) -> TokenStream {
     let mut probe_impls = Vec::new();
     for probe in provider.probes.iter() {
-        probe_impls.push(compile_probe(
-            provider,
-            &probe.name,
-            config,
-            provider_info,
-            &probe.types,
-        ));
+        probe_impls.push(compile_probe(provider, probe, config, provider_info, arch));
     }
+    let registry_records = registry::emit_registry_records(
+        provider,
+        registry::MACHO_SECTION,
+        |probe| is_enabled_check(provider, probe, provider_info),
+        |_| None,
+    );
     let module = config.module_ident();
+    let visibility = config.visibility.tokens();
+    let use_statements = common::provider_use_statements(provider, config);
     quote! {
-        pub(crate) mod #module {
+        #visibility mod #module {
+            #use_statements
             #(#probe_impls)*
+            #registry_records
         }
     }
 }
 
 fn compile_probe(
     provider: &Provider,
-    probe_name: &str,
+    probe: &Probe,
     config: &crate::CompileProvidersConfig,
     provider_info: &ProviderInfo,
-    types: &[DataType],
+    arch: Arch,
 ) -> TokenStream {
+    let probe_name = probe.name.as_str();
+    let types = probe.types.as_slice();
+
     // Retrieve the string names and the Rust identifiers used for the extern functions.
     // These are provided by the macOS linker, but have invalid Rust identifier names, like
     // `foo$bar`. We name them with valid Rust idents, and specify their link name as that of the
@@ -157,28 +141,42 @@ fn compile_probe(
     let stability_fn = format_ident!("stability");
     let typedefs = &provider_info.typedefs;
     let typedef_fn = format_ident!("typedefs");
-    let is_enabled = &provider_info.is_enabled[probe_name];
-    let is_enabled_fn = format_ident!("{}_{}_enabled", &provider.name, probe_name);
+    let is_enabled = is_enabled_check(provider, probe, provider_info);
 
     // The probe function is a little different. We prefix it with `__` because otherwise it has
     // the same name as the macro itself, which leads to conflicts.
-    let probe = &provider_info.probes[probe_name];
+    let probe_link_name = &provider_info.probes[probe_name];
     let extern_probe_fn = format_ident!("__{}", config.probe_ident(probe_name));
 
-    let ffi_param_list = types.iter().map(|typ| {
+    let ffi_param_list = probe.argument_types().into_iter().map(|typ| {
         let ty = typ.to_rust_ffi_type();
         syn::parse2::<syn::FnArg>(quote! { _: #ty }).unwrap()
     });
-    let (unpacked_args, in_regs) = common::construct_probe_args(types);
     let type_check_fn =
         common::construct_type_check(&provider.name, probe_name, &provider.use_statements, types);
 
-    #[cfg(target_arch = "x86_64")]
-    let call_instruction = quote! { "call {extern_probe_fn}" };
-    #[cfg(target_arch = "aarch64")]
-    let call_instruction = quote! { "bl {extern_probe_fn}" };
-    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
-    compile_error!("USDT only supports x86_64 and AArch64 architectures");
+    let call_instruction = match arch {
+        Arch::X86_64 => quote! { "call {extern_probe_fn}" },
+        Arch::AArch64 => quote! { "bl {extern_probe_fn}" },
+        Arch::RiscV64 | Arch::Arm => unreachable!("Only x86_64 and AArch64 are supported on macOS"),
+    };
+    let (unpacked_args, fire_probe) =
+        common::construct_probe_firing(types, arch, config, |in_regs, options| {
+            quote! {
+                unsafe {
+                    ::std::arch::asm!(
+                        ".reference {typedefs}",
+                        #call_instruction,
+                        ".reference {stability}",
+                        typedefs = sym #typedef_fn,
+                        extern_probe_fn = sym #extern_probe_fn,
+                        stability = sym #stability_fn,
+                        #in_regs
+                        #options
+                    );
+                }
+            }
+        });
 
     let impl_block = quote! {
         unsafe extern "C" {
@@ -191,32 +189,37 @@ fn compile_probe(
             fn typedefs();
 
             #[allow(unused)]
-            #[link_name = #is_enabled]
-            fn #is_enabled_fn() -> i32;
-
-            #[allow(unused)]
-            #[link_name = #probe]
+            #[link_name = #probe_link_name]
             fn #extern_probe_fn(#(#ffi_param_list,)*);
         }
-        unsafe {
-            if #is_enabled_fn() != 0 {
-                #unpacked_args
-                #type_check_fn
-                ::std::arch::asm!(
-                    ".reference {typedefs}",
-                    #call_instruction,
-                    ".reference {stability}",
-                    typedefs = sym #typedef_fn,
-                    extern_probe_fn = sym #extern_probe_fn,
-                    stability = sym #stability_fn,
-                    #in_regs
-                    options(nomem, nostack, preserves_flags)
-                );
-            }
+        if #is_enabled {
+            #unpacked_args
+            #type_check_fn
+            #fire_probe
         }
     };
 
-    common::build_probe_macro(config, probe_name, types, impl_block)
+    common::build_probe_macro(config, probe, is_enabled, impl_block)
+}
+
+// Return an expression checking whether the probe is enabled, via the is-enabled function provided
+// by the linker.
+fn is_enabled_check(
+    provider: &Provider,
+    probe: &Probe,
+    provider_info: &ProviderInfo,
+) -> TokenStream {
+    let is_enabled = &provider_info.is_enabled[probe.name.as_str()];
+    let is_enabled_fn = format_ident!("{}_{}_enabled", &provider.name, probe.name);
+    common::unless_probes_disabled(quote! {
+        {
+            unsafe extern "C" {
+                #[link_name = #is_enabled]
+                fn #is_enabled_fn() -> i32;
+            }
+            unsafe { #is_enabled_fn() != 0 }
+        }
+    })
 }
 
 #[derive(Debug, Default, Clone)]
@@ -230,22 +233,24 @@ struct ProviderInfo {
 fn extract_providers(header: &str) -> BTreeMap<String, ProviderInfo> {
     let mut providers = BTreeMap::new();
     for line in header.lines() {
-        if let Some((provider_name, stability)) = is_stability_line(&line) {
-            let mut info = ProviderInfo::default();
-            info.stability = stability.to_string();
+        if let Some((provider_name, stability)) = is_stability_line(line) {
+            let info = ProviderInfo {
+                stability: stability.to_string(),
+                ..Default::default()
+            };
             providers.insert(provider_name.to_string(), info);
         }
-        if let Some((provider_name, typedefs)) = is_typedefs_line(&line) {
+        if let Some((provider_name, typedefs)) = is_typedefs_line(line) {
             providers.get_mut(provider_name).unwrap().typedefs = typedefs.to_string();
         }
-        if let Some((provider_name, probe_name, enabled)) = is_enabled_line(&line) {
+        if let Some((provider_name, probe_name, enabled)) = is_enabled_line(line) {
             providers
                 .get_mut(provider_name)
                 .unwrap()
                 .is_enabled
                 .insert(probe_name.to_string(), enabled.to_string());
         }
-        if let Some((provider_name, probe_name, probe)) = is_probe_line(&line) {
+        if let Some((provider_name, probe_name, probe)) = is_probe_line(line) {
             providers
                 .get_mut(provider_name)
                 .unwrap()
@@ -334,16 +339,22 @@ fn build_header_from_provider(source: &str) -> Result<String, crate::Error> {
     String::from_utf8(output.stdout).map_err(|_| crate::Error::DTraceError)
 }
 
+#[cfg(usdt_backend_linker)]
 pub fn register_probes() -> Result<(), crate::Error> {
     // This function is a NOP, since we're using Apple's linker to create the DOF and call ioctl(2)
     // to send it to the driver.
     Ok(())
 }
 
+#[cfg(usdt_backend_linker)]
+pub fn register_providers(names: &[&str]) -> Result<(), crate::Error> {
+    // As with `register_probes`, the linker has already sent the DOF of every provider.
+    crate::registry::check_providers(names)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::Probe;
 
     #[test]
     fn test_is_stability_line() {
@@ -396,14 +407,19 @@ mod tests {
         let probe = "__dtrace_probe$foo$bar$xxx";
         let stability = "__dtrace_probe$foo$v1$1_1_1";
         let typedefs = "__dtrace_typedefs$foo$v2";
-        let types = vec![];
         let provider = Provider {
             name: provider_name.to_string(),
             probes: vec![Probe {
                 name: probe_name.to_string(),
-                types: types.clone(),
+                types: vec![],
+                attrs: vec![],
+                arg_names: vec![],
+                d_types: vec![],
+                defaults: vec![],
+                span: proc_macro2::Span::call_site(),
             }],
             use_statements: vec![],
+            attributes: Default::default(),
         };
 
         let mut is_enabled_map = BTreeMap::new();
@@ -419,13 +435,13 @@ mod tests {
 
         let tokens = compile_probe(
             &provider,
-            probe_name,
+            &provider.probes[0],
             &crate::CompileProvidersConfig {
                 provider: Some(provider_name.to_string()),
                 ..Default::default()
             },
             &provider_info,
-            &types,
+            Arch::X86_64,
         );
 
         let output = tokens.to_string();
@@ -445,10 +461,7 @@ mod tests {
 
         let needles = &[
             "asm ! (\".reference {typedefs}\"",
-            #[cfg(target_arch = "x86_64")]
             "call {extern_probe_fn}",
-            #[cfg(target_arch = "aarch64")]
-            "bl {extern_probe_fn}",
             "\".reference {stability}",
             "typedefs = sym typedefs",
             &format!(
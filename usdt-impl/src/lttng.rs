@@ -0,0 +1,382 @@
+//! The LTTng-UST implementation of the USDT crate.
+//!
+//! An alternative to SystemTap probes on Linux, for systems traced with LTTng rather than
+//! SystemTap or bpftrace.
+//!
+//! LTTng-UST tracepoints are defined with the C preprocessor macros of `<lttng/tracepoint.h>`,
+//! which expand to the tracepoint definitions, the probes serializing the fields of each event
+//! and the code registering both with the LTTng-UST library. Rather than reproducing all of that
+//! in Rust, the tracepoint provider is generated as C source, which a build script compiles and
+//! links into the program. The generated source also defines a small C function firing each
+//! tracepoint, and one checking whether it's enabled, which the Rust probe macros call via FFI.
+//!
+//! Each probe argument becomes a field of the event of the same name, `arg0`, `arg1` and so on.
+//! Integers are recorded as `ctf_integer` fields and strings as `ctf_string` fields. Pointers to
+//! integers are recorded as the address they point to, and serializable arguments are recorded
+//! as `ctf_sequence` fields holding the bytes of their JSON representation.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{common, registry, DataType, Probe, Provider};
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+
+/// Compile a provider into Rust tokens that implement its probes by firing LTTng-UST tracepoints.
+pub(crate) fn compile_provider(
+    provider: &Provider,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    // Tracepoints record the JSON of serialized arguments as strings, so it's never compressed
+    // nor framed.
+    let config = &crate::CompileProvidersConfig {
+        compression: None,
+        frame_arguments: false,
+        ..config.clone()
+    };
+    let probe_impls = provider
+        .probes
+        .iter()
+        .map(|probe| compile_probe(provider, probe, config))
+        .collect::<Vec<_>>();
+    let registry_records = registry::emit_registry_records(
+        provider,
+        registry::ELF_SECTION,
+        |probe| is_enabled_check(provider, probe),
+        |_| None,
+    );
+    let module = config.module_ident();
+    let visibility = config.visibility.tokens();
+    let use_statements = common::provider_use_statements(provider, config);
+    quote! {
+        #visibility mod #module {
+            #use_statements
+            #(#probe_impls)*
+            #registry_records
+        }
+    }
+}
+
+fn compile_probe(
+    provider: &Provider,
+    probe: &Probe,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    let (unpacked_args, ffi_args) = common::construct_probe_ffi_args(&probe.types, config);
+    let type_check_fn = common::construct_type_check(
+        &provider.name,
+        &probe.name,
+        &provider.use_statements,
+        &probe.types,
+    );
+    let is_enabled = is_enabled_check(provider, probe);
+    let probe_fn = format_ident!("{}", probe_fn_name(provider, probe));
+    let ffi_params = probe.argument_types().into_iter().map(|typ| {
+        let ty = typ.to_rust_ffi_type();
+        quote! { _: #ty }
+    });
+
+    let impl_block = quote! {
+        unsafe extern "C" {
+            fn #probe_fn(#(#ffi_params),*);
+        }
+        if #is_enabled {
+            #unpacked_args
+            #type_check_fn
+            unsafe {
+                #probe_fn(#(#ffi_args),*);
+            }
+        }
+    };
+    common::build_probe_macro(config, probe, is_enabled, impl_block)
+}
+
+// Return an expression checking whether the probe is enabled, via the function generated in the
+// tracepoint provider.
+fn is_enabled_check(provider: &Provider, probe: &Probe) -> TokenStream {
+    let is_enabled_fn = format_ident!("{}_enabled", probe_fn_name(provider, probe));
+    common::unless_probes_disabled(quote! {
+        {
+            unsafe extern "C" {
+                fn #is_enabled_fn() -> i32;
+            }
+            unsafe { #is_enabled_fn() != 0 }
+        }
+    })
+}
+
+// Return the name of the C function firing the probe's tracepoint.
+fn probe_fn_name(provider: &Provider, probe: &Probe) -> String {
+    format!("__usdt_lttng_{}_{}", provider.name, probe.name)
+}
+
+/// Return the name of the tracepoint provider header of a provider.
+pub(crate) fn header_file_name(provider: &Provider) -> String {
+    format!("{}-tp.h", provider.name)
+}
+
+/// Return the C type of the tracepoint argument a probe argument is passed as.
+fn c_type(typ: &DataType) -> String {
+    match typ {
+        DataType::Native(dtrace_parser::DataType::Integer(int))
+        | DataType::Discriminant(_, int)
+        | DataType::NonZero(int) => crate::ffi_integer(int).to_c_type(),
+        DataType::Native(dtrace_parser::DataType::Pointer(int)) => {
+            format!("const {} *", crate::ffi_integer(int).to_c_type())
+        }
+        DataType::Native(dtrace_parser::DataType::String)
+        | DataType::Path
+        | DataType::Display
+        | DataType::VariantName(_)
+        | DataType::Serializable(_) => String::from("const char *"),
+        DataType::UniqueId | DataType::Duration | DataType::Address(_) => String::from("uint64_t"),
+        DataType::Timestamp(..) => typ.to_c_type(),
+        DataType::NativeArray(..) => unreachable!("Arrays are passed as one argument per element"),
+        DataType::Uuid => unreachable!("UUIDs are passed as two arguments"),
+        DataType::SizedSerializable(_) => unreachable!("Tracepoints record the JSON itself"),
+        DataType::Protobuf(_) => unreachable!("Protobuf messages are passed as two arguments"),
+        DataType::NativeSlice(_) => unreachable!("Slices are passed as three arguments"),
+    }
+}
+
+/// Return the LTTng field recording the tracepoint argument with the given name.
+fn ctf_field(typ: &DataType, name: &str) -> String {
+    match typ {
+        DataType::Native(dtrace_parser::DataType::Integer(int))
+        | DataType::Discriminant(_, int)
+        | DataType::NonZero(int) => {
+            format!(
+                "ctf_integer({}, {name}, {name})",
+                crate::ffi_integer(int).to_c_type()
+            )
+        }
+        DataType::Native(dtrace_parser::DataType::Pointer(_)) => {
+            format!("ctf_integer_hex(uintptr_t, {name}, (uintptr_t) {name})")
+        }
+        DataType::Native(dtrace_parser::DataType::String)
+        | DataType::Path
+        | DataType::Display
+        | DataType::VariantName(_) => {
+            format!("ctf_string({name}, {name})")
+        }
+        DataType::Serializable(_) => format!(
+            "ctf_sequence(uint8_t, {name}, (const uint8_t *) {name}, size_t, strlen({name}))"
+        ),
+        DataType::UniqueId | DataType::Duration => {
+            format!("ctf_integer(uint64_t, {name}, {name})")
+        }
+        DataType::Address(_) => format!("ctf_integer_hex(uint64_t, {name}, {name})"),
+        DataType::Timestamp(..) => format!("ctf_integer({}, {name}, {name})", typ.to_c_type()),
+        DataType::NativeArray(..) => unreachable!("Arrays are passed as one argument per element"),
+        DataType::Uuid => unreachable!("UUIDs are passed as two arguments"),
+        DataType::SizedSerializable(_) => unreachable!("Tracepoints record the JSON itself"),
+        DataType::Protobuf(_) => unreachable!("Protobuf messages are passed as two arguments"),
+        DataType::NativeSlice(_) => unreachable!("Slices are passed as three arguments"),
+    }
+}
+
+/// Return the source of the tracepoint provider header of a provider.
+///
+/// The header defines one tracepoint per probe, named after the provider and the probe, with the
+/// probe's arguments as its fields.
+pub(crate) fn tracepoint_provider_header(provider: &Provider) -> String {
+    let guard = format!("USDT_LTTNG_{}_TP_H", provider.name.to_uppercase());
+    let events = provider
+        .probes
+        .iter()
+        .map(|probe| {
+            let types = probe.argument_types();
+            let args = types
+                .iter()
+                .enumerate()
+                .map(|(i, typ)| format!("{}, arg{}", c_type(typ), i))
+                .collect::<Vec<_>>();
+            let fields = types
+                .iter()
+                .enumerate()
+                .map(|(i, typ)| format!("        {}\n", ctf_field(typ, &format!("arg{}", i))))
+                .collect::<Vec<_>>()
+                .concat();
+            format!(
+                "TRACEPOINT_EVENT(\n    {provider},\n    {probe},\n    TP_ARGS({args}),\n    \
+                 TP_FIELDS(\n{fields}    )\n)\n",
+                provider = provider.name,
+                probe = probe.name,
+                args = args.join(", "),
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        r#"#undef TRACEPOINT_PROVIDER
+#define TRACEPOINT_PROVIDER {provider}
+
+#undef TRACEPOINT_INCLUDE
+#define TRACEPOINT_INCLUDE "./{header}"
+
+#if !defined({guard}) || defined(TRACEPOINT_HEADER_MULTI_READ)
+#define {guard}
+
+#include <stdint.h>
+#include <string.h>
+#include <lttng/tracepoint.h>
+
+{events}
+#endif
+
+#include <lttng/tracepoint-event.h>
+"#,
+        provider = provider.name,
+        header = header_file_name(provider),
+    )
+}
+
+/// Return the C source creating the tracepoint probes of the providers, and defining the
+/// functions firing each tracepoint and checking whether it's enabled.
+pub(crate) fn tracepoint_provider_source(providers: &[Provider]) -> String {
+    let includes = providers
+        .iter()
+        .map(|provider| format!("#include \"{}\"\n", header_file_name(provider)))
+        .collect::<Vec<_>>()
+        .concat();
+    let functions = providers
+        .iter()
+        .flat_map(|provider| {
+            provider.probes.iter().map(move |probe| {
+                let types = probe.argument_types();
+                let params = types
+                    .iter()
+                    .enumerate()
+                    .map(|(i, typ)| format!("{} arg{}", c_type(typ), i))
+                    .collect::<Vec<_>>();
+                let params = if params.is_empty() {
+                    String::from("void")
+                } else {
+                    params.join(", ")
+                };
+                let args = (0..types.len())
+                    .map(|i| format!(", arg{}", i))
+                    .collect::<Vec<_>>()
+                    .concat();
+                format!(
+                    "void {probe_fn}({params}) {{\n    tracepoint({provider}, {probe}{args});\n}}\n\n\
+                     int {probe_fn}_enabled(void) {{\n    \
+                     return tracepoint_enabled({provider}, {probe});\n}}\n",
+                    probe_fn = probe_fn_name(provider, probe),
+                    provider = provider.name,
+                    probe = probe.name,
+                )
+            })
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "#define TRACEPOINT_CREATE_PROBES\n#define TRACEPOINT_DEFINE\n\n{includes}\n{functions}"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtrace_parser::{BitWidth, DataType as DType, Integer, Sign};
+
+    fn test_provider() -> Provider {
+        Provider {
+            name: String::from("prov"),
+            probes: vec![
+                Probe {
+                    name: String::from("work"),
+                    types: vec![
+                        DataType::Native(DType::Integer(Integer {
+                            sign: Sign::Unsigned,
+                            width: BitWidth::Bit8,
+                        })),
+                        DataType::Native(DType::String),
+                        DataType::Serializable(syn::parse_str("&Foo").unwrap()),
+                    ],
+                    attrs: vec![],
+                    arg_names: vec![],
+                    d_types: vec![],
+                    defaults: vec![],
+                    span: proc_macro2::Span::call_site(),
+                },
+                Probe {
+                    name: String::from("noargs"),
+                    types: vec![],
+                    attrs: vec![],
+                    arg_names: vec![],
+                    d_types: vec![],
+                    defaults: vec![],
+                    span: proc_macro2::Span::call_site(),
+                },
+            ],
+            use_statements: vec![],
+            attributes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_tracepoint_provider_header() {
+        let header = tracepoint_provider_header(&test_provider());
+        assert!(header.contains("#define TRACEPOINT_PROVIDER prov\n"));
+        assert!(header.contains("#define TRACEPOINT_INCLUDE \"./prov-tp.h\"\n"));
+        assert!(
+            header.contains("TP_ARGS(uint8_t, arg0, const char *, arg1, const char *, arg2),\n")
+        );
+        assert!(header.contains("        ctf_integer(uint8_t, arg0, arg0)\n"));
+        assert!(header.contains("        ctf_string(arg1, arg1)\n"));
+        assert!(header.contains(
+            "        ctf_sequence(uint8_t, arg2, (const uint8_t *) arg2, size_t, strlen(arg2))\n"
+        ));
+        assert!(header.contains("    noargs,\n    TP_ARGS(),\n"));
+    }
+
+    #[test]
+    fn test_tracepoint_provider_source() {
+        let source = tracepoint_provider_source(&[test_provider()]);
+        assert!(source.starts_with("#define TRACEPOINT_CREATE_PROBES\n"));
+        assert!(source.contains("#include \"prov-tp.h\"\n"));
+        assert!(source.contains(
+            "void __usdt_lttng_prov_work(uint8_t arg0, const char * arg1, const char * arg2) {\n    \
+             tracepoint(prov, work, arg0, arg1, arg2);\n}\n"
+        ));
+        assert!(source.contains("void __usdt_lttng_prov_noargs(void) {\n"));
+        assert!(source.contains(
+            "int __usdt_lttng_prov_noargs_enabled(void) {\n    \
+             return tracepoint_enabled(prov, noargs);\n}\n"
+        ));
+    }
+
+    #[test]
+    fn test_compile_probe_calls_tracepoint_function() {
+        let provider = test_provider();
+        let config = crate::CompileProvidersConfig {
+            provider: Some(String::from("prov")),
+            module: Some(String::from("prov")),
+            ..Default::default()
+        };
+        let output = compile_probe(&provider, &provider.probes[0], &config).to_string();
+        let decl = quote! {
+            fn __usdt_lttng_prov_work(
+                _: ::std::os::raw::c_uchar,
+                _: *const ::std::os::raw::c_char,
+                _: *const ::std::os::raw::c_char
+            );
+        };
+        assert!(output.contains(&decl.to_string()));
+        assert!(
+            output.contains(&quote! { fn __usdt_lttng_prov_work_enabled() -> i32; }.to_string())
+        );
+    }
+}
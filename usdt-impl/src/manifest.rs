@@ -0,0 +1,480 @@
+//! A machine-readable manifest of compiled providers.
+//!
+//! A build script may write a manifest of the providers it compiles with
+//! `usdt::Builder::manifest`, for documentation generators and probe catalogs to consume. The
+//! manifest is a JSON object of the following form, where every field shown is always present:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "providers": [
+//!     {
+//!       "name": "test",
+//!       "module": "test",
+//!       "probes": [
+//!         {
+//!           "name": "start-work",
+//!           "macro": "start__work",
+//!           "arguments": [
+//!             {
+//!               "name": "arg0",
+//!               "c_type": "uint8_t",
+//!               "type": { "kind": "integer", "signed": false, "width": 8 },
+//!               "automatic": null
+//!             }
+//!           ]
+//!         }
+//!       ]
+//!     }
+//!   ]
+//! }
+//! ```
+//!
+//! - `version` is [`MANIFEST_VERSION`], which changes only when the schema changes incompatibly.
+//!   New fields, or new kinds of types, may be added without changing it.
+//! - A provider's `name` is its name in DTrace, and `module` the Rust module its macros are
+//!   generated in.
+//! - A probe's `name` is its name in DTrace, with any double-underscore translated into a dash,
+//!   and `macro` the name of the macro firing it.
+//! - Each argument is named as the D variable it's read from, `arg0` for the first argument. An
+//!   argument passed as several values, such as a UUID, is named after the first, and its
+//!   `c_type` lists the C type of each value, separated by commas.
+//! - Values captured automatically, see [`AutoArg`], follow the probe's own arguments, with
+//!   `automatic` set to how they're written in a provider's configuration, `"tid"` or `"cpu"`.
+//!   It's `null` for the probe's own arguments.
+//!
+//! The `type` of an argument is an object whose `kind` is one of:
+//!
+//! | `kind`               | Other fields                                      |
+//! |----------------------|---------------------------------------------------|
+//! | `"integer"`          | `signed`, a boolean, and `width`, in bits         |
+//! | `"pointer"`          | `signed` and `width` of the integer pointed to    |
+//! | `"string"`           |                                                   |
+//! | `"array"`            | `signed` and `width` of each element, and `len`   |
+//! | `"unique-id"`        |                                                   |
+//! | `"path"`             |                                                   |
+//! | `"timestamp"`        | `signed`, and `crate`, `"chrono"` or `"time"`     |
+//! | `"duration"`         |                                                   |
+//! | `"display"`          |                                                   |
+//! | `"uuid"`             |                                                   |
+//! | `"address"`          | `rust_type`, the pointer type                     |
+//! | `"discriminant"`     | `signed` and `width`, and `rust_type`, the enum   |
+//! | `"variant-name"`     | `rust_type`, the enum                             |
+//! | `"serializable"`     | `rust_type`, and `sized`, a boolean               |
+//! | `"protobuf"`         | `rust_type`, the message                          |
+//! | `"slice"`            | `signed` and `width` of each element              |
+//!
+//! Pointer-sized integers have the width of a pointer of the target's architecture, such as 32
+//! bits on 32-bit Arm, and non-zero integers, such as `NonZeroU32`, are integers. A `sized`
+//! serializable type is passed with the length of its JSON.
+//! Providers defined in D only have integers, pointers and strings as arguments of their own.
+//!
+//! [`manifest_probes`] reads back the names of the probes listed in a manifest, such as to check
+//! that a binary built from the providers emits each of them.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{Arch, AutoArg, CompileProvidersConfig, DataType, Error, Provider, TimestampKind};
+use serde::{Deserialize, Serialize};
+
+/// The version of the manifest's schema.
+pub const MANIFEST_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Manifest {
+    version: u32,
+    providers: Vec<ProviderManifest>,
+}
+
+#[derive(Serialize)]
+struct ProviderManifest {
+    name: String,
+    module: String,
+    probes: Vec<ProbeManifest>,
+}
+
+#[derive(Serialize)]
+struct ProbeManifest {
+    name: String,
+    #[serde(rename = "macro")]
+    macro_name: String,
+    arguments: Vec<ArgumentManifest>,
+}
+
+#[derive(Serialize)]
+struct ArgumentManifest {
+    name: String,
+    c_type: String,
+    #[serde(rename = "type")]
+    typ: TypeManifest,
+    automatic: Option<&'static str>,
+}
+
+#[derive(Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum TypeManifest {
+    Integer {
+        signed: bool,
+        width: u8,
+    },
+    Pointer {
+        signed: bool,
+        width: u8,
+    },
+    String,
+    Array {
+        signed: bool,
+        width: u8,
+        len: usize,
+    },
+    UniqueId,
+    Path,
+    Timestamp {
+        signed: bool,
+        #[serde(rename = "crate")]
+        krate: &'static str,
+    },
+    Duration,
+    Display,
+    Uuid,
+    Address {
+        rust_type: String,
+    },
+    Discriminant {
+        signed: bool,
+        width: u8,
+        rust_type: String,
+    },
+    VariantName {
+        rust_type: String,
+    },
+    Serializable {
+        rust_type: String,
+        sized: bool,
+    },
+    Protobuf {
+        rust_type: String,
+    },
+    Slice {
+        signed: bool,
+        width: u8,
+    },
+}
+
+impl TypeManifest {
+    // Describe a data type, with pointer-sized integers as on the given architecture.
+    fn new(typ: &DataType, arch: Option<Arch>) -> Self {
+        use dtrace_parser::DataType as DType;
+        let rust_type = |ty: &syn::Type| quote::quote! { #ty }.to_string();
+        match typ {
+            DataType::Native(DType::Integer(int)) | DataType::NonZero(int) => {
+                let (signed, width) = integer(int, arch);
+                TypeManifest::Integer { signed, width }
+            }
+            DataType::Native(DType::Pointer(int)) => {
+                let (signed, width) = integer(int, arch);
+                TypeManifest::Pointer { signed, width }
+            }
+            DataType::Native(DType::String) => TypeManifest::String,
+            DataType::NativeArray(int, len) => {
+                let (signed, width) = integer(int, arch);
+                TypeManifest::Array {
+                    signed,
+                    width,
+                    len: *len,
+                }
+            }
+            DataType::UniqueId => TypeManifest::UniqueId,
+            DataType::Path => TypeManifest::Path,
+            DataType::Timestamp(kind, sign) => TypeManifest::Timestamp {
+                signed: *sign == dtrace_parser::Sign::Signed,
+                krate: match kind {
+                    TimestampKind::Chrono => "chrono",
+                    TimestampKind::Time => "time",
+                },
+            },
+            DataType::Duration => TypeManifest::Duration,
+            DataType::Display => TypeManifest::Display,
+            DataType::Uuid => TypeManifest::Uuid,
+            DataType::Address(ty) => TypeManifest::Address {
+                rust_type: rust_type(ty),
+            },
+            DataType::Discriminant(ty, int) => {
+                let (signed, width) = integer(int, arch);
+                TypeManifest::Discriminant {
+                    signed,
+                    width,
+                    rust_type: rust_type(ty),
+                }
+            }
+            DataType::VariantName(ty) => TypeManifest::VariantName {
+                rust_type: rust_type(ty),
+            },
+            DataType::Serializable(ty) => TypeManifest::Serializable {
+                rust_type: rust_type(ty),
+                sized: false,
+            },
+            DataType::SizedSerializable(ty) => TypeManifest::Serializable {
+                rust_type: rust_type(ty),
+                sized: true,
+            },
+            DataType::Protobuf(ty) => TypeManifest::Protobuf {
+                rust_type: rust_type(ty),
+            },
+            DataType::NativeSlice(int) => {
+                let (signed, width) = integer(int, arch);
+                TypeManifest::Slice { signed, width }
+            }
+        }
+    }
+}
+
+// Return the sign and the width in bits of an integer on the given architecture, or on the host
+// if it has no probe backend.
+fn integer(int: &dtrace_parser::Integer, arch: Option<Arch>) -> (bool, u8) {
+    let int = crate::target_integer(int, arch);
+    let width = match int.width {
+        dtrace_parser::BitWidth::Bit8 => 8,
+        dtrace_parser::BitWidth::Bit16 => 16,
+        dtrace_parser::BitWidth::Bit32 => 32,
+        dtrace_parser::BitWidth::Bit64 => 64,
+        dtrace_parser::BitWidth::Pointer => usize::BITS as u8,
+    };
+    (int.sign == dtrace_parser::Sign::Signed, width)
+}
+
+// Return how an automatic argument is written in a provider's configuration.
+fn auto_arg_name(arg: &AutoArg) -> &'static str {
+    match arg {
+        AutoArg::ThreadId => "tid",
+        AutoArg::Cpu => "cpu",
+    }
+}
+
+fn provider_manifest(
+    provider: &Provider,
+    config: &CompileProvidersConfig,
+    arch: Option<Arch>,
+) -> ProviderManifest {
+    let probes = provider
+        .probes
+        .iter()
+        .map(|probe| {
+            let own_args = probe.types.iter().map(|typ| (typ.clone(), None));
+            let auto_args = config
+                .auto_args
+                .iter()
+                .map(|arg| (arg.data_type(), Some(auto_arg_name(arg))));
+            let mut index = 0;
+            let arguments = own_args
+                .chain(auto_args)
+                .map(|(typ, automatic)| {
+                    let name = format!("arg{}", index);
+                    index += typ.expand().len();
+                    ArgumentManifest {
+                        name,
+                        c_type: typ.c_type(arch),
+                        typ: TypeManifest::new(&typ, arch),
+                        automatic,
+                    }
+                })
+                .collect();
+            ProbeManifest {
+                name: probe.name.replace("__", "-"),
+                macro_name: config.format_probe(&probe.name),
+                arguments,
+            }
+        })
+        .collect();
+    ProviderManifest {
+        name: provider.name.clone(),
+        module: config.module_ident().to_string(),
+        probes,
+    }
+}
+
+/// Return the manifest of the providers defined in DTrace provider source code, as compiled with
+/// the given configuration, formatted as pretty-printed JSON.
+///
+/// See the [module documentation](self) for the schema of the manifest.
+pub fn provider_source_manifest(
+    source: &str,
+    config: &CompileProvidersConfig,
+) -> Result<String, Error> {
+    let arch = Arch::target();
+    let providers = crate::providers_from_source(source, config)?
+        .iter()
+        .map(|(provider, config)| provider_manifest(provider, config, arch))
+        .collect();
+    let manifest = Manifest {
+        version: MANIFEST_VERSION,
+        providers,
+    };
+    Ok(serde_json::to_string_pretty(&manifest)?)
+}
+
+// The parts of a manifest naming its probes, the only ones read back.
+#[derive(Deserialize)]
+struct ManifestNames {
+    providers: Vec<ProviderNames>,
+}
+
+#[derive(Deserialize)]
+struct ProviderNames {
+    name: String,
+    probes: Vec<ProbeNames>,
+}
+
+#[derive(Deserialize)]
+struct ProbeNames {
+    name: String,
+}
+
+/// Return the name of the provider and of the probe, as they appear to DTrace, of each probe
+/// listed in a manifest.
+///
+/// Only the names are read, so this accepts manifests of any version with the same layout of
+/// providers and probes.
+pub fn manifest_probes(manifest: &str) -> Result<Vec<(String, String)>, Error> {
+    let manifest: ManifestNames = serde_json::from_str(manifest)?;
+    Ok(manifest
+        .providers
+        .into_iter()
+        .flat_map(|provider| {
+            provider
+                .probes
+                .into_iter()
+                .map(move |probe| (provider.name.clone(), probe.name))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_provider_source_manifest() {
+        let config = CompileProvidersConfig {
+            auto_args: vec![AutoArg::ThreadId],
+            ..Default::default()
+        };
+        let manifest = provider_source_manifest(
+            "provider test { probe start__work(uint8_t, char*); probe stop(); };",
+            &config,
+        )
+        .unwrap();
+        let manifest: serde_json::Value = serde_json::from_str(&manifest).unwrap();
+        assert_eq!(
+            manifest,
+            serde_json::json!({
+                "version": 1,
+                "providers": [{
+                    "name": "test",
+                    "module": "test",
+                    "probes": [
+                        {
+                            "name": "start-work",
+                            "macro": "start__work",
+                            "arguments": [
+                                {
+                                    "name": "arg0",
+                                    "c_type": "uint8_t",
+                                    "type": { "kind": "integer", "signed": false, "width": 8 },
+                                    "automatic": null,
+                                },
+                                {
+                                    "name": "arg1",
+                                    "c_type": "char*",
+                                    "type": { "kind": "string" },
+                                    "automatic": null,
+                                },
+                                {
+                                    "name": "arg2",
+                                    "c_type": "uint64_t",
+                                    "type": { "kind": "integer", "signed": false, "width": 64 },
+                                    "automatic": "tid",
+                                },
+                            ],
+                        },
+                        {
+                            "name": "stop",
+                            "macro": "stop",
+                            "arguments": [
+                                {
+                                    "name": "arg0",
+                                    "c_type": "uint64_t",
+                                    "type": { "kind": "integer", "signed": false, "width": 64 },
+                                    "automatic": "tid",
+                                },
+                            ],
+                        },
+                    ],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_manifest_probes() {
+        let manifest = provider_source_manifest(
+            "provider test { probe start__work(uint8_t); probe stop(); };",
+            &CompileProvidersConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            manifest_probes(&manifest).unwrap(),
+            [
+                (String::from("test"), String::from("start-work")),
+                (String::from("test"), String::from("stop")),
+            ]
+        );
+        assert!(manifest_probes("{}").is_err());
+    }
+
+    #[test]
+    fn test_type_manifest() {
+        let to_json = |typ: &DataType| {
+            serde_json::to_value(TypeManifest::new(typ, Some(Arch::X86_64))).unwrap()
+        };
+        assert_eq!(
+            to_json(&DataType::Uuid),
+            serde_json::json!({ "kind": "uuid" })
+        );
+        assert_eq!(
+            to_json(&DataType::Duration),
+            serde_json::json!({ "kind": "duration" })
+        );
+        assert_eq!(
+            to_json(&DataType::Display),
+            serde_json::json!({ "kind": "display" })
+        );
+        assert_eq!(
+            to_json(&DataType::VariantName(syn::parse_str("State").unwrap())),
+            serde_json::json!({ "kind": "variant-name", "rust_type": "State" })
+        );
+        assert_eq!(
+            to_json(&DataType::SizedSerializable(syn::parse_str("Foo").unwrap())),
+            serde_json::json!({ "kind": "serializable", "rust_type": "Foo", "sized": true })
+        );
+        assert_eq!(
+            to_json(&DataType::Timestamp(
+                TimestampKind::Time,
+                dtrace_parser::Sign::Signed
+            )),
+            serde_json::json!({ "kind": "timestamp", "signed": true, "crate": "time" })
+        );
+    }
+}
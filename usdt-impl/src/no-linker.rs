@@ -1,4 +1,7 @@
 //! Implementation of USDT functionality on platforms without runtime linker support.
+//!
+//! With the `dtrace-linux` feature, the DOF of the probes is also registered on Linux, where
+//! `stapsdt` emits the DTrace probe records of this module alongside its notes.
 
 // Copyright 2022 Oxide Computer Company
 //
@@ -14,62 +17,47 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::convert::TryFrom;
+#[cfg(usdt_dof)]
 use std::fs::OpenOptions;
+#[cfg(usdt_dof)]
 use std::os::unix::io::AsRawFd;
 
-use crate::record::{emit_probe_record, process_section};
-use crate::{common, Probe, Provider};
+use crate::record::emit_probe_record;
+#[cfg(usdt_dof)]
+use crate::record::process_selected_section;
+use crate::target::{Arch, Os};
+use crate::{common, registry, Probe, Provider};
+#[cfg(usdt_dof)]
 use dof::{serialize_section, Section};
 use proc_macro2::TokenStream;
 use quote::quote;
 
-/// Compile a DTrace provider definition into Rust tokens that implement its probes.
-pub fn compile_provider_source(
-    source: &str,
-    config: &crate::CompileProvidersConfig,
-) -> Result<TokenStream, crate::Error> {
-    let dfile = dtrace_parser::File::try_from(source)?;
-    let providers = dfile
-        .providers()
-        .iter()
-        .map(|provider| {
-            let provider = Provider::from(provider);
-            // Ensure that the name of the module in the config is set, either by the caller or
-            // defaulting to the provider name.
-            let config = crate::CompileProvidersConfig {
-                provider: Some(provider.name.clone()),
-                probe_format: config.probe_format.clone(),
-                module: match &config.module {
-                    None => Some(provider.name.clone()),
-                    other => other.clone(),
-                },
-            };
-            compile_provider(&provider, &config)
-        })
-        .collect::<Vec<_>>();
-    Ok(quote! {
-        #(#providers)*
-    })
-}
-
-pub fn compile_provider_from_definition(
+/// Compile a DTrace provider into Rust tokens that implement its probes.
+pub(crate) fn compile_provider(
     provider: &Provider,
     config: &crate::CompileProvidersConfig,
+    os: Os,
+    arch: Arch,
 ) -> TokenStream {
-    compile_provider(provider, config)
-}
-
-fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig) -> TokenStream {
     let probe_impls = provider
         .probes
         .iter()
-        .map(|probe| compile_probe(provider, probe, config))
+        .map(|probe| compile_probe(provider, probe, config, os, arch))
         .collect::<Vec<_>>();
+    let registry_records = registry::emit_registry_records(
+        provider,
+        registry::ELF_SECTION,
+        |probe| is_enabled_check(provider, probe, os, arch),
+        |_| None,
+    );
     let module = config.module_ident();
+    let visibility = config.visibility.tokens();
+    let use_statements = common::provider_use_statements(provider, config);
     quote! {
-        pub(crate) mod #module {
+        #visibility mod #module {
+            #use_statements
             #(#probe_impls)*
+            #registry_records
         }
     }
 }
@@ -78,10 +66,33 @@ fn compile_probe(
     provider: &Provider,
     probe: &Probe,
     config: &crate::CompileProvidersConfig,
+    os: Os,
+    arch: Arch,
 ) -> TokenStream {
-    let (unpacked_args, in_regs) = common::construct_probe_args(&probe.types);
-    let is_enabled_rec = emit_probe_record(&provider.name, &probe.name, None);
-    let probe_rec = emit_probe_record(&provider.name, &probe.name, Some(&probe.types));
+    let is_enabled = is_enabled_check(provider, probe, os, arch);
+    // The is-enabled probe has a record of its own, which is deduplicated like the probe itself.
+    let is_enabled = if config.dedupe_probes {
+        quote! {
+            {
+                #[inline(never)]
+                fn __usdt_probe_is_enabled() -> bool {
+                    #is_enabled
+                }
+                __usdt_probe_is_enabled()
+            }
+        }
+    } else {
+        is_enabled
+    };
+    let probe_rec = emit_probe_record(
+        &provider.name,
+        &probe.name,
+        Some(&probe.argument_types()),
+        &probe.translated_argument_types(arch),
+        &provider.attributes,
+        os,
+        arch,
+    );
     let type_check_fn = common::construct_type_check(
         &provider.name,
         &probe.name,
@@ -89,36 +100,83 @@ fn compile_probe(
         &probe.types,
     );
 
-    let impl_block = quote! {
-        {
-            let mut is_enabled: u64;
-            unsafe {
-                ::std::arch::asm!(
-                    "990:   clr rax",
-                    #is_enabled_rec,
-                    out("rax") is_enabled,
-                    options(nomem, nostack)
-                );
-            }
-
-            if is_enabled != 0 {
-                #unpacked_args
-                #type_check_fn
+    let (unpacked_args, fire_probe) =
+        common::construct_probe_firing(&probe.types, arch, config, |in_regs, options| {
+            quote! {
                 unsafe {
                     ::std::arch::asm!(
                         "990:   nop",
                         #probe_rec,
                         #in_regs
-                        options(nomem, nostack, preserves_flags)
+                        #options
                     );
                 }
             }
+        });
+
+    let impl_block = quote! {
+        {
+            if #is_enabled {
+                #unpacked_args
+                #type_check_fn
+                #fire_probe
+            }
         }
     };
-    common::build_probe_macro(config, &probe.name, &probe.types, impl_block)
+    common::build_probe_macro(config, probe, is_enabled, impl_block)
+}
+
+// Return an expression checking whether the probe is enabled, via an is-enabled probe.
+//
+// The is-enabled probe zeroes the register returning its result, which DTrace replaces with an
+// instruction setting it to one while the probe is enabled.
+pub(crate) fn is_enabled_check(
+    provider: &Provider,
+    probe: &Probe,
+    os: Os,
+    arch: Arch,
+) -> TokenStream {
+    let is_enabled_rec = emit_probe_record(
+        &provider.name,
+        &probe.name,
+        None,
+        &[],
+        &provider.attributes,
+        os,
+        arch,
+    );
+    let (clear, reg) = match arch {
+        Arch::AArch64 => ("990:   mov x0, #0", quote! { "x0" }),
+        _ => ("990:   clr rax", quote! { "rax" }),
+    };
+    quote! {
+        {
+            let mut is_enabled: u64;
+            unsafe {
+                ::std::arch::asm!(
+                    #clear,
+                    #is_enabled_rec,
+                    out(#reg) is_enabled,
+                    options(nomem, nostack)
+                );
+            }
+            is_enabled != 0
+        }
+    }
 }
 
-fn extract_probe_records_from_section() -> Result<Section, crate::Error> {
+// As with the probe registry, bind the symbols to those of the module containing this code,
+// so that a shared library registers its own probes.
+#[cfg(usdt_dof)]
+::std::arch::global_asm!(
+    ".hidden __start_set_dtrace_probes",
+    ".hidden __stop_set_dtrace_probes"
+);
+
+#[cfg(usdt_dof)]
+fn extract_probe_records_from_section(
+    selected: impl Fn(&str) -> bool,
+) -> Result<Section, crate::Error> {
     unsafe extern "C" {
         #[link_name = "__start_set_dtrace_probes"]
         static dtrace_probes_start: usize;
@@ -133,7 +191,7 @@ fn extract_probe_records_from_section() -> Result<Section, crate::Error> {
     // writable (to implement one-time registration), so an immutable variable here
     // leads to _two_ sections, one writable and one read-only. A mutable variable
     // here ensures this ends up in a mutable section, the same as the probe records.
-    #[cfg(any(target_os = "illumos", target_os = "freebsd"))]
+    #[cfg(any(target_os = "illumos", target_os = "freebsd", target_os = "linux"))]
     #[link_section = "set_dtrace_probes"]
     #[used]
     static mut FORCE_LOAD: [u64; 0] = [];
@@ -143,11 +201,25 @@ fn extract_probe_records_from_section() -> Result<Section, crate::Error> {
         let stop = (&dtrace_probes_stop as *const usize) as usize;
         std::slice::from_raw_parts_mut(start as *mut u8, stop - start)
     };
-    process_section(data, /* register = */ true)
+    process_selected_section(data, selected)
 }
 
+#[cfg(usdt_dof)]
 pub fn register_probes() -> Result<(), crate::Error> {
-    let section = extract_probe_records_from_section()?;
+    register_section(extract_probe_records_from_section(|_| true)?)
+}
+
+#[cfg(usdt_dof)]
+pub fn register_providers(names: &[&str]) -> Result<(), crate::Error> {
+    registry::check_providers(names)?;
+    register_section(extract_probe_records_from_section(|name| {
+        names.iter().any(|selected| *selected == name)
+    })?)
+}
+
+// Send the DOF of the probes in the section to the kernel.
+#[cfg(usdt_dof)]
+pub(crate) fn register_section(section: Section) -> Result<(), crate::Error> {
     let module_name = section
         .providers
         .values()
@@ -168,6 +240,24 @@ pub fn register_probes() -> Result<(), crate::Error> {
     ioctl_section(&serialize_section(&section), modname)
 }
 
+/// The device DOF is registered through.
+#[cfg(usdt_dof)]
+const HELPER_DEVICE: &str = "/dev/dtrace/helper";
+
+// Return the error of failing to open the helper device, distinguishing a device which doesn't
+// exist, or has no driver behind it, from one which may not be opened, say for lack of permission.
+#[cfg(usdt_dof)]
+fn helper_open_error(source: std::io::Error) -> crate::Error {
+    match source.raw_os_error() {
+        Some(libc::ENOENT | libc::ENXIO | libc::ENODEV) => crate::Error::HelperUnavailable {
+            path: HELPER_DEVICE,
+            source,
+        },
+        _ => crate::Error::IO(source),
+    }
+}
+
+#[cfg(usdt_dof)]
 fn ioctl_section(buf: &[u8], modname: [std::os::raw::c_char; 64]) -> Result<(), crate::Error> {
     let helper = dof::dof_bindings::dof_helper {
         dofhp_mod: modname,
@@ -183,11 +273,15 @@ fn ioctl_section(buf: &[u8], modname: [std::os::raw::c_char; 64]) -> Result<(),
     let cmd: i32 = 0x64746803;
     #[cfg(target_os = "freebsd")]
     let cmd: u64 = 0xc0587a03;
+    // `_IOW(DTRACEHIOC, 3, dof_helper_t)`, where the helper ioctls of DTrace on Linux are 0xd8.
+    #[cfg(target_os = "linux")]
+    let cmd = ((1 << 30) | (std::mem::size_of_val(&helper) << 16) | (0xd8 << 8) | 3) as libc::Ioctl;
 
     let file = OpenOptions::new()
         .read(true)
         .write(true)
-        .open("/dev/dtrace/helper")?;
+        .open(HELPER_DEVICE)
+        .map_err(helper_open_error)?;
     if unsafe { libc::ioctl(file.as_raw_fd(), cmd, data) } < 0 {
         Err(crate::Error::IO(std::io::Error::last_os_error()))
     } else {
@@ -15,9 +15,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::target::{Arch, Os};
 use crate::DataType;
 use byteorder::{NativeEndian, ReadBytesExt};
-use dof::{Probe, Provider, Section};
+use dof::{Probe, Provider, ProviderAttributes, Section};
+use dtrace_parser::Attributes;
 use std::collections::BTreeMap;
 use std::mem::size_of;
 use std::sync::atomic::AtomicU8;
@@ -25,12 +27,35 @@ use std::sync::atomic::Ordering;
 
 // Version number for probe records containing data about all probes.
 //
+// Version 2 added the stability attributes of the probe's provider.
+//
+// Version 3 added the translated type of each argument, following the types of the arguments.
+//
 // NOTE: This must have a maximum of `u8::MAX - 1`. See `read_record_version` for
 // details.
-pub(crate) const PROBE_REC_VERSION: u8 = 1;
+pub(crate) const PROBE_REC_VERSION: u8 = 3;
 
 /// Extract records for all defined probes from our custom linker sections.
-pub fn process_section(mut data: &mut [u8], register: bool) -> Result<Section, crate::Error> {
+pub fn process_section(data: &mut [u8], register: bool) -> Result<Section, crate::Error> {
+    process_records(data, register, &|_| true)
+}
+
+/// Extract and register the records of the probes of the selected providers from our custom linker
+/// sections, given the name of each provider.
+///
+/// The records of other providers are left as they are, so that they may be registered later.
+pub fn process_selected_section(
+    data: &mut [u8],
+    selected: impl Fn(&str) -> bool,
+) -> Result<Section, crate::Error> {
+    process_records(data, /* register = */ true, &selected)
+}
+
+fn process_records(
+    mut data: &mut [u8],
+    register: bool,
+    selected: &dyn Fn(&str) -> bool,
+) -> Result<Section, crate::Error> {
     let mut providers = BTreeMap::new();
 
     while !data.is_empty() {
@@ -41,7 +66,7 @@ pub fn process_section(mut data: &mut [u8], register: bool) -> Result<Section, c
         // Read the length without consuming it
         let len = (&data[..size_of::<u32>()]).read_u32::<NativeEndian>()? as usize;
         let (rec, rest) = data.split_at_mut(len);
-        process_probe_record(&mut providers, rec, register)?;
+        process_probe_record(&mut providers, rec, register, selected)?;
         data = rest;
     }
 
@@ -64,18 +89,11 @@ pub(crate) fn addr_to_info(addr: u64) -> (Option<String>, Option<String>) {
         if libc::dladdr(addr as *const libc::c_void, &mut info as *mut _) == 0 {
             (None, None)
         } else {
-            (
-                Some(
-                    std::ffi::CStr::from_ptr(info.dli_sname)
-                        .to_string_lossy()
-                        .to_string(),
-                ),
-                Some(
-                    std::ffi::CStr::from_ptr(info.dli_fname)
-                        .to_string_lossy()
-                        .to_string(),
-                ),
-            )
+            // On Linux, the symbol is only found if it's exported, and is null otherwise.
+            let to_string = |s: *const libc::c_char| {
+                (!s.is_null()).then(|| std::ffi::CStr::from_ptr(s).to_string_lossy().to_string())
+            };
+            (to_string(info.dli_sname), to_string(info.dli_fname))
         }
     }
 }
@@ -171,6 +189,7 @@ fn process_probe_record(
     providers: &mut BTreeMap<String, Provider>,
     rec: &mut [u8],
     register: bool,
+    selected: &dyn Fn(&str) -> bool,
 ) -> Result<(), crate::Error> {
     // First four bytes are the length, next byte is the version number.
     let (rec, mut data) = {
@@ -180,7 +199,7 @@ fn process_probe_record(
         let (rec, data) = rec.split_at_mut(5);
         (rec, &*data)
     };
-    let version = read_record_version(&mut rec[4], register);
+    let version = rec[4];
 
     // If this record comes from a future version of the data format, we skip it
     // and hope that the author of main will *also* include a call to a more
@@ -196,15 +215,39 @@ fn process_probe_record(
     let n_args = data.read_u8()? as usize;
     let flags = data.read_u16::<NativeEndian>()?;
     let address = data.read_u64::<NativeEndian>()?;
+    let attributes = if version >= 2 {
+        ProviderAttributes {
+            provider: data.read_u32::<NativeEndian>()?,
+            module: data.read_u32::<NativeEndian>()?,
+            function: data.read_u32::<NativeEndian>()?,
+            name: data.read_u32::<NativeEndian>()?,
+            args: data.read_u32::<NativeEndian>()?,
+        }
+    } else {
+        ProviderAttributes::default()
+    };
     let provname = data.read_cstr();
+
+    // The records of providers which aren't selected are left unregistered. Another thread may
+    // have registered this record since its version was read above, in which case it's skipped.
+    if !selected(provname) || read_record_version(&mut rec[4], register) > PROBE_REC_VERSION {
+        return Ok(());
+    }
+
     let probename = data.read_cstr();
-    let args = {
+    let mut read_args = || {
         let mut args = Vec::with_capacity(n_args);
         for _ in 0..n_args {
             args.push(limit_string_length(data.read_cstr(), MAX_ARG_TYPE_LEN));
         }
         args
     };
+    let args = read_args();
+    let translated_args = if version >= 3 {
+        read_args()
+    } else {
+        args.clone()
+    };
 
     let funcname = match addr_to_info(address).0 {
         Some(s) => limit_string_length(s, MAX_FUNC_NAME_LEN),
@@ -215,6 +258,7 @@ fn process_probe_record(
     let provider = providers.entry(provname.clone()).or_insert(Provider {
         name: provname,
         probes: BTreeMap::new(),
+        attributes,
     });
 
     let probename = limit_string_length(probename, MAX_PROBE_NAME_LEN);
@@ -225,8 +269,10 @@ fn process_probe_record(
         offsets: vec![],
         enabled_offsets: vec![],
         arguments: vec![],
+        translated_arguments: vec![],
     });
     probe.arguments = args;
+    probe.translated_arguments = translated_args;
 
     // We expect to get records in address order for a given probe; our offsets
     // would be negative otherwise.
@@ -257,19 +303,39 @@ impl<'a> ReadCstrExt<'a> for &'a [u8] {
     }
 }
 
+// Encode stability attributes as a `dof_attr_t`, as with the `DOF_ATTR` macro.
+pub(crate) fn dof_attr(attributes: &Attributes) -> u32 {
+    ((attributes.name as u32) << 24)
+        | ((attributes.data as u32) << 16)
+        | ((attributes.class as u32) << 8)
+}
+
 // Construct the ASM record for a probe. If `types` is `None`, then is is an is-enabled probe.
-#[allow(dead_code)]
-pub(crate) fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataType]>) -> String {
-    #[cfg(not(target_os = "freebsd"))]
-    let section_ident = r#"set_dtrace_probes,"aw","progbits""#;
-    #[cfg(target_os = "freebsd")]
-    let section_ident = r#"set_dtrace_probes,"awR","progbits""#;
+//
+// The `translated_types` are those DTrace presents each argument as, see
+// `Probe::translated_argument_types`.
+pub(crate) fn emit_probe_record(
+    prov: &str,
+    probe: &str,
+    types: Option<&[DataType]>,
+    translated_types: &[String],
+    attributes: &dtrace_parser::ProviderAttributes,
+    os: Os,
+    arch: Arch,
+) -> String {
+    let section_ident = if os == Os::FreeBsd {
+        r#"set_dtrace_probes,"awR","progbits""#
+    } else {
+        r#"set_dtrace_probes,"aw","progbits""#
+    };
     let is_enabled = types.is_none();
     let n_args = types.map_or(0, |typ| typ.len());
     let arguments = types.map_or_else(String::new, |types| {
         types
             .iter()
-            .map(|typ| format!(".asciz \"{}\"", typ.to_c_type()))
+            .map(|typ| typ.to_c_type_for(arch))
+            .chain(translated_types.iter().cloned())
+            .map(|typ| format!(".asciz \"{}\"", typ))
             .collect::<Vec<_>>()
             .join("\n")
     });
@@ -283,9 +349,10 @@ pub(crate) fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataTyp
                     .byte {n_args}
                     .2byte {flags}
                     .8byte 990b         // address
+                    {attributes}
                     .asciz "{prov}"
                     .asciz "{probe}"
-                    {arguments}         // null-terminated strings for each argument
+                    {arguments}         // null-terminated strings for each argument, then each translated one
                     .balign 8
             992:    .popsection
                     {yeet}
@@ -294,10 +361,16 @@ pub(crate) fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataTyp
         version = PROBE_REC_VERSION,
         n_args = n_args,
         flags = if is_enabled { 1 } else { 0 },
+        attributes = attributes
+            .entities()
+            .iter()
+            .map(|(_, attributes)| format!(".4byte {:#x}", dof_attr(attributes)))
+            .collect::<Vec<_>>()
+            .join("\n                    "),
         prov = prov,
         probe = probe.replace("__", "-"),
         arguments = arguments,
-        yeet = if cfg!(any(target_os = "illumos", target_os = "freebsd")) {
+        yeet = if matches!(os, Os::Illumos | Os::FreeBsd) {
             // The illumos and FreeBSD linkers may yeet our probes section into the trash under
             // certain conditions. To counteract this, we yeet references to the
             // probes section into another section. This causes the linker to
@@ -322,12 +395,15 @@ mod test {
     use super::emit_probe_record;
     use super::process_probe_record;
     use super::process_section;
+    use super::process_selected_section;
     use super::DataType;
     use super::PROBE_REC_VERSION;
+    use super::{Arch, Os};
     use super::{MAX_PROBE_NAME_LEN, MAX_PROVIDER_NAME_LEN};
     use dtrace_parser::BitWidth;
     use dtrace_parser::DataType as DType;
     use dtrace_parser::Integer;
+    use dtrace_parser::ProviderAttributes;
     use dtrace_parser::Sign;
 
     #[test]
@@ -340,6 +416,9 @@ mod test {
         rec.write_u8(0).unwrap();
         rec.write_u16::<NativeEndian>(0).unwrap();
         rec.write_u64::<NativeEndian>(0x1234).unwrap();
+        for attr in [0x05050500, 0, 0, 0x06050500, 0x05050400] {
+            rec.write_u32::<NativeEndian>(attr).unwrap();
+        }
         rec.write_cstr("provider");
         rec.write_cstr("probe");
         // fix the length field
@@ -349,17 +428,78 @@ mod test {
             .unwrap();
 
         let mut providers = BTreeMap::new();
-        process_probe_record(&mut providers, &mut rec, true).unwrap();
+        process_probe_record(&mut providers, &mut rec, true, &|_| true).unwrap();
 
-        let probe = providers
-            .get("provider")
-            .unwrap()
-            .probes
-            .get("probe")
-            .unwrap();
+        let provider = providers.get("provider").unwrap();
+        let probe = provider.probes.get("probe").unwrap();
 
         assert_eq!(probe.name, "probe");
         assert_eq!(probe.address, 0x1234);
+        assert_eq!(provider.attributes.provider, 0x05050500);
+        assert_eq!(provider.attributes.module, 0);
+        assert_eq!(provider.attributes.args, 0x05050400);
+    }
+
+    #[test]
+    fn test_process_probe_record_version_1() {
+        // Records from before stability attributes were recorded are still accepted.
+        let mut rec = Vec::<u8>::new();
+        rec.write_u32::<NativeEndian>(0).unwrap();
+        rec.write_u8(1).unwrap();
+        rec.write_u8(0).unwrap();
+        rec.write_u16::<NativeEndian>(0).unwrap();
+        rec.write_u64::<NativeEndian>(0x1234).unwrap();
+        rec.write_cstr("provider");
+        rec.write_cstr("probe");
+        let len = rec.len();
+        (&mut rec[0..])
+            .write_u32::<NativeEndian>(len as u32)
+            .unwrap();
+
+        let mut providers = BTreeMap::new();
+        process_probe_record(&mut providers, &mut rec, true, &|_| true).unwrap();
+
+        let provider = providers.get("provider").unwrap();
+        assert_eq!(provider.probes.get("probe").unwrap().address, 0x1234);
+        assert_eq!(provider.attributes, Default::default());
+    }
+
+    #[test]
+    fn test_process_probe_record_translated_arguments() {
+        // Records from before translated types were recorded translate each argument to its own
+        // type.
+        for (version, translated) in [(PROBE_REC_VERSION, "my_event_t *"), (2, "uint64_t")] {
+            let mut rec = Vec::<u8>::new();
+            rec.write_u32::<NativeEndian>(0).unwrap();
+            rec.write_u8(version).unwrap();
+            rec.write_u8(1).unwrap();
+            rec.write_u16::<NativeEndian>(0).unwrap();
+            rec.write_u64::<NativeEndian>(0x1234).unwrap();
+            for _ in 0..5 {
+                rec.write_u32::<NativeEndian>(0).unwrap();
+            }
+            rec.write_cstr("provider");
+            rec.write_cstr("probe");
+            rec.write_cstr("uint64_t");
+            if version >= 3 {
+                rec.write_cstr("my_event_t *");
+            }
+            let len = rec.len();
+            (&mut rec[0..])
+                .write_u32::<NativeEndian>(len as u32)
+                .unwrap();
+
+            let mut providers = BTreeMap::new();
+            process_probe_record(&mut providers, &mut rec, true, &|_| true).unwrap();
+            let probe = &providers["provider"].probes["probe"];
+            assert_eq!(probe.arguments, ["uint64_t"]);
+            assert_eq!(
+                probe.translated_arguments,
+                [translated],
+                "version {}",
+                version
+            );
+        }
     }
 
     #[test]
@@ -373,6 +513,9 @@ mod test {
         rec.write_u8(0).unwrap();
         rec.write_u16::<NativeEndian>(0).unwrap();
         rec.write_u64::<NativeEndian>(0x1234).unwrap();
+        for _ in 0..5 {
+            rec.write_u32::<NativeEndian>(0).unwrap();
+        }
         rec.write_cstr(&long_name);
         rec.write_cstr(&long_name);
         // fix the length field
@@ -382,12 +525,12 @@ mod test {
             .unwrap();
 
         let mut providers = BTreeMap::new();
-        process_probe_record(&mut providers, &mut rec, true).unwrap();
+        process_probe_record(&mut providers, &mut rec, true, &|_| true).unwrap();
 
         let expected_provider_name = &long_name[..MAX_PROVIDER_NAME_LEN - 1];
         let expected_probe_name = &long_name[..MAX_PROBE_NAME_LEN - 1];
 
-        assert!(providers.get(&long_name).is_none());
+        assert!(!providers.contains_key(&long_name));
         let probe = providers
             .get(expected_provider_name)
             .unwrap()
@@ -404,6 +547,11 @@ mod test {
     // The version argument is used to control the probe record version, which helps test one-time
     // registration of probes.
     fn make_record(version: u8) -> Vec<u8> {
+        make_provider_record(version, "provider")
+    }
+
+    // Write two probe records, from the provider of the given name.
+    fn make_provider_record(version: u8, provider: &str) -> Vec<u8> {
         let mut data = Vec::<u8>::new();
 
         // write a dummy length for the first record
@@ -412,7 +560,10 @@ mod test {
         data.write_u8(0).unwrap();
         data.write_u16::<NativeEndian>(0).unwrap();
         data.write_u64::<NativeEndian>(0x1234).unwrap();
-        data.write_cstr("provider");
+        for _ in 0..5 {
+            data.write_u32::<NativeEndian>(0).unwrap();
+        }
+        data.write_cstr(provider);
         data.write_cstr("probe");
         let len = data.len();
         (&mut data[0..])
@@ -424,7 +575,10 @@ mod test {
         data.write_u8(0).unwrap();
         data.write_u16::<NativeEndian>(0).unwrap();
         data.write_u64::<NativeEndian>(0x12ab).unwrap();
-        data.write_cstr("provider");
+        for _ in 0..5 {
+            data.write_u32::<NativeEndian>(0).unwrap();
+        }
+        data.write_cstr(provider);
         data.write_cstr("probe");
         let len2 = data.len() - len;
         (&mut data[len..])
@@ -463,6 +617,22 @@ mod test {
         assert_eq!(section.providers.len(), 0);
     }
 
+    #[test]
+    fn test_process_selected_section() {
+        // Only the records of the selected providers are registered, so that the others are
+        // registered by a later pass.
+        let mut data = make_provider_record(PROBE_REC_VERSION, "a");
+        let len = data.len();
+        data.extend(make_provider_record(PROBE_REC_VERSION, "b"));
+        let section = process_selected_section(&mut data, |name| name == "b").unwrap();
+        assert_eq!(section.providers.keys().collect::<Vec<_>>(), ["b"]);
+        assert_eq!(data[4], PROBE_REC_VERSION);
+        assert_eq!(data[len + 4], u8::MAX);
+        let section = process_section(&mut data, true).unwrap();
+        assert_eq!(section.providers.keys().collect::<Vec<_>>(), ["a"]);
+        assert_eq!(data[4], u8::MAX);
+    }
+
     #[test]
     fn test_process_section_future_version() {
         // Ensure that we _don't_ modify a future version number in a probe record, but that the
@@ -495,7 +665,20 @@ mod test {
             })),
             DataType::Native(DType::String),
         ];
-        let record = emit_probe_record(provider, probe, Some(&types));
+        let translated = [String::from("my_event_t *"), String::from("char*")];
+        let attributes = ProviderAttributes {
+            name: "Evolving/Evolving/Common".parse().unwrap(),
+            ..Default::default()
+        };
+        let record = emit_probe_record(
+            provider,
+            probe,
+            Some(&types),
+            &translated,
+            &attributes,
+            Os::Illumos,
+            Arch::X86_64,
+        );
         let mut lines = record.lines();
         println!("{}", record);
         lines.next(); // empty line
@@ -509,8 +692,25 @@ mod test {
             .next()
             .unwrap()
             .contains(&format!(".byte {}", types.len())));
-        for (typ, line) in types.iter().zip(lines.skip(4)) {
-            assert!(line.contains(&format!(".asciz \"{}\"", typ.to_c_type())));
+        let mut lines = lines.skip(2);
+        for attr in [0, 0, 0, 0x05050500, 0] {
+            assert!(lines
+                .next()
+                .unwrap()
+                .contains(&format!(".4byte {:#x}", attr)));
+        }
+        let mut lines = lines.skip(2);
+        for typ in types.iter() {
+            assert!(lines
+                .next()
+                .unwrap()
+                .contains(&format!(".asciz \"{}\"", typ.to_c_type_for(Arch::X86_64))));
+        }
+        for typ in translated.iter() {
+            assert!(lines
+                .next()
+                .unwrap()
+                .contains(&format!(".asciz \"{}\"", typ)));
         }
     }
 
@@ -525,10 +725,44 @@ mod test {
             })),
             DataType::Native(dtrace_parser::DataType::String),
         ];
-        let record = emit_probe_record(provider, probe, Some(&types));
+        let record = emit_probe_record(
+            provider,
+            probe,
+            Some(&types),
+            &[],
+            &Default::default(),
+            Os::Illumos,
+            Arch::X86_64,
+        );
         assert!(
             record.contains("my-probe"),
             "Expected double-underscores to be translated to a single dash"
         );
     }
+
+    #[test]
+    fn test_emit_probe_record_for_os() {
+        let freebsd = emit_probe_record(
+            "provider",
+            "probe",
+            None,
+            &[],
+            &Default::default(),
+            Os::FreeBsd,
+            Arch::X86_64,
+        );
+        assert!(freebsd.contains(r#""awR""#));
+        assert!(freebsd.contains("yeet_dtrace_probes"));
+        let solaris = emit_probe_record(
+            "provider",
+            "probe",
+            None,
+            &[],
+            &Default::default(),
+            Os::Solaris,
+            Arch::X86_64,
+        );
+        assert!(solaris.contains(r#""aw""#));
+        assert!(!solaris.contains("yeet_dtrace_probes"));
+    }
 }
@@ -0,0 +1,594 @@
+//! A runtime registry of the probes compiled into a program.
+//!
+//! Each compiled provider emits a record for each of its probes into a custom linker section,
+//! `set_usdt_probes` in ELF objects and `__DATA,__usdt_probes` in Mach-O ones. The linker gathers
+//! the records of all providers into a single array, which is read back at runtime via the
+//! symbols marking the start and end of the section.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{DataType, Probe, Provider, TimestampKind};
+use dtrace_parser::{BitWidth, Integer, Sign};
+use proc_macro2::TokenStream;
+use quote::quote;
+
+/// The name of the section containing the probe records in ELF objects.
+pub(crate) const ELF_SECTION: &str = "set_usdt_probes";
+
+/// The name of the section containing the probe records in Mach-O objects.
+pub(crate) const MACHO_SECTION: &str = "__DATA,__usdt_probes";
+
+/// Information about a probe compiled into the program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeInfo {
+    /// The name of the probe's provider.
+    pub provider: String,
+    /// The name of the probe, as it appears to DTrace. Double-underscores in the probe's name in
+    /// Rust are translated into dashes.
+    pub name: String,
+    /// The types of the probe's arguments.
+    pub types: Vec<DataType>,
+}
+
+impl From<&ProbeRecord> for ProbeInfo {
+    fn from(rec: &ProbeRecord) -> Self {
+        Self {
+            provider: String::from(rec.provider),
+            name: String::from(rec.name),
+            types: rec.types.iter().map(DataType::from).collect(),
+        }
+    }
+}
+
+/// Return information about every probe compiled into the program.
+///
+/// This includes the probes of all providers, including those defined in a library dependency,
+/// regardless of whether they have been registered with [`register_probes`](crate::register_probes).
+/// Called from a shared library, these are the probes of that library. On platforms without a
+/// probe backend, no probes are returned.
+pub fn probes() -> impl Iterator<Item = ProbeInfo> {
+    probe_records().iter().map(ProbeInfo::from)
+}
+
+/// A summary of the probes registered by
+/// [`register_probes_with_report`](crate::register_probes_with_report).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Registration {
+    /// The number of providers whose probes were registered.
+    pub providers: usize,
+    /// The number of probes registered, each counted once however many sites it has.
+    pub probes: usize,
+}
+
+// Summarize the probes compiled into the module, all of which are registered by a successful call
+// to `register_probes`.
+pub(crate) fn registration() -> Registration {
+    let probes = probe_records()
+        .iter()
+        .map(|rec| (rec.provider, rec.name))
+        .collect::<std::collections::BTreeSet<_>>();
+    let providers = probes
+        .iter()
+        .map(|(provider, _)| provider)
+        .collect::<std::collections::BTreeSet<_>>();
+    Registration {
+        providers: providers.len(),
+        probes: probes.len(),
+    }
+}
+
+// Return an error naming each of the given providers which isn't compiled into the module.
+#[cfg(not(usdt_backend_noop))]
+pub(crate) fn check_providers(names: &[&str]) -> Result<(), crate::Error> {
+    let unknown = names
+        .iter()
+        .filter(|name| !probe_records().iter().any(|rec| rec.provider == **name))
+        .map(|name| String::from(*name))
+        .collect::<Vec<_>>();
+    if unknown.is_empty() {
+        Ok(())
+    } else {
+        Err(crate::Error::UnknownProviders(unknown))
+    }
+}
+
+// Return an error naming the first of the selected providers defined in more than one place.
+//
+// A provider is normally defined once, however many crates use it. One defined in several places,
+// such as by two crates picking the same name, has its probes merged by DTrace, and it's then
+// unclear which of them fire.
+pub(crate) fn check_unique_providers(selected: impl Fn(&str) -> bool) -> Result<(), crate::Error> {
+    let mut definitions = std::collections::BTreeMap::new();
+    for rec in probe_records().iter().filter(|rec| selected(rec.provider)) {
+        let definition = definitions.entry(rec.provider).or_insert(rec.definition);
+        if *definition != rec.definition {
+            return Err(crate::Error::DuplicateProvider {
+                name: String::from(rec.provider),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Return `true` if the named probe is currently enabled.
+///
+/// The probe is looked up by the names of its provider and of the probe itself, as they appear to
+/// DTrace, though the double-underscores of a probe's name in Rust are accepted too. This is
+/// `false` for probes which are not compiled into the program. If a probe of the same name is
+/// defined in several places, this is `true` if any of them is enabled.
+///
+/// Note that with DTrace, probes are only enabled once registered with
+/// [`register_probes`](crate::register_probes), and that the check is itself listed as an
+/// additional site of the probe. This is always `false` when probes are disabled, see
+/// [`probes_disabled`](crate::probes_disabled).
+pub fn is_enabled(provider: &str, probe: &str) -> bool {
+    let probe = probe.replace("__", "-");
+    !crate::probes_disabled()
+        && probe_records()
+            .iter()
+            .filter(|rec| rec.provider == provider && rec.name == probe)
+            .any(|rec| (rec.is_enabled)())
+}
+
+/// Return `true` if any probe of the named provider is currently enabled.
+///
+/// This checks each probe of the provider as [`is_enabled`] does, say before some setup shared by
+/// several of its probes, and is `false` for a provider which isn't compiled into the program.
+pub fn provider_enabled(provider: &str) -> bool {
+    !crate::probes_disabled()
+        && probe_records()
+            .iter()
+            .filter(|rec| rec.provider == provider)
+            .any(|rec| (rec.is_enabled)())
+}
+
+/// Return the value of the named probe's semaphore, the number of tracers which have enabled it.
+///
+/// The probe is looked up as by [`is_enabled`], and this is 0 for probes which are not compiled
+/// into the program. Only SystemTap probes have a semaphore, so this is also 0 for LTTng-UST
+/// tracepoints, whether or not they're enabled. If a probe of the same name is defined in several
+/// places, this is the largest of their semaphores.
+#[cfg(target_os = "linux")]
+pub fn enabled_count(provider: &str, probe: &str) -> u16 {
+    let probe = probe.replace("__", "-");
+    probe_records()
+        .iter()
+        .filter(|rec| rec.provider == provider && rec.name == probe)
+        .filter_map(|rec| rec.semaphore)
+        .map(|semaphore| semaphore())
+        .max()
+        .unwrap_or(0)
+}
+
+// The linker defines the symbols bounding the section in each executable and shared library
+// containing it. Referring to them as hidden binds the references to those of the module with this
+// code, rather than to those of whichever module happens to export them first, so that each
+// shared library lists its own probes.
+#[cfg(any(usdt_backend_standard, usdt_backend_stapsdt))]
+::std::arch::global_asm!(
+    ".hidden __start_set_usdt_probes",
+    ".hidden __stop_set_usdt_probes"
+);
+
+#[cfg(any(usdt_backend_standard, usdt_backend_stapsdt))]
+fn probe_records() -> &'static [ProbeRecord] {
+    unsafe extern "C" {
+        #[link_name = "__start_set_usdt_probes"]
+        static usdt_probes_start: usize;
+        #[link_name = "__stop_set_usdt_probes"]
+        static usdt_probes_stop: usize;
+    }
+
+    // Ensure the section exists, so that the linker defines the symbols above even if no probes
+    // are defined. As with the DOF records, the variable must be mutable on illumos, so that it
+    // ends up in the same writable section as the records.
+    #[link_section = "set_usdt_probes"]
+    #[used]
+    static mut FORCE_LOAD: [ProbeRecord; 0] = [];
+
+    unsafe {
+        let start = (&raw const usdt_probes_start).cast::<ProbeRecord>();
+        let stop = (&raw const usdt_probes_stop).cast::<ProbeRecord>();
+        std::slice::from_raw_parts(start, stop.offset_from(start) as usize)
+    }
+}
+
+#[cfg(usdt_backend_linker)]
+fn probe_records() -> &'static [ProbeRecord] {
+    unsafe extern "C" {
+        #[link_name = "\x01section$start$__DATA$__usdt_probes"]
+        static usdt_probes_start: usize;
+        #[link_name = "\x01section$end$__DATA$__usdt_probes"]
+        static usdt_probes_stop: usize;
+    }
+
+    #[link_section = "__DATA,__usdt_probes"]
+    #[used]
+    static mut FORCE_LOAD: [ProbeRecord; 0] = [];
+
+    unsafe {
+        let start = (&raw const usdt_probes_start).cast::<ProbeRecord>();
+        let stop = (&raw const usdt_probes_stop).cast::<ProbeRecord>();
+        std::slice::from_raw_parts(start, stop.offset_from(start) as usize)
+    }
+}
+
+#[cfg(usdt_backend_etw)]
+fn probe_records() -> &'static [ProbeRecord] {
+    crate::etw::runtime::probe_records()
+}
+
+#[cfg(usdt_backend_noop)]
+fn probe_records() -> &'static [ProbeRecord] {
+    &[]
+}
+
+/// The record of a probe stored in the registry's linker section.
+///
+/// This is constructed in the code generated for each provider, and so may only contain data
+/// which can be built in a constant context.
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct ProbeRecord {
+    pub provider: &'static str,
+    pub name: &'static str,
+    pub types: &'static [TypeRecord],
+    /// The path of the module defining the probe's provider, telling apart providers of the same
+    /// name defined in several places.
+    pub definition: &'static str,
+    pub is_enabled: fn() -> bool,
+    /// Read the probe's semaphore, for backends enabling probes with one.
+    pub semaphore: Option<fn() -> u16>,
+}
+
+/// The record of the [`DataType`] of a probe argument.
+#[doc(hidden)]
+#[derive(Debug)]
+pub enum TypeRecord {
+    Integer(IntegerRecord),
+    Pointer(IntegerRecord),
+    String,
+    NativeArray(IntegerRecord, usize),
+    UniqueId,
+    Path,
+    /// A timestamp, and whether it's signed.
+    Timestamp(TimestampKind, bool),
+    Duration,
+    Display,
+    Uuid,
+    /// A serializable type, as the source of the Rust type.
+    Serializable(&'static str),
+    /// A serializable type passed with the length of its JSON, as the source of the Rust type.
+    SizedSerializable(&'static str),
+    /// A protobuf message, as the source of the Rust type.
+    Protobuf(&'static str),
+    NativeSlice(IntegerRecord),
+}
+
+/// The record of an integer type, with a `width` of zero for the width of a pointer.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct IntegerRecord {
+    pub signed: bool,
+    pub width: u8,
+}
+
+impl From<&IntegerRecord> for Integer {
+    fn from(rec: &IntegerRecord) -> Self {
+        Integer {
+            sign: if rec.signed {
+                Sign::Signed
+            } else {
+                Sign::Unsigned
+            },
+            width: match rec.width {
+                8 => BitWidth::Bit8,
+                16 => BitWidth::Bit16,
+                32 => BitWidth::Bit32,
+                64 => BitWidth::Bit64,
+                _ => BitWidth::Pointer,
+            },
+        }
+    }
+}
+
+impl From<&TypeRecord> for DataType {
+    fn from(rec: &TypeRecord) -> Self {
+        use dtrace_parser::DataType as DType;
+        match rec {
+            TypeRecord::Integer(int) => DataType::Native(DType::Integer(int.into())),
+            TypeRecord::Pointer(int) => DataType::Native(DType::Pointer(int.into())),
+            TypeRecord::String => DataType::Native(DType::String),
+            TypeRecord::NativeArray(int, len) => DataType::NativeArray(int.into(), *len),
+            TypeRecord::UniqueId => DataType::UniqueId,
+            TypeRecord::Path => DataType::Path,
+            TypeRecord::Uuid => DataType::Uuid,
+            TypeRecord::Duration => DataType::Duration,
+            TypeRecord::Display => DataType::Display,
+            TypeRecord::Timestamp(kind, signed) => DataType::Timestamp(
+                *kind,
+                if *signed {
+                    Sign::Signed
+                } else {
+                    Sign::Unsigned
+                },
+            ),
+            // Unwrap safety: The source was generated from a valid type.
+            TypeRecord::Serializable(ty) => DataType::Serializable(syn::parse_str(ty).unwrap()),
+            TypeRecord::SizedSerializable(ty) => {
+                DataType::SizedSerializable(syn::parse_str(ty).unwrap())
+            }
+            TypeRecord::Protobuf(ty) => DataType::Protobuf(syn::parse_str(ty).unwrap()),
+            TypeRecord::NativeSlice(int) => DataType::NativeSlice(int.into()),
+        }
+    }
+}
+
+fn integer_record(int: &Integer) -> TokenStream {
+    let signed = int.sign == Sign::Signed;
+    let width: u8 = match int.width {
+        BitWidth::Bit8 => 8,
+        BitWidth::Bit16 => 16,
+        BitWidth::Bit32 => 32,
+        BitWidth::Bit64 => 64,
+        BitWidth::Pointer => 0,
+    };
+    quote! { ::usdt::IntegerRecord { signed: #signed, width: #width } }
+}
+
+fn type_record(typ: &DataType) -> TokenStream {
+    use dtrace_parser::DataType as DType;
+    match typ {
+        DataType::Native(DType::Integer(int))
+        | DataType::Discriminant(_, int)
+        | DataType::NonZero(int) => {
+            let int = integer_record(int);
+            quote! { ::usdt::TypeRecord::Integer(#int) }
+        }
+        DataType::Native(DType::Pointer(int)) => {
+            let int = integer_record(int);
+            quote! { ::usdt::TypeRecord::Pointer(#int) }
+        }
+        DataType::Native(DType::String) | DataType::VariantName(_) => {
+            quote! { ::usdt::TypeRecord::String }
+        }
+        DataType::NativeArray(int, len) => {
+            let int = integer_record(int);
+            quote! { ::usdt::TypeRecord::NativeArray(#int, #len) }
+        }
+        DataType::UniqueId => quote! { ::usdt::TypeRecord::UniqueId },
+        DataType::Path => quote! { ::usdt::TypeRecord::Path },
+        DataType::Address(_) => {
+            let int = integer_record(&crate::integer64(Sign::Unsigned));
+            quote! { ::usdt::TypeRecord::Integer(#int) }
+        }
+        DataType::Uuid => quote! { ::usdt::TypeRecord::Uuid },
+        DataType::Duration => quote! { ::usdt::TypeRecord::Duration },
+        DataType::Display => quote! { ::usdt::TypeRecord::Display },
+        DataType::Timestamp(kind, sign) => {
+            let kind = match kind {
+                TimestampKind::Chrono => quote! { ::usdt::TimestampKind::Chrono },
+                TimestampKind::Time => quote! { ::usdt::TimestampKind::Time },
+            };
+            let signed = *sign == Sign::Signed;
+            quote! { ::usdt::TypeRecord::Timestamp(#kind, #signed) }
+        }
+        DataType::Serializable(ty) => {
+            let ty = quote! { #ty }.to_string();
+            quote! { ::usdt::TypeRecord::Serializable(#ty) }
+        }
+        DataType::SizedSerializable(ty) => {
+            let ty = quote! { #ty }.to_string();
+            quote! { ::usdt::TypeRecord::SizedSerializable(#ty) }
+        }
+        DataType::Protobuf(ty) => {
+            let ty = quote! { #ty }.to_string();
+            quote! { ::usdt::TypeRecord::Protobuf(#ty) }
+        }
+        DataType::NativeSlice(int) => {
+            let int = integer_record(int);
+            quote! { ::usdt::TypeRecord::NativeSlice(#int) }
+        }
+    }
+}
+
+/// Construct the registry records of all the probes of a provider, placed in the given linker
+/// section.
+///
+/// The `is_enabled` function returns an expression checking whether a probe is enabled, which is
+/// called from a function of its own, as is the expression reading the probe's semaphore returned
+/// by `semaphore`, for backends which have one.
+pub(crate) fn emit_registry_records(
+    provider: &Provider,
+    section: &str,
+    is_enabled: impl Fn(&Probe) -> TokenStream,
+    semaphore: impl Fn(&Probe) -> Option<TokenStream>,
+) -> TokenStream {
+    let records = registry_records(provider, is_enabled, semaphore);
+    let n_records = provider.probes.len();
+    quote! {
+        const _: () = {
+            #[link_section = #section]
+            #[used]
+            static PROBE_RECORDS: [::usdt::ProbeRecord; #n_records] = #records;
+        };
+    }
+}
+
+/// Construct an array of the registry records of all the probes of a provider, as
+/// [`emit_registry_records`] does, for backends listing the records themselves.
+pub(crate) fn registry_records(
+    provider: &Provider,
+    is_enabled: impl Fn(&Probe) -> TokenStream,
+    semaphore: impl Fn(&Probe) -> Option<TokenStream>,
+) -> TokenStream {
+    let prov = &provider.name;
+    let records = provider.probes.iter().map(|probe| {
+        let name = probe.name.replace("__", "-");
+        let types = probe.types.iter().map(type_record);
+        let is_enabled = is_enabled(probe);
+        let semaphore = match semaphore(probe) {
+            Some(semaphore) => quote! {
+                Some({
+                    fn semaphore() -> u16 {
+                        #semaphore
+                    }
+                    semaphore
+                })
+            },
+            None => quote! { None },
+        };
+        quote! {
+            ::usdt::ProbeRecord {
+                provider: #prov,
+                name: #name,
+                types: &[#(#types),*],
+                definition: ::std::module_path!(),
+                is_enabled: {
+                    fn is_enabled() -> bool {
+                        #is_enabled
+                    }
+                    is_enabled
+                },
+                semaphore: #semaphore,
+            }
+        }
+    });
+    quote! { [#(#records),*] }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtrace_parser::DataType as DType;
+
+    #[test]
+    fn test_type_record_round_trip() {
+        let types = [
+            DataType::Native(DType::Integer(Integer {
+                sign: Sign::Signed,
+                width: BitWidth::Pointer,
+            })),
+            DataType::Native(DType::Pointer(Integer {
+                sign: Sign::Unsigned,
+                width: BitWidth::Bit16,
+            })),
+            DataType::Native(DType::String),
+            DataType::NativeArray(
+                Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit64,
+                },
+                4,
+            ),
+            DataType::UniqueId,
+            DataType::Timestamp(TimestampKind::Time, Sign::Signed),
+            DataType::Uuid,
+            DataType::Serializable(syn::parse_str("&Vec<Foo>").unwrap()),
+            DataType::NativeSlice(Integer {
+                sign: Sign::Signed,
+                width: BitWidth::Bit32,
+            }),
+        ];
+        let records = [
+            TypeRecord::Integer(IntegerRecord {
+                signed: true,
+                width: 0,
+            }),
+            TypeRecord::Pointer(IntegerRecord {
+                signed: false,
+                width: 16,
+            }),
+            TypeRecord::String,
+            TypeRecord::NativeArray(
+                IntegerRecord {
+                    signed: false,
+                    width: 64,
+                },
+                4,
+            ),
+            TypeRecord::UniqueId,
+            TypeRecord::Timestamp(TimestampKind::Time, true),
+            TypeRecord::Uuid,
+            TypeRecord::Serializable("& Vec < Foo >"),
+            TypeRecord::NativeSlice(IntegerRecord {
+                signed: true,
+                width: 32,
+            }),
+        ];
+        for (typ, rec) in types.iter().zip(&records) {
+            assert_eq!(&DataType::from(rec), typ);
+        }
+        assert_eq!(
+            type_record(&types[1]).to_string(),
+            quote! {
+                ::usdt::TypeRecord::Pointer(::usdt::IntegerRecord { signed: false, width: 16u8 })
+            }
+            .to_string()
+        );
+        assert_eq!(
+            type_record(&types[5]).to_string(),
+            quote! { ::usdt::TypeRecord::Timestamp(::usdt::TimestampKind::Time, true) }.to_string()
+        );
+        assert_eq!(
+            type_record(&types[7]).to_string(),
+            quote! { ::usdt::TypeRecord::Serializable("& Vec < Foo >") }.to_string()
+        );
+    }
+
+    #[test]
+    fn test_emit_registry_records() {
+        let provider = Provider {
+            name: String::from("prov"),
+            probes: vec![Probe {
+                name: String::from("my__probe"),
+                types: vec![DataType::UniqueId],
+                attrs: vec![],
+                arg_names: vec![],
+                d_types: vec![],
+                defaults: vec![],
+                span: proc_macro2::Span::call_site(),
+            }],
+            use_statements: vec![],
+            attributes: Default::default(),
+        };
+        let expected = quote! {
+            const _: () = {
+                #[link_section = "set_usdt_probes"]
+                #[used]
+                static PROBE_RECORDS: [::usdt::ProbeRecord; 1usize] = [
+                    ::usdt::ProbeRecord {
+                        provider: "prov",
+                        name: "my-probe",
+                        types: &[::usdt::TypeRecord::UniqueId],
+                        definition: ::std::module_path!(),
+                        is_enabled: {
+                            fn is_enabled() -> bool {
+                                false
+                            }
+                            is_enabled
+                        },
+                        semaphore: None,
+                    }
+                ];
+            };
+        };
+        assert_eq!(
+            emit_registry_records(&provider, ELF_SECTION, |_| quote! { false }, |_| None)
+                .to_string(),
+            expected.to_string()
+        );
+    }
+}
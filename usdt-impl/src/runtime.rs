@@ -0,0 +1,479 @@
+//! Providers defined at runtime, from the source of a D provider file.
+//!
+//! Providers are usually compiled into the program, either from a D file or from a Rust module,
+//! and their probes fired with the macros generated for them. [`register_provider_source`]
+//! instead parses the source of a D provider file while the program runs, and registers its
+//! probes with DTrace. Since there are no macros to fire them with, they're fired by name with
+//! [`fire`], which checks the arguments against the types declared in the source. A probe fired
+//! often may instead be looked up once with [`probe`], so that firing it while it's disabled
+//! costs only the check of whether it's enabled.
+//!
+//! ```ignore
+//! usdt::runtime::register_provider_source("provider plugin { probe event(uint64_t, char*); };")?;
+//! usdt::runtime::fire("plugin", "event", &[1u64.into(), "loaded".into()])?;
+//! ```
+//!
+//! Each probe is fired from one of [`MAX_RUNTIME_PROBES`] sites compiled into the program, which
+//! are handed out as probes are registered, and never reused.
+//!
+//! This is only supported on illumos and FreeBSD, on x86_64, where probes are registered by
+//! passing their DOF to the kernel while the program runs. Elsewhere, registering a provider is
+//! an [`Error::RuntimeProvidersUnsupported`]:
+//!
+//! - The SystemTap probes used on Linux are described by notes in the program's binary, written
+//!   when it's compiled, and which tracers read from the file. A running program has no way to
+//!   describe any more probes to them.
+//! - On macOS, the linker turns the probes it finds in the program into DOF, so there's likewise
+//!   nothing to register probes defined after linking with.
+//! - Providers aren't defined at runtime on Windows, nor on platforms without probes at all.
+//!
+//! Like the compiled probes, these are disabled by setting [`DISABLE_ENV_VAR`](crate::DISABLE_ENV_VAR) when they're
+//! registered: [`register_provider_source`] then registers nothing, on every platform, and
+//! [`fire`] fires nothing, without building its arguments.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Error;
+use dtrace_parser::DataType;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// The maximum number of probes which may be registered at runtime, across all providers.
+pub const MAX_RUNTIME_PROBES: usize = 64;
+
+/// An argument to a probe fired with [`fire`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg<'a> {
+    /// An integer, for an integer or pointer argument. Signed integers are sign-extended.
+    Integer(u64),
+    /// A string, for a `char*` argument, which is passed null-terminated.
+    String(&'a str),
+}
+
+impl<'a> From<&'a str> for Arg<'a> {
+    fn from(s: &'a str) -> Self {
+        Arg::String(s)
+    }
+}
+
+macro_rules! integer_args {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Arg<'_> {
+                fn from(value: $ty) -> Self {
+                    Arg::Integer(value as u64)
+                }
+            }
+        )*
+    };
+}
+
+integer_args!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+/// A probe registered at runtime, looked up once with [`probe`] to be fired repeatedly.
+///
+/// Firing a probe by name with [`fire`] looks it up on each call, while this only checks whether
+/// it's enabled, and builds nothing unless it is.
+#[derive(Debug, Clone)]
+pub struct Probe {
+    provider: String,
+    name: String,
+    slot: usize,
+    types: Arc<[DataType]>,
+}
+
+impl Probe {
+    /// Return the name of the probe's provider.
+    pub fn provider(&self) -> &str {
+        &self.provider
+    }
+
+    /// Return the name of the probe, as DTrace names it.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return `true` if the probe is enabled by a tracer.
+    pub fn is_enabled(&self) -> bool {
+        slot_enabled(self.slot)
+    }
+
+    /// Fire the probe, if it's enabled, as [`fire`] does.
+    ///
+    /// The arguments are only checked against the probe's types when it's enabled, so that a
+    /// disabled probe costs no more than the check of its is-enabled site.
+    pub fn fire(&self, args: &[Arg<'_>]) -> Result<(), Error> {
+        if !self.is_enabled() {
+            return Ok(());
+        }
+        if !arguments_match(&self.types, args) {
+            return Err(Error::InvalidProbeArguments {
+                provider: self.provider.clone(),
+                probe: self.name.clone(),
+            });
+        }
+        fire_slot(self.slot, args);
+        Ok(())
+    }
+}
+
+// The probes registered at runtime, by provider and then probe name.
+static PROBES: Mutex<BTreeMap<String, BTreeMap<String, Probe>>> = Mutex::new(BTreeMap::new());
+
+/// Parse the source of a D provider file, and register the probes of its providers with DTrace.
+///
+/// The probes are then fired with [`fire`]. A provider may only be registered once, and none of
+/// the providers in the source are registered if any of them can't be.
+///
+/// See the [module documentation](self) for the platforms supporting this. Nothing is parsed or
+/// registered if probes are disabled with [`DISABLE_ENV_VAR`](crate::DISABLE_ENV_VAR).
+pub fn register_provider_source(source: &str) -> Result<(), Error> {
+    if crate::probes_disabled_by_env() {
+        return Ok(());
+    }
+    let file = dtrace_parser::File::try_from(source)?;
+    for provider in file.providers() {
+        for probe in provider.probes.iter() {
+            if probe.types.len() > crate::MAX_PROBE_ARGUMENTS {
+                return Err(Error::TooManyArguments {
+                    probe: format!("{}:{}", provider.name, probe.name),
+                    count: probe.types.len(),
+                });
+            }
+        }
+    }
+    register_providers(file.providers())
+}
+
+/// Fire a probe registered with [`register_provider_source`], if it's enabled.
+///
+/// The provider and probe are named as in the D source, where a probe named `foo__bar` may also
+/// be written `foo-bar`, as DTrace names it. Each argument must match the type declared for it:
+/// integers for integer and pointer types, and strings for `char*`. When probes are disabled with
+/// [`DISABLE_ENV_VAR`](crate::DISABLE_ENV_VAR), nothing was registered, and this does nothing.
+///
+/// A probe fired often is better looked up once with [`probe`], and fired with [`Probe::fire`].
+pub fn fire(provider: &str, probe: &str, args: &[Arg<'_>]) -> Result<(), Error> {
+    if crate::probes_disabled() {
+        return Ok(());
+    }
+    let slot = with_probe(provider, probe, |probe| {
+        if !probe.is_enabled() {
+            return Ok(None);
+        }
+        if !arguments_match(&probe.types, args) {
+            return Err(Error::InvalidProbeArguments {
+                provider: probe.provider.clone(),
+                probe: probe.name.clone(),
+            });
+        }
+        Ok(Some(probe.slot))
+    })?;
+    if let Some(slot) = slot {
+        fire_slot(slot, args);
+    }
+    Ok(())
+}
+
+/// Look up a probe registered with [`register_provider_source`], to fire it with [`Probe::fire`].
+///
+/// The provider and probe are named as for [`fire`].
+pub fn probe(provider: &str, probe: &str) -> Result<Probe, Error> {
+    with_probe(provider, probe, |probe| Ok(probe.clone()))
+}
+
+// Call a function with the probe of the given name, without allocating unless it isn't found.
+fn with_probe<T>(
+    provider: &str,
+    probe: &str,
+    f: impl FnOnce(&Probe) -> Result<T, Error>,
+) -> Result<T, Error> {
+    let name = if probe.contains("__") {
+        Cow::Owned(probe.replace("__", "-"))
+    } else {
+        Cow::Borrowed(probe)
+    };
+    // Probes may be fired while unwinding from a panic, so a poisoned lock isn't one too.
+    let probes = PROBES
+        .lock()
+        .unwrap_or_else(std::sync::PoisonError::into_inner);
+    match probes.get(provider).and_then(|probes| probes.get(&*name)) {
+        Some(probe) => f(probe),
+        None => Err(Error::UnknownProbe {
+            provider: provider.to_string(),
+            probe: name.into_owned(),
+        }),
+    }
+}
+
+// Return `true` if the arguments are of the types declared for a probe.
+fn arguments_match(types: &[DataType], args: &[Arg<'_>]) -> bool {
+    types.len() == args.len()
+        && types.iter().zip(args).all(|(ty, arg)| {
+            matches!(
+                (ty, arg),
+                (DataType::Integer(_) | DataType::Pointer(_), Arg::Integer(_))
+                    | (DataType::String, Arg::String(_))
+            )
+        })
+}
+
+// The sites probes registered at runtime are fired from. Each is a pair of functions: one
+// containing the is-enabled site, which returns whether the probe is enabled in `rax`, and one
+// containing the probe site itself, which finds its arguments in the registers they're passed in
+// by the C calling convention. Their addresses are collected into a table, `[is-enabled, probe]`
+// for each slot.
+#[cfg(all(usdt_backend_standard, target_arch = "x86_64"))]
+macro_rules! runtime_sites {
+    ($($slot:literal)*) => {
+        ::std::arch::global_asm!(
+            ".pushsection .text",
+            $(
+                concat!("__usdt_runtime_enabled_", $slot, ":"),
+                "clr rax",
+                "ret",
+                concat!("__usdt_runtime_probe_", $slot, ":"),
+                "nop",
+                "ret",
+            )*
+            ".popsection",
+            ".pushsection .data.rel.ro",
+            ".p2align 3",
+            ".globl __usdt_runtime_sites",
+            ".hidden __usdt_runtime_sites",
+            "__usdt_runtime_sites:",
+            $(
+                concat!(
+                    ".8byte __usdt_runtime_enabled_", $slot, ", __usdt_runtime_probe_", $slot
+                ),
+            )*
+            ".popsection",
+        );
+    };
+}
+
+#[cfg(all(usdt_backend_standard, target_arch = "x86_64"))]
+runtime_sites!(
+    0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 33 34
+    35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+);
+
+#[cfg(all(usdt_backend_standard, target_arch = "x86_64"))]
+unsafe extern "C" {
+    #[link_name = "__usdt_runtime_sites"]
+    static RUNTIME_SITES: [[usize; 2]; MAX_RUNTIME_PROBES];
+}
+
+// Build the DOF of the providers' probes, assigning each a site, and send it to the kernel.
+#[cfg(all(usdt_backend_standard, target_arch = "x86_64"))]
+fn register_providers(providers: &[dtrace_parser::Provider]) -> Result<(), Error> {
+    use crate::record::{addr_to_info, dof_attr};
+
+    let mut probes = PROBES.lock().unwrap();
+    if let Some(provider) = providers
+        .iter()
+        .find(|provider| probes.contains_key(&provider.name))
+    {
+        return Err(Error::DuplicateRuntimeProvider(provider.name.clone()));
+    }
+    let used = probes.values().map(BTreeMap::len).sum::<usize>();
+    let count = providers.iter().map(|p| p.probes.len()).sum::<usize>();
+    if used + count > MAX_RUNTIME_PROBES {
+        return Err(Error::TooManyRuntimeProbes);
+    }
+
+    let mut section = dof::Section::default();
+    let mut registered = BTreeMap::new();
+    let mut slot = used;
+    for provider in providers.iter() {
+        let attributes = &provider.attributes;
+        let mut dof_provider = dof::Provider {
+            name: provider.name.clone(),
+            probes: BTreeMap::new(),
+            attributes: dof::ProviderAttributes {
+                provider: dof_attr(&attributes.provider),
+                module: dof_attr(&attributes.module),
+                function: dof_attr(&attributes.function),
+                name: dof_attr(&attributes.name),
+                args: dof_attr(&attributes.args),
+            },
+        };
+        let mut provider_probes = BTreeMap::new();
+        for probe in provider.probes.iter() {
+            let [enabled, site] = unsafe { RUNTIME_SITES[slot] };
+            let address = enabled.min(site) as u64;
+            let name = probe.name.replace("__", "-");
+            dof_provider.probes.insert(
+                name.clone(),
+                dof::Probe {
+                    name: name.clone(),
+                    function: addr_to_info(address)
+                        .0
+                        .unwrap_or_else(|| format!("__usdt_runtime_site_{slot}")),
+                    address,
+                    offsets: vec![(site as u64 - address) as u32],
+                    enabled_offsets: vec![(enabled as u64 - address) as u32],
+                    arguments: probe.types.iter().map(DataType::to_c_type).collect(),
+                    translated_arguments: vec![],
+                },
+            );
+            provider_probes.insert(
+                name.clone(),
+                Probe {
+                    provider: provider.name.clone(),
+                    name,
+                    slot,
+                    types: probe.types.clone().into(),
+                },
+            );
+            slot += 1;
+        }
+        registered.insert(provider.name.clone(), provider_probes);
+        section
+            .providers
+            .insert(provider.name.clone(), dof_provider);
+    }
+
+    crate::no_linker::register_section(section)?;
+    probes.extend(registered);
+    Ok(())
+}
+
+#[cfg(not(all(usdt_backend_standard, target_arch = "x86_64")))]
+fn register_providers(_: &[dtrace_parser::Provider]) -> Result<(), Error> {
+    Err(Error::RuntimeProvidersUnsupported)
+}
+
+// Return whether the probe at the site of the given slot is enabled, which it never is when
+// probes are disabled.
+#[cfg(all(usdt_backend_standard, target_arch = "x86_64"))]
+fn slot_enabled(slot: usize) -> bool {
+    if crate::probes_disabled() {
+        return false;
+    }
+    let [enabled, _] = unsafe { RUNTIME_SITES[slot] };
+    let enabled: unsafe extern "C" fn() -> u64 = unsafe { std::mem::transmute(enabled) };
+    unsafe { enabled() != 0 }
+}
+
+// Fire the probe at the site of the given slot, which must be enabled.
+#[cfg(all(usdt_backend_standard, target_arch = "x86_64"))]
+fn fire_slot(slot: usize, args: &[Arg<'_>]) {
+    type Site = unsafe extern "C" fn(u64, u64, u64, u64, u64, u64);
+
+    let [_, site] = unsafe { RUNTIME_SITES[slot] };
+
+    // The strings must outlive the call to the probe site, which passes pointers to them.
+    let strings = args
+        .iter()
+        .map(|arg| match arg {
+            Arg::String(s) => Some(crate::to_str_probe_arg(s)),
+            Arg::Integer(_) => None,
+        })
+        .collect::<Vec<_>>();
+    let mut regs = [0u64; crate::MAX_PROBE_ARGUMENTS];
+    for (reg, (arg, string)) in regs.iter_mut().zip(args.iter().zip(&strings)) {
+        *reg = match (arg, string) {
+            (_, Some(string)) => string.as_ptr() as u64,
+            (Arg::Integer(value), None) => *value,
+            (Arg::String(_), None) => unreachable!("strings are always converted"),
+        };
+    }
+    let site: Site = unsafe { std::mem::transmute(site) };
+    unsafe { site(regs[0], regs[1], regs[2], regs[3], regs[4], regs[5]) };
+}
+
+// No probe is ever registered, so there's no site to check or fire.
+#[cfg(not(all(usdt_backend_standard, target_arch = "x86_64")))]
+fn slot_enabled(_: usize) -> bool {
+    unreachable!("probes are never registered at runtime on this platform")
+}
+
+#[cfg(not(all(usdt_backend_standard, target_arch = "x86_64")))]
+fn fire_slot(_: usize, _: &[Arg<'_>]) {
+    unreachable!("probes are never registered at runtime on this platform")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use dtrace_parser::{BitWidth, Integer, Sign};
+
+    #[test]
+    fn test_arg_from() {
+        assert_eq!(Arg::from(1u8), Arg::Integer(1));
+        assert_eq!(Arg::from(-1i32), Arg::Integer(u64::MAX));
+        assert_eq!(Arg::from("foo"), Arg::String("foo"));
+    }
+
+    #[test]
+    fn test_arguments_match() {
+        let int = Integer {
+            sign: Sign::Unsigned,
+            width: BitWidth::Bit64,
+        };
+        let types = [
+            DataType::Integer(int),
+            DataType::Pointer(int),
+            DataType::String,
+        ];
+        assert!(arguments_match(
+            &types,
+            &[1u64.into(), 0x1000usize.into(), "foo".into()]
+        ));
+        assert!(!arguments_match(&types, &[1u64.into(), 0x1000usize.into()]));
+        assert!(!arguments_match(
+            &types,
+            &["1".into(), 0x1000usize.into(), "foo".into()]
+        ));
+        assert!(!arguments_match(
+            &types,
+            &[1u64.into(), 0x1000usize.into(), 2u64.into()]
+        ));
+    }
+
+    #[test]
+    fn test_register_provider_source_errors() {
+        assert!(matches!(
+            register_provider_source("provider foo { probe bar(; };"),
+            Err(Error::ParseError(_))
+        ));
+        assert!(matches!(
+            register_provider_source(
+                "provider foo { probe bar(uint8_t, uint8_t, uint8_t, uint8_t, uint8_t, uint8_t, \
+                 uint8_t); };"
+            ),
+            Err(Error::TooManyArguments { count: 7, .. })
+        ));
+    }
+
+    #[cfg(not(all(usdt_backend_standard, target_arch = "x86_64")))]
+    #[test]
+    fn test_runtime_providers_unsupported() {
+        assert!(matches!(
+            register_provider_source("provider foo { probe bar(uint8_t); };"),
+            Err(Error::RuntimeProvidersUnsupported)
+        ));
+        assert!(matches!(
+            fire("foo", "bar", &[1u8.into()]),
+            Err(Error::UnknownProbe { .. })
+        ));
+        assert!(matches!(
+            probe("foo", "bar__baz"),
+            Err(Error::UnknownProbe { probe, .. }) if probe == "bar-baz"
+        ));
+    }
+}
@@ -0,0 +1,131 @@
+//! Short backtraces passed as probe arguments.
+//!
+//! With the `backtrace` feature, a probe argument declared as `&usdt::Backtrace` takes the frames
+//! of the stack captured with [`Backtrace::capture`], which are serialized like any other argument,
+//! as a JSON array of strings: `{"ok":["app::handle at src/handler.rs:42","app::main",...]}`. Each
+//! frame is the demangled name of its function, followed by its file and line when debug info has
+//! them, or the address of the frame's instruction when even the name can't be resolved.
+//!
+//! Capturing a backtrace walks the stack, and then resolves each frame's symbol. The first capture
+//! in a process reads and parses the program's debug info, which may take milliseconds or more,
+//! and each later one still takes microseconds per frame. The capture belongs in the probe's
+//! argument closure, so that this cost is only paid while the probe is enabled, and at most
+//! [`MAX_BACKTRACE_FRAMES`] frames are captured, however many are asked for.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use serde::Serialize;
+
+/// The largest number of frames captured in a [`Backtrace`].
+pub const MAX_BACKTRACE_FRAMES: usize = 64;
+
+/// The innermost frames of the stack, passed to a probe as a JSON array of strings.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct Backtrace {
+    frames: Vec<String>,
+}
+
+impl Backtrace {
+    /// Capture at most `max_frames` frames of the calling thread's stack, innermost first.
+    ///
+    /// The frames of the capture itself are left out, so the first frame is that of the caller, or
+    /// of the probe's argument closure. No more than [`MAX_BACKTRACE_FRAMES`] frames are captured.
+    #[inline(never)]
+    pub fn capture(max_frames: usize) -> Self {
+        let max_frames = max_frames.min(MAX_BACKTRACE_FRAMES);
+        let mut frames = Vec::with_capacity(max_frames);
+        if max_frames == 0 {
+            return Backtrace { frames };
+        }
+        // Frames are skipped up to this function's own, which is never inlined, so that only
+        // the frames kept are resolved.
+        let capture = Backtrace::capture as fn(usize) -> Backtrace as usize;
+        let mut in_capture = true;
+        ::backtrace::trace(|frame| {
+            if in_capture {
+                in_capture = frame.symbol_address() as usize != capture;
+                return true;
+            }
+            frames.push(describe_frame(frame).unwrap_or_else(|| format!("{:p}", frame.ip())));
+            frames.len() < max_frames
+        });
+        Backtrace { frames }
+    }
+
+    /// Return the description of each captured frame, innermost first.
+    pub fn frames(&self) -> &[String] {
+        &self.frames
+    }
+}
+
+// Return the function of a frame, and its file and line if known, or `None` if the frame's
+// symbol can't be resolved.
+fn describe_frame(frame: &::backtrace::Frame) -> Option<String> {
+    let mut description = None;
+    ::backtrace::resolve_frame(frame, |symbol| {
+        if description.is_some() {
+            return;
+        }
+        let Some(name) = symbol.name() else {
+            return;
+        };
+        description = Some(match (symbol.filename(), symbol.lineno()) {
+            (Some(file), Some(line)) => format!("{:#} at {}:{}", name, file.display(), line),
+            _ => format!("{:#}", name),
+        });
+    });
+    description
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[inline(never)]
+    fn capture_here(max_frames: usize) -> Backtrace {
+        Backtrace::capture(max_frames)
+    }
+
+    #[test]
+    fn test_capture() {
+        let backtrace = capture_here(4);
+        assert!(!backtrace.frames().is_empty());
+        assert!(backtrace.frames().len() <= 4);
+        assert!(
+            backtrace.frames()[0].contains("capture_here"),
+            "{:?}",
+            backtrace.frames()
+        );
+        assert!(!backtrace.frames()[0].contains("Backtrace::capture"));
+    }
+
+    #[test]
+    fn test_capture_is_capped() {
+        assert!(capture_here(usize::MAX).frames().len() <= MAX_BACKTRACE_FRAMES);
+        assert!(capture_here(0).frames().is_empty());
+    }
+
+    #[test]
+    fn test_serialize() {
+        let backtrace = Backtrace {
+            frames: vec![String::from("app::main at src/main.rs:3")],
+        };
+        assert_eq!(
+            serde_json::to_string(&backtrace).unwrap(),
+            r#"["app::main at src/main.rs:3"]"#
+        );
+    }
+}
@@ -14,66 +14,52 @@
 
 //! The SystemTap probe version 3 of the USDT crate.
 //!
-//! Used on Linux platforms without DTrace.
+//! Used on Linux platforms without DTrace. With the `dtrace-linux` feature, each probe also has
+//! the DTrace probe record of `no_linker`, for DTrace on Linux to find once its DOF is registered.
 //!
 //! Name of the file comes from the `NT_STAPSDT` SystemTap probe descriptors'
 //! type name in `readelf` output.
 
 #[path = "stapsdt/args.rs"]
-mod args;
+pub(crate) mod args;
 
-use crate::{common, DataType};
+use crate::target::{Arch, Os};
+use crate::{common, registry};
 use crate::{Probe, Provider};
-use args::format_argument;
 use proc_macro2::TokenStream;
 use quote::{format_ident, quote};
-use std::convert::TryFrom;
 
-pub fn compile_provider_source(
-    source: &str,
-    config: &crate::CompileProvidersConfig,
-) -> Result<TokenStream, crate::Error> {
-    let dfile = dtrace_parser::File::try_from(source)?;
-    let providers = dfile
-        .providers()
-        .iter()
-        .map(|provider| {
-            let provider = Provider::from(provider);
-            // Ensure that the name of the module in the config is set, either by the caller or
-            // defaulting to the provider name.
-            let config = crate::CompileProvidersConfig {
-                provider: Some(provider.name.clone()),
-                probe_format: config.probe_format.clone(),
-                module: match &config.module {
-                    None => Some(provider.name.clone()),
-                    other => other.clone(),
-                },
-            };
-            compile_provider(&provider, &config)
-        })
-        .collect::<Vec<_>>();
-    Ok(quote! {
-        #(#providers)*
-    })
-}
-
-pub fn compile_provider_from_definition(
+pub(crate) fn compile_provider(
     provider: &Provider,
     config: &crate::CompileProvidersConfig,
+    arch: Arch,
 ) -> TokenStream {
-    compile_provider(provider, config)
-}
-
-fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig) -> TokenStream {
     let probe_impls = provider
         .probes
         .iter()
-        .map(|probe| compile_probe(provider, probe, config))
+        .map(|probe| compile_probe(provider, probe, config, arch))
         .collect::<Vec<_>>();
+    let registry_records = registry::emit_registry_records(
+        provider,
+        registry::ELF_SECTION,
+        |probe| {
+            or_dof_enabled(
+                provider,
+                probe,
+                arch,
+                is_nonzero(registry_semaphore(provider, probe, arch, config)),
+            )
+        },
+        |probe| Some(registry_semaphore(provider, probe, arch, config)),
+    );
     let module = config.module_ident();
+    let visibility = config.visibility.tokens();
+    let use_statements = common::provider_use_statements(provider, config);
     quote! {
-        pub(crate) mod #module {
+        #visibility mod #module {
+            #use_statements
             #(#probe_impls)*
+            #registry_records
         }
     }
 }
@@ -88,13 +74,24 @@ fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig)
 /// detecting prelink address adjustments (its contents do not matter at all).
 ///
 /// This method generates the ELF note assembly and an `.ifndef _.stapsdt.base`
-/// section for the address adjustments. Additionally, this method generates a
+/// section for the address adjustments. The sections are those of the config,
+/// `.note.stapsdt`, `.probes` and `.stapsdt.base` by default. Additionally, this method generates a
 /// 16 bit "semaphore" (counter) and links it to the ELF note. This semaphore
 /// is then used to gate invocations of the probe by reading its value at
 /// runtime and checking it against 0. A value of 0 means that no consumers are
 /// currently active and the probe and any parameter massaging work can be
 /// skipped.
 ///
+/// The note is not allocated, so the linker resolves the probe, base and
+/// semaphore addresses in it to their link-time values, and no dynamic
+/// relocations are involved. Consumers relocate those addresses by the
+/// difference between the runtime and link-time addresses of `.stapsdt.base`,
+/// which is what makes the same note correct for position-independent
+/// executables (including musl's static PIE) and shared libraries. This
+/// relies on the base section surviving `--gc-sections`, hence the `R`
+/// (retain) flag, and on the `comdat` group collapsing every object's copy
+/// into a single section.
+///
 /// ### Summary
 ///
 /// A STAPSDT probe in plain pseudo-Rust would look roughly like this:
@@ -125,34 +122,20 @@ fn compile_provider(provider: &Provider, config: &crate::CompileProvidersConfig)
 /// then the above `nop()` instruction will turn into an interrupt instruction
 /// that transfers control to the kernel which will then run the probe's kernel
 /// side code (such as an eBPF program).
-fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataType]>) -> String {
+fn emit_probe_record(
+    prov: &str,
+    probe: &str,
+    arguments: &str,
+    arch: Arch,
+    config: &crate::CompileProvidersConfig,
+) -> String {
     let sema_name = format!("__usdt_sema_{}_{}", prov, probe);
-    let arguments = types.map_or_else(String::new, |types| {
-        types
-            .iter()
-            .enumerate()
-            .map(format_argument)
-            .collect::<Vec<_>>()
-            .join(" ")
-    });
+    let semaphore = emit_semaphore(prov, probe, arch, config);
     format!(
         r#"// First define the semaphore
-// Note: This uses ifndef to make sure the same probe name can be used
-// in multiple places but they all use the same semaphore. This can be
-// used to eg. guard additional preparatory work far away from the
-// actual probe site that will only be used by the probe.
-.ifndef {sema_name}
-        .pushsection .probes, "aw", "progbits"
-        .weak {sema_name}
-        .hidden {sema_name}
-{sema_name}:
-        .zero 2
-        .type {sema_name}, @object
-        .size {sema_name}, 2
-        .popsection
-.endif
+{semaphore}
 // Second define the actual USDT probe
-        .pushsection .note.stapsdt, "", "note"
+        .pushsection {note_section}, "", "note"
         .balign 4
         .4byte 992f-991f, 994f-993f, 3    // length, type
 991:
@@ -160,9 +143,9 @@ fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataType]>) -> Str
 992:
         .balign 4
 993:
-        .8byte 990b             // probe PC address
-        .8byte _.stapsdt.base   // link-time sh_addr of base .stapsdt.base section
-        .8byte {sema_name}      // probe semaphore address
+        {address} 990b             // probe PC address
+        {address} _.stapsdt.base   // link-time sh_addr of base .stapsdt.base section
+        {address} {sema_name}      // probe semaphore address
         .asciz "{prov}"         // provider name
         .asciz "{probe}"        // probe name
         .asciz "{arguments}"    // argument format (null-terminated string)
@@ -172,7 +155,7 @@ fn emit_probe_record(prov: &str, probe: &str, types: Option<&[DataType]>) -> Str
 // Finally define (if not defined yet) the base used to detect prelink
 // address adjustments.
 .ifndef _.stapsdt.base
-        .pushsection .stapsdt.base, "aGR", "progbits", .stapsdt.base, comdat
+        .pushsection {base_section}, "aGR", "progbits", {base_section}, comdat
         .weak _.stapsdt.base
         .hidden _.stapsdt.base
 _.stapsdt.base:
@@ -183,16 +166,159 @@ _.stapsdt.base:
         prov = prov,
         probe = probe.replace("__", "-"),
         arguments = arguments,
+        address = address_directive(arch),
+        note_section = config.stapsdt_note_section(),
+        base_section = config.stapsdt_base_section(),
+    )
+}
+
+/// Return the directive emitting an address into the note, as wide as the target's pointers.
+fn address_directive(arch: Arch) -> &'static str {
+    match arch.pointer_width() {
+        "32" => ".4byte",
+        _ => ".8byte",
+    }
+}
+
+/// Emit the semaphore of a probe, if it isn't defined yet.
+///
+/// This uses ifndef to make sure the same probe name can be used in multiple places but they all
+/// use the same semaphore. This can be used to eg. guard additional preparatory work far away from
+/// the actual probe site that will only be used by the probe.
+fn emit_semaphore(
+    prov: &str,
+    probe: &str,
+    arch: Arch,
+    config: &crate::CompileProvidersConfig,
+) -> String {
+    let sema_name = format!("__usdt_sema_{}_{}", prov, probe);
+    let section = config.stapsdt_semaphore_section();
+    // `@` starts a comment in Arm assembly, where symbol types are prefixed with `%` instead.
+    let object = match arch {
+        Arch::Arm => "%object",
+        _ => "@object",
+    };
+    format!(
+        r#".ifndef {sema_name}
+        .pushsection {section}, "aw", "progbits"
+        .weak {sema_name}
+        .hidden {sema_name}
+{sema_name}:
+        .zero 2
+        .type {sema_name}, {object}
+        .size {sema_name}, 2
+        .popsection
+.endif"#
     )
 }
 
+// Return an expression reading the probe's semaphore, the number of tracers which enabled it.
+fn semaphore(provider: &Provider, probe: &Probe) -> TokenStream {
+    let sema_name = format_ident!("__usdt_sema_{}_{}", provider.name, probe.name);
+    quote! {
+        {
+            unsafe extern "C" {
+                // Note: C libraries use a struct containing an unsigned short
+                // for the semaphore counter. Using just a u16 here directly
+                // offers the slightest risk that on some platforms the struct
+                // wrapping could be loadbearing but it is not to the best of
+                // knowledge.
+                static #sema_name: u16;
+            }
+            unsafe { (&raw const #sema_name).read_volatile() }
+        }
+    }
+}
+
+// Return an expression checking whether the probe is enabled, by reading its semaphore.
+fn is_enabled_check(provider: &Provider, probe: &Probe, arch: Arch) -> TokenStream {
+    common::unless_probes_disabled(or_dof_enabled(
+        provider,
+        probe,
+        arch,
+        is_nonzero(semaphore(provider, probe)),
+    ))
+}
+
+// Return whether probes get DTrace probe records too, for the `dtrace-linux` feature, on the
+// architectures DTrace on Linux supports. These must be those `build.rs` registers the DOF on.
+fn emits_dof(arch: Arch) -> bool {
+    cfg!(feature = "dtrace-linux") && matches!(arch, Arch::X86_64 | Arch::AArch64)
+}
+
+// Return an expression checking whether the probe is enabled by the given check, or, with its
+// DTrace probe record, by DTrace's is-enabled probe.
+fn or_dof_enabled(
+    provider: &Provider,
+    probe: &Probe,
+    arch: Arch,
+    check: TokenStream,
+) -> TokenStream {
+    if emits_dof(arch) {
+        let dof_check = crate::no_linker::is_enabled_check(provider, probe, Os::Linux, arch);
+        quote! { (#check || #dof_check) }
+    } else {
+        check
+    }
+}
+
+// Return an expression checking whether a semaphore read by the given expression is non-zero.
+fn is_nonzero(semaphore: TokenStream) -> TokenStream {
+    quote! {
+        {
+            let semaphore: u16 = #semaphore;
+            semaphore != 0
+        }
+    }
+}
+
+// Return an expression reading the probe's semaphore, for the probe registry. As the probe's macro
+// may never be called, this also defines the semaphore.
+fn registry_semaphore(
+    provider: &Provider,
+    probe: &Probe,
+    arch: Arch,
+    config: &crate::CompileProvidersConfig,
+) -> TokenStream {
+    let define = emit_semaphore(&provider.name, &probe.name, arch, config);
+    let semaphore = semaphore(provider, probe);
+    quote! {
+        {
+            #[allow(named_asm_labels)]
+            unsafe {
+                ::std::arch::asm!(#define, options(nomem, nostack, preserves_flags));
+            }
+            #semaphore
+        }
+    }
+}
+
 fn compile_probe(
     provider: &Provider,
     probe: &Probe,
     config: &crate::CompileProvidersConfig,
+    arch: Arch,
 ) -> TokenStream {
-    let (unpacked_args, in_regs) = common::construct_probe_args(&probe.types);
-    let probe_rec = emit_probe_record(&provider.name, &probe.name, Some(&probe.types));
+    let mut probe_rec = emit_probe_record(
+        &provider.name,
+        &probe.name,
+        &probe.stapsdt_arg_spec(arch),
+        arch,
+        config,
+    );
+    // The DTrace probe record refers to the same `nop` as the note.
+    if emits_dof(arch) {
+        probe_rec.push('\n');
+        probe_rec.push_str(&crate::record::emit_probe_record(
+            &provider.name,
+            &probe.name,
+            Some(&probe.argument_types()),
+            &probe.translated_argument_types(arch),
+            &provider.attributes,
+            Os::Linux,
+            arch,
+        ));
+    }
     let type_check_fn = common::construct_type_check(
         &provider.name,
         &probe.name,
@@ -200,39 +326,43 @@ fn compile_probe(
         &probe.types,
     );
 
-    let sema_name = format_ident!("__usdt_sema_{}_{}", provider.name, probe.name);
-    let impl_block = quote! {
-        unsafe extern "C" {
-            // Note: C libraries use a struct containing an unsigned short
-            // for the semaphore counter. Using just a u16 here directly
-            // offers the slightest risk that on some platforms the struct
-            // wrapping could be loadbearing but it is not to the best of
-            // knowledge.
-            static #sema_name: u16;
-        }
-
-        let is_enabled: u16;
-        unsafe {
-            is_enabled = (&raw const #sema_name).read_volatile();
-        }
+    let (unpacked_args, fire_probe) =
+        common::construct_probe_firing(&probe.types, arch, config, |in_regs, options| {
+            quote! {
+                #[allow(named_asm_labels)]
+                unsafe {
+                    ::std::arch::asm!(
+                        "990:   nop",
+                        #probe_rec,
+                        #in_regs
+                        #options
+                    );
+                }
+            }
+        });
 
-        if is_enabled != 0 {
+    let is_enabled = is_enabled_check(provider, probe, arch);
+    let impl_block = quote! {
+        if #is_enabled {
             #unpacked_args
             #type_check_fn
-            #[allow(named_asm_labels)]
-            unsafe {
-                ::std::arch::asm!(
-                    "990:   nop",
-                    #probe_rec,
-                    #in_regs
-                    options(nomem, nostack, preserves_flags)
-                );
-            }
+            #fire_probe
         }
     };
-    common::build_probe_macro(config, &probe.name, &probe.types, impl_block)
+    common::build_probe_macro(config, probe, is_enabled, impl_block)
 }
 
+#[cfg(all(usdt_backend_stapsdt, not(usdt_dof)))]
 pub fn register_probes() -> Result<(), crate::Error> {
     Ok(())
 }
+
+#[cfg(all(usdt_backend_stapsdt, not(usdt_dof)))]
+pub fn register_providers(names: &[&str]) -> Result<(), crate::Error> {
+    // The notes of every provider are static, so there is nothing to register.
+    crate::registry::check_providers(names)
+}
+
+// The notes need no registering, but the DOF of the DTrace probe records does.
+#[cfg(all(usdt_backend_stapsdt, usdt_dof))]
+pub use crate::no_linker::{register_probes, register_providers};
@@ -14,6 +14,7 @@
 
 //! Helpers for generating GNU Assembler format for use in STAPSDT probes.
 
+use crate::target::Arch;
 use crate::DataType;
 use dtrace_parser::{BitWidth, DataType as NativeDataType, Integer, Sign};
 
@@ -21,15 +22,15 @@ use dtrace_parser::{BitWidth, DataType as NativeDataType, Integer, Sign};
 /// that reads the integer's value from the correct register. Effectively this
 /// means generating a string like `%REG` where `REG` is the register that the
 /// data is located in.
-fn integer_to_asm_op(integer: &Integer, reg_index: u8) -> &'static str {
+fn integer_to_asm_op(integer: &Integer, reg_index: u8, arch: Arch) -> &'static str {
     // See common.rs for note on argument passing and maximum supported
     // argument count.
     assert!(
         reg_index <= 5,
         "Up to 6 probe arguments are currently supported"
     );
-    if cfg!(target_arch = "x86_64") {
-        match (integer.width, reg_index) {
+    match arch {
+        Arch::X86_64 => match (integer.width, reg_index) {
             (BitWidth::Bit8, 0) => "%dil",
             (BitWidth::Bit16, 0) => "%di",
             (BitWidth::Bit32, 0) => "%edi",
@@ -54,38 +55,17 @@ fn integer_to_asm_op(integer: &Integer, reg_index: u8) -> &'static str {
             (BitWidth::Bit16, 5) => "%r9w",
             (BitWidth::Bit32, 5) => "%r9d",
             (BitWidth::Bit64, 5) => "%r9",
-            #[cfg(target_pointer_width = "32")]
-            (BitWidth::Pointer, 0) => "%edi",
-            #[cfg(target_pointer_width = "64")]
             (BitWidth::Pointer, 0) => "%rdi",
-            #[cfg(target_pointer_width = "32")]
-            (BitWidth::Pointer, 1) => "%esi",
-            #[cfg(target_pointer_width = "64")]
             (BitWidth::Pointer, 1) => "%rsi",
-            #[cfg(target_pointer_width = "32")]
-            (BitWidth::Pointer, 2) => "%edx",
-            #[cfg(target_pointer_width = "64")]
             (BitWidth::Pointer, 2) => "%rdx",
-            #[cfg(target_pointer_width = "32")]
-            (BitWidth::Pointer, 3) => "%ecx",
-            #[cfg(target_pointer_width = "64")]
             (BitWidth::Pointer, 3) => "%rcx",
-            #[cfg(target_pointer_width = "32")]
-            (BitWidth::Pointer, 4) => "%e8",
-            #[cfg(target_pointer_width = "64")]
             (BitWidth::Pointer, 4) => "%r8",
-            #[cfg(target_pointer_width = "32")]
-            (BitWidth::Pointer, 5) => "%e9",
-            #[cfg(target_pointer_width = "64")]
             (BitWidth::Pointer, 5) => "%r9",
-            #[cfg(not(any(target_pointer_width = "32", target_pointer_width = "64")))]
-            (BitWidth::Pointer, _) => compile_error!("Unsupported pointer width"),
             _ => unreachable!(),
-        }
-    } else if cfg!(target_arch = "aarch64") {
+        },
         // GNU Assembly syntax for SystemTap only uses the extended register
         // for some reason.
-        match reg_index {
+        Arch::AArch64 => match reg_index {
             0 => "x0",
             1 => "x1",
             2 => "x2",
@@ -93,15 +73,66 @@ fn integer_to_asm_op(integer: &Integer, reg_index: u8) -> &'static str {
             4 => "x4",
             5 => "x5",
             _ => unreachable!(),
-        }
-    } else {
-        unreachable!("Unsupported Linux target architecture")
+        },
+        // Likewise, RISC-V uses the full argument register regardless of the
+        // integer's width.
+        Arch::RiscV64 => match reg_index {
+            0 => "a0",
+            1 => "a1",
+            2 => "a2",
+            3 => "a3",
+            4 => "a4",
+            5 => "a5",
+            _ => unreachable!(),
+        },
+        // 32-bit Arm has no sub-registers, and the probe's arguments are all passed in registers,
+        // see `abi_registers`.
+        Arch::Arm => match reg_index {
+            0 => "r0",
+            1 => "r1",
+            2 => "r2",
+            3 => "r3",
+            4 => "r4",
+            5 => "r5",
+            _ => unreachable!(),
+        },
+    }
+}
+
+/// Convert a GNU Assembler register operand into an operand reading the
+/// memory it points to.
+fn deref_asm_op(op: &str, arch: Arch) -> String {
+    match arch {
+        Arch::X86_64 => format!("({})", op),
+        // AArch64 memory operands are bracketed, as in `8@[x0]`.
+        Arch::AArch64 => format!("[{}]", op),
+        // RISC-V memory operands always carry an offset.
+        Arch::RiscV64 => format!("0({})", op),
+        Arch::Arm => format!("[{}]", op),
+    }
+}
+
+/// Return `true` if a value of this type is too wide for a register of the architecture, and is
+/// instead spilled to the stack, with its address passed in the register.
+///
+/// This is the case for 64-bit values on 32-bit Arm, which the tracer reads from memory.
+pub(crate) fn is_spilled(typ: &DataType, arch: Arch) -> bool {
+    if arch != Arch::Arm {
+        return false;
+    }
+    match typ {
+        DataType::Native(NativeDataType::Integer(int))
+        | DataType::NativeArray(int, _)
+        | DataType::Discriminant(_, int)
+        | DataType::NonZero(int) => int.width == BitWidth::Bit64,
+        DataType::UniqueId | DataType::Timestamp(..) | DataType::Duration | DataType::Uuid => true,
+        _ => false,
     }
 }
 
 /// Convert an Integer type into its STAPSDT probe arguments definition
 /// signedness and size value as a String.
-fn integer_to_arg_size(integer: &Integer) -> &'static str {
+fn integer_to_arg_size(integer: &Integer, arch: Arch) -> &'static str {
     match integer.width {
         BitWidth::Bit8 => match integer.sign {
             Sign::Unsigned => "1",
@@ -119,12 +150,14 @@ fn integer_to_arg_size(integer: &Integer) -> &'static str {
             Sign::Unsigned => "8",
             _ => "-8",
         },
-        #[cfg(target_pointer_width = "32")]
-        BitWidth::Pointer => "4",
-        #[cfg(target_pointer_width = "64")]
-        BitWidth::Pointer => "8",
-        #[cfg(not(any(target_pointer_width = "32", target_pointer_width = "64")))]
-        BitWidth::Pointer => compile_error!("Unsupported pointer width"),
+        // This is the pointer width of the target, not the host, which is 64 bits on all
+        // supported architectures but 32-bit Arm.
+        BitWidth::Pointer => match (integer.sign, arch.pointer_width()) {
+            (Sign::Unsigned, "32") => "4",
+            (_, "32") => "-4",
+            (Sign::Unsigned, _) => "8",
+            _ => "-8",
+        },
     }
 }
 
@@ -138,23 +171,32 @@ const UNIQUE_ID: Integer = Integer {
     width: BitWidth::Bit64,
 };
 
+const UUID_HALF: Integer = Integer {
+    sign: Sign::Unsigned,
+    width: BitWidth::Bit64,
+};
+
 /// Convert a type and register index to its GNU Assembler operation as a
 /// String.
-fn native_data_type_to_asm_op(typ: &NativeDataType, reg_index: u8) -> String {
+fn native_data_type_to_asm_op(typ: &NativeDataType, reg_index: u8, arch: Arch) -> String {
     match typ {
-        NativeDataType::Integer(int) => integer_to_asm_op(int, reg_index).into(),
+        NativeDataType::Integer(int) => integer_to_asm_op(int, reg_index, arch).into(),
         // Integer pointers are dereferenced by wrapping the pointer assembly
         // into parentheses.
-        NativeDataType::Pointer(_) => format!("({})", integer_to_asm_op(&POINTER, reg_index)),
-        NativeDataType::String => integer_to_asm_op(&POINTER, reg_index).into(),
+        NativeDataType::Pointer(_) => {
+            deref_asm_op(integer_to_asm_op(&POINTER, reg_index, arch), arch)
+        }
+        NativeDataType::String => integer_to_asm_op(&POINTER, reg_index, arch).into(),
     }
 }
 
 /// Convert a type to its GNU Assembler size representation as a string.
-fn native_data_type_to_arg_size(typ: &NativeDataType) -> &'static str {
+fn native_data_type_to_arg_size(typ: &NativeDataType, arch: Arch) -> &'static str {
     match typ {
-        NativeDataType::Integer(int) => integer_to_arg_size(int),
-        NativeDataType::Pointer(_) | NativeDataType::String => integer_to_arg_size(&POINTER),
+        NativeDataType::Integer(int) => integer_to_arg_size(int, arch),
+        // Integer pointers are dereferenced, so the argument is the integer they point to.
+        NativeDataType::Pointer(int) => integer_to_arg_size(int, arch),
+        NativeDataType::String => integer_to_arg_size(&POINTER, arch),
         // Note: If NativeDataType::Float becomes supported, it will need an
         // "f" suffix in the type, eg. `4f` or `8f`.
     }
@@ -162,21 +204,64 @@ fn native_data_type_to_arg_size(typ: &NativeDataType) -> &'static str {
 
 /// Convert a DataType and register index to its GNU Assembler operation as a
 /// String.
-fn data_type_to_asm_op(typ: &DataType, reg_index: u8) -> String {
+pub(crate) fn data_type_to_asm_op(typ: &DataType, reg_index: u8, arch: Arch) -> String {
+    if is_spilled(typ, arch) {
+        return deref_asm_op(integer_to_asm_op(&POINTER, reg_index, arch), arch);
+    }
     match typ {
-        DataType::Native(ty) => native_data_type_to_asm_op(ty, reg_index),
-        DataType::UniqueId => integer_to_asm_op(&UNIQUE_ID, reg_index).into(),
-        DataType::Serializable(_) => integer_to_asm_op(&POINTER, reg_index).into(),
+        DataType::Native(ty) => native_data_type_to_asm_op(ty, reg_index, arch),
+        DataType::NativeArray(int, _) | DataType::Discriminant(_, int) | DataType::NonZero(int) => {
+            integer_to_asm_op(int, reg_index, arch).into()
+        }
+        DataType::UniqueId | DataType::Duration => {
+            integer_to_asm_op(&UNIQUE_ID, reg_index, arch).into()
+        }
+        DataType::Timestamp(_, sign) => integer_to_asm_op(
+            &Integer {
+                sign: *sign,
+                width: BitWidth::Bit64,
+            },
+            reg_index,
+            arch,
+        )
+        .into(),
+        DataType::Uuid => integer_to_asm_op(&UUID_HALF, reg_index, arch).into(),
+        DataType::Address(_)
+        | DataType::Protobuf(_)
+        | DataType::NativeSlice(_)
+        | DataType::Path
+        | DataType::Display
+        | DataType::VariantName(_)
+        | DataType::Serializable(_)
+        | DataType::SizedSerializable(_) => integer_to_asm_op(&POINTER, reg_index, arch).into(),
     }
 }
 
 /// Convert a DataType to its STAPSDT probe argument size representation as a
 /// String.
-fn data_type_to_arg_size(typ: &DataType) -> &'static str {
+pub(crate) fn data_type_to_arg_size(typ: &DataType, arch: Arch) -> &'static str {
     match typ {
-        DataType::Native(ty) => native_data_type_to_arg_size(ty),
-        DataType::UniqueId => integer_to_arg_size(&UNIQUE_ID),
-        DataType::Serializable(_) => integer_to_arg_size(&POINTER),
+        DataType::Native(ty) => native_data_type_to_arg_size(ty, arch),
+        DataType::NativeArray(int, _) | DataType::Discriminant(_, int) | DataType::NonZero(int) => {
+            integer_to_arg_size(int, arch)
+        }
+        DataType::UniqueId | DataType::Duration => integer_to_arg_size(&UNIQUE_ID, arch),
+        DataType::Timestamp(_, sign) => integer_to_arg_size(
+            &Integer {
+                sign: *sign,
+                width: BitWidth::Bit64,
+            },
+            arch,
+        ),
+        DataType::Uuid => integer_to_arg_size(&UUID_HALF, arch),
+        DataType::Address(_)
+        | DataType::Protobuf(_)
+        | DataType::NativeSlice(_)
+        | DataType::Path
+        | DataType::Display
+        | DataType::VariantName(_)
+        | DataType::Serializable(_)
+        | DataType::SizedSerializable(_) => integer_to_arg_size(&POINTER, arch),
     }
 }
 
@@ -199,11 +284,144 @@ fn data_type_to_arg_size(typ: &DataType) -> &'static str {
 /// 2. Read an i32 through a pointer in RSI: `-4@(%rsi)`.
 /// 3. Read an f64 through a pointer in RDI: `8f@(%rdi)`.
 ///    (Not sure if `-` should be added.)
-/// 4. Read a u64 through a pointer with an offset: `8%-4(%rdi)`.
-pub(crate) fn format_argument((reg_index, typ): (usize, &DataType)) -> String {
+/// 4. Read a u64 through a pointer with an offset: `8@-4(%rdi)`.
+pub(crate) fn format_argument(arch: Arch, reg_index: usize, typ: &DataType) -> String {
     format!(
         "{}@{}",
-        data_type_to_arg_size(typ),
-        data_type_to_asm_op(typ, u8::try_from(reg_index).unwrap())
+        data_type_to_arg_size(typ, arch),
+        data_type_to_asm_op(typ, u8::try_from(reg_index).unwrap(), arch)
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn integer(sign: Sign, width: BitWidth) -> Integer {
+        Integer { sign, width }
+    }
+
+    #[test]
+    fn test_format_integer_argument() {
+        let typ = DataType::Native(NativeDataType::Integer(integer(
+            Sign::Signed,
+            BitWidth::Bit16,
+        )));
+        assert_eq!(format_argument(Arch::X86_64, 2, &typ), "-2@%dx");
+        assert_eq!(format_argument(Arch::AArch64, 2, &typ), "-2@x2");
+        assert_eq!(format_argument(Arch::RiscV64, 2, &typ), "-2@a2");
+        assert_eq!(format_argument(Arch::Arm, 2, &typ), "-2@r2");
+    }
+
+    // Pointers are 32 bits on Arm, and 64-bit values are read from the stack, through the address
+    // in their register.
+    #[test]
+    fn test_format_arm_argument() {
+        let string = DataType::Native(NativeDataType::String);
+        assert_eq!(format_argument(Arch::Arm, 0, &string), "4@r0");
+        let int = DataType::Native(NativeDataType::Integer(integer(
+            Sign::Signed,
+            BitWidth::Bit64,
+        )));
+        assert_eq!(format_argument(Arch::Arm, 4, &int), "-8@[r4]");
+        assert_eq!(format_argument(Arch::Arm, 5, &DataType::UniqueId), "8@[r5]");
+        let ptr = DataType::Native(NativeDataType::Pointer(integer(
+            Sign::Unsigned,
+            BitWidth::Bit64,
+        )));
+        assert_eq!(format_argument(Arch::Arm, 1, &ptr), "8@[r1]");
+        assert!(!is_spilled(&ptr, Arch::Arm));
+        assert!(!is_spilled(&int, Arch::X86_64));
+    }
+
+    // Signed integers carry a leading `-`, so that consumers sign-extend negative values.
+    #[test]
+    fn test_integer_argument_sign() {
+        for (width, size) in [
+            (BitWidth::Bit8, "1"),
+            (BitWidth::Bit16, "2"),
+            (BitWidth::Bit32, "4"),
+            (BitWidth::Bit64, "8"),
+            (BitWidth::Pointer, "8"),
+        ] {
+            let unsigned =
+                DataType::Native(NativeDataType::Integer(integer(Sign::Unsigned, width)));
+            let signed = DataType::Native(NativeDataType::Integer(integer(Sign::Signed, width)));
+            assert_eq!(data_type_to_arg_size(&unsigned, Arch::X86_64), size);
+            assert_eq!(
+                data_type_to_arg_size(&signed, Arch::X86_64),
+                format!("-{}", size)
+            );
+            let signed_ptr =
+                DataType::Native(NativeDataType::Pointer(integer(Sign::Signed, width)));
+            assert_eq!(
+                data_type_to_arg_size(&signed_ptr, Arch::X86_64),
+                format!("-{}", size)
+            );
+        }
+        let typ = DataType::Native(NativeDataType::Integer(integer(
+            Sign::Signed,
+            BitWidth::Bit8,
+        )));
+        assert_eq!(format_argument(Arch::X86_64, 0, &typ), "-1@%dil");
+    }
+
+    // Each fixed-width integer is read from the sub-register of its width, in each of the
+    // registers arguments are passed in.
+    #[test]
+    fn test_x86_64_integer_registers() {
+        let registers = [
+            ["%dil", "%di", "%edi", "%rdi"],
+            ["%sil", "%si", "%esi", "%rsi"],
+            ["%dl", "%dx", "%edx", "%rdx"],
+            ["%cl", "%cx", "%ecx", "%rcx"],
+            ["%r8b", "%r8w", "%r8d", "%r8"],
+            ["%r9b", "%r9w", "%r9d", "%r9"],
+        ];
+        let widths = [
+            (BitWidth::Bit8, "1"),
+            (BitWidth::Bit16, "2"),
+            (BitWidth::Bit32, "4"),
+            (BitWidth::Bit64, "8"),
+        ];
+        for (reg_index, ops) in registers.iter().enumerate() {
+            for ((width, size), op) in widths.iter().zip(ops) {
+                let unsigned =
+                    DataType::Native(NativeDataType::Integer(integer(Sign::Unsigned, *width)));
+                let signed =
+                    DataType::Native(NativeDataType::Integer(integer(Sign::Signed, *width)));
+                assert_eq!(
+                    format_argument(Arch::X86_64, reg_index, &unsigned),
+                    format!("{}@{}", size, op)
+                );
+                assert_eq!(
+                    format_argument(Arch::X86_64, reg_index, &signed),
+                    format!("-{}@{}", size, op)
+                );
+            }
+        }
+    }
+
+    // Pointers have the width of the target's, whatever the host building the probes.
+    #[test]
+    fn test_format_string_argument() {
+        let typ = DataType::Native(NativeDataType::String);
+        assert_eq!(format_argument(Arch::X86_64, 4, &typ), "8@%r8");
+        assert_eq!(format_argument(Arch::AArch64, 4, &typ), "8@x4");
+        assert_eq!(format_argument(Arch::RiscV64, 4, &typ), "8@a4");
+        assert_eq!(format_argument(Arch::Arm, 4, &typ), "4@r4");
+    }
+
+    // A dereferenced pointer is read with the size of the integer it points to, using each
+    // architecture's syntax for memory operands.
+    #[test]
+    fn test_format_pointer_argument() {
+        let typ = DataType::Native(NativeDataType::Pointer(integer(
+            Sign::Signed,
+            BitWidth::Bit32,
+        )));
+        assert_eq!(format_argument(Arch::X86_64, 1, &typ), "-4@(%rsi)");
+        assert_eq!(format_argument(Arch::AArch64, 1, &typ), "-4@[x1]");
+        assert_eq!(format_argument(Arch::RiscV64, 1, &typ), "-4@0(a1)");
+    }
+}
@@ -0,0 +1,577 @@
+//! Selection of the probe implementation for the compilation target.
+//!
+//! Probe code is generated by procedural macros or build scripts, both of which run on the build
+//! host, not on the target the probes are compiled for. When cross-compiling, say from macOS to
+//! Linux, the host's configuration says nothing about which backend or instruction set the
+//! generated code must use. The choice is therefore made for the _target_.
+//!
+//! Build scripts are told about the target through the `CARGO_CFG_TARGET_*` environment
+//! variables, and in that case code is generated for exactly that target. Procedural macros have
+//! no such information. There, the code for every supported target is generated, with each
+//! variant gated on the `cfg` describing its target, so that the compiler retains only the one
+//! matching the actual target.
+//!
+//! Targets the generated assembly can't be built for, such as an architecture without a backend,
+//! or the 32-bit pointers of the x32 ABI on a 64-bit architecture, are rejected with a
+//! `compile_error!` naming the target, rather than leaving the assembler to fail on the generated
+//! code. Such targets may still build the probes as no-ops with the `disabled` feature.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{empty, etw, linker, no_linker, stapsdt, CompileProvidersConfig, Provider};
+use proc_macro2::TokenStream;
+use quote::quote;
+use std::env;
+
+/// An operating system with a probe backend.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Os {
+    Linux,
+    MacOS,
+    Illumos,
+    Solaris,
+    FreeBsd,
+    Windows,
+}
+
+impl Os {
+    const ALL: [Os; 6] = [
+        Os::Linux,
+        Os::MacOS,
+        Os::Illumos,
+        Os::Solaris,
+        Os::FreeBsd,
+        Os::Windows,
+    ];
+
+    fn from_cfg(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|os| os.cfg_name() == name)
+    }
+
+    /// Return the value of `target_os` for this OS.
+    pub(crate) fn cfg_name(&self) -> &'static str {
+        match self {
+            Os::Linux => "linux",
+            Os::MacOS => "macos",
+            Os::Illumos => "illumos",
+            Os::Solaris => "solaris",
+            Os::FreeBsd => "freebsd",
+            Os::Windows => "windows",
+        }
+    }
+
+    /// Return the architectures supported by the backend of this OS.
+    fn arches(&self) -> &'static [Arch] {
+        match self {
+            Os::Linux => &[Arch::X86_64, Arch::AArch64, Arch::RiscV64, Arch::Arm],
+            _ => &[Arch::X86_64, Arch::AArch64],
+        }
+    }
+}
+
+/// An instruction set for which probes can be generated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Arch {
+    X86_64,
+    AArch64,
+    /// 64-bit RISC-V, which is only supported on Linux.
+    RiscV64,
+    /// 32-bit Arm, such as armv7, which is only supported on Linux.
+    Arm,
+}
+
+impl Arch {
+    const ALL: [Arch; 4] = [Arch::X86_64, Arch::AArch64, Arch::RiscV64, Arch::Arm];
+
+    fn from_cfg(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|arch| arch.cfg_name() == name)
+    }
+
+    /// Return the architecture this code is running on, if probes support it.
+    pub fn host() -> Option<Self> {
+        Self::from_cfg(std::env::consts::ARCH)
+    }
+
+    /// Return the architecture probes are compiled for, if probes support it. This is the target
+    /// of a build script, or otherwise the host.
+    pub(crate) fn target() -> Option<Self> {
+        match env::var("CARGO_CFG_TARGET_ARCH") {
+            Ok(arch) => Self::from_cfg(&arch),
+            Err(_) => Self::host(),
+        }
+    }
+
+    /// Return the value of `target_arch` for this architecture.
+    pub(crate) fn cfg_name(&self) -> &'static str {
+        match self {
+            Arch::X86_64 => "x86_64",
+            Arch::AArch64 => "aarch64",
+            Arch::RiscV64 => "riscv64",
+            Arch::Arm => "arm",
+        }
+    }
+
+    /// Return the value of `target_pointer_width` for this architecture.
+    pub(crate) fn pointer_width(&self) -> &'static str {
+        match self {
+            Arch::Arm => "32",
+            _ => "64",
+        }
+    }
+}
+
+/// A compilation target, as far as the generated probes are concerned.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Target {
+    /// An OS with a probe backend, on a supported architecture.
+    Supported(Os, Arch),
+    /// An OS with a probe backend, on an architecture the backend doesn't support.
+    UnsupportedArch(Os),
+    /// A supported OS and architecture, with pointers of another width than the architecture's
+    /// own, such as the x32 ABI.
+    UnsupportedPointerWidth(Os, Arch),
+    /// Any other OS, on which probes are no-ops.
+    NoOp,
+}
+
+impl Target {
+    /// Construct a target from the values of `target_os`, `target_arch` and
+    /// `target_pointer_width`.
+    fn from_cfg(os: &str, arch: &str, pointer_width: &str) -> Self {
+        match (Os::from_cfg(os), Arch::from_cfg(arch)) {
+            (Some(os), Some(arch)) if os.arches().contains(&arch) => {
+                if pointer_width == arch.pointer_width() {
+                    Target::Supported(os, arch)
+                } else {
+                    Target::UnsupportedPointerWidth(os, arch)
+                }
+            }
+            (Some(os), _) => Target::UnsupportedArch(os),
+            (None, _) => Target::NoOp,
+        }
+    }
+
+    /// Return the target described by the environment of a build script, if any.
+    fn from_env() -> Option<Self> {
+        let os = env::var("CARGO_CFG_TARGET_OS").ok()?;
+        let arch = env::var("CARGO_CFG_TARGET_ARCH").ok()?;
+        let pointer_width = env::var("CARGO_CFG_TARGET_POINTER_WIDTH").ok()?;
+        Some(Self::from_cfg(&os, &arch, &pointer_width))
+    }
+
+    /// Return every target, along with the `cfg` predicate that selects it.
+    ///
+    /// The predicates are mutually exclusive, and together cover all targets.
+    fn all() -> Vec<(TokenStream, Self)> {
+        let mut targets = Vec::new();
+        for os in Os::ALL {
+            let os_name = os.cfg_name();
+            let arch_names = os.arches().iter().map(|arch| arch.cfg_name());
+            for &arch in os.arches() {
+                let arch_name = arch.cfg_name();
+                let width = arch.pointer_width();
+                targets.push((
+                    quote! {
+                        all(target_os = #os_name, target_arch = #arch_name, target_pointer_width = #width)
+                    },
+                    Target::Supported(os, arch),
+                ));
+                targets.push((
+                    quote! {
+                        all(
+                            target_os = #os_name,
+                            target_arch = #arch_name,
+                            not(target_pointer_width = #width)
+                        )
+                    },
+                    Target::UnsupportedPointerWidth(os, arch),
+                ));
+            }
+            targets.push((
+                quote! { all(target_os = #os_name, not(any(#(target_arch = #arch_names),*))) },
+                Target::UnsupportedArch(os),
+            ));
+        }
+        let os_names = Os::ALL.map(|os| os.cfg_name());
+        targets.push((
+            quote! { not(any(#(target_os = #os_names),*)) },
+            Target::NoOp,
+        ));
+        targets
+    }
+
+    /// Compile each provider for this target, returning one item per provider.
+    fn compile_providers(
+        &self,
+        source: &str,
+        providers: &[(Provider, CompileProvidersConfig)],
+    ) -> Result<Vec<TokenStream>, crate::Error> {
+        match *self {
+            Target::Supported(Os::Linux, arch) => Ok(providers
+                .iter()
+                .map(|(provider, config)| stapsdt::compile_provider(provider, config, arch))
+                .collect()),
+            Target::Supported(Os::MacOS, arch) => {
+                // The linker backend relies on the platform `dtrace(1)` to generate the probes,
+                // which is only available when building on macOS itself.
+                if cfg!(target_os = "macos") {
+                    linker::compile_providers(source, providers, arch)
+                } else {
+                    Ok(vec![quote! {
+                        compile_error!("USDT probes for macOS targets can only be built on macOS");
+                    }])
+                }
+            }
+            // ETW events don't depend on the architecture, though their fields assume 64-bit
+            // pointers.
+            Target::Supported(Os::Windows, _) => Ok(providers
+                .iter()
+                .map(|(provider, config)| etw::compile_provider(provider, config))
+                .collect()),
+            Target::Supported(os, arch) => Ok(providers
+                .iter()
+                .map(|(provider, config)| no_linker::compile_provider(provider, config, os, arch))
+                .collect()),
+            Target::UnsupportedArch(os) => {
+                let arch_names = os
+                    .arches()
+                    .iter()
+                    .map(|arch| arch.cfg_name())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let msg = format!(
+                    "USDT only supports the {} architectures on {}, \
+                     enable the `disabled` feature of usdt to compile the probes to nothing",
+                    arch_names,
+                    os.cfg_name()
+                );
+                Ok(vec![quote! { compile_error!(#msg); }])
+            }
+            Target::UnsupportedPointerWidth(os, arch) => {
+                let msg = format!(
+                    "USDT only supports {}-bit pointers on {} {}, \
+                     enable the `disabled` feature of usdt to compile the probes to nothing",
+                    arch.pointer_width(),
+                    os.cfg_name(),
+                    arch.cfg_name()
+                );
+                Ok(vec![quote! { compile_error!(#msg); }])
+            }
+            Target::NoOp => Ok(providers
+                .iter()
+                .map(|(provider, config)| empty::compile_provider(provider, config))
+                .collect()),
+        }
+    }
+}
+
+/// Compile the given providers into Rust code for the compilation target.
+///
+/// The `source` is the D source of all the providers, which some backends hand to the platform's
+/// DTrace tooling. With the `disabled` feature, the probes are disabled on every target.
+///
+/// Any automatic arguments in a provider's config are appended to its probes' arguments here, and
+/// serializable arguments are passed with the length of their JSON if `json_len` is set, so that
+/// every backend, and the D source, describe the probes as they're fired. The D source is also
+/// regenerated for providers whose probes were renamed with a `prefix`.
+pub(crate) fn compile_providers(
+    source: &str,
+    providers: &[(Provider, CompileProvidersConfig)],
+) -> Result<TokenStream, crate::Error> {
+    compile_providers_for(source, providers, Target::from_env())
+}
+
+/// Compile the given providers into Rust code for every target, each variant gated on its `cfg`,
+/// even in a build script, so that the code may be built for any target.
+#[cfg(feature = "generate")]
+pub(crate) fn compile_portable_providers(
+    source: &str,
+    providers: &[(Provider, CompileProvidersConfig)],
+) -> Result<TokenStream, crate::Error> {
+    compile_providers_for(source, providers, None)
+}
+
+// Compile the given providers for a single target, or for all of them if it's `None`.
+fn compile_providers_for(
+    source: &str,
+    providers: &[(Provider, CompileProvidersConfig)],
+    target: Option<Target>,
+) -> Result<TokenStream, crate::Error> {
+    let providers = providers
+        .iter()
+        .map(|(provider, config)| {
+            let provider = provider
+                .with_auto_args(&config.auto_args)
+                .with_json_len(config.json_len);
+            (provider, config.clone())
+        })
+        .collect::<Vec<_>>();
+    let providers = providers.as_slice();
+    let regenerated;
+    let source = if providers.iter().any(|(_, config)| {
+        !config.auto_args.is_empty() || config.json_len || config.prefix.is_some()
+    }) {
+        regenerated = providers
+            .iter()
+            .map(|(provider, _)| provider.to_d_source())
+            .collect::<Vec<_>>()
+            .join("\n");
+        regenerated.as_str()
+    } else {
+        source
+    };
+    if cfg!(feature = "disabled") {
+        let items = providers
+            .iter()
+            .map(|(provider, config)| empty::compile_disabled_provider(provider, config));
+        return Ok(quote! { #(#items)* });
+    }
+    match target {
+        Some(target) => {
+            let items = target.compile_providers(source, providers)?;
+            Ok(quote! { #(#items)* })
+        }
+        None => compile_providers_for_all_targets(source, providers),
+    }
+}
+
+fn compile_providers_for_all_targets(
+    source: &str,
+    providers: &[(Provider, CompileProvidersConfig)],
+) -> Result<TokenStream, crate::Error> {
+    let mut out = TokenStream::new();
+    for (cfg, target) in Target::all() {
+        for item in target.compile_providers(source, providers)? {
+            out.extend(quote! {
+                #[cfg(#cfg)]
+                #item
+            });
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DataType, Probe};
+    use dtrace_parser::{BitWidth, DataType as DType, Integer, Sign};
+
+    fn test_providers() -> Vec<(Provider, CompileProvidersConfig)> {
+        let provider = Provider {
+            name: String::from("prov"),
+            probes: vec![Probe {
+                name: String::from("probe"),
+                types: vec![DataType::Native(DType::Integer(Integer {
+                    sign: Sign::Unsigned,
+                    width: BitWidth::Bit8,
+                }))],
+                attrs: vec![],
+                arg_names: vec![],
+                d_types: vec![],
+                defaults: vec![],
+                span: proc_macro2::Span::call_site(),
+            }],
+            use_statements: vec![],
+            attributes: Default::default(),
+        };
+        let config = CompileProvidersConfig {
+            provider: Some(String::from("prov")),
+            module: Some(String::from("prov")),
+            ..Default::default()
+        };
+        vec![(provider, config)]
+    }
+
+    #[test]
+    fn test_target_from_cfg() {
+        assert_eq!(
+            Target::from_cfg("linux", "aarch64", "64"),
+            Target::Supported(Os::Linux, Arch::AArch64)
+        );
+        assert_eq!(
+            Target::from_cfg("illumos", "x86_64", "64"),
+            Target::Supported(Os::Illumos, Arch::X86_64)
+        );
+        assert_eq!(
+            Target::from_cfg("freebsd", "powerpc64", "64"),
+            Target::UnsupportedArch(Os::FreeBsd)
+        );
+        assert_eq!(
+            Target::from_cfg("linux", "riscv64", "64"),
+            Target::Supported(Os::Linux, Arch::RiscV64)
+        );
+        assert_eq!(
+            Target::from_cfg("illumos", "riscv64", "64"),
+            Target::UnsupportedArch(Os::Illumos)
+        );
+        assert_eq!(
+            Target::from_cfg("windows", "x86_64", "64"),
+            Target::Supported(Os::Windows, Arch::X86_64)
+        );
+        assert_eq!(Target::from_cfg("haiku", "x86_64", "64"), Target::NoOp);
+        assert_eq!(
+            Target::from_cfg("linux", "x86_64", "32"),
+            Target::UnsupportedPointerWidth(Os::Linux, Arch::X86_64)
+        );
+        assert_eq!(
+            Target::from_cfg("linux", "arm", "32"),
+            Target::Supported(Os::Linux, Arch::Arm)
+        );
+        assert_eq!(
+            Target::from_cfg("freebsd", "arm", "32"),
+            Target::UnsupportedArch(Os::FreeBsd)
+        );
+    }
+
+    #[test]
+    fn test_all_targets_are_distinct() {
+        let targets = Target::all();
+        for (i, (_, target)) in targets.iter().enumerate() {
+            assert!(!targets[i + 1..].iter().any(|(_, other)| other == target));
+        }
+        assert!(targets.iter().any(|(_, target)| *target == Target::NoOp));
+    }
+
+    // Cross-compiling must select the section names of the target backend, regardless of the
+    // host doing the compiling.
+    #[test]
+    fn test_linux_target_emits_stapsdt_notes() {
+        let target = Target::Supported(Os::Linux, Arch::X86_64);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        let output = items[0].to_string();
+        assert!(output.contains(".note.stapsdt"));
+        assert_eq!(
+            output.contains("set_dtrace_probes"),
+            cfg!(feature = "dtrace-linux")
+        );
+    }
+
+    // With `dtrace-linux`, probes have DTrace probe records next to their notes, only on the
+    // architectures DTrace on Linux supports.
+    #[cfg(feature = "dtrace-linux")]
+    #[test]
+    fn test_linux_target_emits_dtrace_probe_records() {
+        let target = Target::Supported(Os::Linux, Arch::AArch64);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        let output = items[0].to_string();
+        assert!(output.contains(".note.stapsdt"));
+        assert!(output.contains("set_dtrace_probes"));
+        assert!(output.contains("mov x0, #0"));
+        let target = Target::Supported(Os::Linux, Arch::RiscV64);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        assert!(!items[0].to_string().contains("set_dtrace_probes"));
+    }
+
+    // 32-bit Arm records 32-bit addresses in the notes, and types symbols with `%`.
+    #[test]
+    fn test_linux_arm_target_emits_32_bit_notes() {
+        let target = Target::Supported(Os::Linux, Arch::Arm);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        let output = items[0].to_string();
+        assert!(output.contains(".4byte 990b"));
+        assert!(!output.contains(".8byte"));
+        assert!(output.contains("%object"));
+        assert!(output.contains("in (\"r0\")"));
+    }
+
+    #[test]
+    fn test_linux_target_uses_configured_sections() {
+        let target = Target::Supported(Os::Linux, Arch::X86_64);
+        let mut providers = test_providers();
+        providers[0].1.stapsdt_note_section = Some(String::from(".note.app"));
+        providers[0].1.stapsdt_semaphore_section = Some(String::from(".app.semaphores"));
+        providers[0].1.stapsdt_base_section = Some(String::from(".app.base"));
+        let items = target.compile_providers("", &providers).unwrap();
+        let output = items[0].to_string();
+        assert!(output.contains(".pushsection .note.app,"));
+        assert!(output.contains(".pushsection .app.semaphores,"));
+        assert!(output.contains(".pushsection .app.base,"));
+        assert!(output.contains(".app.base, comdat"));
+        assert!(!output.contains(".note.stapsdt"));
+        assert!(!output.contains(".probes,"));
+    }
+
+    #[test]
+    fn test_riscv64_linux_target_uses_argument_registers() {
+        let target = Target::Supported(Os::Linux, Arch::RiscV64);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        let output = items[0].to_string();
+        assert!(output.contains(".note.stapsdt"));
+        assert!(output.contains("1@a0"));
+        assert!(output.contains(&quote! { in("a0") }.to_string()));
+    }
+
+    #[test]
+    fn test_windows_target_writes_etw_events() {
+        let target = Target::Supported(Os::Windows, Arch::X86_64);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        let output = items[0].to_string();
+        assert!(output.contains("__USDT_ETW_PROVIDER"));
+        assert!(!output.contains("asm"));
+    }
+
+    // The x32 ABI is rejected with an error naming the target, rather than left to the assembler.
+    #[test]
+    fn test_32_bit_pointers_are_a_compile_error() {
+        let target = Target::UnsupportedPointerWidth(Os::Linux, Arch::X86_64);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        let output = items[0].to_string();
+        assert!(output.starts_with("compile_error !"));
+        assert!(output.contains("64-bit pointers on linux x86_64"));
+        assert!(!output.contains("asm"));
+    }
+
+    #[test]
+    fn test_illumos_target_emits_probe_records() {
+        let target = Target::Supported(Os::Illumos, Arch::X86_64);
+        let items = target.compile_providers("", &test_providers()).unwrap();
+        let output = items[0].to_string();
+        assert!(output.contains("set_dtrace_probes"));
+        assert!(!output.contains(".note.stapsdt"));
+    }
+
+    // Disabled probes have no target-specific code at all, and never call the argument closure.
+    #[test]
+    fn test_disabled_provider_is_not_fired() {
+        let (provider, config) = &test_providers()[0];
+        let output = empty::compile_disabled_provider(provider, config).to_string();
+        assert!(output.contains(&quote! { if false }.to_string()));
+        assert!(output.contains("type_check"));
+        assert!(!output.contains("asm"));
+        assert!(!output.contains("is_enabled"));
+    }
+
+    // On macOS hosts, generating the macOS variants calls out to `dtrace(1)`, which requires a
+    // real provider definition.
+    #[cfg(not(target_os = "macos"))]
+    #[test]
+    fn test_all_targets_are_cfg_gated() {
+        let output = compile_providers_for_all_targets("", &test_providers())
+            .unwrap()
+            .to_string();
+        let needle = quote! {
+            #[cfg(all(target_os = "linux", target_arch = "aarch64", target_pointer_width = "64"))]
+        };
+        assert!(output.contains(&needle.to_string()));
+        let needle = quote! {
+            #[cfg(all(target_os = "linux", target_arch = "x86_64", not(target_pointer_width = "64")))]
+        };
+        assert!(output.contains(&needle.to_string()));
+        assert!(output.contains(".note.stapsdt"));
+        assert!(output.contains("set_dtrace_probes"));
+    }
+}
@@ -0,0 +1,35 @@
+//! Integration test verifying that unique IDs seeded near the last prefix keep being handed out.
+//!
+//! Seeding only works before any ID is materialized, so this is the only test in its process.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use usdt_impl::UniqueId;
+
+// Each thread takes a shard of its own, so that the prefixes wrap around past `u32::MAX` rather
+// than panicking, and the IDs stay distinct.
+#[test]
+fn test_seed_near_last_prefix() {
+    assert!(UniqueId::seed(u32::MAX - 1));
+    let mut prefixes = (0..4)
+        .map(|_| std::thread::spawn(|| UniqueId::new().as_u64() >> 32))
+        .map(|thread| thread.join().expect("Materializing an ID panicked"))
+        .collect::<Vec<_>>();
+    prefixes.sort_unstable();
+    assert_eq!(
+        prefixes,
+        [0, 1, u64::from(u32::MAX) - 1, u64::from(u32::MAX)]
+    );
+}
@@ -45,27 +45,35 @@ use usdt_impl::compile_provider_source;
 /// The macro looks for the file relative to the root of the package, so `"test.d"`
 /// in this case would be in the same directory as `"Cargo.toml"`.
 ///
-/// By default probe macros are named `{provider}_{probe}!`. Arguments are passed
-/// via a closure that returns a tuple. Note that the provided closure is only
-/// evaluated when the probe is enabled. One can then add points of instrumentation
-/// by invoking the macro:
+/// By default probe macros are named after the probe, in a module named after the
+/// provider, such as `test::stop!`. Arguments are passed via a closure that returns
+/// a tuple. Note that the provided closure is only evaluated when the probe is
+/// enabled. One can then add points of instrumentation by invoking the macro:
 ///
 /// ```ignore
 /// fn do_stuff(count: u8, name: String) {
 ///     // doing stuff
-///     test_stop!(|| (name, count));
+///     test::stop!(|| (name, count));
 /// }
 /// ```
 ///
-/// The probe macro names can be customized by adding `, format =
-/// my_prefix_{provider}_{probe}` to the macro invocation where `{provider}` and
-/// `{probe}` are optional and will be substituted with the actual provider and
-/// probe names:
+/// The probe macro names can be customized by adding `, probe_format =
+/// "my_prefix_{provider}_{probe}"` to the macro invocation, where `{provider}` and
+/// `{probe}` will be substituted with the actual provider and probe names. For
+/// example, this names the macros `test::dtrace_test_start!` and
+/// `test::dtrace_test_stop!`:
 ///
 /// ```ignore
-/// dtrace_provider!("test.d", format = "dtrace_{provider}_{probe}");
+/// dtrace_provider!("test.d", probe_format = "dtrace_{provider}_{probe}");
 /// ```
 ///
+/// The format should include `{probe}`, as it's an error for two probes of a
+/// provider to end up with the same macro name. Likewise, the generated module may
+/// be renamed with `module = "..."`, which may also be a path from the root of the
+/// crate, such as `"telemetry::http"`, when the macro is invoked in that module's
+/// parent. In a file defining several providers, each may be given a module of its
+/// own with `modules = "net=net_probes,disk=storage::disk"`.
+///
 /// Note
 /// ----
 /// The only supported types are integers of specific bit-width (e.g., `uint16_t`),
@@ -23,3 +23,119 @@ pub fn root_command() -> String {
 pub fn root_command() -> String {
     String::from("sudo")
 }
+
+/// The format of a probe argument traced by [`trace_probe_args`].
+#[cfg(not(any(target_os = "linux", windows)))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ArgFormat {
+    /// An integer, printed in decimal.
+    Int,
+    /// A string, copied in from the traced process.
+    Str,
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+impl ArgFormat {
+    // Return the `printf` conversion and the D expression printing the argument at the index.
+    fn d_printf(&self, index: usize) -> (&'static str, String) {
+        match self {
+            ArgFormat::Int => ("%d", format!("arg{}", index)),
+            ArgFormat::Str => ("%s", format!("copyinstr(arg{})", index)),
+        }
+    }
+}
+
+/// Trace the values a probe of this process is fired with, by running a DTrace script with the
+/// [`root_command`].
+///
+/// The probe is named as DTrace sees it, by its provider without the PID and its probe name, and
+/// its arguments are printed in the given formats. Once DTrace has enabled the probe, `fire` is
+/// called to fire it, and the values of its first firing are returned in order, as printed. The
+/// probes must already be registered, and string values must not contain newlines. It panics if
+/// DTrace fails, or the probe doesn't fire within 30 seconds.
+///
+/// This is only available on platforms where DTrace traces the probes by default, so not on
+/// Linux, whose probes are only registered with DTrace with the `dtrace-linux` feature, which the
+/// tests using this aren't built with, nor on Windows.
+#[cfg(not(any(target_os = "linux", windows)))]
+pub fn trace_probe_args(
+    provider: &str,
+    probe: &str,
+    args: &[ArgFormat],
+    fire: impl FnOnce(),
+) -> Vec<String> {
+    use std::io::{BufRead, BufReader, Read};
+    use std::process::{Command, Stdio};
+    use std::sync::mpsc::channel;
+    use std::time::{Duration, Instant};
+
+    // Printed by DTrace once the probe is enabled, before which firing it would go unseen.
+    const BEGIN_SENTINEL: &str = "usdt-tests-common: tracing";
+    const MAX_WAIT: Duration = Duration::from_secs(30);
+
+    let (formats, exprs): (Vec<_>, Vec<_>) = args
+        .iter()
+        .enumerate()
+        .map(|(i, arg)| arg.d_printf(i))
+        .unzip();
+    let exprs = exprs
+        .iter()
+        .map(|expr| format!(", {}", expr))
+        .collect::<String>();
+    let script = format!(
+        "{}{}:::{} {{ printf(\"{}\\n\"{}); exit(0); }}",
+        provider,
+        std::process::id(),
+        probe,
+        formats.join("\\n"),
+        exprs,
+    );
+    let mut dtrace = Command::new(root_command())
+        .arg("dtrace")
+        .arg("-Z")
+        .arg("-q")
+        .arg("-n")
+        .arg(&script)
+        .arg("-n")
+        .arg(format!("BEGIN {{ printf(\"{}\\n\"); }}", BEGIN_SENTINEL))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to spawn DTrace subprocess");
+
+    // The output is read on a thread of its own, so that reading it may time out.
+    let stdout = dtrace.stdout.take().expect("Expected piped stdout");
+    let (tx, rx) = channel();
+    std::thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            if tx
+                .send(line.expect("Failed to read DTrace stdout"))
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+    let deadline = Instant::now() + MAX_WAIT;
+    let mut next_line = || {
+        let line = rx.recv_timeout(deadline.saturating_duration_since(Instant::now()));
+        if line.is_err() {
+            let _ = dtrace.kill();
+            let mut stderr = String::new();
+            if let Some(mut pipe) = dtrace.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr);
+            }
+            panic!(
+                "DTrace did not trace {}:::{} within {:?}, running:\n{}\n{}",
+                provider, probe, MAX_WAIT, script, stderr
+            );
+        }
+        line.unwrap()
+    };
+    while next_line() != BEGIN_SENTINEL {}
+    fire();
+    let values = (0..args.len()).map(|_| next_line()).collect();
+    let status = dtrace.wait().expect("Failed to wait for DTrace subprocess");
+    assert!(status.success(), "DTrace process failed: {}", status);
+    values
+}
@@ -0,0 +1,81 @@
+//! Benchmark firing probes which are disabled, enabled with only native arguments, and enabled
+//! with a serializable argument.
+//!
+//! On Linux, probes are enabled the way a tracer enables them, by incrementing their semaphores.
+//! Elsewhere, only disabled probes are measured. That firing them doesn't allocate is checked by
+//! the `allocations` test.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::Serialize;
+
+#[derive(Serialize)]
+struct Event {
+    id: u64,
+    name: &'static str,
+}
+
+#[usdt::provider]
+mod bench {
+    fn native(_: u64, _: u32, _: &str) {}
+    fn serializable(_: u64, _: &crate::Event) {}
+}
+
+fn fire_native(i: u64) {
+    bench::native!(|| (i, i as u32, "native"));
+}
+
+#[cfg(all(target_os = "linux", not(feature = "disabled")))]
+fn fire_serializable(i: u64) {
+    let event = Event {
+        id: i,
+        name: "serializable",
+    };
+    bench::serializable!(|| (i, &event));
+}
+
+// Enable a probe of the `bench` provider, as a tracer does, by incrementing its semaphore.
+#[cfg(all(target_os = "linux", not(feature = "disabled")))]
+macro_rules! enable {
+    ($semaphore:ident) => {{
+        unsafe extern "C" {
+            static mut $semaphore: u16;
+        }
+        unsafe {
+            let semaphore = &raw mut $semaphore;
+            semaphore.write_volatile(semaphore.read_volatile() + 1);
+        }
+    }};
+}
+
+fn probes(c: &mut Criterion) {
+    usdt::register_probes().unwrap();
+    c.bench_function("disabled", |b| b.iter(|| fire_native(black_box(1))));
+
+    // The probes stay enabled once they are, so they're measured after the disabled one.
+    #[cfg(all(target_os = "linux", not(feature = "disabled")))]
+    {
+        enable!(__usdt_sema_bench_native);
+        enable!(__usdt_sema_bench_serializable);
+        c.bench_function("enabled native", |b| b.iter(|| fire_native(black_box(1))));
+        c.bench_function("enabled serializable", |b| {
+            b.iter(|| fire_serializable(black_box(1)))
+        });
+    }
+}
+
+criterion_group!(benches, probes);
+criterion_main!(benches);
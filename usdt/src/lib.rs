@@ -118,6 +118,47 @@
 //! }
 //! ```
 //!
+//! Any `use` statements in the provider module must be absolute, e.g., `crate::Arg` rather than
+//! `super::Arg`. The generated probe macros may be invoked from anywhere in the crate, and the
+//! imports are resolved at each call site, so relative imports (`super::` or `self::`) are
+//! rejected with a compiler error pointing at the offending path.
+//!
+//! The module may also define type aliases, such as `type Bytes = Vec<u8>;`, for use in the
+//! probes' arguments. The macro resolves them, so that `fn received(b: &Bytes) {}` is exactly
+//! `fn received(b: &Vec<u8>) {}`, and the aliases themselves aren't emitted. They may refer to each
+//! other and to imported types, but may not be generic.
+//!
+//! A provider may instead be defined by an inherent impl block, whose empty methods are its probes:
+//!
+//! ```rust,ignore
+//! #[derive(serde::Serialize)]
+//! struct Connection {
+//!     id: u64,
+//! }
+//!
+//! #[usdt::provider]
+//! impl Connection {
+//!     fn opened(id: u64) {}
+//!     fn sent(&self, bytes: u64) {}
+//! }
+//!
+//! Connection::opened(1);
+//! connection.sent(512);
+//! ```
+//!
+//! The provider is named after the type, here `Connection`, and its probe macros are generated in
+//! a module named after the type in snake case, `connection`, next to the impl block. Both may be
+//! renamed with `provider` and `module`. Each method is emitted with a body firing its probe with
+//! the method's arguments, which the caller has evaluated, whether or not the probe is enabled. A
+//! method taking `&self` passes the value as the probe's first argument, serialized like any other
+//! `&T`, so the type must implement `Serialize`, and that argument is `arg0` in DTrace. Methods
+//! may not take `self` by value or `&mut self`, and those without a receiver pass only their
+//! arguments. `Self` may be used in the arguments' types.
+//!
+//! An impl block has no use statements, so the types of the arguments are named as they are next
+//! to the block. The probe macros may still be called directly, wherever those names resolve.
+//! Trait impls and generic types aren't supported.
+//!
 //! The `arg` parameter to the `stop` probe will be converted into JSON, and its fields may be
 //! accessed in DTrace with the `json` function. The signature is `json(string, key)`, where `key`
 //! is used to access the named key of a JSON-encoded string. For example:
@@ -129,14 +170,149 @@
 //! would print the first element of the vector `Arg::buffer`.
 //!
 //! > **Important**: Notice that the JSON key used in the above example to access the data inside
-//! DTrace is `"ok.buffer[0]"`. JSON values serialized to DTrace are always `Result` types,
-//! because the internal serialization method is _fallible_. So they are always encoded as objects
-//! like `{"ok": _}` or `{"err": "some error message"}`. In the error case, the message is
-//! created by formatting the `serde_json::error::Error` that describes why serialization failed.
+//! > DTrace is `"ok.buffer[0]"`. JSON values serialized to DTrace are always `Result` types,
+//! > because the internal serialization method is _fallible_. So they are always encoded as objects
+//! > like `{"ok": _}` or `{"err": "some error message"}`. In the error case, the message is
+//! > created by formatting the `serde_json::error::Error` that describes why serialization failed.
+//!
+//! To keep a pathologically large argument from stalling DTrace, JSON longer than 64 KiB is
+//! replaced by a marker with its full length, as in `{"ok": {"__truncated": true, "len": N}}`.
+//! The limit may be changed with `max_serialized_len`, such as
+//! `#[usdt::provider(max_serialized_len = 1024)]`, or [`Builder::max_serialized_len`].
+//!
+//! Keys may be renamed in the JSON passed to probes, and nowhere else, with `rename_json_keys`, a
+//! comma-separated list of keys and the keys they're renamed to. With
+//! `#[usdt::provider(rename_json_keys = "request_id=id,timestamp=ts")]`, or
+//! [`Builder::rename_json_keys`], a field `request_id` of any struct in an argument, or a string
+//! key `"request_id"` of any map, becomes `id`. The types' own `Serialize` implementations are
+//! unchanged.
+//!
+//! A probe argument may also be declared as `&dyn Serialize`, to pass values of different types to
+//! the same probe. It accepts a reference to any serializable type. As `Serialize` can't be made
+//! into a trait object, a value whose type is only known at runtime is passed as a
+//! `&dyn` [`ErasedSerialize`] instead:
+//!
+//! ```rust,ignore
+//! #[usdt::provider]
+//! mod events {
+//!     fn event(_: &dyn serde::Serialize) {}
+//! }
+//!
+//! let payload: &dyn usdt::ErasedSerialize = if failed { &error } else { &response };
+//! events::event!(|| payload);
+//! ```
+//!
+//! For log-like probes, an argument declared as `impl usdt::Fields` takes key/value pairs rather
+//! than a struct defined for each event. Any array, slice or vector of pairs of a string key and a
+//! serializable value may be passed, and the pairs are serialized as the members of a JSON object,
+//! in the order they're given:
+//!
+//! ```rust,ignore
+//! #[usdt::provider]
+//! mod log {
+//!     fn event(_: impl usdt::Fields) {}
+//! }
+//!
+//! log::event!(|| [("method", "GET"), ("path", "/index.html")]);
+//! ```
+//!
+//! arrives at DTrace as `{"ok":{"method":"GET","path":"/index.html"}}`, so that a member is read
+//! with `json(copyinstr(arg0), "ok.path")`. The values all have the same type, so values of
+//! several types are passed as `&dyn` [`ErasedSerialize`] or a `serde_json::Value`.
+//!
+//! JSON already held in a `serde_json::Value` may be passed to an argument of that type, or a
+//! reference to it. It's written straight into the buffer passed to the probe, and arrives at
+//! DTrace as the JSON `char *` of any serializable argument, wrapped in `{"ok": _}`, as it is when
+//! passed as a `&dyn ErasedSerialize`.
 //!
 //! > **Note**: It's not possible to define probes in D that accept a serializable type, because the
-//! corresponding C type is just `char *`. There's currently no way to disambiguate such a type
-//! from an actual string, when generating the Rust probe macros.
+//! > corresponding C type is just `char *`. There's currently no way to disambiguate such a type
+//! > from an actual string, when generating the Rust probe macros.
+//!
+//! ## Compressed arguments
+//!
+//! With the `gzip` or `zstd` features, the JSON of serializable arguments may be compressed before
+//! it's passed to the probes, for consumers which can decompress it, with
+//! `#[usdt::provider(compression = "zstd")]` or [`Builder::compression`]. Such an argument points
+//! to an 8 byte header, followed by the compressed JSON:
+//!
+//! - Byte 0 is `0xff`, which never starts a JSON string, so a consumer can tell the two apart.
+//! - Byte 1 is the encoding, 1 for gzip and 2 for zstd.
+//! - Byte 2 is the version of the header, currently 1, and byte 3 is reserved and always zero.
+//! - Bytes 4 to 7 are the length of the compressed data, as a little-endian `uint32_t`.
+//!
+//! Should compressing an argument fail, its JSON is passed as a null-terminated string instead.
+//! Compression applies to DTrace and SystemTap probes only, while LTTng-UST tracepoints and ETW
+//! events always record the JSON itself.
+//!
+//! ## Framed arguments
+//!
+//! So that a decoder can detect how a serializable argument is encoded, whichever version of this
+//! crate fired the probe, `#[usdt::provider(frame_arguments = true)]` or
+//! [`Builder::frame_arguments`] prepends a 4 byte header to the JSON of each argument:
+//!
+//! - Byte 0 is `0xfe`, which never starts a JSON string, nor a compressed argument.
+//! - Byte 1 is the format, 1 for the JSON described above.
+//! - Byte 2 is the version of the format, currently 1, and byte 3 is reserved and always zero.
+//!
+//! The null-terminated JSON follows, so a script reads it with `copyinstr(arg0 + 4)` once it has
+//! checked the header. This layout, and the meaning of format 1 at version 1, are stable: new
+//! encodings get formats of their own. Compressed arguments carry their own header, and aren't
+//! framed, and LTTng-UST tracepoints and ETW events always record the JSON itself.
+//!
+//! ## Serializing without allocating
+//!
+//! With the `json-core` feature, serializable arguments are written by `serde-json-core` into a
+//! 1 KiB buffer on the stack, rather than by `serde_json` into one on the heap, so that firing a
+//! probe doesn't allocate. JSON which doesn't fit, or is longer than the maximum serialized length,
+//! is replaced by `{"ok":{"__truncated":true}}`. As `serde-json-core` panics on `char`s, tuple
+//! variants, maps with keys other than strings, and `Serialize` implementations returning an
+//! error, arguments of such types must not be passed to probes with this feature.
+//!
+//! ## Backtraces
+//!
+//! With the `backtrace` feature, an argument declared as `&usdt::Backtrace` passes the innermost
+//! frames of the stack, captured with the `backtrace` crate by [`Backtrace::capture`], as a JSON
+//! array of strings, each the function of a frame and its file and line:
+//!
+//! ```rust,ignore
+//! #[usdt::provider]
+//! mod errors {
+//!     fn failed(code: u64, trace: &usdt::Backtrace) {}
+//! }
+//!
+//! errors::failed!(|| (code, usdt::Backtrace::capture(8)));
+//! ```
+//!
+//! Capturing walks the stack and resolves the symbol of each frame, which reads the program's
+//! debug info the first time, and may take milliseconds. Capturing inside the argument closure, as
+//! above, only pays for it while the probe is enabled. At most [`MAX_BACKTRACE_FRAMES`] frames,
+//! 64, are captured, however many are asked for.
+//!
+//! ## Length-prefixed JSON
+//!
+//! A consumer reading the JSON of a serializable argument with `copyinstr` has to find its null
+//! byte, and truncates it at `strsize`. Setting `json_len = true`, as in
+//! `#[usdt::provider(json_len = true)]` or [`Builder::json_len`], instead passes each serializable
+//! argument as two: a `char *` to the JSON, and a `uint64_t` with its length in bytes, without the
+//! null byte. A probe `fn payload(_: &Payload, _: u8)` then has the arguments
+//! `(char *, uint64_t, uint8_t)`, and counts each serializable argument twice towards the limit of
+//! six. In D, copy in exactly that many bytes:
+//!
+//! ```ignore
+//! my_provider*:::payload
+//! {
+//!     this->json = (char *)copyin(arg0, arg1);
+//!     tracemem(this->json, 65536, arg1);
+//! }
+//! ```
+//!
+//! With `bpftrace`, `buf(arg0, arg1)` does the same. A framed or compressed argument is passed
+//! with the length of its header and data.
+//!
+//! Doc comments and `#[deprecated]` attributes on the probe functions are carried over to the
+//! generated probe macros, so that the macros are documented by `cargo doc` and firing a
+//! deprecated probe emits the usual deprecation warning.
 //!
 //! See the [probe_test_attr] example for a complete example implementing probes in Rust.
 //!
@@ -148,7 +324,9 @@
 //! probe macros. This can be any string, and will have the keys `{provider}` and `{probe}`
 //! interpolated to the actual names of the provider and probe. As an example, consider a provider
 //! named `foo` with a probe named `bar`, and a format string of `probe_{provider}_{probe}` -- the
-//! name of the generated probe macro will be `probe_foo_bar`.
+//! name of the generated probe macro will be `probe_foo_bar`. The format should include
+//! `{probe}`: it's an error for the format to give two probes the same name, or to produce a name
+//! which isn't a valid Rust identifier.
 //!
 //! In addition, when using the attribute macro version, the name of the _provider_ as seen by
 //! DTrace can be configured. This defaults to the name of the provider module. For example,
@@ -162,8 +340,41 @@
 //! ```
 //!
 //! The probe `bar` will appear in DTrace as `foo:::bar`, and will be accessible in Rust via the
-//! macro `probes::bar!`. Note that it's not possible to rename the probe module when using the
-//! attribute macro version.
+//! macro `probes::bar!`. The provider name must be a valid DTrace identifier, starting with a
+//! letter and containing only letters, digits and underscores.
+//!
+//! Every probe of a provider may be given a common prefix with the `prefix` argument, or
+//! [`Builder::prefix`], rather than writing it into the name of each probe function:
+//!
+//! ```ignore
+//! #[usdt::provider(prefix = "http_")]
+//! mod server {
+//!     fn request(_: &str) {}
+//!     fn response(_: u16) {}
+//! }
+//! ```
+//!
+//! The probes appear in DTrace as `server:::http_request` and `server:::http_response`, with the
+//! same names in their SystemTap notes, and are fired with `server::http_request!` and
+//! `server::http_response!`. The `{probe}` of a `probe_format` is the prefixed name. Each prefixed
+//! name must itself be a valid probe name.
+//!
+//! The generated module may also be renamed, with the `module` argument. Given
+//! `#[usdt::provider(provider = "foo", module = "foo_probes")]` on the module above, the probe is
+//! accessible in Rust via the macro `foo_probes::bar!` instead.
+//!
+//! The probe macros refer to each other through the path of their module, which is assumed to be
+//! at the root of the crate. A provider module nested in another must be given its full path from
+//! the root of the crate as the `module`, of which the last segment names the generated module:
+//!
+//! ```ignore
+//! mod telemetry {
+//!     #[usdt::provider(module = "telemetry::http")]
+//!     pub mod http {
+//!         fn request(_: &str) {}
+//!     }
+//! }
+//! ```
 //!
 //! Conversely, one can change the name of the generated provider _module_ when using the builder
 //! version, but not the name of the provider as it appears to DTrace. Given a file `"test.d"` that
@@ -180,6 +391,121 @@
 //! the macro `probes::bar!`. Note that it's not possible to rename the provider as it appears in
 //! DTrace when using the builder version.
 //!
+//! A single `module` names the module of every provider in the file. With several providers, each
+//! may instead be given a module of its own with [`Builder::modules`], or with
+//! `modules = "net=net_probes,disk=storage::disk"` in `dtrace_provider!`. Providers left out are
+//! generated in the `module`, if given, or else in a module of their own name.
+//!
+//! ## Generating code ahead of time
+//!
+//! Rather than generating the probes at every build, the code may be generated once and checked
+//! in. With the `generate` feature, `Builder::generate` returns the formatted Rust source a build
+//! would write, and may be called from anywhere, such as a small binary or a test. The `dusty` tool
+//! does the same with `dusty --generate test.d > src/probes.rs`.
+//!
+//! ```ignore
+//! let source = usdt::Builder::new("test.d").module("probes").generate().unwrap();
+//! std::fs::write("src/probes.rs", source).unwrap();
+//! ```
+//!
+//! The code is generated for every target, with each variant gated on its `cfg`, so that it may be
+//! built anywhere. It needs the crate to depend on `usdt`, as code generated at build time does,
+//! and is included at the root of the crate with `include!("probes.rs")`. A file declared as a
+//! module of its own, with `mod vendored;`, instead needs the full path of the generated module as
+//! the `module`, such as `vendored::probes`. As the code for macOS is generated by the platform's
+//! `dtrace`, code generated elsewhere fails to build for macOS. The code must be generated again
+//! when the `usdt` crate is upgraded, or the provider file changes.
+//!
+//! ## Stability attributes
+//!
+//! DTrace providers may declare the [stability] of their interface, as attributes of the form
+//! `name/data/class`, such as `Evolving/Evolving/Common`. In D, these are declared with pragmas
+//! following the provider, which are honored by both the build script and the `dtrace_provider!`
+//! macro:
+//!
+//! ```d
+//! #pragma D attributes Evolving/Evolving/Common provider foo provider
+//! #pragma D attributes Evolving/Evolving/Common provider foo args
+//! ```
+//!
+//! With the attribute macro, the `stability` argument sets the attributes of every part of the
+//! provider's interface, and the `provider_stability`, `module_stability`, `function_stability`,
+//! `name_stability` and `args_stability` arguments set those of a single part:
+//!
+//! ```ignore
+//! #[usdt::provider(
+//!     stability = "Evolving/Evolving/Common",
+//!     module_stability = "Private/Private/Unknown",
+//! )]
+//! mod foo {
+//!     fn bar() {}
+//! }
+//! ```
+//!
+//! Undeclared attributes default to `Internal/Internal/Unknown`. The attributes are part of the
+//! probe information registered with DTrace, and are ignored by the SystemTap and LTTng-UST
+//! implementations on Linux.
+//!
+//! [stability]: https://illumos.org/books/dtrace/chp-stab.html
+//!
+//! ## Registration metadata
+//!
+//! The DOF registered with DTrace describes each provider and nothing more. Of what it holds, the
+//! provider's name is set with `provider`, or in the D source, the probes' names by the probe
+//! functions and `prefix`, their arguments' types by the functions' signatures and `d_type`, and
+//! the stability attributes as above. The module of each probe, as in
+//! `provider:module:function:name`, is the name of the program or shared library the probes are
+//! in, and the DOF header has no flags to set.
+//!
+//! In particular, a provider can't be registered for a single consumer. Every consumer sees the
+//! probes of every process, and selects those of one with `provider$pid`, as in
+//! `dtrace -n 'foo$1234:::bar'`, or with `dtrace -p 1234`.
+//!
+//! ## Translated argument types
+//!
+//! With the attribute macro, an argument passed natively as a single probe argument, such as a
+//! raw pointer to a struct, may be given the D type scripts see it as, its translated type:
+//!
+//! ```ignore
+//! #[usdt::provider]
+//! mod foo {
+//!     fn event(#[d_type = "my_event_t *"] _: *const crate::Event) {}
+//! }
+//! ```
+//!
+//! `dtrace -l -v` lists the argument as `args[0]: my_event_t *`, and a translator from its native
+//! type, declared in a D library, converts it when a script reads `args[0]`. The translated types
+//! are part of the probe information registered on illumos and FreeBSD, and are ignored by the
+//! other implementations.
+//!
+//! Exporting probes
+//! ----------------
+//!
+//! The generated module, and the macros and functions firing its probes, are `pub(crate)` by
+//! default. A library may instead export them for the crates depending on it to fire, say to share
+//! a provider between the services of a project, with `visibility = "pub"`:
+//!
+//! ```ignore
+//! #[usdt::provider(visibility = "pub")]
+//! pub mod my_provider {
+//!     fn my_probe(_: u8) {}
+//! }
+//! ```
+//!
+//! or [`Builder::visibility`] with [`Visibility::Public`]. Another crate then fires the probe with
+//! `my_lib::my_provider::my_probe!(|| 1)`. The macros are exported with `#[macro_export]`, under
+//! hidden names at the root of the library, and re-exported from the module under their own, so
+//! the modules in a `module` path must themselves be public. Paths from the root of the library,
+//! such as in `use crate::Arg;`, are resolved within the library wherever the macro is called, as
+//! are the macros' references to `usdt`, which the module re-exports. The calling crate needn't
+//! depend on `usdt` itself.
+//!
+//! A provider defined once in such a library may be fired from the library and any number of the
+//! crates depending on it, which all share the one provider. Each call site is a site of the same
+//! probe, so `dtrace -l` lists the provider once, with the probe at every site across the crates.
+//! The library registers the probes of the whole program, and may re-export
+//! [`register_probes`] for the program to call.
+//!
 //! Double-underscores
 //! ------------------
 //!
@@ -218,6 +544,116 @@
 //! expensive to construct. However, this cost will only be incurred if the probe is actually
 //! enabled.
 //!
+//! A closure returning a tuple literal, as in `my_probe!(|| (a, b))` or `my_probe!(|| { (a, b) })`,
+//! is checked against the number of the probe's arguments, failing to compile with an error such
+//! as "probe `my_probe` expects 2 arguments, closure returned 3". Any other closure is checked by
+//! the types of the arguments it returns.
+//!
+//! Arguments which are already at hand, or cheap to compute, may be passed directly instead, as
+//! in `my_probe!(count)` or `my_probe!((id, &name))`, without the `||` of a closure. The macro
+//! wraps them in one itself. Unlike a closure's body, the expression is then evaluated where the
+//! macro is called, whether or not the probe is enabled, so expensive arguments should still be
+//! returned from a closure. Anything callable without arguments, such as a function's name, is
+//! taken to be a closure and only called once the probe is enabled. Arguments left to their
+//! defaults can only be left out of a closure, see below.
+//!
+//! Building the arguments may itself fail, for example when they're read from behind a poisoned
+//! lock. Prefixing the closure with `try` allows it to return a `Result` of the arguments instead.
+//! On `Ok`, the probe fires with the contained arguments. On `Err`, the probe is skipped entirely
+//! and the error is discarded, rather than panicking or firing with some placeholder arguments.
+//!
+//! ```ignore
+//! my_provider::my_probe!(try || state.lock().map(|s| s.count).map_err(|e| e.to_string()));
+//! ```
+//!
+//! The error type must be known, so closures ending in `Ok(...)` may need a type annotation, as in
+//! `Ok::<_, MyError>(...)`.
+//!
+//! Probes defined with the attribute macro may also be fired with their arguments named after the
+//! parameters of the probe function, in any order. The named arguments are still only evaluated
+//! when the probe is enabled, just as if they'd been returned from a closure:
+//!
+//! ```ignore
+//! #[usdt::provider]
+//! mod my_provider {
+//!     fn stop(message: &str, arg: &Arg) {}
+//! }
+//!
+//! my_provider::stop!(message = "done", arg = &arg);
+//! my_provider::stop!(|| ("done", &arg));
+//! ```
+//!
+//! Each argument must be named exactly once. A probe with any parameter named `_` can only be
+//! fired with a closure, as can probes defined in D, which have no parameter names.
+//!
+//! The trailing parameters of a probe function may be given defaults with `#[default = ...]`,
+//! which the probe's macro supplies when a call leaves those arguments out. A provider can then
+//! evolve by adding arguments at the end of a probe, each with a default, while the existing call
+//! sites still build and fire the probe with the defaults:
+//!
+//! ```ignore
+//! #[usdt::provider]
+//! mod my_provider {
+//!     // `elapsed` was added later.
+//!     fn served(path: &str, status: u16, #[default = 0] elapsed: u64) {}
+//! }
+//!
+//! my_provider::served!(|| ("/", 200));
+//! my_provider::served!(|| ("/", 200, 12));
+//! my_provider::served!(path = "/", status = 200);
+//! ```
+//!
+//! The defaults are only evaluated when the probe is enabled. Arguments may only be left out of
+//! a closure returning a tuple literal, with a single argument returned as `|| (a,)`, and of named
+//! arguments, while any other closure returns all of them. Fire functions always take all of the
+//! arguments. Scripts see the probe's new arguments with their defaults wherever they aren't
+//! given, so should treat the defaults as "unknown".
+//!
+//! Each probe is also fired by a plain function, `fire_<probe>`, next to its macro, which takes
+//! the probe's arguments themselves. This suits code where a macro is awkward, such as generated
+//! code, or where a function pointer is needed:
+//!
+//! ```ignore
+//! let fire: fn(u8, &str) = my_provider::fire_my_probe;
+//! fire(1, "one");
+//! ```
+//!
+//! The function still does nothing with the arguments when the probe isn't enabled, but they've
+//! been evaluated by the time it's called, so the macro remains the way to fire a probe with
+//! arguments that are expensive to build.
+//!
+//! The closure may borrow anything in scope for as long as the probe is fired. Each argument is
+//! accepted as anything it can be borrowed as, such as a `&u64` for a `u64` or a `&String` for a
+//! `&str`, so a method can pass the fields of `self` without cloning them:
+//!
+//! ```ignore
+//! impl Worker {
+//!     fn stop(&self, msg: &str) {
+//!         my_provider::stop!(|| (msg, &self.arg, &self.count));
+//!     }
+//! }
+//! ```
+//!
+//! Probes may be fired freely in `async` code. A probe macro expands to a block which calls the
+//! closure, if at all, and fires the probe before the macro returns, so neither the closure nor
+//! the values it returns outlive it. Nothing borrowed by the arguments is then held across an
+//! `.await`, and firing a probe has no effect on whether the surrounding future is `Send`:
+//!
+//! ```ignore
+//! tokio::spawn(async move {
+//!     my_provider::my_probe!(|| (1, name.as_str()));
+//!     socket.readable().await?;
+//!     // ...
+//! });
+//! ```
+//!
+//! Probes may also be fired while unwinding from a panic, from the `Drop` implementation of a
+//! scope guard for example, as firing a probe never panics itself. An argument which fails to
+//! serialize is passed as a message describing the error, and a probe fired from the destructor of
+//! a thread-local, after the buffer reused for serialized arguments is gone, uses a new buffer.
+//! The closure and the `Serialize` or `Display` implementations of its arguments must not panic,
+//! though, as a second panic while unwinding aborts the process.
+//!
 //! Data types
 //! ----------
 //!
@@ -232,9 +668,155 @@
 //! - `char *`
 //! - `T: serde::Serialize` (Only when defining probes in Rust)
 //!
+//! In Rust, `usize` and `isize` arguments are passed with the pointer width of the target, rather
+//! than that of the host building the probes. They are described as `uint64_t` and `int64_t` on
+//! 64-bit targets, and as `uint32_t` and `int32_t` on 32-bit Arm.
+//!
+//! D files may also declare aliases of these types with `typedef`, such as
+//! `typedef uint64_t request_id_t;`, and use them as probe argument types. Typedefs may alias other
+//! typedefs, and may be declared anywhere in the file. `translator` blocks are accepted too, and
+//! preserved by `dtrace_parser::File::translators`, while `#pragma D` directives other than
+//! `attributes` are ignored.
+//!
+//! On Linux, the SystemTap notes describe a pointer to an integer as reading the integer it points
+//! to, so that `bpftrace` and other consumers see the integer itself rather than the pointer.
+//!
+//! When defining probes in Rust, raw pointers, `*const T` or `*mut T` to any `T`, are passed as
+//! their address, a `uint64_t`, which scripts may use to correlate events on the same object. The
+//! pointee is never read nor serialized, so the pointer needn't be valid.
+//!
+//! Field-less enums with a primitive representation may be passed as their discriminant, rather
+//! than serialized, by marking the argument with the enum's `#[repr(...)]`. The macro can't see
+//! the definition of an imported enum, so the representation is repeated on the argument, and a
+//! representation other than the enum's own fails to compile:
+//!
+//! ```ignore
+//! #[repr(u8)]
+//! pub enum State {
+//!     Idle,
+//!     Busy,
+//! }
+//!
+//! #[usdt::provider]
+//! mod machine {
+//!     fn transition(#[repr(u8)] _: crate::State) {}
+//! }
+//! ```
+//!
+//! Here `args[0]` is a `uint8_t`, and the enum needn't be `Copy`. Enums with data are serialized
+//! like any other type.
+//!
+//! A field-less enum may instead be passed as the name of its variant, a `char *`, which is
+//! lighter than its JSON. Derive [`VariantName`] for the enum, which generates a `match` of each
+//! variant to its name, and mark the argument `#[variant_name]`:
+//!
+//! ```ignore
+//! #[derive(usdt::VariantName)]
+//! pub enum State {
+//!     Idle,
+//!     Busy,
+//! }
+//!
+//! #[usdt::provider]
+//! mod machine {
+//!     fn entered(#[variant_name] _: &crate::State) {}
+//! }
+//! ```
+//!
+//! Firing `machine::entered!(|| State::Busy)` then passes the string `"Busy"`. The derive fails
+//! to compile for an enum with data, which is serialized as usual.
+//!
+//! Non-zero integers, such as `NonZeroU32` or `NonZero<u32>`, are passed as the integer they wrap,
+//! so `fn id(_: NonZeroU64) {}` has a `uint64_t` as `args[0]`.
+//!
 //! Currently, up to six (6) arguments are supported, though this limitation may be lifted in the
 //! future.
 //!
+//! When defining probes in Rust, arrays of integers such as `[u64; 4]` or `&[u64; 4]` are passed
+//! natively, as one argument per element, so that the above probe would have four `uint64_t`
+//! arguments. This is only the case as long as the probe's arguments, counting each element, are
+//! at most six. Larger arrays are serialized to JSON like any other array. Note that an array
+//! passed natively must be given as exactly that array type, by value or by reference, rather than
+//! as any type implementing `AsRef<[T]>`.
+//!
+//! Strings may be taken as `&str` or `String`, and also as `Cow<str>`, `Box<str>`, `Rc<str>` or
+//! `Arc<str>`, all of which are `char *` arguments. Any of them may be passed anything implementing
+//! `AsRef<str>`, rather than being serialized to JSON.
+//!
+//! Likewise, `&Path` and `PathBuf` are `char *` arguments, which may be passed anything
+//! implementing `AsRef<Path>`, such as `fn opened(p: &Path) {}`. On Unix, the path's bytes are
+//! passed as they are, so a path which isn't valid UTF-8 reaches the tracer unchanged, while on
+//! other platforms it's converted to UTF-8 lossily, replacing invalid sequences with U+FFFD. As
+//! with strings, a path containing a null byte appears truncated at it.
+//!
+//! Other types behind a `Box`, `Rc` or `Arc`, such as `&Arc<Arg>`, are serialized as the value they
+//! point to, without cloning it. Only that value must implement `Serialize`, so serde's `rc`
+//! feature isn't needed.
+//!
+//! With the `chrono` or `time` features, timestamps of those crates, `chrono::DateTime<Utc>` and
+//! `time::OffsetDateTime`, are passed natively as the nanoseconds since the Unix epoch, rather
+//! than serialized. They are `uint64_t` arguments, where times before the epoch are passed as zero,
+//! unless the argument is marked `#[signed]`, which passes it as an `int64_t` instead:
+//!
+//! ```ignore
+//! #[usdt::provider]
+//! mod events {
+//!     use chrono::{DateTime, Utc};
+//!     fn received(_: DateTime<Utc>, #[signed] _: DateTime<Utc>) {}
+//! }
+//! ```
+//!
+//! The crate defining the probes must depend on `chrono` or `time` directly.
+//!
+//! A `std::time::Duration` is passed natively too, as its nanoseconds in a `uint64_t`, saturated
+//! at `u64::MAX`, which is over 584 years. This is the way to trace a span measured with an
+//! `Instant`: an `Instant` has no absolute value, so it can't be passed itself, but the difference
+//! between two of them can, as in `fn span_ns(d: Duration) {}` fired with
+//! `span_ns!(|| start.elapsed())`.
+//!
+//! An argument of type `&dyn std::fmt::Display`, or `impl std::fmt::Display`, is formatted with
+//! its `Display` implementation and passed as a `char *`, as a string would be, rather than
+//! serialized to JSON. Any value implementing `Display` may be passed to such a probe. It's
+//! formatted into a buffer which is reused by later probes fired on the same thread.
+//!
+//! Similarly, with the `uuid` feature, a `uuid::Uuid` is passed natively as two `uint64_t`
+//! arguments, rather than serialized, and so counts as two of the six arguments. Reading the UUID's
+//! 16 bytes as a big-endian integer, the first argument is its most significant half and the
+//! second its least significant half, so that the UUID may be formatted in D as:
+//!
+//! ```text
+//! printf("%08x-%04x-%04x-%04x-%012x\n", arg0 >> 32, (arg0 >> 16) & 0xffff, arg0 & 0xffff,
+//!     arg1 >> 48, arg1 & 0xffffffffffff);
+//! ```
+//!
+//! With the `prost` feature, a `prost::Message` argument marked `#[protobuf]` is encoded to
+//! protobuf with `encode_to_vec`, rather than serialized to JSON. It's passed as two `uint64_t`
+//! arguments, the address of the encoded bytes and their length, which a script copies in as
+//! `copyin(arg0, arg1)`. The encoding is never compressed, and the crate defining the probes must
+//! depend on `prost` directly:
+//!
+//! ```ignore
+//! #[usdt::provider]
+//! mod rpc {
+//!     fn request(#[protobuf] _: &crate::proto::Request) {}
+//! }
+//! ```
+//!
+//! Slices and vectors of integers are serialized by default. Marking one `#[native]`, as in
+//! `fn samples(#[native] _: &[u32]) {}`, instead passes it as three `uint64_t` arguments: the
+//! address of its first element, the number of elements, and the size of each element in bytes,
+//! such as 4 for a `u32`. The probe accepts anything implementing `AsRef<[u32]>`, and the three
+//! arguments count towards the limit of six. A script copies in all the elements at once, and
+//! indexes them as an array of the element type:
+//!
+//! ```ignore
+//! telemetry*:::samples
+//! {
+//!     this->samples = (uint32_t *)copyin(arg0, arg1 * arg2);
+//!     printf("%d samples, the first being %u\n", arg1, this->samples[0]);
+//! }
+//! ```
+//!
 //! Registration
 //! ------------
 //!
@@ -259,6 +841,69 @@
 //! function during some initialization routines required by their library. There is no harm in
 //! calling this method multiple times, even in concurrent situations.
 //!
+//! On Linux, probes are SystemTap notes, which need no registering. With the `dtrace-linux`
+//! feature, probes on x86-64 and aarch64 Linux also have DTrace probe records, whose DOF
+//! `register_probes` loads as on illumos, so that the DTrace on Linux port sees them too. This
+//! requires its kernel module, or helper daemon, to provide `/dev/dtrace/helper`: without it,
+//! `register_probes` returns [`Error::HelperUnavailable`], and only tools reading the notes see the
+//! probes.
+//!
+//! A shared library, such as a `cdylib` loaded with `dlopen(3)`, keeps its probes separately from
+//! the executable and other libraries. `register_probes` registers the probes of the module it's
+//! linked into, so a library loaded at runtime should call it itself, such as from an
+//! initialization function it exports.
+//!
+//! Setting the `USDT_DISABLE` environment variable, [`DISABLE_ENV_VAR`], to anything but an empty
+//! string or `0` disables every probe, as a safety valve for operators. `register_probes` then
+//! registers nothing, and probes report themselves disabled even where a tracer enabled them, such
+//! as through SystemTap semaphores, so that their arguments are never built. The variable is read
+//! once, by the first call registering probes, and [`probes_disabled`] reports the outcome.
+//!
+//! Listing probes
+//! --------------
+//!
+//! The [`probes`] function returns information about every probe compiled into the program,
+//! including its provider, its name as it appears to DTrace, and the [`DataType`]s of its
+//! arguments. This is independent of registration, and may be used to, say, expose the available
+//! probes of a service for debugging. On platforms without a probe backend, no probes are listed.
+//!
+//! Similarly, [`is_enabled`] checks whether a probe is currently enabled, given the names of its
+//! provider and of the probe. This is useful when the probe isn't known statically, and is `false`
+//! for any probe which isn't compiled into the program. [`provider_enabled`] checks whether any
+//! probe of a provider is enabled, for setup shared by several of its probes.
+//!
+//! On Linux, [`enabled_count`] returns the value of a SystemTap probe's semaphore, which the kernel
+//! increments for each tracer enabling the probe, to report how many are attached to it. It's 0
+//! for unknown probes, and for LTTng-UST tracepoints, which have no semaphore.
+//!
+//! On Linux, [`stapsdt::notes`] instead returns the SystemTap notes describing each probe site of
+//! the executable, with its address and argument specification, as `readelf -n` shows them.
+//!
+//! Manifest
+//! --------
+//!
+//! A build script may also describe the providers it compiles for tools outside the program, such
+//! as documentation generators or a catalog of the probes of several services. With
+//! [`Builder::manifest`], it writes `usdt-manifest.json` to `OUT_DIR`, listing each provider, its
+//! probes, and the name, C type and [`DataType`] of each of their arguments. The schema is
+//! documented in the [`manifest`] module, and versioned so that tools may rely on it.
+//!
+//! The manifest also serves to check a build, say in CI. [`verify::verify_manifest`] compares the
+//! probes it lists with those a built binary describes, in its SystemTap notes on Linux and in its
+//! DOF or probe records elsewhere, and reports the missing probes, as well as any others of the
+//! manifest's providers. The `dusty` tool in the repository does the same given `--manifest`,
+//! exiting with an error on a mismatch, and lists a binary's probes given `--list`.
+//!
+//! Providers defined at runtime
+//! ----------------------------
+//!
+//! On illumos and FreeBSD, [`runtime::register_provider_source`] registers the providers of D
+//! source only known while the program runs, say from a plugin's configuration, and
+//! [`runtime::fire`] fires their probes by name. This isn't possible with the SystemTap probes
+//! used on Linux, which are described by notes written into the binary at compile time, nor on
+//! macOS, where the linker generates the probes' DOF, and registering a provider there returns
+//! [`Error::RuntimeProvidersUnsupported`]. See the [`runtime`] module for details.
+//!
 //! Unique IDs
 //! ----------
 //!
@@ -269,6 +914,117 @@
 //! purpose. It may be passed as any argument to a probe function, and is guaranteed to be unique
 //! between different invocations of the same probe. See the type's documentation for details.
 //!
+//! Probes in generic functions
+//! ----------------------------
+//!
+//! A probe macro expands into the code firing the probe where it's called. When that's in a
+//! generic function, the code is duplicated in every monomorphization of the function, along with
+//! the probe's record. The probe then has one site per monomorphization, all of which are listed
+//! by `dtrace -l` or `readelf -n`, and which grow the binary.
+//!
+//! Setting `dedupe_probes = true`, as in `#[usdt::provider(dedupe_probes = true)]`,
+//! `dtrace_provider!("test.d", dedupe_probes = true)` or [`Builder::dedupe_probes`], instead fires
+//! each probe from a function of its own, which isn't generic and so is emitted once. This costs
+//! a function call each time an enabled probe fires, but nothing when it's disabled.
+//!
+//! Unused probes
+//! -------------
+//!
+//! A probe which is defined but never fired still has its macro, function and records generated.
+//! Setting `warn_unused_probes = true`, as in `#[usdt::provider(warn_unused_probes = true)]`,
+//! reports each probe fired neither with its macro nor its fire function as dead code, pointing at
+//! the probe's definition, with a warning such as "function `fire_my_probe` is never used". This
+//! is off by default, since a library may well define probes for the crates using it to fire.
+//! Probes defined in D have no definition in the crate to point at, so they're never reported.
+//!
+//! Probes fired once
+//! -----------------
+//!
+//! For one-off events, such as the end of a program's startup, `once_macros = true` also
+//! generates a macro firing each probe at most once per process, named after the probe's macro
+//! with an `_once` suffix:
+//!
+//! ```ignore
+//! #[usdt::provider(once_macros = true)]
+//! mod lifecycle {
+//!     fn ready(_: u64) {}
+//! }
+//!
+//! lifecycle::ready_once!(|| elapsed_ms);
+//! ```
+//!
+//! The macro checks whether the probe is enabled first, so that calls while it's disabled don't
+//! use up its firing, and its closure isn't called then. The first call finding the probe enabled
+//! fires it, and no other call does, across all threads and call sites: the probe has a single
+//! atomic flag, which that call sets. A fallible closure which fails still uses up the firing.
+//! With a [`Builder`], this is [`Builder::once_macros`].
+//!
+//! Probes fired conditionally
+//! --------------------------
+//!
+//! To skip uninteresting events without touching the provider, `if_macros = true` also generates
+//! a macro firing each probe only when a predicate holds, named after the probe's macro with an
+//! `_if` suffix. It takes the predicate, a `FnOnce() -> bool`, before the usual arguments:
+//!
+//! ```ignore
+//! #[usdt::provider(if_macros = true)]
+//! mod requests {
+//!     fn slow(_: &str, _: u64) {}
+//! }
+//!
+//! requests::slow_if!(|| elapsed_ms > 100, || (path, elapsed_ms));
+//! ```
+//!
+//! The probe is checked to be enabled first, then the predicate is called, and only if it returns
+//! `true` is the argument closure called and the probe fired. Both gates are skipped entirely
+//! while the probe is disabled. With a [`Builder`], this is [`Builder::if_macros`].
+//!
+//! Automatic arguments
+//! -------------------
+//!
+//! The probes of a provider may also be passed values captured where they fire, without the
+//! argument closures returning them. Set `auto_args` to a comma-separated list of these, as in
+//! `#[usdt::provider(auto_args = "tid,cpu")]`, `dtrace_provider!("test.d", auto_args = "tid")` or
+//! [`Builder::auto_args`]:
+//!
+//! - `tid`: the ID of the firing thread, as given by the OS, as a `uint64_t`
+//! - `cpu`: the CPU the thread runs on, as an `int32_t`, or -1 where the OS doesn't tell
+//!
+//! These follow each probe's own arguments, always in the order above, whatever the order they're
+//! listed in. For a probe declared as `probe work(uint8_t)` with `auto_args = "tid,cpu"`, `arg1` is
+//! the thread ID and `arg2` the CPU. They're only captured when the probe is enabled, and count
+//! towards the six arguments a probe may take.
+//!
+//! SystemTap sections
+//! ------------------
+//!
+//! On Linux, each probe's note is placed in the `.note.stapsdt` section, its semaphore in
+//! `.probes`, and the notes are relocated by the address of `.stapsdt.base`. A program linked with
+//! a custom linker script may place these in sections of its own choosing with
+//! `stapsdt_note_section`, `stapsdt_semaphore_section` and `stapsdt_base_section`, as in
+//! `#[usdt::provider(stapsdt_semaphore_section = ".data.probes")]`, or the [`Builder`] methods of
+//! the same names. Section names may only contain ASCII letters, digits, `_`, `.` and `$`, and
+//! mustn't start with a digit.
+//!
+//! Tracers look for the notes and the base by their default section names, so the linker script
+//! must map other sections back to those in the final binary for the probes to be found. The same
+//! goes for [`stapsdt::notes`], which reads `.note.stapsdt`.
+//!
+//! Disabling probes
+//! ----------------
+//!
+//! The `disabled` feature compiles every probe to nothing, on every target, without changing the
+//! providers. The argument closures are still type-checked, so code that fires probes stays valid,
+//! but they're never called. There's no is-enabled check, no inline assembly, and no probe records
+//! or notes in the binary, nor any code converting arguments, such as serializing them to JSON.
+//! [`register_probes`] does nothing, and [`probes`] lists no probes.
+//!
+//! This differs from targets without a probe backend, such as OpenBSD, where the probes are also
+//! no-ops but the argument closures are still called, and the compiler may only remove the
+//! arguments' evaluation if it has no side effects. Note that a build script's dependency on
+//! `usdt` is separate from the crate's own, so the feature must be enabled on both when probes are
+//! generated with the [`Builder`].
+//!
 //! About the `asm` feature
 //! -----------------------
 //!
@@ -276,6 +1032,9 @@
 //! require nightly Rust with old versions of Rust. Currently, all supported versions of Rust
 //! support inline assembly, and the `asm` feature is a no-op.
 //!
+//! The generated probes only use stable Rust, calling `::std::arch::asm!` by its full path, so
+//! crates defining probes need neither `#![feature(asm)]` nor an import of the macro.
+//!
 //! The next breaking change to `usdt` will remove the `asm` feature entirely.
 //!
 //! [dtrace]: https://illumos.org/books/dtrace/preface.html#preface
@@ -293,18 +1052,52 @@ use std::fs::{File, OpenOptions};
 use std::path::{Path, PathBuf};
 use std::{env, fs};
 
-pub use usdt_attr_macro::provider;
+pub mod stapsdt;
+pub mod verify;
+
+pub use usdt_attr_macro::{provider, VariantName};
+#[cfg(target_os = "linux")]
+pub use usdt_impl::enabled_count;
+#[cfg(all(windows, not(feature = "disabled")))]
+#[doc(hidden)]
+pub use usdt_impl::etw::runtime as etw;
+#[cfg(feature = "gzip")]
+#[doc(hidden)]
+pub use usdt_impl::gzip_probe_arg;
+pub use usdt_impl::manifest;
+#[doc(hidden)]
+pub use usdt_impl::registry::{IntegerRecord, ProbeRecord, TypeRecord};
+pub use usdt_impl::runtime;
+#[cfg(feature = "zstd")]
+#[doc(hidden)]
+pub use usdt_impl::zstd_probe_arg;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+#[doc(hidden)]
+pub use usdt_impl::CompressedProbeArg;
 #[doc(hidden)]
-pub use usdt_impl::to_json;
-pub use usdt_impl::{Error, UniqueId};
+pub use usdt_impl::{
+    current_cpu, current_thread_id, to_display_probe_arg, to_framed_json_probe_arg, to_json,
+    to_json_bounded, to_json_probe_arg, to_path_probe_arg, to_str_probe_arg, ClosureProbeArgs,
+    DisplayProbeArg, JsonProbeArg, ProbeArgs, RenameKeys, SerializeFields, StrProbeArg,
+    ValueProbeArgs,
+};
+pub use usdt_impl::{
+    is_enabled, probes, probes_disabled, provider_enabled, AutoArg, Compression, DataType,
+    ErasedSerialize, Error, Fields, ProbeInfo, Registration, TimestampKind, UniqueId, VariantName,
+    Visibility, DISABLE_ENV_VAR,
+};
+#[cfg(feature = "backtrace")]
+pub use usdt_impl::{Backtrace, MAX_BACKTRACE_FRAMES};
 pub use usdt_macro::dtrace_provider;
 
 /// A simple struct used to build DTrace probes into Rust code in a build.rs script.
 #[derive(Debug)]
 pub struct Builder {
-    source_file: PathBuf,
+    source_files: Vec<PathBuf>,
     out_file: PathBuf,
     config: usdt_impl::CompileProvidersConfig,
+    lttng: bool,
+    manifest: bool,
 }
 
 impl Builder {
@@ -314,12 +1107,43 @@ impl Builder {
         let mut out_file = source_file.clone();
         out_file.set_extension("rs");
         Builder {
-            source_file,
+            source_files: vec![source_file],
             out_file,
             config: usdt_impl::CompileProvidersConfig::default(),
+            lttng: false,
+            manifest: false,
         }
     }
 
+    /// Construct a new builder from paths to several D provider definition files, whose providers
+    /// are generated into a single `"probes.rs"` output file.
+    ///
+    /// Providers of the same name in several files are merged, with each probe appearing once. It's
+    /// an error for two files to define a probe of the same provider with different arguments.
+    pub fn from_files<I, P>(files: I) -> Self
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        Builder {
+            source_files: files
+                .into_iter()
+                .map(|file| file.as_ref().to_path_buf())
+                .collect(),
+            out_file: PathBuf::from("probes.rs"),
+            config: usdt_impl::CompileProvidersConfig::default(),
+            lttng: false,
+            manifest: false,
+        }
+    }
+
+    /// Add another D provider definition file, whose providers are merged with those of the others
+    /// as with [`Builder::from_files`].
+    pub fn file<P: AsRef<Path>>(mut self, file: P) -> Self {
+        self.source_files.push(file.as_ref().to_path_buf());
+        self
+    }
+
     /// Set the output filename of the generated Rust code. The default has the same stem as the
     /// provider file, with the `".rs"` extension.
     pub fn out_file<P: AsRef<Path>>(mut self, file: P) -> Self {
@@ -337,25 +1161,269 @@ impl Builder {
         self
     }
 
+    /// Prepend a prefix to the name of every probe, as DTrace and SystemTap see it, and as the
+    /// probe macros are named. See [Configurable names](crate#configurable-names).
+    pub fn prefix(mut self, prefix: &str) -> Self {
+        self.config.prefix = Some(prefix.to_string());
+        self
+    }
+
     /// Set the name of the module containing the generated probe macros.
+    ///
+    /// This may also be a path from the root of the crate, such as `telemetry::http`, in which case
+    /// the generated code must be included in the module of the preceding segments, `telemetry`.
     pub fn module(mut self, module: &str) -> Self {
         self.config.module = Some(module.to_string());
         self
     }
 
-    /// Generate the Rust code from the D provider file, writing the result to the output file.
+    /// Set the modules of individual providers, given as pairs of a provider name and its module.
+    ///
+    /// With several providers in the source, each is otherwise generated in a module of its own
+    /// name, or in the one set by [`Builder::module`]. Naming a provider the source doesn't define
+    /// is an [`Error::UnknownProviders`].
+    pub fn modules(mut self, modules: &[(&str, &str)]) -> Self {
+        self.config.modules = modules
+            .iter()
+            .map(|(provider, module)| (provider.to_string(), module.to_string()))
+            .collect();
+        self
+    }
+
+    /// Set the maximum length of the JSON a serializable probe argument is converted into.
+    ///
+    /// Longer JSON is replaced by `{"__truncated":true,"len":N}`, where `N` is its full length. The
+    /// default is 64 KiB.
+    pub fn max_serialized_len(mut self, len: usize) -> Self {
+        self.config.max_serialized_len = Some(len);
+        self
+    }
+
+    /// Rename keys in the JSON of serializable arguments, given as pairs of a key and the key it's
+    /// renamed to. See [Inline Rust probes](crate#inline-rust-probes).
+    pub fn rename_json_keys(mut self, renames: &[(&str, &str)]) -> Self {
+        self.config.rename_json_keys = renames
+            .iter()
+            .map(|(from, to)| (from.to_string(), to.to_string()))
+            .collect();
+        self
+    }
+
+    /// Compress the JSON of serializable arguments, which requires the feature of the same name as
+    /// the compression. See [Compressed arguments](crate#compressed-arguments).
+    pub fn compression(mut self, compression: Compression) -> Self {
+        self.config.compression = Some(compression);
+        self
+    }
+
+    /// Fire each probe from a function of its own, so that a probe fired in a generic function
+    /// has a single site, rather than one per monomorphization of that function. See
+    /// [Probes in generic functions](crate#probes-in-generic-functions).
+    pub fn dedupe_probes(mut self, dedupe: bool) -> Self {
+        self.config.dedupe_probes = dedupe;
+        self
+    }
+
+    /// Pass each serializable argument as a pointer to its JSON followed by the JSON's length. See
+    /// [Length-prefixed JSON](crate#length-prefixed-json).
+    pub fn json_len(mut self, json_len: bool) -> Self {
+        self.config.json_len = json_len;
+        self
+    }
+
+    /// Prepend a header identifying the encoding and its version to the JSON of serializable
+    /// arguments. See [Framed arguments](crate#framed-arguments).
+    pub fn frame_arguments(mut self, frame_arguments: bool) -> Self {
+        self.config.frame_arguments = frame_arguments;
+        self
+    }
+
+    /// Also generate a macro firing each probe at most once per process, such as `start_once!`
+    /// for the probe `start`. See [Probes fired once](crate#probes-fired-once).
+    pub fn once_macros(mut self, once_macros: bool) -> Self {
+        self.config.once_macros = once_macros;
+        self
+    }
+
+    /// Also generate a macro firing each probe only when a predicate holds, such as `start_if!`
+    /// for the probe `start`. See [Probes fired conditionally](crate#probes-fired-conditionally).
+    pub fn if_macros(mut self, if_macros: bool) -> Self {
+        self.config.if_macros = if_macros;
+        self
+    }
+
+    /// Place the notes of SystemTap probes in the given section, rather than `.note.stapsdt`. See
+    /// [SystemTap sections](crate#systemtap-sections).
+    pub fn stapsdt_note_section(mut self, section: &str) -> Self {
+        self.config.stapsdt_note_section = Some(section.to_string());
+        self
+    }
+
+    /// Place the semaphores of SystemTap probes in the given section, rather than `.probes`. See
+    /// [SystemTap sections](crate#systemtap-sections).
+    pub fn stapsdt_semaphore_section(mut self, section: &str) -> Self {
+        self.config.stapsdt_semaphore_section = Some(section.to_string());
+        self
+    }
+
+    /// Relocate the notes of SystemTap probes by the address of the given section, rather than
+    /// `.stapsdt.base`. See [SystemTap sections](crate#systemtap-sections).
+    pub fn stapsdt_base_section(mut self, section: &str) -> Self {
+        self.config.stapsdt_base_section = Some(section.to_string());
+        self
+    }
+
+    /// Set the visibility of the generated modules, and of the macros and functions firing their
+    /// probes, `Visibility::Public` exporting them for other crates. See
+    /// [Exporting probes](crate#exporting-probes).
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.config.visibility = visibility;
+        self
+    }
+
+    /// Pass the given values, captured automatically when each probe fires, after the probe's own
+    /// arguments. See [Automatic arguments](crate#automatic-arguments).
+    pub fn auto_args(mut self, args: &[AutoArg]) -> Self {
+        let mut args = args.to_vec();
+        args.sort();
+        args.dedup();
+        self.config.auto_args = args;
+        self
+    }
+
+    /// Implement the probes as LTTng-UST tracepoints, rather than SystemTap probes, when building
+    /// for Linux.
+    ///
+    /// Each probe becomes a tracepoint of the same provider and name, with one field per argument.
+    /// The tracepoint provider is generated as C source, which is compiled with the C compiler
+    /// named by the `CC` environment variable, `cc` by default, and linked into the crate along
+    /// with `liblttng-ust`. The LTTng-UST development headers must therefore be installed.
+    ///
+    /// On targets other than Linux, this has no effect.
+    pub fn lttng(mut self, lttng: bool) -> Self {
+        self.lttng = lttng;
+        self
+    }
+
+    /// Also write a JSON manifest of the compiled providers, their probes, and the types of the
+    /// probes' arguments to `usdt-manifest.json` in `OUT_DIR`. See [Manifest](crate#manifest) for
+    /// its schema.
+    pub fn manifest(mut self, manifest: bool) -> Self {
+        self.manifest = manifest;
+        self
+    }
+
+    /// Generate the Rust code from the D provider files, writing the result to the output file.
     pub fn build(self) -> Result<(), Error> {
-        let source = fs::read_to_string(self.source_file)?;
-        let tokens = usdt_impl::compile_provider_source(&source, &self.config)?;
-        let mut out_file = Path::new(&env::var("OUT_DIR")?).to_path_buf();
-        out_file.push(
+        let source = self.read_source()?;
+        let out_dir = PathBuf::from(env::var("OUT_DIR")?);
+        let out_file = out_dir.join(
             self.out_file
                 .file_name()
                 .expect("Could not extract filename"),
         );
+        let lttng = self.lttng && !cfg!(feature = "disabled");
+        let tokens = if lttng && env::var("CARGO_CFG_TARGET_OS")? == "linux" {
+            let providers = usdt_impl::compile_lttng_provider_source(&source, &self.config)?;
+            let stem = out_file
+                .file_stem()
+                .expect("Could not extract filename")
+                .to_string_lossy()
+                .into_owned();
+            build_lttng_providers(&providers, &out_dir, &stem)?;
+            providers.tokens
+        } else {
+            usdt_impl::compile_provider_source(&source, &self.config)?
+        };
         fs::write(out_file, tokens.to_string().as_bytes())?;
+        if self.manifest {
+            let manifest = usdt_impl::manifest::provider_source_manifest(&source, &self.config)?;
+            fs::write(out_dir.join("usdt-manifest.json"), manifest)?;
+        }
         Ok(())
     }
+
+    /// Generate the Rust code from the D provider files as formatted source, to be checked in
+    /// rather than generated at build time.
+    ///
+    /// Unlike [`Builder::build`], this needn't run in a build script, and the code is generated for
+    /// every target, each variant gated on its `cfg`. The output file, [`Builder::lttng`] and
+    /// [`Builder::manifest`] are ignored. See
+    /// [Generating code ahead of time](crate#generating-code-ahead-of-time). This requires the
+    /// `generate` feature.
+    #[cfg(feature = "generate")]
+    pub fn generate(self) -> Result<String, Error> {
+        let source = self.read_source()?;
+        usdt_impl::format_provider_source(&source, &self.config)
+    }
+
+    // Read the D provider files, returning their source merged into one.
+    fn read_source(&self) -> Result<String, Error> {
+        let sources = self
+            .source_files
+            .iter()
+            .map(fs::read_to_string)
+            .collect::<Result<Vec<_>, _>>()?;
+        // Check each file on its own first, so that errors point at the line of the file they're on.
+        for (path, source) in self.source_files.iter().zip(sources.iter()) {
+            dtrace_parser::File::try_from(source.as_str())
+                .map_err(|e| e.with_path(&path.display().to_string()))?;
+        }
+        match sources.as_slice() {
+            [source] => Ok(source.clone()),
+            sources => {
+                let sources = sources.iter().map(String::as_str).collect::<Vec<_>>();
+                Ok(usdt_impl::merge_provider_sources(&sources)?)
+            }
+        }
+    }
+}
+
+// Compile the LTTng-UST tracepoint providers into a static library in `out_dir`, and instruct
+// Cargo to link it along with `liblttng-ust`.
+fn build_lttng_providers(
+    providers: &usdt_impl::LttngProviders,
+    out_dir: &Path,
+    stem: &str,
+) -> Result<(), Error> {
+    for (name, header) in providers.headers.iter() {
+        fs::write(out_dir.join(name), header)?;
+    }
+    let source_file = out_dir.join(format!("{}-tp.c", stem));
+    let object_file = out_dir.join(format!("{}-tp.o", stem));
+    let lib_name = format!("usdt_lttng_{}", stem);
+    fs::write(&source_file, &providers.source)?;
+
+    let cc = env::var("CC").unwrap_or_else(|_| String::from("cc"));
+    let status = std::process::Command::new(cc)
+        .arg("-c")
+        .arg("-fPIC")
+        .arg("-I")
+        .arg(out_dir)
+        .arg("-o")
+        .arg(&object_file)
+        .arg(&source_file)
+        .status()?;
+    if !status.success() {
+        return Err(Error::LttngError);
+    }
+    let ar = env::var("AR").unwrap_or_else(|_| String::from("ar"));
+    let status = std::process::Command::new(ar)
+        .arg("crs")
+        .arg(out_dir.join(format!("lib{}.a", lib_name)))
+        .arg(&object_file)
+        .status()?;
+    if !status.success() {
+        return Err(Error::LttngError);
+    }
+
+    println!("cargo:rerun-if-env-changed=CC");
+    println!("cargo:rerun-if-env-changed=AR");
+    println!("cargo:rustc-link-search=native={}", out_dir.display());
+    println!("cargo:rustc-link-lib=static={}", lib_name);
+    println!("cargo:rustc-link-lib=dylib=lttng-ust");
+    println!("cargo:rustc-link-lib=dylib=dl");
+    Ok(())
 }
 
 /// Register an application's probes with DTrace.
@@ -371,11 +1439,76 @@ impl Builder {
 /// actually defines the probes. It's also safe to call this function multiple times, even in
 /// concurrent situations. Probes will be registered at most once.
 ///
+/// A provider of the same name defined in more than one place, say by two crates, is an
+/// [`Error::DuplicateProvider`], and nothing is registered, as DTrace would merge their probes.
+/// Rename all but one of them, or namespace them, say by the name of their crate.
+///
+/// On illumos and FreeBSD, probes are registered through the DTrace helper device. Where it
+/// doesn't exist, as when DTrace isn't loaded or in a container without the device, the error is
+/// [`Error::HelperUnavailable`], while failing to open it for other reasons, such as permissions,
+/// or the kernel rejecting the probes, is an [`Error::IO`]. The probes are then never enabled, so
+/// firing them remains a no-op, and a program may carry on without tracing:
+///
+/// ```
+/// match usdt::register_probes() {
+///     Ok(()) => {}
+///     Err(usdt::Error::HelperUnavailable { .. }) => eprintln!("tracing unavailable"),
+///     Err(e) => panic!("failed to register probes: {e}"),
+/// }
+/// ```
+///
+/// When the `USDT_DISABLE` environment variable is set to anything but an empty string or `0`,
+/// nothing is registered, and every probe is disabled, see [`DISABLE_ENV_VAR`].
+///
 /// [probe_test_macro]: https://github.com/oxidecomputer/usdt/tree/master/probe-test-macro
 pub fn register_probes() -> Result<(), Error> {
     usdt_impl::register_probes()
 }
 
+/// Register an application's probes with DTrace, as [`register_probes`] does, and return how many
+/// providers and probes were registered.
+///
+/// This gives a concrete signal that tracing is live, say for a health check. Each provider and
+/// probe is counted once, however many sites it has. The counts cover the probes compiled into the
+/// program, or into the shared library calling this, which on Linux are those of its SystemTap
+/// notes. On platforms without a probe backend, or with the `disabled` feature, both are zero.
+///
+/// ```
+/// let registration = usdt::register_probes_with_report().unwrap();
+/// println!(
+///     "Registered {} probes of {} providers",
+///     registration.probes, registration.providers
+/// );
+/// ```
+pub fn register_probes_with_report() -> Result<Registration, Error> {
+    usdt_impl::register_probes_with_report()
+}
+
+/// Register the probes of only the named providers with DTrace, leaving the others unregistered.
+///
+/// This suits a plugin system, for example, where the probes of a plugin should only be visible
+/// once the plugin is loaded. The providers are named as they appear to DTrace, and any provider
+/// left out may be registered by a later call, or by [`register_probes`]. A name which doesn't
+/// match any provider in the program is reported as an [`Error::UnknownProviders`], and none of
+/// the providers are registered.
+///
+/// ```
+/// # #[usdt::provider]
+/// # mod plugin_a { fn loaded() {} }
+/// usdt::register_providers(&["plugin_a"]).unwrap();
+/// ```
+///
+/// Notes
+/// -----
+///
+/// On Linux, the SystemTap notes of every provider are static, and are visible to tracers whether
+/// registered or not, so this only checks the names. The same goes for macOS, where the linker
+/// registers all providers as the program is loaded. On platforms without a probe backend, or with
+/// the `disabled` feature, nothing is registered and no names are checked.
+pub fn register_providers(names: &[&str]) -> Result<(), Error> {
+    usdt_impl::register_providers(names)
+}
+
 /// Extract embedded USDT probe records from a file.
 ///
 /// DTrace in general works by storing metadata about the probes in a special
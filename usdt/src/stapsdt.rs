@@ -0,0 +1,161 @@
+//! Inspection of the SystemTap probe descriptors of the running program.
+//!
+//! On Linux, each probe site is described by a note in the `.note.stapsdt` section of the binary,
+//! which is what tools like `bpftrace` and `readelf -n` read. [`notes`] reads these back, so that a
+//! program may check the probes it emits without shelling out to those tools.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::Error;
+use goblin::elf::Elf;
+use memmap2::Mmap;
+use std::fs::File;
+use std::path::Path;
+
+/// The name of the section containing the notes.
+const NOTE_SECTION: &str = ".note.stapsdt";
+
+/// The owner of the notes.
+const NOTE_OWNER: &str = "stapsdt";
+
+/// The type of the notes describing a probe site.
+const NT_STAPSDT: u32 = 3;
+
+/// A SystemTap probe descriptor, describing a single site of a probe.
+///
+/// The addresses are link-time addresses, as written in the binary. Consumers relocate them by the
+/// difference between `base` and the runtime address of the `.stapsdt.base` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NoteInfo {
+    /// The name of the probe's provider.
+    pub provider: String,
+    /// The name of the probe.
+    pub name: String,
+    /// The address of the probe site.
+    pub location: u64,
+    /// The address of the `.stapsdt.base` section.
+    pub base: u64,
+    /// The address of the probe's semaphore, which is 0 if it has none.
+    pub semaphore: u64,
+    /// The specification of the probe's arguments, such as `1@%dil 8@%rsi`.
+    pub arguments: String,
+}
+
+/// Return the SystemTap probe descriptors of the running executable.
+///
+/// These are read from the executable's file, so describe the probes of the executable, and not
+/// those of any shared library it loads. The result is empty on platforms other than Linux, where
+/// the probes aren't described by SystemTap notes, or if the file can't be read.
+pub fn notes() -> Vec<NoteInfo> {
+    if cfg!(target_os = "linux") {
+        std::env::current_exe()
+            .ok()
+            .and_then(|path| notes_from_file(path).ok())
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    }
+}
+
+/// Return the SystemTap probe descriptors of the ELF object at the given path.
+///
+/// This is empty for an object without a `.note.stapsdt` section.
+pub fn notes_from_file<P: AsRef<Path>>(path: P) -> Result<Vec<NoteInfo>, Error> {
+    let file = File::open(path)?;
+    let map = unsafe { Mmap::map(&file) }?;
+    let elf = Elf::parse(&map).map_err(|_| Error::InvalidFile)?;
+    let Some(notes) = elf.iter_note_sections(&map, Some(NOTE_SECTION)) else {
+        return Ok(Vec::new());
+    };
+    let address_size = if elf.is_64 { 8 } else { 4 };
+    let mut infos = Vec::new();
+    for note in notes {
+        let note = note.map_err(|_| Error::InvalidFile)?;
+        if note.name != NOTE_OWNER || note.n_type != NT_STAPSDT {
+            continue;
+        }
+        let info =
+            parse_note(note.desc, address_size, elf.little_endian).ok_or(Error::InvalidFile)?;
+        infos.push(info);
+    }
+    Ok(infos)
+}
+
+// Parse the descriptor of a note: the addresses of the probe site, the base section and the
+// semaphore, followed by the provider name, the probe name and the argument specification, each
+// NUL-terminated.
+fn parse_note(desc: &[u8], address_size: usize, little_endian: bool) -> Option<NoteInfo> {
+    let (addresses, rest) = desc.split_at_checked(3 * address_size)?;
+    let mut addresses = addresses.chunks_exact(address_size).map(|bytes| {
+        let mut buf = [0; 8];
+        if little_endian {
+            buf[..address_size].copy_from_slice(bytes);
+            u64::from_le_bytes(buf)
+        } else {
+            buf[8 - address_size..].copy_from_slice(bytes);
+            u64::from_be_bytes(buf)
+        }
+    });
+    let mut strings = rest
+        .split(|byte| *byte == 0)
+        .map(|bytes| String::from_utf8_lossy(bytes).into_owned());
+    Some(NoteInfo {
+        location: addresses.next()?,
+        base: addresses.next()?,
+        semaphore: addresses.next()?,
+        provider: strings.next()?,
+        name: strings.next()?,
+        arguments: strings.next()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_note() {
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&0x1234_u64.to_le_bytes());
+        desc.extend_from_slice(&0x5678_u64.to_le_bytes());
+        desc.extend_from_slice(&0_u64.to_le_bytes());
+        desc.extend_from_slice(b"prov\0probe\0");
+        desc.extend_from_slice(b"1@%dil 8@%rsi\0");
+        let info = parse_note(&desc, 8, true).unwrap();
+        assert_eq!(
+            info,
+            NoteInfo {
+                provider: String::from("prov"),
+                name: String::from("probe"),
+                location: 0x1234,
+                base: 0x5678,
+                semaphore: 0,
+                arguments: String::from("1@%dil 8@%rsi"),
+            }
+        );
+
+        let mut desc = Vec::new();
+        desc.extend_from_slice(&0x1234_u32.to_be_bytes());
+        desc.extend_from_slice(&0x5678_u32.to_be_bytes());
+        desc.extend_from_slice(&0x9abc_u32.to_be_bytes());
+        desc.extend_from_slice(b"prov\0probe\0\0");
+        let info = parse_note(&desc, 4, false).unwrap();
+        assert_eq!((info.location, info.semaphore), (0x1234, 0x9abc));
+        assert!(info.arguments.is_empty());
+
+        assert!(parse_note(&desc[..11], 4, false).is_none());
+        assert!(parse_note(&desc[..16], 4, false).is_none());
+    }
+}
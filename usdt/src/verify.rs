@@ -0,0 +1,134 @@
+//! Verification of the probes emitted into a built artifact.
+//!
+//! A build may check that the binary it produced contains the probes it expects, say to catch a
+//! linker dropping them, or a provider left out of the build. [`file_probes`] lists the probes an
+//! object file describes, and [`verify_manifest`] compares them with the
+//! [manifest](crate#manifest) written by the build script. The `dusty` tool in the repository
+//! does the same from the command line, exiting with an error on any mismatch.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::{probe_records, stapsdt, Error};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+/// The differences between the probes listed in a manifest and those found in a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// The provider and name of each probe of the manifest which the file doesn't contain.
+    pub missing: Vec<(String, String)>,
+    /// The provider and name of each probe the file contains, of a provider of the manifest, which
+    /// the manifest doesn't list.
+    pub unexpected: Vec<(String, String)>,
+}
+
+impl Verification {
+    /// Return `true` if the file contains exactly the probes of the manifest's providers.
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.unexpected.is_empty()
+    }
+}
+
+/// Return the provider and name, as they appear to DTrace, of each probe described by an object
+/// file, in order.
+///
+/// The probes are read from the file's SystemTap notes on Linux, and otherwise from its DOF or the
+/// probe records written by this crate. A probe fired from several sites is listed once. The
+/// result is empty for a file describing no probes, or with SystemTap notes in a section other
+/// than `.note.stapsdt`.
+pub fn file_probes<P: AsRef<Path>>(path: P) -> Result<Vec<(String, String)>, Error> {
+    let notes = match stapsdt::notes_from_file(&path) {
+        Ok(notes) => notes,
+        Err(Error::InvalidFile) => Vec::new(),
+        Err(e) => return Err(e),
+    };
+    let probes: BTreeSet<_> = if notes.is_empty() {
+        match probe_records(&path) {
+            Ok(sections) => sections
+                .into_iter()
+                .flat_map(|section| section.providers.into_values())
+                .flat_map(|provider| {
+                    provider
+                        .probes
+                        .into_keys()
+                        .map(move |probe| (provider.name.clone(), probe))
+                })
+                .collect(),
+            Err(Error::InvalidFile) => BTreeSet::new(),
+            Err(e) => return Err(e),
+        }
+    } else {
+        notes
+            .into_iter()
+            .map(|note| (note.provider, note.name))
+            .collect()
+    };
+    Ok(probes.into_iter().collect())
+}
+
+/// Compare the probes described by an object file with those listed in a manifest.
+///
+/// Probes of providers which the manifest doesn't list aren't reported, since a binary usually
+/// also contains the probes of its dependencies.
+pub fn verify_manifest<P: AsRef<Path>>(path: P, manifest: &str) -> Result<Verification, Error> {
+    let expected = usdt_impl::manifest::manifest_probes(manifest)?;
+    let found = file_probes(path)?;
+    Ok(compare(expected, found))
+}
+
+// Compare the expected probes with those found, ignoring probes of providers not expected.
+fn compare(expected: Vec<(String, String)>, found: Vec<(String, String)>) -> Verification {
+    let expected: BTreeSet<_> = expected.into_iter().collect();
+    let found: BTreeSet<_> = found.into_iter().collect();
+    let providers: BTreeSet<_> = expected.iter().map(|(provider, _)| provider).collect();
+    Verification {
+        missing: expected.difference(&found).cloned().collect(),
+        unexpected: found
+            .difference(&expected)
+            .filter(|(provider, _)| providers.contains(provider))
+            .cloned()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[(&str, &str)]) -> Vec<(String, String)> {
+        names
+            .iter()
+            .map(|(provider, probe)| (provider.to_string(), probe.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_compare() {
+        let expected = names(&[("net", "send"), ("net", "recv"), ("disk", "read")]);
+        let verification = compare(expected.clone(), expected.clone());
+        assert!(verification.is_ok());
+
+        let found = names(&[("net", "send"), ("net", "drop"), ("other", "ping")]);
+        let verification = compare(expected, found);
+        assert!(!verification.is_ok());
+        assert_eq!(
+            verification,
+            Verification {
+                missing: names(&[("disk", "read"), ("net", "recv")]),
+                unexpected: names(&[("net", "drop")]),
+            }
+        );
+    }
+}
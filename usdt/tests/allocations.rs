@@ -0,0 +1,85 @@
+//! Test that firing a disabled probe, or an enabled one with only native arguments, doesn't
+//! allocate, counting allocations with a global allocator of its own.
+
+// Copyright 2024 Oxide Computer Company
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::hint::black_box;
+
+const ITERATIONS: u64 = 1000;
+
+// The system allocator, counting the allocations made by each thread, so that those of the test
+// harness aren't counted.
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+#[usdt::provider]
+mod allocs {
+    fn native(_: u64, _: u32, _: &str) {}
+}
+
+fn fire_native(i: u64) {
+    allocs::native!(|| (i, i as u32, "native"));
+}
+
+// Return the allocations made by firing the probe `ITERATIONS` times, once it's been fired once, so
+// that buffers reused between probes are already allocated.
+fn allocations() -> usize {
+    fire_native(0);
+    let before = ALLOCATIONS.with(Cell::get);
+    for i in 0..ITERATIONS {
+        fire_native(black_box(i));
+    }
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+// This is one test, as the probe stays enabled once it is.
+#[test]
+fn test_native_probes_dont_allocate() {
+    usdt::register_probes().unwrap();
+    assert_eq!(allocations(), 0, "Firing a disabled probe allocated");
+
+    // Enable the probe, as a tracer does, by incrementing its semaphore.
+    #[cfg(all(target_os = "linux", not(feature = "disabled")))]
+    {
+        unsafe extern "C" {
+            static mut __usdt_sema_allocs_native: u16;
+        }
+        unsafe { (&raw mut __usdt_sema_allocs_native).write_volatile(1) };
+        assert_eq!(
+            allocations(),
+            0,
+            "Firing an enabled probe with only native arguments allocated"
+        );
+    }
+}